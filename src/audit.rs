@@ -0,0 +1,60 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+const AUDIT_LOG_PATH: &str = "jh_admin_audit.log";
+
+/// One change made through the CLI, stored as a line of JSON in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub operator: String,
+    pub module: String,
+    pub action: String,
+    pub target: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(module: &str, action: &str, target: &str) -> Self {
+        Self {
+            timestamp: Local::now().to_rfc3339(),
+            operator: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_default(),
+            module: module.to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn with_change(mut self, before: Option<&str>, after: Option<&str>) -> Self {
+        self.before = before.map(str::to_string);
+        self.after = after.map(str::to_string);
+        self
+    }
+}
+
+/// Location of the audit log, overridable through `JH_AUDIT_LOG`.
+pub fn audit_log_path() -> PathBuf {
+    std::env::var("JH_AUDIT_LOG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(AUDIT_LOG_PATH))
+}
+
+/// Appends `entry` to the audit log.
+pub fn record(entry: &AuditEntry) -> std::io::Result<()> {
+    append(&audit_log_path(), entry)
+}
+
+pub fn append(path: &Path, entry: &AuditEntry) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let line = serde_json::to_string(entry)?;
+    writeln!(file, "{}", line)
+}
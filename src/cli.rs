@@ -1,5 +1,6 @@
-use crate::io::{get_input, is_quit, select_index};
+use crate::io::{clear_screen, get_input, is_quit, select_index};
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CLI {
     modules: Vec<Box<dyn SafeModule>>,
     state: CLIState,
@@ -29,7 +30,7 @@ impl CLI {
 
     pub fn run_loop(&mut self) {
         loop {
-            print!("{}[2J", 27 as char);
+            clear_screen();
             match self.state {
                 CLIState::ModSelect => {
                     self.print_modules();
@@ -87,7 +88,7 @@ impl<T: ?Sized + Module> SafeModule for T {
         <T as Module>::MODULE_DESC
     }
     fn run_module(&mut self) {
-        let _ = <T as Module>::run_loop(self);
+        <T as Module>::run_loop(self);
     }
 }
 
@@ -102,7 +103,9 @@ pub trait Module: SafeModule {
     where
         Self: Sized;
     fn run_loop(&mut self);
+    #[allow(dead_code)]
     fn print_tools(&self);
+    #[allow(dead_code)]
     fn select_tool(&mut self);
 }
 
@@ -120,7 +123,7 @@ impl<T: ?Sized + Tool> SafeTool for T {
         <T as Tool>::TOOL_DESC
     }
     fn run_tool(&self) {
-        <T as Tool>::run_tool(&self);
+        <T as Tool>::run_tool(self);
     }
 }
 
@@ -0,0 +1,234 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, StatusCode};
+
+use crate::io::verbose;
+
+/// Controls how often and how long a request is retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the first attempt
+    pub max_retries: u32,
+    /// Delay used for the first retry, doubled on each subsequent one
+    pub base_delay: Duration,
+    /// Upper bound for a single backoff delay
+    pub max_delay: Duration,
+    /// Upper bound for the total time spent waiting between attempts
+    pub max_total: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_total: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    /// The request could not be sent or the connection failed
+    Request(reqwest::Error),
+    /// The server answered with a non-success status
+    Status { status: StatusCode, body: String },
+    /// The response body could not be decoded
+    Decode(reqwest::Error),
+    /// The server answered with a success status but reported an error in the payload
+    Api(String),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Request(e) => write!(f, "Request failed: {}", e),
+            HttpError::Status { status, body } => write!(f, "{}\n{}", status, body),
+            HttpError::Decode(e) => write!(f, "Failed to parse response: {}", e),
+            HttpError::Api(message) => write!(f, "API error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Sends HTTP requests with retries on rate limits, server errors and connection failures.
+///
+/// Retries honour `Retry-After` when the server sends it and otherwise use exponential
+/// backoff with full jitter. Requests that are not idempotent (POST, PATCH) are only retried
+/// when the server cannot have acted on them: on 429 and when the connection failed. The
+/// number of retries is reported when running with `--verbose`.
+pub struct RequestExecutor {
+    client: Client,
+    policy: RetryPolicy,
+}
+
+impl RequestExecutor {
+    pub fn new() -> Self {
+        Self::with_policy(RetryPolicy::default())
+    }
+
+    pub fn with_policy(policy: RetryPolicy) -> Self {
+        Self {
+            client: Client::new(),
+            policy,
+        }
+    }
+
+    /// Executes the request produced by `build`, retrying it as needed.
+    ///
+    /// `build` is called once per attempt since a `RequestBuilder` is consumed when sent.
+    /// `label` is only used in verbose output.
+    pub fn execute<F>(&self, label: &str, build: F) -> Result<Response, HttpError>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let started = Instant::now();
+        let mut waited = Duration::ZERO;
+        let mut attempt = 0;
+
+        loop {
+            let request = build(&self.client).build().map_err(HttpError::Request)?;
+            let idempotent = !matches!(*request.method(), Method::POST | Method::PATCH);
+            let (error, retry_after) = match self.client.execute(request) {
+                Ok(response) if response.status().is_success() => {
+                    if verbose() && attempt > 0 {
+                        eprintln!(
+                            "[http] {}: succeeded after {} retr{} ({:.1?})",
+                            label,
+                            attempt,
+                            if attempt == 1 { "y" } else { "ies" },
+                            started.elapsed()
+                        );
+                    }
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = parse_retry_after(&response);
+                    let body = response.text().unwrap_or_default();
+                    let error = HttpError::Status { status, body };
+                    if !is_retryable_status(status, idempotent) {
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+                Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => {
+                    (HttpError::Request(e), None)
+                }
+                Err(e) => return Err(HttpError::Request(e)),
+            };
+
+            if attempt >= self.policy.max_retries {
+                if verbose() {
+                    eprintln!("[http] {}: giving up after {} retries", label, attempt);
+                }
+                return Err(error);
+            }
+
+            // A Retry-After longer than the remaining budget is not shortened: retrying early
+            // would only be rate limited again
+            let delay = retry_after.unwrap_or_else(|| self.backoff_delay(attempt));
+            if waited + delay > self.policy.max_total {
+                if verbose() {
+                    eprintln!(
+                        "[http] {}: giving up after {} retries, retry budget of {:?} exhausted",
+                        label, attempt, self.policy.max_total
+                    );
+                }
+                return Err(error);
+            }
+
+            attempt += 1;
+            if verbose() {
+                let reason = match &error {
+                    HttpError::Status { status, .. } => status.to_string(),
+                    other => other.to_string(),
+                };
+                eprintln!(
+                    "[http] {}: retry {}/{} in {:.1?} ({})",
+                    label, attempt, self.policy.max_retries, delay, reason
+                );
+            }
+            thread::sleep(delay);
+            waited += delay;
+        }
+    }
+
+    /// Exponential backoff with full jitter: a random delay in `[0, base * 2^attempt]`.
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .policy
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.policy.max_delay);
+        let random = RandomState::new().hash_one(attempt);
+        let millis = ceiling.as_millis() as u64;
+        Duration::from_millis(if millis == 0 {
+            0
+        } else {
+            random % (millis + 1)
+        })
+    }
+}
+
+impl Default for RequestExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 429 means the request was not processed; after a server error an idempotent request can
+/// be sent again safely, but a POST may already have taken effect.
+fn is_retryable_status(status: StatusCode, idempotent: bool) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || (idempotent && status.is_server_error())
+}
+
+/// Reads `Retry-After` as either delay-seconds or an IMF-fixdate.
+fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = parse_http_date(value)?;
+    Some(
+        at.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Parses an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let mut parts = value.split_whitespace().skip(1);
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|p| p.parse::<i64>().ok());
+    let (h, m, s) = (clock.next()??, clock.next()??, clock.next()??);
+    if parts.next()? != "GMT" {
+        return None;
+    }
+
+    // Days since the epoch for a proleptic Gregorian date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + h * 3600 + m * 60 + s;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
@@ -1,4 +1,14 @@
-use std::io::{stdin, stdout, Write};
+use std::borrow::Cow;
+use std::io::{stdin, stdout, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::config::Configurer;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{ColorMode, Context, Editor, Helper};
 
 pub fn get_input(message: &str) -> String {
     print!("{}: ", message);
@@ -8,6 +18,116 @@ pub fn get_input(message: &str) -> String {
     buffer.trim().to_string()
 }
 
+/// Completes the word before the cursor from a list of candidates, ignoring case.
+struct WordCompleter<'a> {
+    candidates: &'a [String],
+}
+
+/// Characters that end a word, so that lists such as `cn,mail` and filter fragments such as
+/// `title=Manager` complete each name separately.
+fn is_word_separator(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(
+            c,
+            ',' | ';' | '(' | ')' | '&' | '|' | '!' | '=' | '<' | '>' | '~'
+        )
+}
+
+impl Completer for WordCompleter<'_> {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(is_word_separator)
+            .map(|i| i + line[i..].chars().next().map_or(1, char::len_utf8))
+            .unwrap_or(0);
+        let word = line[start..pos].to_ascii_lowercase();
+        let matches = self
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.to_ascii_lowercase().starts_with(&word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate.clone(),
+            })
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for WordCompleter<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for WordCompleter<'_> {}
+
+impl Validator for WordCompleter<'_> {}
+
+impl Helper for WordCompleter<'_> {}
+
+/// Like `get_input`, with Tab completing words from `candidates` when reading from a terminal.
+pub(crate) fn get_completed_input(message: &str, candidates: &[String]) -> String {
+    if candidates.is_empty() || !stdin().is_terminal() {
+        return get_input(message);
+    }
+    let Ok(mut editor) = Editor::<WordCompleter, DefaultHistory>::new() else {
+        return get_input(message);
+    };
+    editor.set_helper(Some(WordCompleter { candidates }));
+    match editor.readline(&format!("{}: ", message)) {
+        Ok(line) => line.trim().to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Shows a `*` for every character typed instead of the character itself.
+struct MaskingHelper;
+
+impl Completer for MaskingHelper {
+    type Candidate = Pair;
+}
+
+impl Hinter for MaskingHelper {
+    type Hint = String;
+}
+
+impl Highlighter for MaskingHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned("*".repeat(line.chars().count()))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        kind != CmdKind::MoveCursor
+    }
+}
+
+impl Validator for MaskingHelper {}
+
+impl Helper for MaskingHelper {}
+
+/// Like `get_input` for passwords: the input is masked when reading from a terminal and never
+/// kept in the line history.
+pub(crate) fn get_secret_input(message: &str) -> String {
+    if !stdin().is_terminal() {
+        return get_input(message);
+    }
+    let Ok(mut editor) = Editor::<MaskingHelper, DefaultHistory>::new() else {
+        return get_input(message);
+    };
+    editor.set_helper(Some(MaskingHelper));
+    editor.set_color_mode(ColorMode::Forced);
+    editor.set_auto_add_history(false);
+    match editor.readline(&format!("{}: ", message)) {
+        Ok(line) => line.trim().to_string(),
+        Err(_) => String::new(),
+    }
+}
+
 #[inline(always)]
 pub(crate) fn is_quit(input: &str) -> bool {
     input == "q" || input == "Q"
@@ -23,3 +143,20 @@ pub(crate) fn select_index(input: &str, len: usize) -> Option<usize> {
 pub(crate) fn clear_screen() {
     print!("{}[2J", 27 as char);
 }
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn set_verbose(enabled: bool) {
+    VERBOSE.store(enabled, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub(crate) fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// Asks a yes/no question, defaulting to no.
+pub(crate) fn confirm(message: &str) -> bool {
+    let input = get_input(&format!("{} [y/N]", message));
+    input.eq_ignore_ascii_case("y") || input.eq_ignore_ascii_case("yes")
+}
@@ -15,9 +15,41 @@ use syn::{ItemFn, LitStr, Result, Token, parse::Parse, parse::ParseStream};
 ///
 /// # Example
 /// ```
+/// # mod cli {
+/// #     pub trait SafeTool {
+/// #         fn name(&self) -> &'static str;
+/// #         fn desc(&self) -> &'static str;
+/// #         fn run_tool(&self);
+/// #     }
+/// #     #[derive(Clone)]
+/// #     pub enum ModuleState { ToolSelect, InTool(usize), Quit }
+/// #     pub trait Module {
+/// #         const MODULE_NAME: &'static str;
+/// #         const MODULE_DESC: &'static str;
+/// #         type Output;
+/// #         type Error;
+/// #         fn init_module(tools: Vec<Box<dyn SafeTool>>) -> Self where Self: Sized;
+/// #         fn run_loop(&mut self);
+/// #         fn print_tools(&self);
+/// #         fn select_tool(&mut self);
+/// #     }
+/// # }
+/// # mod io {
+/// #     pub fn get_input(_: &str) -> String { "q".to_string() }
+/// #     pub fn is_quit(input: &str) -> bool { input == "q" }
+/// #     pub fn select_index(_: &str, _: usize) -> Option<usize> { None }
+/// # }
+/// use cli::Module;
+/// use jh_admin_cli_macros::Module;
+///
 /// #[derive(Module)]
 /// #[module(name = "Test Module", desc = "A test module")]
 /// struct TestModule;
+///
+/// # fn main() {
+/// assert_eq!(TestModule::MODULE_NAME, "Test Module");
+/// TestModule::init_module(Vec::new()).run_loop();
+/// # }
 /// ```
 #[proc_macro_derive(Module, attributes(module))]
 pub fn derive_module(input: TokenStream) -> TokenStream {
@@ -47,39 +79,34 @@ pub fn derive_module(input: TokenStream) -> TokenStream {
     let mut module_desc = None;
 
     for attr in &input.attrs {
-        if attr.path().is_ident("module") {
-            match &attr.meta {
-                Meta::List(meta_list) => {
-                    let nested_meta = meta_list.parse_args_with(
-                        syn::punctuated::Punctuated::<Meta, syn::Token![,]>::parse_terminated,
-                    );
-
-                    if let Ok(nested) = nested_meta {
-                        for meta in nested {
-                            if let Meta::NameValue(name_value) = meta {
-                                if name_value.path.is_ident("name") {
-                                    if let Expr::Lit(ExprLit {
-                                        lit: Lit::Str(lit_str),
-                                        ..
-                                    }) = &name_value.value
-                                    {
-                                        module_name = Some(lit_str.value());
-                                    }
-                                }
-                                if name_value.path.is_ident("desc") {
-                                    if let Expr::Lit(ExprLit {
-                                        lit: Lit::Str(lit_str),
-                                        ..
-                                    }) = &name_value.value
-                                    {
-                                        module_desc = Some(lit_str.value());
-                                    }
-                                }
-                            }
+        if attr.path().is_ident("module")
+            && let Meta::List(meta_list) = &attr.meta
+        {
+            let nested_meta = meta_list.parse_args_with(
+                syn::punctuated::Punctuated::<Meta, syn::Token![,]>::parse_terminated,
+            );
+
+            if let Ok(nested) = nested_meta {
+                for meta in nested {
+                    if let Meta::NameValue(name_value) = meta {
+                        if name_value.path.is_ident("name")
+                            && let Expr::Lit(ExprLit {
+                                lit: Lit::Str(lit_str),
+                                ..
+                            }) = &name_value.value
+                        {
+                            module_name = Some(lit_str.value());
+                        }
+                        if name_value.path.is_ident("desc")
+                            && let Expr::Lit(ExprLit {
+                                lit: Lit::Str(lit_str),
+                                ..
+                            }) = &name_value.value
+                        {
+                            module_desc = Some(lit_str.value());
                         }
                     }
                 }
-                _ => {}
             }
         }
     }
@@ -280,14 +307,30 @@ impl Parse for ToolArgs {
 ///
 /// # Example
 /// ```
+/// # mod cli {
+/// #     pub trait Tool {
+/// #         const TOOL_NAME: &'static str;
+/// #         const TOOL_DESC: &'static str;
+/// #         fn run_tool(&self);
+/// #     }
+/// # }
+/// use cli::Tool;
+/// use jh_admin_cli_macros::derive_tool;
+///
 /// #[derive_tool(id = "ListUsersTool", name = "List Users", desc = "Lists all users in the system")]
 /// fn list_users() {
 ///     // Function implementation here
 /// }
-/// ```
 ///
-/// The macro also supports functions with parameters:
+/// # fn main() {
+/// assert_eq!(ListUsersTool::TOOL_NAME, "List Users");
+/// ListUsersTool.run_tool();
+/// # }
 /// ```
+///
+/// Functions with parameters are rejected, since a tool is run without arguments:
+/// ```compile_fail
+/// # use jh_admin_cli_macros::derive_tool;
 /// #[derive_tool(id = "GreetTool", name = "Greeting Tool")]
 /// fn greet(name: &str, formal: bool) {
 ///     if formal {
@@ -22,23 +22,82 @@
 mod cli;
 mod modules;
 mod io;
+mod http;
+mod audit;
 mod models;
+#[cfg(test)]
+mod tests;
 
+use clap::Parser;
 use dotenv::dotenv;
-use modules::email::ListEmailUsers;
+use modules::email::*;
 use modules::ldap::*;
 use cli::Module;
 use cli::*;
 
+#[derive(Parser)]
+#[command(about = "Command-line interface for managing JH Admin tasks")]
+struct Args {
+    /// Print diagnostic output such as HTTP retry counts
+    #[arg(short, long)]
+    verbose: bool,
+    /// LDAP target to work on, configured with LDAP_<NAME>_* variables (overrides LDAP_TARGET)
+    #[arg(long, value_name = "NAME")]
+    ldap_target: Option<String>,
+}
+
 fn main() {
+    let args = Args::parse();
+    io::set_verbose(args.verbose);
+    set_ldap_target(args.ldap_target);
     // Read environment variables from .env file
     dotenv().ok();
-    let tool = FetchUsersTool;
-    let email = ListEmailUsers;
     // Initialize the LDAP module with all tools
-    let ldap_module = LDAPModule::init_module(vec![Box::new(tool), Box::new(email)]);
+    let ldap_module = LDAPModule::init_module(vec![
+        Box::new(FetchUsersTool),
+        Box::new(SearchUsersTool),
+        Box::new(CreateUserTool),
+        Box::new(DisableUserTool),
+        Box::new(EnableUserTool),
+        Box::new(UnlockUserTool),
+        Box::new(ListLockedOutTool),
+        Box::new(ResetPasswordTool),
+        Box::new(ListGroupsTool),
+        Box::new(ListGroupMembersTool),
+        Box::new(ShowUserGroupsTool),
+        Box::new(AddGroupMemberTool),
+        Box::new(RemoveGroupMemberTool),
+        Box::new(BulkAddGroupMembersTool),
+        Box::new(AccountReportTool),
+        Box::new(ShowEntryTool),
+        Box::new(ExportLdifTool),
+        Box::new(ImportLdifTool),
+        Box::new(TakeSnapshotTool),
+        Box::new(DiffSnapshotsTool),
+        Box::new(DiffSnapshotLiveTool),
+        Box::new(BrowseOusTool),
+        Box::new(MoveObjectTool),
+        Box::new(BulkMoveTool),
+        Box::new(ComputerInventoryTool),
+        Box::new(BulkEditAttributesTool),
+        Box::new(OrgChartTool),
+        Box::new(CompareUserTool),
+        Box::new(SchemaBrowserTool),
+        Box::new(PasswordPolicyTool),
+    ]);
+    // Initialize the Email module with all tools
+    let email_module = EmailModule::init_module(vec![
+        Box::new(ListEmailUsers),
+        Box::new(ShowEmailUser),
+        Box::new(AddEmailUser),
+        Box::new(DeleteEmailUser),
+        Box::new(ListEmailAdmins),
+        Box::new(ChangeEmailUserRole),
+        Box::new(ListMailPolicies),
+        Box::new(AssignMailPolicy),
+    ]);
 
     // Create a new CLI with our modules
-    let mut cli = CLI::new(vec![Box::new(ldap_module)]);
+    let mut cli = CLI::new(vec![Box::new(ldap_module), Box::new(email_module)]);
     cli.run_loop();
 }
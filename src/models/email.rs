@@ -1,19 +1,21 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum RespData {
     Vector(Option<Vec<Data>>),
-    Item(Option<Data>),
+    Item(Option<Box<Data>>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ZohoApiResponse {
+pub struct ZohoResponse<T> {
     pub status: Option<Status>,
-    pub data: Option<RespData>,
+    pub data: Option<T>,
 }
 
+pub type ZohoApiResponse = ZohoResponse<RespData>;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Status {
     pub code: Option<i32>,
@@ -193,9 +195,35 @@ pub struct Address {
     pub state: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// The mail policy assigned to an account.
+///
+/// Zoho keys the policy name by the (org-specific) policy id, e.g.
+/// `{"1082700000192558003": "Business Policy", "zoid": 1082700000}`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct PolicyId {
-    #[serde(rename = "1082700000192558003")]
-    pub business_policy: Option<String>,
     pub zoid: Option<i64>,
+    #[serde(flatten)]
+    pub policies: BTreeMap<String, String>,
+}
+
+impl PolicyId {
+    /// Formats the assigned policies as `name (id)`.
+    pub fn describe(&self) -> String {
+        self.policies
+            .iter()
+            .map(|(id, name)| format!("{} ({})", name, id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MailPolicy {
+    #[serde(rename = "policyId")]
+    pub policy_id: Option<String>,
+    #[serde(rename = "policyName")]
+    pub policy_name: Option<String>,
+    pub description: Option<String>,
+    #[serde(rename = "isDefault")]
+    pub is_default: Option<bool>,
 }
@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A machine the monitoring module should know about.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Host {
+    /// Fully qualified name used to reach the host
+    pub hostname: String,
+    /// Short name, e.g. the computer account name
+    pub name: String,
+    pub os: Option<String>,
+    pub os_version: Option<String>,
+    /// Where the host is managed, e.g. its OU
+    pub location: Option<String>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub enabled: bool,
+    /// No sign of life for longer than the stale threshold when the inventory was built
+    pub stale: bool,
+    /// Where the entry came from, e.g. `ldap`
+    pub source: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct HostInventory {
+    pub updated: Option<DateTime<Utc>>,
+    pub hosts: Vec<Host>,
+}
@@ -1,14 +1,18 @@
+use chrono::{Local, TimeZone};
 use jh_admin_cli_macros::{Module, derive_tool};
-use std::fs::{self, File};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
 use std::io::{Read, Write};
 use std::path::Path;
 use std::time::{Duration, SystemTime};
-use serde::{Deserialize, Serialize};
-use reqwest::blocking::Client;
 use url::Url;
 use webbrowser;
 
-use crate::models::email::{RespData, ZohoApiResponse};
+use crate::audit::{self, AuditEntry};
+use crate::http::{HttpError, RequestExecutor};
+use crate::io::{confirm, get_input, get_secret_input, select_index};
+use crate::models::email::{Data, MailPolicy, RespData, ZohoApiResponse, ZohoResponse};
 
 #[derive(Module)]
 #[module(
@@ -18,86 +22,140 @@ use crate::models::email::{RespData, ZohoApiResponse};
 pub struct EmailModule;
 
 #[derive(Debug, Serialize, Deserialize)]
-struct TokenInfo {
-    access_token: String,
-    refresh_token: String,
+pub(crate) struct TokenInfo {
+    pub(crate) access_token: String,
+    pub(crate) refresh_token: String,
     last_refresh: SystemTime,
 }
 
 static mut CURRENT_ACCESS_TOKEN: Option<String> = None;
 const TOKEN_FILE_PATH: &str = "zoho_refresh_token.json";
 const REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60); // 30 minutes
+const ACCOUNTS_PAGE_SIZE: usize = 100;
+/// Account roles accepted by Zoho, from least to most privileged.
+const ROLES: [&str; 3] = ["member", "admin", "super_admin"];
+
+/// Base URLs of the Zoho accounts (OAuth) and mail APIs.
+///
+/// Overridable through `ZOHO_ACCOUNTS_URL` and `ZOHO_MAIL_URL`, e.g. to point at a local stand-in.
+#[derive(Debug, Clone)]
+pub struct ZohoEndpoints {
+    pub accounts: String,
+    pub mail: String,
+}
+
+impl ZohoEndpoints {
+    pub fn from_env() -> Self {
+        Self {
+            accounts: std::env::var("ZOHO_ACCOUNTS_URL")
+                .unwrap_or_else(|_| "https://accounts.zoho.com".to_string()),
+            mail: std::env::var("ZOHO_MAIL_URL")
+                .unwrap_or_else(|_| "https://mail.zoho.com".to_string()),
+        }
+    }
+
+    fn token_url(&self) -> String {
+        format!("{}/oauth/v2/token", self.accounts.trim_end_matches('/'))
+    }
+
+    fn organization_url(&self, zoid: &str) -> String {
+        format!(
+            "{}/api/organization/{}",
+            self.mail.trim_end_matches('/'),
+            zoid
+        )
+    }
+}
 
-fn get_auth_code() -> String {
+fn get_auth_code(endpoints: &ZohoEndpoints) -> String {
     let client_id = "";
     let scope = "ZohoMail.organization.accounts.ALL";
     let redirect_uri = "http://localhost/";
 
     let auth_url = format!(
-        "https://accounts.zoho.com/oauth/v2/auth?response_type=code&client_id={}&scope={}&redirect_uri={}&access_type=offline",
-        client_id, scope, redirect_uri
+        "{}/oauth/v2/auth?response_type=code&client_id={}&scope={}&redirect_uri={}&access_type=offline",
+        endpoints.accounts.trim_end_matches('/'),
+        client_id,
+        scope,
+        redirect_uri
     );
 
     println!("Opening browser for Zoho authorization...");
     webbrowser::open(&auth_url).expect("Failed to open web browser");
 
-    println!("After authorizing, you will be redirected to a URL like: http://localhost/?code=YOUR_AUTH_CODE");
+    println!(
+        "After authorizing, you will be redirected to a URL like: http://localhost/?code=YOUR_AUTH_CODE"
+    );
     println!("Please enter the authorization code from the URL:");
 
     let mut auth_code = String::new();
-    std::io::stdin().read_line(&mut auth_code).expect("Failed to read line");
+    std::io::stdin()
+        .read_line(&mut auth_code)
+        .expect("Failed to read line");
 
     // Trim whitespace and extract only the code parameter if full URL is pasted
     let auth_code = auth_code.trim();
     if auth_code.starts_with("http") {
         let url = Url::parse(auth_code).expect("Failed to parse URL");
-        let code = url.query_pairs()
+        url.query_pairs()
             .find(|(key, _)| key == "code")
             .map(|(_, value)| value.to_string())
-            .expect("No code found in URL");
-        code
+            .expect("No code found in URL")
     } else {
         auth_code.to_string()
     }
 }
 
-fn exchange_auth_code_for_tokens(auth_code: &str) -> TokenInfo {
+/// Reads a field of an OAuth token response, surfacing Zoho's `{"error": ...}` payloads.
+fn token_field(token_response: &serde_json::Value, field: &str) -> Result<String, HttpError> {
+    if let Some(error) = token_response["error"].as_str() {
+        return Err(HttpError::Api(error.to_string()));
+    }
+    token_response[field]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| HttpError::Api(format!("No {} in token response", field)))
+}
+
+pub(crate) fn request_tokens(
+    endpoints: &ZohoEndpoints,
+    auth_code: &str,
+) -> Result<TokenInfo, HttpError> {
     let client_id = "";
     // let client_secret = std::env::var("ZOHO_CLIENT_SECRET").expect("ZOHO_CLIENT_SECRET not set");
     let client_secret = "";
     let redirect_uri = "http://localhost/";
 
-    let client = Client::new();
-    let response = client
-        .post("https://accounts.zoho.com/oauth/v2/token")
-        .form(&[
-            ("client_id", client_id),
-            ("client_secret", client_secret),
-            ("grant_type", "authorization_code"),
-            ("redirect_uri", redirect_uri),
-            ("code", auth_code),
-        ])
-        .send()
-        .expect("Failed to send token request");
-
-    if !response.status().is_success() {
-        panic!("Error getting tokens: {} - {}",
-            response.status(),
-            response.text().unwrap_or_else(|_| "No error message".to_string()));
-    }
-
-    let token_response: serde_json::Value = response.json().expect("Failed to parse token response");
-
-    let token_info = TokenInfo {
-        access_token: token_response["access_token"].as_str().expect("No access token").to_string(),
-        refresh_token: token_response["refresh_token"].as_str().expect("No refresh token").to_string(),
+    let token_response: serde_json::Value = RequestExecutor::new()
+        .execute("token exchange", |client| {
+            client.post(endpoints.token_url()).form(&[
+                ("client_id", client_id),
+                ("client_secret", client_secret),
+                ("grant_type", "authorization_code"),
+                ("redirect_uri", redirect_uri),
+                ("code", auth_code),
+            ])
+        })?
+        .json()
+        .map_err(HttpError::Decode)?;
+
+    Ok(TokenInfo {
+        access_token: token_field(&token_response, "access_token")?,
+        refresh_token: token_field(&token_response, "refresh_token")?,
         last_refresh: SystemTime::now(),
-    };
+    })
+}
+
+fn exchange_auth_code_for_tokens(endpoints: &ZohoEndpoints, auth_code: &str) -> TokenInfo {
+    let token_info = request_tokens(endpoints, auth_code)
+        .unwrap_or_else(|e| panic!("Error getting tokens: {}", e));
 
     // Store the token info to a file
-    let file_content = serde_json::to_string_pretty(&token_info).expect("Failed to serialize token info");
+    let file_content =
+        serde_json::to_string_pretty(&token_info).expect("Failed to serialize token info");
     let mut file = File::create(TOKEN_FILE_PATH).expect("Failed to create token file");
-    file.write_all(file_content.as_bytes()).expect("Failed to write token info");
+    file.write_all(file_content.as_bytes())
+        .expect("Failed to write token info");
 
     // Store the access token for immediate use
     unsafe {
@@ -107,35 +165,30 @@ fn exchange_auth_code_for_tokens(auth_code: &str) -> TokenInfo {
     token_info
 }
 
-fn refresh_access_token(refresh_token: &str) -> String {
+pub(crate) fn refresh_access_token(
+    endpoints: &ZohoEndpoints,
+    refresh_token: &str,
+) -> Result<String, HttpError> {
     // let client_id = std::env::var("ZOHO_CLIENT_ID").expect("ZOHO_CLIENT_ID not set");
     let client_id = "";
     // let client_secret = std::env::var("ZOHO_CLIENT_SECRET").expect("ZOHO_CLIENT_SECRET not set");
     let client_secret = "";
 
-    let client = Client::new();
-    let response = client
-        .post("https://accounts.zoho.com/oauth/v2/token")
-        .form(&[
-            ("client_id", client_id),
-            ("client_secret", client_secret),
-            ("grant_type", "refresh_token"),
-            ("refresh_token", refresh_token),
-        ])
-        .send()
-        .expect("Failed to send refresh token request");
-
-    if !response.status().is_success() {
-        panic!("Error refreshing token: {} - {}",
-            response.status(),
-            response.text().unwrap_or_else(|_| "No error message".to_string()));
-    }
-
-    let token_response: serde_json::Value = response.json().expect("Failed to parse refresh token response");
-    token_response["access_token"].as_str().expect("No access token").to_string()
+    let token_response: serde_json::Value = RequestExecutor::new()
+        .execute("token refresh", |client| {
+            client.post(endpoints.token_url()).form(&[
+                ("client_id", client_id),
+                ("client_secret", client_secret),
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ])
+        })?
+        .json()
+        .map_err(HttpError::Decode)?;
+    token_field(&token_response, "access_token")
 }
 
-fn get_oauth_token() -> String {
+fn get_oauth_token(endpoints: &ZohoEndpoints) -> String {
     // Check if we have a valid access token in memory
     unsafe {
         // Use &raw const to create a raw pointer
@@ -150,24 +203,33 @@ fn get_oauth_token() -> String {
         // Read token info from file
         let mut file = File::open(TOKEN_FILE_PATH).expect("Failed to open token file");
         let mut contents = String::new();
-        file.read_to_string(&mut contents).expect("Failed to read token file");
+        file.read_to_string(&mut contents)
+            .expect("Failed to read token file");
 
-        let mut token_info: TokenInfo = serde_json::from_str(&contents).expect("Failed to parse token info");
+        let mut token_info: TokenInfo =
+            serde_json::from_str(&contents).expect("Failed to parse token info");
 
         // Check if we need to refresh (more than 30 minutes since last refresh)
         let now = SystemTime::now();
-        if now.duration_since(token_info.last_refresh).unwrap_or(Duration::from_secs(0)) > REFRESH_INTERVAL {
+        if now
+            .duration_since(token_info.last_refresh)
+            .unwrap_or(Duration::from_secs(0))
+            > REFRESH_INTERVAL
+        {
             println!("Refreshing access token...");
-            let new_access_token = refresh_access_token(&token_info.refresh_token);
+            let new_access_token = refresh_access_token(endpoints, &token_info.refresh_token)
+                .unwrap_or_else(|e| panic!("Error refreshing token: {}", e));
 
             // Update token info
             token_info.access_token = new_access_token;
             token_info.last_refresh = now;
 
             // Save updated token info
-            let file_content = serde_json::to_string_pretty(&token_info).expect("Failed to serialize token info");
+            let file_content =
+                serde_json::to_string_pretty(&token_info).expect("Failed to serialize token info");
             let mut file = File::create(TOKEN_FILE_PATH).expect("Failed to create token file");
-            file.write_all(file_content.as_bytes()).expect("Failed to write token info");
+            file.write_all(file_content.as_bytes())
+                .expect("Failed to write token info");
         }
 
         // Store the access token for future use
@@ -179,59 +241,774 @@ fn get_oauth_token() -> String {
     } else {
         // No refresh token file, need to go through full authorization flow
         println!("No existing token found. Starting authorization process...");
-        let auth_code = get_auth_code();
-        let token_info = exchange_auth_code_for_tokens(&auth_code);
+        let auth_code = get_auth_code(endpoints);
+        let token_info = exchange_auth_code_for_tokens(endpoints, &auth_code);
         token_info.access_token
     }
 }
 
+/// Authenticated client for the Zoho Mail organization APIs.
+pub struct ZohoClient {
+    executor: RequestExecutor,
+    endpoints: ZohoEndpoints,
+    zoid: String,
+    token: String,
+}
+
+/// Fields sent when creating a Zoho mail account.
+#[derive(Debug, Serialize)]
+pub struct NewAccount {
+    #[serde(rename = "primaryEmailAddress")]
+    pub primary_email_address: String,
+    pub password: String,
+    #[serde(rename = "firstName")]
+    pub first_name: String,
+    #[serde(rename = "lastName")]
+    pub last_name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+}
+
+impl ZohoClient {
+    pub fn new(endpoints: ZohoEndpoints, zoid: &str, token: &str) -> Self {
+        Self {
+            executor: RequestExecutor::new(),
+            endpoints,
+            zoid: zoid.to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    /// Builds a client from the environment, going through the OAuth flow if needed.
+    pub fn from_env() -> Self {
+        let endpoints = ZohoEndpoints::from_env();
+        let token = get_oauth_token(&endpoints);
+        let zoid = std::env::var("ZOHO_ZOID").unwrap_or_default();
+        Self::new(endpoints, &zoid, &token)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_retry_policy(mut self, policy: crate::http::RetryPolicy) -> Self {
+        self.executor = RequestExecutor::with_policy(policy);
+        self
+    }
+
+    fn send<T: DeserializeOwned>(
+        &self,
+        label: &str,
+        build: impl Fn(&reqwest::blocking::Client) -> reqwest::blocking::RequestBuilder,
+    ) -> Result<ZohoResponse<T>, HttpError> {
+        let response: ZohoResponse<T> = self
+            .executor
+            .execute(label, |client| {
+                build(client).header("Authorization", format!("Zoho-oauthtoken {}", self.token))
+            })?
+            .json()
+            .map_err(HttpError::Decode)?;
+        match &response.status {
+            Some(status) if status.code.is_some_and(|code| !(200..300).contains(&code)) => Err(
+                HttpError::Api(status.description.clone().unwrap_or_default()),
+            ),
+            _ => Ok(response),
+        }
+    }
+
+    /// Fetches every account in the organization, following pagination.
+    pub fn list_accounts(&self) -> Result<Vec<Data>, HttpError> {
+        let url = format!("{}/accounts", self.endpoints.organization_url(&self.zoid));
+        let mut accounts = Vec::new();
+        loop {
+            let start = accounts.len();
+            let response: ZohoApiResponse = self.send("list accounts", |client| {
+                client
+                    .get(&url)
+                    .query(&[("start", start), ("limit", ACCOUNTS_PAGE_SIZE)])
+            })?;
+            let page = match response.data {
+                Some(RespData::Vector(Some(page))) => page,
+                Some(RespData::Item(Some(item))) => vec![*item],
+                _ => Vec::new(),
+            };
+            let page_len = page.len();
+            accounts.extend(page);
+            if page_len < ACCOUNTS_PAGE_SIZE {
+                return Ok(accounts);
+            }
+        }
+    }
+
+    pub fn create_account(&self, account: &NewAccount) -> Result<Data, HttpError> {
+        let url = format!("{}/accounts", self.endpoints.organization_url(&self.zoid));
+        let response: ZohoApiResponse =
+            self.send("create account", |client| client.post(&url).json(account))?;
+        match response.data {
+            Some(RespData::Item(Some(data))) => Ok(*data),
+            Some(RespData::Vector(Some(mut data))) if !data.is_empty() => Ok(data.remove(0)),
+            _ => Err(HttpError::Api("No account in response".to_string())),
+        }
+    }
+
+    /// Fetches a single account by account id, zuid or email address.
+    pub fn get_account(&self, id_or_email: &str) -> Result<Data, HttpError> {
+        // The id is typed by the user: escape it so that `/`, `?` or `#` stay part of it
+        let mut url = Url::parse(&format!(
+            "{}/accounts",
+            self.endpoints.organization_url(&self.zoid)
+        ))
+        .map_err(|e| HttpError::Api(format!("Invalid mail API URL: {}", e)))?;
+        url.path_segments_mut()
+            .map_err(|_| HttpError::Api("Invalid mail API URL".to_string()))?
+            .push(id_or_email);
+        let response: ZohoApiResponse =
+            self.send("get account", |client| client.get(url.clone()))?;
+        match response.data {
+            Some(RespData::Item(Some(data))) => Ok(*data),
+            Some(RespData::Vector(Some(mut data))) if !data.is_empty() => Ok(data.remove(0)),
+            _ => Err(HttpError::Api(format!(
+                "No account found for {}",
+                id_or_email
+            ))),
+        }
+    }
+
+    /// Deletes the accounts identified by their `zuid`.
+    pub fn delete_accounts(&self, zuids: &[i64]) -> Result<(), HttpError> {
+        let url = format!("{}/accounts", self.endpoints.organization_url(&self.zoid));
+        let body = serde_json::json!({ "accountList": zuids });
+        self.send::<serde_json::Value>("delete accounts", |client| {
+            client.delete(&url).json(&body)
+        })?;
+        Ok(())
+    }
+
+    /// Changes the role of the account identified by its `zuid`.
+    pub fn change_role(&self, zuid: i64, role: &str) -> Result<(), HttpError> {
+        let url = format!(
+            "{}/accounts/{}",
+            self.endpoints.organization_url(&self.zoid),
+            zuid
+        );
+        let body = serde_json::json!({ "mode": "changeRole", "zuid": zuid, "role": role });
+        self.send::<serde_json::Value>("change role", |client| client.put(&url).json(&body))?;
+        Ok(())
+    }
+
+    pub fn list_policies(&self) -> Result<Vec<MailPolicy>, HttpError> {
+        let url = format!("{}/policy", self.endpoints.organization_url(&self.zoid));
+        let response = self.send::<Vec<MailPolicy>>("list policies", |client| client.get(&url))?;
+        Ok(response.data.unwrap_or_default())
+    }
+
+    /// Assigns the mail policy `policy_id` to the accounts identified by their `zuid`.
+    pub fn assign_policy(&self, policy_id: &str, zuids: &[i64]) -> Result<(), HttpError> {
+        let url = format!(
+            "{}/policy/{}",
+            self.endpoints.organization_url(&self.zoid),
+            policy_id
+        );
+        let body = serde_json::json!({ "mode": "assignUsers", "userList": zuids });
+        self.send::<serde_json::Value>("assign policy", |client| client.put(&url).json(&body))?;
+        Ok(())
+    }
+}
+
+fn format_email_user(user: &Data) -> String {
+    format!(
+        "User: {}, Email(s): {}",
+        user.display_name.as_deref().unwrap_or_default(),
+        user.email_address
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|e| format!(
+                "{}{}",
+                e.mail_id.as_deref().unwrap_or_default(),
+                if e.is_alias.unwrap_or(false) {
+                    " (Alias)"
+                } else {
+                    ""
+                }
+            ))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+/// Formats a Zoho timestamp (milliseconds since the epoch) in local time.
+///
+/// Zoho uses `-1` (or `0`) for events that never happened.
+fn format_timestamp(millis: i64) -> String {
+    if millis <= 0 {
+        return "Never".to_string();
+    }
+    match Local.timestamp_millis_opt(millis).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+        None => millis.to_string(),
+    }
+}
+
+/// Formats a storage amount given in KB.
+fn format_storage_kb(kb: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    let mut value = kb as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "Yes" } else { "No" }
+}
+
+/// Writes `label: value` for fields that are present in the payload.
+fn write_field(out: &mut impl Write, label: &str, value: Option<impl std::fmt::Display>) {
+    if let Some(value) = value {
+        let _ = writeln!(out, "  {:<24}{}", format!("{}:", label), value);
+    }
+}
+
+/// Renders every known field of an account, grouped by topic.
+pub(crate) fn write_account_details(account: &Data, out: &mut impl Write) {
+    let _ = writeln!(out, "Identity");
+    write_field(out, "Display name", account.display_name.as_ref());
+    write_field(out, "First name", account.first_name.as_ref());
+    write_field(out, "Last name", account.last_name.as_ref());
+    write_field(
+        out,
+        "Account display name",
+        account.account_display_name.as_ref(),
+    );
+    write_field(out, "Account name", account.account_name.as_ref());
+    write_field(out, "Account ID", account.account_id.as_ref());
+    write_field(out, "ZUID", account.zuid);
+    write_field(out, "Role", account.role.as_ref());
+    write_field(out, "IAM role", account.iam_user_role.as_ref());
+    write_field(out, "Custom admin", account.is_custom_admin.map(yes_no));
+    write_field(out, "Account type", account.account_type.as_ref());
+    write_field(out, "Plan type", account.plan_type);
+    write_field(out, "Enabled", account.enabled.map(yes_no));
+    write_field(out, "Active", account.status.map(yes_no));
+    write_field(out, "Mailbox status", account.mailbox_status.as_ref());
+    write_field(
+        out,
+        "Gender",
+        account.gender.as_ref().filter(|g| !g.is_empty()),
+    );
+    write_field(out, "Language", account.language.as_ref());
+    write_field(out, "Time zone", account.time_zone.as_ref());
+    write_field(out, "Country", account.country.as_ref());
+    write_field(
+        out,
+        "Mobile number",
+        account.mobile_number.as_ref().filter(|n| !n.is_empty()),
+    );
+    write_field(
+        out,
+        "Phone number",
+        account.phone_number.as_ref().filter(|n| !n.is_empty()),
+    );
+    write_field(
+        out,
+        "Created",
+        account.account_creation_time.map(format_timestamp),
+    );
+    write_field(
+        out,
+        "Mailbox created",
+        account.mailbox_creation_time.map(format_timestamp),
+    );
+    write_field(out, "Expires", account.user_expiry.map(format_timestamp));
+
+    let _ = writeln!(out, "Addresses");
+    write_field(out, "Primary email", account.primary_email_address.as_ref());
+    write_field(out, "Mailbox address", account.mailbox_address.as_ref());
+    write_field(
+        out,
+        "Incoming user name",
+        account.incoming_user_name.as_ref(),
+    );
+    for address in account.email_address.as_deref().unwrap_or_default() {
+        let mut flags = Vec::new();
+        if address.is_primary.unwrap_or(false) {
+            flags.push("primary");
+        }
+        if address.is_alias.unwrap_or(false) {
+            flags.push("alias");
+        }
+        if !address.is_confirmed.unwrap_or(true) {
+            flags.push("unconfirmed");
+        }
+        let flags = if flags.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", flags.join(", "))
+        };
+        write_field(
+            out,
+            "Email address",
+            Some(format!(
+                "{}{}",
+                address.mail_id.as_deref().unwrap_or_default(),
+                flags
+            )),
+        );
+    }
+    if let Some(postal) = &account.address {
+        let lines: Vec<&str> = [
+            &postal.street_addr,
+            &postal.city,
+            &postal.state,
+            &postal.postal_code,
+            &postal.country,
+        ]
+        .into_iter()
+        .filter_map(|part| part.as_deref().filter(|p| !p.is_empty()))
+        .collect();
+        write_field(
+            out,
+            "Postal address",
+            Some(lines.join(", ")).filter(|a| !a.is_empty()),
+        );
+    }
+
+    let _ = writeln!(out, "Security");
+    write_field(out, "Two-factor auth", account.tfa_enabled.map(yes_no));
+    write_field(out, "IAM status", account.iam_status);
+    write_field(out, "Last login", account.last_login.map(format_timestamp));
+    write_field(out, "Last client", account.last_client.as_ref());
+    write_field(
+        out,
+        "Last password reset",
+        account.last_password_reset.map(format_timestamp),
+    );
+    write_field(
+        out,
+        "Mail policy",
+        account.policy_id.as_ref().map(|p| p.describe()),
+    );
+    write_field(out, "Spam check", account.spamcheck_enabled.map(yes_no));
+    write_field(out, "Web access blocked", account.web_blocked.map(yes_no));
+    write_field(
+        out,
+        "Incoming blocked",
+        account.incoming_blocked.map(yes_no),
+    );
+    write_field(
+        out,
+        "Outgoing blocked",
+        account.outgoing_blocked.map(yes_no),
+    );
+
+    let _ = writeln!(out, "Storage");
+    write_field(out, "Used", account.used_storage.map(format_storage_kb));
+    write_field(
+        out,
+        "Allowed",
+        account.allowed_storage.map(format_storage_kb),
+    );
+    write_field(out, "Plan storage (GB)", account.plan_storage);
+    write_field(out, "Basic storage", account.basic_storage.as_ref());
+    for (key, value) in account.extra_storage.iter().flatten() {
+        write_field(out, &format!("Extra storage {}", key), Some(value));
+    }
+    if let Some(ediscovery) = &account.ediscovery_storage {
+        write_field(
+            out,
+            "eDiscovery enabled",
+            ediscovery.is_ediscovery_storage_enabled.map(yes_no),
+        );
+        write_field(
+            out,
+            "eDiscovery used",
+            ediscovery.ediscovery_used_storage.map(format_storage_kb),
+        );
+        write_field(
+            out,
+            "eDiscovery allowed",
+            ediscovery.ediscovery_allowed_storage.map(format_storage_kb),
+        );
+    }
+
+    let _ = writeln!(out, "Protocols");
+    write_field(out, "IMAP access", account.imap_access_enabled.map(yes_no));
+    write_field(out, "IMAP blocked", account.imap_blocked.map(yes_no));
+    write_field(out, "POP access", account.pop_access_enabled.map(yes_no));
+    write_field(out, "POP blocked", account.pop_blocked.map(yes_no));
+    write_field(
+        out,
+        "POP fetch time",
+        account.pop_fetch_time.map(format_timestamp),
+    );
+    write_field(out, "ActiveSync", account.active_sync_enabled.map(yes_no));
+    write_field(out, "SMTP", account.smtp_status.map(yes_no));
+    write_field(out, "MX", account.mx_status.map(yes_no));
+
+    let details = account.send_mail_details.as_deref().unwrap_or_default();
+    if !details.is_empty() {
+        let _ = writeln!(out, "Send Mail Details");
+    }
+    for detail in details {
+        write_field(out, "From", detail.from_address.as_ref());
+        write_field(out, "  Display name", detail.display_name.as_ref());
+        write_field(
+            out,
+            "  Server",
+            detail
+                .server_name
+                .as_ref()
+                .map(|server| match detail.server_port {
+                    Some(port) => format!("{}:{}", server, port),
+                    None => server.clone(),
+                }),
+        );
+        write_field(out, "  Connection", detail.connection_type.as_ref());
+        write_field(out, "  User name", detail.user_name.as_ref());
+        write_field(out, "  Mode", detail.mode.as_ref());
+        write_field(out, "  Validated", detail.validated.map(yes_no));
+        write_field(out, "  Active", detail.status.map(yes_no));
+    }
+}
+
+/// Finds an account by one of its email addresses (primary or alias).
+pub(crate) fn find_account_by_email<'a>(accounts: &'a [Data], email: &str) -> Option<&'a Data> {
+    accounts.iter().find(|account| {
+        account
+            .primary_email_address
+            .as_deref()
+            .is_some_and(|e| e.eq_ignore_ascii_case(email))
+            || account
+                .email_address
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .any(|address| {
+                    address
+                        .mail_id
+                        .as_deref()
+                        .is_some_and(|e| e.eq_ignore_ascii_case(email))
+                })
+    })
+}
+
+/// Prompts for a comma separated list of email addresses and resolves them to accounts.
+///
+/// Returns `None` (after printing why) if the accounts cannot be listed or an address is unknown.
+fn prompt_accounts(client: &ZohoClient) -> Option<Vec<Data>> {
+    let input = get_input("Email addresses (comma separated)");
+    let accounts = match client.list_accounts() {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            println!("Error: {}", e);
+            return None;
+        }
+    };
+    let mut selected = Vec::new();
+    for email in input.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match find_account_by_email(&accounts, email) {
+            Some(account) if account.zuid.is_some() => selected.push(account.clone()),
+            _ => {
+                println!("No account found for {}", email);
+                return None;
+            }
+        }
+    }
+    if selected.is_empty() {
+        println!("No email addresses given");
+        return None;
+    }
+    Some(selected)
+}
+
+fn is_admin(account: &Data) -> bool {
+    matches!(account.role.as_deref(), Some("admin" | "super_admin"))
+        || account.is_custom_admin.unwrap_or(false)
+}
+
+pub(crate) fn write_admins(client: &ZohoClient, out: &mut impl Write) -> Result<(), HttpError> {
+    let mut admins: Vec<Data> = client
+        .list_accounts()?
+        .into_iter()
+        .filter(is_admin)
+        .collect();
+    // Super admins first, then admins, then custom admins
+    admins.sort_by_key(|a| {
+        std::cmp::Reverse(ROLES.iter().position(|r| Some(*r) == a.role.as_deref()))
+    });
+    for admin in &admins {
+        let _ = writeln!(
+            out,
+            "{:<12} {}{}",
+            admin.role.as_deref().unwrap_or("unknown"),
+            format_email_user(admin),
+            if admin.is_custom_admin.unwrap_or(false) {
+                " [Custom admin]"
+            } else {
+                ""
+            }
+        );
+    }
+    let _ = writeln!(out, "{} admin account(s)", admins.len());
+    Ok(())
+}
+
+/// Changes an account's role and records the change in the audit log.
+pub(crate) fn change_account_role(
+    client: &ZohoClient,
+    account: &Data,
+    role: &str,
+) -> Result<AuditEntry, HttpError> {
+    let zuid = account
+        .zuid
+        .ok_or_else(|| HttpError::Api("Account has no zuid".to_string()))?;
+    client.change_role(zuid, role)?;
+    let target = account
+        .primary_email_address
+        .clone()
+        .unwrap_or_else(|| zuid.to_string());
+    Ok(AuditEntry::new("email", "change_role", &target)
+        .with_change(account.role.as_deref(), Some(role)))
+}
+
+pub(crate) fn write_policies(client: &ZohoClient, out: &mut impl Write) -> Result<(), HttpError> {
+    for policy in client.list_policies()? {
+        let _ = writeln!(
+            out,
+            "{} ({}){}",
+            policy.policy_name.as_deref().unwrap_or_default(),
+            policy.policy_id.as_deref().unwrap_or_default(),
+            if policy.is_default.unwrap_or(false) {
+                " [Default]"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn write_email_users(
+    client: &ZohoClient,
+    out: &mut impl Write,
+) -> Result<(), HttpError> {
+    for user in client.list_accounts()? {
+        let _ = writeln!(out, "{}", format_email_user(&user));
+    }
+    Ok(())
+}
+
 #[derive_tool(
     id = "ListEmailUsers",
     name = "ListCurrentEmailUsers",
     desc = "Lists all users in the system (ZOHO)"
 )]
 pub fn list_email_users() {
-    let oauth_token = get_oauth_token();
-    // let zoid = std::env::var("ZOHO_ZOID").expect("ZOHO_ZOID not set");
-    let zoid = "";
-
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .get(format!(
-            "https://mail.zoho.com/api/organization/{}/accounts?limit=100",
-            zoid
-        ))
-        .header("Authorization", format!("Zoho-oauthtoken {}", oauth_token))
-        .send()
-        .expect("Failed to send request");
-
-    if response.status().is_success() {
-        let users = response
-            .json::<ZohoApiResponse>()
-            .expect("Failed to parse response");
-        match users.data.unwrap() {
-            RespData::Item(e) => println!("{:?}", e.unwrap().first_name),
-            RespData::Vector(v) => {
-                for user in v.unwrap() {
-                    println!(
-                        "User: {}, Email(s): {}",
-                        user.display_name.unwrap(),
-                        user.email_address
-                            .unwrap_or(vec![])
-                            .iter()
-                            .map(|e| format!(
-                                "{}{}",
-                                e.mail_id.clone().unwrap(),
-                                if e.is_alias.unwrap() { " (Alias)" } else { "" }
-                            ))
-                            .collect::<Vec<String>>()
-                            .join(", ")
-                    )
-                }
+    let client = ZohoClient::from_env();
+    if let Err(e) = write_email_users(&client, &mut std::io::stdout()) {
+        println!("Error: {}", e);
+    }
+}
+
+#[derive_tool(
+    id = "AddEmailUser",
+    name = "Add Email User",
+    desc = "Creates a new mail account (ZOHO)"
+)]
+pub fn add_email_user() {
+    let account = NewAccount {
+        primary_email_address: get_input("Email address"),
+        first_name: get_input("First name"),
+        last_name: get_input("Last name"),
+        display_name: get_input("Display name"),
+        password: get_secret_input("Initial password"),
+    };
+    let client = ZohoClient::from_env();
+    match client.create_account(&account) {
+        Ok(user) => println!("Created {}", format_email_user(&user)),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+#[derive_tool(
+    id = "DeleteEmailUser",
+    name = "Delete Email User",
+    desc = "Deletes a mail account by email address (ZOHO)"
+)]
+pub fn delete_email_user() {
+    let email = get_input("Email address");
+    let client = ZohoClient::from_env();
+    let accounts = match client.list_accounts() {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let Some(account) = find_account_by_email(&accounts, &email) else {
+        println!("No account found for {}", email);
+        return;
+    };
+    let Some(zuid) = account.zuid else {
+        println!("Account {} has no zuid", email);
+        return;
+    };
+    if !confirm(&format!("Delete {}", format_email_user(account))) {
+        println!("Aborted");
+        return;
+    }
+    match client.delete_accounts(&[zuid]) {
+        Ok(()) => println!("Deleted {}", email),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+#[derive_tool(
+    id = "ShowEmailUser",
+    name = "Show Email User",
+    desc = "Shows the full details of one account by email or account id (ZOHO)"
+)]
+pub fn show_email_user() {
+    let id_or_email = get_input("Email address or account ID");
+    let client = ZohoClient::from_env();
+    match client.get_account(&id_or_email) {
+        Ok(account) => write_account_details(&account, &mut std::io::stdout()),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+#[derive_tool(
+    id = "ListEmailAdmins",
+    name = "List Email Admins",
+    desc = "Lists all admins and super admins of the organization (ZOHO)"
+)]
+pub fn list_email_admins() {
+    let client = ZohoClient::from_env();
+    if let Err(e) = write_admins(&client, &mut std::io::stdout()) {
+        println!("Error: {}", e);
+    }
+}
+
+#[derive_tool(
+    id = "ChangeEmailUserRole",
+    name = "Change Email User Role",
+    desc = "Promotes or demotes an account (member, admin, super admin) (ZOHO)"
+)]
+pub fn change_email_user_role() {
+    let email = get_input("Email address");
+    let client = ZohoClient::from_env();
+    let account = match client.get_account(&email) {
+        Ok(account) => account,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let current = account
+        .role
+        .clone()
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("{} is currently {}", format_email_user(&account), current);
+    for (index, role) in ROLES.iter().enumerate() {
+        println!("{}. {}", index + 1, role);
+    }
+    let Some(role) =
+        select_index(&get_input("Select the new role"), ROLES.len()).map(|index| ROLES[index - 1])
+    else {
+        println!("Invalid selection");
+        return;
+    };
+    if role == current {
+        println!("{} already has the role {}", email, role);
+        return;
+    }
+    if !confirm(&format!(
+        "Change role of {} from {} to {}",
+        email, current, role
+    )) {
+        println!("Aborted");
+        return;
+    }
+
+    match change_account_role(&client, &account, role) {
+        Ok(entry) => {
+            println!("Privilege change: {} {} -> {}", entry.target, current, role);
+            if let Err(e) = audit::record(&entry) {
+                println!("Warning: failed to write audit log: {}", e);
             }
         }
-    } else {
-        println!("Error: {}", response.status());
-        println!("{}", response.text().expect("Failed to get response text"));
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+#[derive_tool(
+    id = "ListMailPolicies",
+    name = "List Mail Policies",
+    desc = "Lists the mail policies of the organization (ZOHO)"
+)]
+pub fn list_mail_policies() {
+    let client = ZohoClient::from_env();
+    if let Err(e) = write_policies(&client, &mut std::io::stdout()) {
+        println!("Error: {}", e);
+    }
+}
+
+#[derive_tool(
+    id = "AssignMailPolicy",
+    name = "Assign Mail Policy",
+    desc = "Assigns a mail policy to one or more accounts (ZOHO)"
+)]
+pub fn assign_mail_policy() {
+    let client = ZohoClient::from_env();
+    let policies = match client.list_policies() {
+        Ok(policies) => policies,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    for (index, policy) in policies.iter().enumerate() {
+        println!(
+            "{}. {} ({})",
+            index + 1,
+            policy.policy_name.as_deref().unwrap_or_default(),
+            policy.policy_id.as_deref().unwrap_or_default()
+        );
+    }
+    let Some(policy) = select_index(&get_input("Select a policy"), policies.len())
+        .and_then(|index| policies.get(index - 1))
+    else {
+        println!("Invalid selection");
+        return;
+    };
+    let Some(policy_id) = policy.policy_id.as_deref() else {
+        println!("Policy has no id");
+        return;
+    };
+    let Some(accounts) = prompt_accounts(&client) else {
+        return;
+    };
+
+    for account in &accounts {
+        println!(
+            "{}: {} -> {}",
+            account.primary_email_address.as_deref().unwrap_or_default(),
+            account
+                .policy_id
+                .as_ref()
+                .map(|p| p.describe())
+                .unwrap_or_default(),
+            policy.policy_name.as_deref().unwrap_or_default()
+        );
+    }
+    if !confirm(&format!("Assign policy to {} account(s)", accounts.len())) {
+        println!("Aborted");
+        return;
+    }
+    let zuids: Vec<i64> = accounts.iter().filter_map(|a| a.zuid).collect();
+    match client.assign_policy(policy_id, &zuids) {
+        Ok(()) => println!("Policy assigned"),
+        Err(e) => println!("Error: {}", e),
     }
 }
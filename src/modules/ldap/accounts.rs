@@ -0,0 +1,277 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Mod, Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::search::{first_value, format_table, search_all};
+use super::users::{USER_FILTER, find_user, required_input};
+use super::{format_filetime, format_uac, uac};
+use crate::audit::{self, AuditEntry};
+use crate::io::confirm;
+
+const ACCOUNT_ATTRS: [&str; 5] = [
+    "sAMAccountName",
+    "displayName",
+    "userAccountControl",
+    "lockoutTime",
+    "msDS-User-Account-Control-Computed",
+];
+
+/// Accounts with a lockout time set. This includes lockouts that have already expired,
+/// which are filtered out using `msDS-User-Account-Control-Computed`.
+const LOCKOUT_FILTER: &str = "(lockoutTime>=1)";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountAction {
+    Disable,
+    Enable,
+    Unlock,
+}
+
+impl AccountAction {
+    fn audit_action(self) -> &'static str {
+        match self {
+            AccountAction::Disable => "disable_user",
+            AccountAction::Enable => "enable_user",
+            AccountAction::Unlock => "unlock_user",
+        }
+    }
+}
+
+/// The state of a user account relevant to enabling, disabling and unlocking it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountState {
+    pub dn: String,
+    pub account_name: String,
+    pub display_name: String,
+    pub account_control: u32,
+    /// `lockoutTime` as a FILETIME, 0 when not locked out
+    pub lockout_time: i64,
+    /// Whether the account is currently locked out. AD does not set `LOCKOUT` in
+    /// `userAccountControl`; it is only reported by the constructed
+    /// `msDS-User-Account-Control-Computed` attribute.
+    pub locked_out: bool,
+}
+
+impl AccountState {
+    pub fn from_entry(entry: &SearchEntry) -> Self {
+        let number = |attr| first_value(entry, attr).and_then(|v| v.parse::<i64>().ok());
+        let lockout_time = number("lockoutTime").unwrap_or(0);
+        let locked_out = match number("msDS-User-Account-Control-Computed") {
+            Some(computed) => computed as u32 & uac::LOCKOUT != 0,
+            None => lockout_time > 0,
+        };
+        Self {
+            dn: entry.dn.clone(),
+            account_name: first_value(entry, "sAMAccountName")
+                .unwrap_or_default()
+                .to_string(),
+            display_name: first_value(entry, "displayName")
+                .unwrap_or_default()
+                .to_string(),
+            account_control: number("userAccountControl").unwrap_or(0) as u32,
+            lockout_time,
+            locked_out,
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.account_control & uac::ACCOUNTDISABLE != 0
+    }
+
+    /// Flags as shown to the user, including the computed lockout state.
+    pub fn describe_flags(&self) -> String {
+        let flags = if self.locked_out {
+            self.account_control | uac::LOCKOUT
+        } else {
+            self.account_control
+        };
+        format_uac(flags)
+    }
+
+    /// The state the account is expected to be in after `action`.
+    pub fn after(&self, action: AccountAction) -> AccountState {
+        let mut after = self.clone();
+        match action {
+            AccountAction::Disable => after.account_control |= uac::ACCOUNTDISABLE,
+            AccountAction::Enable => after.account_control &= !uac::ACCOUNTDISABLE,
+            AccountAction::Unlock => {
+                after.lockout_time = 0;
+                after.locked_out = false;
+            }
+        }
+        after
+    }
+}
+
+/// The modification that performs `action`. Unlocking writes `lockoutTime=0`, the only
+/// value AD accepts for that attribute.
+pub fn account_mods(state: &AccountState, action: AccountAction) -> Vec<Mod<String>> {
+    match action {
+        AccountAction::Disable | AccountAction::Enable => vec![Mod::Replace(
+            "userAccountControl".to_string(),
+            HashSet::from([state.after(action).account_control.to_string()]),
+        )],
+        AccountAction::Unlock => vec![Mod::Replace(
+            "lockoutTime".to_string(),
+            HashSet::from(["0".to_string()]),
+        )],
+    }
+}
+
+pub fn load_account(
+    ldap: &mut LdapConnection,
+    base: &str,
+    account_name: &str,
+) -> Result<AccountState, LdapToolError> {
+    find_user(ldap, base, account_name, &ACCOUNT_ATTRS)
+        .map(|entry| AccountState::from_entry(&entry))
+}
+
+/// Applies `action` and reads the account back. Returns the state before and after.
+pub fn apply_account_action(
+    ldap: &mut LdapConnection,
+    base: &str,
+    account_name: &str,
+    action: AccountAction,
+) -> Result<(AccountState, AccountState), LdapToolError> {
+    let before = load_account(ldap, base, account_name)?;
+    ldap.conn()
+        .modify(&before.dn, account_mods(&before, action))?
+        .success()?;
+    let after = load_account(ldap, base, account_name)?;
+    Ok((before, after))
+}
+
+pub fn write_flag_change(
+    out: &mut impl Write,
+    before: &AccountState,
+    after: &AccountState,
+) -> std::io::Result<()> {
+    writeln!(out, "{}", after.dn)?;
+    writeln!(out, "  Before: {}", before.describe_flags())?;
+    writeln!(out, "  After:  {}", after.describe_flags())
+}
+
+/// Lists the accounts that are currently locked out.
+pub fn locked_out_accounts(
+    ldap: &mut LdapConnection,
+    base: &str,
+) -> Result<Vec<AccountState>, LdapToolError> {
+    let filter = format!("(&{}{})", USER_FILTER, LOCKOUT_FILTER);
+    Ok(
+        search_all(ldap, base, Scope::Subtree, &filter, &ACCOUNT_ATTRS)?
+            .iter()
+            .map(AccountState::from_entry)
+            .filter(|account| account.locked_out)
+            .collect(),
+    )
+}
+
+fn run_account_action(action: AccountAction) -> Result<(), LdapToolError> {
+    let account_name = required_input("sAMAccountName")?;
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+
+    let current = load_account(&mut ldap, &base, &account_name)?;
+    let unchanged = match action {
+        AccountAction::Disable => current.is_disabled(),
+        AccountAction::Enable => !current.is_disabled(),
+        AccountAction::Unlock => current.lockout_time == 0,
+    };
+    if unchanged {
+        println!(
+            "{} ({}) needs no change: {}",
+            current.account_name,
+            current.dn,
+            current.describe_flags()
+        );
+        return Ok(());
+    }
+    println!("{}", current.dn);
+    println!("  Current: {}", current.describe_flags());
+    println!("  New:     {}", current.after(action).describe_flags());
+    if !confirm("Apply this change") {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    let (before, after) = apply_account_action(&mut ldap, &base, &account_name, action)?;
+    let _ = write_flag_change(&mut std::io::stdout(), &before, &after);
+    let entry = AuditEntry::new("ldap", action.audit_action(), &after.dn).with_change(
+        Some(&before.describe_flags()),
+        Some(&after.describe_flags()),
+    );
+    if let Err(e) = audit::record(&entry) {
+        println!("Warning: failed to write audit log: {}", e);
+    }
+    Ok(())
+}
+
+#[derive_tool(
+    id = "DisableUserTool",
+    name = "Disable User",
+    desc = "Disables a user account"
+)]
+pub fn disable_user() {
+    if let Err(e) = run_account_action(AccountAction::Disable) {
+        println!("Error: {}", e);
+    }
+}
+
+#[derive_tool(
+    id = "EnableUserTool",
+    name = "Enable User",
+    desc = "Enables a disabled user account"
+)]
+pub fn enable_user() {
+    if let Err(e) = run_account_action(AccountAction::Enable) {
+        println!("Error: {}", e);
+    }
+}
+
+#[derive_tool(
+    id = "UnlockUserTool",
+    name = "Unlock User",
+    desc = "Unlocks a locked-out user account"
+)]
+pub fn unlock_user() {
+    if let Err(e) = run_account_action(AccountAction::Unlock) {
+        println!("Error: {}", e);
+    }
+}
+
+fn run_list_locked_out() -> Result<(), LdapToolError> {
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let accounts = locked_out_accounts(&mut ldap, &base)?;
+    let rows: Vec<Vec<String>> = accounts
+        .iter()
+        .map(|account| {
+            vec![
+                account.account_name.clone(),
+                account.display_name.clone(),
+                format_filetime(account.lockout_time),
+            ]
+        })
+        .collect();
+    print!(
+        "{}",
+        format_table(&["sAMAccountName", "displayName", "lockoutTime"], &rows)
+    );
+    println!("Total: {} locked-out user(s)", accounts.len());
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ListLockedOutTool",
+    name = "List Locked-out Users",
+    desc = "Lists user accounts that are currently locked out"
+)]
+pub fn list_locked_out_users() {
+    if let Err(e) = run_list_locked_out() {
+        println!("Error: {}", e);
+    }
+}
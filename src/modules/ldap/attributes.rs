@@ -0,0 +1,415 @@
+//! Well-known Active Directory attribute values, and decoding of attribute values for display.
+
+use std::io::Write;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, Utc};
+use ldap3::SearchEntry;
+
+/// `userAccountControl` flags (MS-ADTS 2.2.16).
+pub mod uac {
+    pub const SCRIPT: u32 = 0x0001;
+    pub const ACCOUNTDISABLE: u32 = 0x0002;
+    pub const HOMEDIR_REQUIRED: u32 = 0x0008;
+    pub const LOCKOUT: u32 = 0x0010;
+    pub const PASSWD_NOTREQD: u32 = 0x0020;
+    pub const PASSWD_CANT_CHANGE: u32 = 0x0040;
+    pub const ENCRYPTED_TEXT_PWD_ALLOWED: u32 = 0x0080;
+    pub const TEMP_DUPLICATE_ACCOUNT: u32 = 0x0100;
+    pub const NORMAL_ACCOUNT: u32 = 0x0200;
+    pub const INTERDOMAIN_TRUST_ACCOUNT: u32 = 0x0800;
+    pub const WORKSTATION_TRUST_ACCOUNT: u32 = 0x1000;
+    pub const SERVER_TRUST_ACCOUNT: u32 = 0x2000;
+    pub const DONT_EXPIRE_PASSWORD: u32 = 0x10000;
+    pub const MNS_LOGON_ACCOUNT: u32 = 0x20000;
+    pub const SMARTCARD_REQUIRED: u32 = 0x40000;
+    pub const TRUSTED_FOR_DELEGATION: u32 = 0x80000;
+    pub const NOT_DELEGATED: u32 = 0x100000;
+    pub const USE_DES_KEY_ONLY: u32 = 0x200000;
+    pub const DONT_REQ_PREAUTH: u32 = 0x400000;
+    pub const PASSWORD_EXPIRED: u32 = 0x800000;
+    pub const TRUSTED_TO_AUTH_FOR_DELEGATION: u32 = 0x1000000;
+    pub const PARTIAL_SECRETS_ACCOUNT: u32 = 0x4000000;
+}
+
+/// `pwdProperties` flags of the domain password policy.
+pub mod pwd_properties {
+    pub const DOMAIN_PASSWORD_COMPLEX: u32 = 0x01;
+    pub const DOMAIN_PASSWORD_NO_ANON_CHANGE: u32 = 0x02;
+    pub const DOMAIN_PASSWORD_NO_CLEAR_CHANGE: u32 = 0x04;
+    pub const DOMAIN_LOCKOUT_ADMINS: u32 = 0x08;
+    pub const DOMAIN_PASSWORD_STORE_CLEARTEXT: u32 = 0x10;
+    pub const DOMAIN_REFUSE_PASSWORD_CHANGE: u32 = 0x20;
+}
+
+/// `groupType` flags (MS-ADTS 2.2.12).
+pub mod group_type {
+    pub const BUILTIN_LOCAL_GROUP: u32 = 0x0000_0001;
+    pub const ACCOUNT_GROUP: u32 = 0x0000_0002;
+    pub const RESOURCE_GROUP: u32 = 0x0000_0004;
+    pub const UNIVERSAL_GROUP: u32 = 0x0000_0008;
+    pub const APP_BASIC_GROUP: u32 = 0x0000_0010;
+    pub const APP_QUERY_GROUP: u32 = 0x0000_0020;
+    pub const SECURITY_ENABLED: u32 = 0x8000_0000;
+}
+
+/// Names of the `groupType` flags, using the names shown by AD tools.
+pub const GROUP_TYPE_FLAGS: &[(u32, &str)] = &[
+    (group_type::BUILTIN_LOCAL_GROUP, "BUILTIN"),
+    (group_type::ACCOUNT_GROUP, "GLOBAL"),
+    (group_type::RESOURCE_GROUP, "DOMAIN_LOCAL"),
+    (group_type::UNIVERSAL_GROUP, "UNIVERSAL"),
+    (group_type::APP_BASIC_GROUP, "APP_BASIC"),
+    (group_type::APP_QUERY_GROUP, "APP_QUERY"),
+    (group_type::SECURITY_ENABLED, "SECURITY"),
+];
+
+/// `sAMAccountType` values (MS-ADTS 2.2.15). Unlike the flag attributes this is an enumeration.
+pub const SAM_ACCOUNT_TYPES: &[(u32, &str)] = &[
+    (0x0000_0000, "DOMAIN_OBJECT"),
+    (0x1000_0000, "GROUP_OBJECT"),
+    (0x1000_0001, "NON_SECURITY_GROUP_OBJECT"),
+    (0x2000_0000, "ALIAS_OBJECT"),
+    (0x2000_0001, "NON_SECURITY_ALIAS_OBJECT"),
+    (0x3000_0000, "USER_OBJECT"),
+    (0x3000_0001, "MACHINE_ACCOUNT"),
+    (0x3000_0002, "TRUST_ACCOUNT"),
+    (0x4000_0000, "APP_BASIC_GROUP"),
+    (0x4000_0001, "APP_QUERY_GROUP"),
+];
+
+/// Names of the `userAccountControl` flags, in bit order.
+pub const UAC_FLAGS: &[(u32, &str)] = &[
+    (uac::SCRIPT, "SCRIPT"),
+    (uac::ACCOUNTDISABLE, "ACCOUNTDISABLE"),
+    (uac::HOMEDIR_REQUIRED, "HOMEDIR_REQUIRED"),
+    (uac::LOCKOUT, "LOCKOUT"),
+    (uac::PASSWD_NOTREQD, "PASSWD_NOTREQD"),
+    (uac::PASSWD_CANT_CHANGE, "PASSWD_CANT_CHANGE"),
+    (
+        uac::ENCRYPTED_TEXT_PWD_ALLOWED,
+        "ENCRYPTED_TEXT_PWD_ALLOWED",
+    ),
+    (uac::TEMP_DUPLICATE_ACCOUNT, "TEMP_DUPLICATE_ACCOUNT"),
+    (uac::NORMAL_ACCOUNT, "NORMAL_ACCOUNT"),
+    (uac::INTERDOMAIN_TRUST_ACCOUNT, "INTERDOMAIN_TRUST_ACCOUNT"),
+    (uac::WORKSTATION_TRUST_ACCOUNT, "WORKSTATION_TRUST_ACCOUNT"),
+    (uac::SERVER_TRUST_ACCOUNT, "SERVER_TRUST_ACCOUNT"),
+    (uac::DONT_EXPIRE_PASSWORD, "DONT_EXPIRE_PASSWORD"),
+    (uac::MNS_LOGON_ACCOUNT, "MNS_LOGON_ACCOUNT"),
+    (uac::SMARTCARD_REQUIRED, "SMARTCARD_REQUIRED"),
+    (uac::TRUSTED_FOR_DELEGATION, "TRUSTED_FOR_DELEGATION"),
+    (uac::NOT_DELEGATED, "NOT_DELEGATED"),
+    (uac::USE_DES_KEY_ONLY, "USE_DES_KEY_ONLY"),
+    (uac::DONT_REQ_PREAUTH, "DONT_REQ_PREAUTH"),
+    (uac::PASSWORD_EXPIRED, "PASSWORD_EXPIRED"),
+    (
+        uac::TRUSTED_TO_AUTH_FOR_DELEGATION,
+        "TRUSTED_TO_AUTH_FOR_DELEGATION",
+    ),
+    (uac::PARTIAL_SECRETS_ACCOUNT, "PARTIAL_SECRETS_ACCOUNT"),
+];
+
+/// Names of the flags set in `value`. Bits without a name are shown in hex.
+pub fn decode_flags(value: u32, names: &[(u32, &str)]) -> Vec<String> {
+    let mut flags = Vec::new();
+    let mut known = 0;
+    for (bit, name) in names {
+        known |= bit;
+        if value & bit != 0 {
+            flags.push(name.to_string());
+        }
+    }
+    if value & !known != 0 {
+        flags.push(format!("0x{:X}", value & !known));
+    }
+    flags
+}
+
+/// Formats a `userAccountControl` value as `NAME | NAME (0x...)`.
+pub fn format_uac(value: u32) -> String {
+    format_flags(value, UAC_FLAGS)
+}
+
+/// Seconds between the FILETIME epoch (1601-01-01) and the Unix epoch.
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Converts a FILETIME (100ns intervals since 1601-01-01 UTC), as used by `lastLogonTimestamp`,
+/// `pwdLastSet`, `lockoutTime` and `accountExpires`. `0` and `i64::MAX` mean "never".
+pub fn filetime_to_datetime(filetime: i64) -> Option<DateTime<Utc>> {
+    if filetime <= 0 || filetime == i64::MAX {
+        return None;
+    }
+    let seconds = filetime / 10_000_000 - FILETIME_EPOCH_OFFSET;
+    let nanos = (filetime % 10_000_000) * 100;
+    DateTime::from_timestamp(seconds, nanos as u32)
+}
+
+pub fn format_filetime(filetime: i64) -> String {
+    match filetime_to_datetime(filetime) {
+        Some(time) => format_time(time),
+        None => "Never".to_string(),
+    }
+}
+
+/// Parses an AD interval such as `maxPwdAge` or `lockoutDuration`: a negative number of 100ns
+/// units. `0` and the minimum value (`-9223372036854775808`) mean "never" and give `None`.
+pub fn parse_interval(value: &str) -> Option<TimeDelta> {
+    let ticks: i64 = value.trim().parse().ok()?;
+    if ticks == 0 || ticks == i64::MIN {
+        return None;
+    }
+    Some(TimeDelta::microseconds(ticks.abs() / 10))
+}
+
+/// Formats flags as `NAME | NAME (0x...)`.
+fn format_flags(value: u32, names: &[(u32, &str)]) -> String {
+    let flags = decode_flags(value, names);
+    if flags.is_empty() {
+        format!("none (0x{:X})", value)
+    } else {
+        format!("{} (0x{:X})", flags.join(" | "), value)
+    }
+}
+
+/// Decodes a binary SID into its string form, e.g. `S-1-5-21-...-500`.
+pub fn decode_sid(bytes: &[u8]) -> Option<String> {
+    let revision = *bytes.first()?;
+    let count = *bytes.get(1)? as usize;
+    if bytes.len() != 8 + count * 4 {
+        return None;
+    }
+    // The identifier authority is a 48-bit big-endian value
+    let authority = bytes[2..8]
+        .iter()
+        .fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+    let mut sid = format!("S-{}-{}", revision, authority);
+    for chunk in bytes[8..].chunks_exact(4) {
+        let sub_authority = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        sid.push_str(&format!("-{}", sub_authority));
+    }
+    Some(sid)
+}
+
+/// Decodes a binary GUID. The first three fields are little-endian, as in `objectGUID`.
+pub fn decode_guid(bytes: &[u8]) -> Option<String> {
+    let b: &[u8; 16] = bytes.try_into().ok()?;
+    Some(format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        u16::from_le_bytes([b[4], b[5]]),
+        u16::from_le_bytes([b[6], b[7]]),
+        b[8],
+        b[9],
+        b[10],
+        b[11],
+        b[12],
+        b[13],
+        b[14],
+        b[15]
+    ))
+}
+
+/// Parses an LDAP generalized time in UTC such as `20240101120000.0Z`.
+pub fn parse_generalized_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.strip_suffix('Z')?;
+    let seconds = value.split('.').next()?;
+    NaiveDateTime::parse_from_str(seconds, "%Y%m%d%H%M%S")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Formats a number of bytes with a binary unit, e.g. `4.2 KB`.
+pub fn format_byte_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S %:z")
+        .to_string()
+}
+
+/// How the values of an attribute are shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeKind {
+    Text,
+    Sid,
+    Guid,
+    FileTime,
+    GeneralizedTime,
+    Interval,
+    UserAccountControl,
+    GroupType,
+    SamAccountType,
+    /// Binary data shown by size only, such as `thumbnailPhoto` or certificates
+    Binary,
+}
+
+const ATTRIBUTE_KINDS: &[(&str, AttributeKind)] = &[
+    ("objectSid", AttributeKind::Sid),
+    ("sIDHistory", AttributeKind::Sid),
+    ("tokenGroups", AttributeKind::Sid),
+    ("securityIdentifier", AttributeKind::Sid),
+    ("objectGUID", AttributeKind::Guid),
+    ("msExchMailboxGuid", AttributeKind::Guid),
+    ("mS-DS-ConsistencyGuid", AttributeKind::Guid),
+    ("schemaIDGUID", AttributeKind::Guid),
+    ("attributeSecurityGUID", AttributeKind::Guid),
+    ("accountExpires", AttributeKind::FileTime),
+    ("badPasswordTime", AttributeKind::FileTime),
+    ("lastLogoff", AttributeKind::FileTime),
+    ("lastLogon", AttributeKind::FileTime),
+    ("lastLogonTimestamp", AttributeKind::FileTime),
+    ("lockoutTime", AttributeKind::FileTime),
+    ("pwdLastSet", AttributeKind::FileTime),
+    ("creationTime", AttributeKind::FileTime),
+    (
+        "msDS-UserPasswordExpiryTimeComputed",
+        AttributeKind::FileTime,
+    ),
+    ("whenChanged", AttributeKind::GeneralizedTime),
+    ("whenCreated", AttributeKind::GeneralizedTime),
+    ("dSCorePropagationData", AttributeKind::GeneralizedTime),
+    ("msTSExpireDate", AttributeKind::GeneralizedTime),
+    ("lockoutDuration", AttributeKind::Interval),
+    ("lockOutObservationWindow", AttributeKind::Interval),
+    ("maxPwdAge", AttributeKind::Interval),
+    ("minPwdAge", AttributeKind::Interval),
+    ("forceLogoff", AttributeKind::Interval),
+    ("msDS-LockoutDuration", AttributeKind::Interval),
+    ("msDS-LockoutObservationWindow", AttributeKind::Interval),
+    ("msDS-MaximumPasswordAge", AttributeKind::Interval),
+    ("msDS-MinimumPasswordAge", AttributeKind::Interval),
+    ("userAccountControl", AttributeKind::UserAccountControl),
+    (
+        "msDS-User-Account-Control-Computed",
+        AttributeKind::UserAccountControl,
+    ),
+    ("groupType", AttributeKind::GroupType),
+    ("sAMAccountType", AttributeKind::SamAccountType),
+    ("thumbnailPhoto", AttributeKind::Binary),
+    ("jpegPhoto", AttributeKind::Binary),
+    ("userCertificate", AttributeKind::Binary),
+    ("msExchSafeSendersHash", AttributeKind::Binary),
+    ("logonHours", AttributeKind::Binary),
+    ("nTSecurityDescriptor", AttributeKind::Binary),
+];
+
+/// How values of `attr` are shown, matching the name case-insensitively.
+pub fn attribute_kind(attr: &str) -> AttributeKind {
+    ATTRIBUTE_KINDS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(attr))
+        .map(|(_, kind)| *kind)
+        .unwrap_or(AttributeKind::Text)
+}
+
+/// Renders a text value of `attr` for display. Values that cannot be decoded are shown as-is,
+/// except binary ones, which are decoded or summarized like `display_binary` does.
+pub fn display_value(attr: &str, value: &str) -> String {
+    let number = || value.trim().parse::<i64>().ok();
+    let decoded = match attribute_kind(attr) {
+        AttributeKind::FileTime => number().map(|n| match filetime_to_datetime(n) {
+            Some(time) => format_time(time),
+            None => "Never".to_string(),
+        }),
+        AttributeKind::GeneralizedTime => parse_generalized_time(value).map(format_time),
+        AttributeKind::Interval => number().map(|_| match parse_interval(value) {
+            Some(interval) => format_interval(interval),
+            None => "Never".to_string(),
+        }),
+        AttributeKind::UserAccountControl => number().map(|n| format_uac(n as u32)),
+        // groupType is signed, so security groups come back negative
+        AttributeKind::GroupType => number().map(|n| format_flags(n as u32, GROUP_TYPE_FLAGS)),
+        AttributeKind::SamAccountType => {
+            number().map(
+                |n| match SAM_ACCOUNT_TYPES.iter().find(|(v, _)| *v as i64 == n) {
+                    Some((_, name)) => format!("{} (0x{:X})", name, n),
+                    None => format!("0x{:X}", n),
+                },
+            )
+        }
+        // ldap3 returns binary values that happen to be valid UTF-8 as text
+        AttributeKind::Sid | AttributeKind::Guid | AttributeKind::Binary => {
+            Some(display_binary(attr, value.as_bytes()))
+        }
+        AttributeKind::Text => None,
+    };
+    decoded.unwrap_or_else(|| value.to_string())
+}
+
+/// Renders a binary value of `attr`: SIDs and GUIDs in their string form, anything else by size.
+pub fn display_binary(attr: &str, bytes: &[u8]) -> String {
+    let decoded = match attribute_kind(attr) {
+        AttributeKind::Sid => decode_sid(bytes),
+        AttributeKind::Guid => decode_guid(bytes),
+        _ => None,
+    };
+    decoded.unwrap_or_else(|| format!("<binary, {}>", format_byte_size(bytes.len())))
+}
+
+/// Formats an interval in days, hours and minutes, e.g. `42 days` or `30 minutes`.
+pub fn format_interval(interval: TimeDelta) -> String {
+    let minutes = interval.num_minutes();
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    let mut parts = Vec::new();
+    for (amount, unit) in [(days, "day"), (hours, "hour"), (minutes, "minute")] {
+        if amount > 0 {
+            parts.push(format!(
+                "{} {}{}",
+                amount,
+                unit,
+                if amount == 1 { "" } else { "s" }
+            ));
+        }
+    }
+    if parts.is_empty() {
+        format!("{} seconds", interval.num_seconds())
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// All values of `attr` decoded for display, whether the server sent them as text or binary
+/// (`SearchEntry::construct` moves values that are not valid UTF-8 to `bin_attrs`).
+pub fn display_values(entry: &SearchEntry, attr: &str) -> Vec<String> {
+    let mut shown: Vec<String> = entry
+        .attrs
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(attr))
+        .flat_map(|(name, values)| values.iter().map(|v| display_value(name, v)))
+        .collect();
+    shown.extend(
+        entry
+            .bin_attrs
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(attr))
+            .flat_map(|(name, values)| values.iter().map(|v| display_binary(name, v))),
+    );
+    shown
+}
+
+/// Writes every attribute of `entry`, sorted by name, one value per line.
+pub fn write_entry(out: &mut impl Write, entry: &SearchEntry) -> std::io::Result<()> {
+    writeln!(out, "dn: {}", entry.dn)?;
+    let mut names: Vec<&String> = entry.attrs.keys().chain(entry.bin_attrs.keys()).collect();
+    names.sort_by_key(|name| name.to_ascii_lowercase());
+    names.dedup();
+    for name in names {
+        for value in display_values(entry, name) {
+            writeln!(out, "{}: {}", name, value)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use jh_admin_cli_macros::derive_tool;
+use ldap3::SearchEntry;
+
+use super::attributes::display_values;
+use super::connection::{
+    DEFAULT_TARGET, LdapTarget, LdapToolError, configured_targets, connect, parse_target_name,
+};
+use super::schema::attribute_list_input;
+use super::search::format_table;
+use super::users::{find_user, required_input};
+use crate::io::{confirm, get_input};
+
+pub const COMPARE_ATTRS: [&str; 8] = [
+    "displayName",
+    "userPrincipalName",
+    "mail",
+    "title",
+    "department",
+    "manager",
+    "userAccountControl",
+    "memberOf",
+];
+
+/// One attribute of a user as it is set in each directory compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeComparison {
+    pub attr: String,
+    /// Values per directory, in the order the directories were given; empty when the
+    /// attribute or the user is missing
+    pub values: Vec<Vec<String>>,
+    pub differs: bool,
+}
+
+/// Shortens `value` to a DN relative to `base` when it lies below it, so that references such
+/// as `manager` compare equal across directories with different base DNs.
+fn relative_to(value: &str, base: &str) -> String {
+    let suffix = format!(",{}", base);
+    if value.len() > suffix.len()
+        && value.is_char_boundary(value.len() - suffix.len())
+        && value[value.len() - suffix.len()..].eq_ignore_ascii_case(&suffix)
+    {
+        value[..value.len() - suffix.len()].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Compares `attrs` of the same user read from several directories. Each user is given with
+/// the base DN of its directory, or `None` when the directory has no such user; a missing user
+/// makes every attribute differ. Values are compared as case-insensitive sets.
+pub fn compare_users(
+    attrs: &[String],
+    users: &[(&str, Option<&SearchEntry>)],
+) -> Vec<AttributeComparison> {
+    attrs
+        .iter()
+        .map(|attr| {
+            let values: Vec<Vec<String>> = users
+                .iter()
+                .map(|(base, entry)| {
+                    let mut shown: Vec<String> = entry
+                        .map(|entry| display_values(entry, attr))
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|value| relative_to(value, base))
+                        .collect();
+                    shown.sort_by_key(|value| value.to_lowercase());
+                    shown
+                })
+                .collect();
+            let normalized: Vec<Vec<String>> = values
+                .iter()
+                .map(|shown| shown.iter().map(|value| value.to_lowercase()).collect())
+                .collect();
+            let differs = users.iter().any(|(_, entry)| entry.is_none())
+                || normalized.windows(2).any(|pair| pair[0] != pair[1]);
+            AttributeComparison {
+                attr: attr.clone(),
+                values,
+                differs,
+            }
+        })
+        .collect()
+}
+
+/// Reads target names separated by commas; at least two distinct directories are required.
+pub fn parse_target_list(input: &str) -> Result<Vec<Option<String>>, LdapToolError> {
+    let mut targets: Vec<Option<String>> = Vec::new();
+    for name in input.split(',') {
+        let target = parse_target_name(name)?;
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    if targets.len() < 2 {
+        return Err(LdapToolError::Config(
+            "Name at least two LDAP targets to compare".to_string(),
+        ));
+    }
+    Ok(targets)
+}
+
+fn run_compare_user() -> Result<(), LdapToolError> {
+    let account_name = required_input("sAMAccountName")?;
+    let configured = configured_targets();
+    let prompt = if configured.is_empty() {
+        "Targets (comma separated, default for the default target)".to_string()
+    } else {
+        format!(
+            "Targets (comma separated: {}, {})",
+            DEFAULT_TARGET,
+            configured.join(", ")
+        )
+    };
+    let targets = parse_target_list(&get_input(&prompt))?;
+    let attrs = attribute_list_input(
+        "Attributes (comma separated, empty for the defaults)",
+        &COMPARE_ATTRS,
+    )?;
+    let only_differences = confirm("Show only attributes that differ?");
+
+    let attr_refs: Vec<&str> = attrs.iter().map(String::as_str).collect();
+    let mut directories = Vec::new();
+    for name in &targets {
+        let target = LdapTarget::load(name.as_deref())?;
+        let mut ldap = connect(&target.config)?;
+        let entry = match find_user(&mut ldap, &target.base_dn, &account_name, &attr_refs) {
+            Ok(entry) => Some(entry),
+            Err(LdapToolError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        let _ = ldap.conn().unbind();
+        directories.push((target, entry));
+    }
+
+    let users: Vec<(&str, Option<&SearchEntry>)> = directories
+        .iter()
+        .map(|(target, entry)| (target.base_dn.as_str(), entry.as_ref()))
+        .collect();
+    let comparisons = compare_users(&attrs, &users);
+
+    let mut headers = vec!["".to_string(), "Attribute".to_string()];
+    headers.extend(
+        directories
+            .iter()
+            .map(|(target, _)| target.label().to_string()),
+    );
+    let mut rows = vec![{
+        let mut row = vec!["".to_string(), "dn".to_string()];
+        row.extend(directories.iter().map(|(_, entry)| match entry {
+            Some(entry) => entry.dn.clone(),
+            None => "(not found)".to_string(),
+        }));
+        row
+    }];
+    for comparison in comparisons
+        .iter()
+        .filter(|c| c.differs || !only_differences)
+    {
+        let mut row = vec![
+            if comparison.differs { "*" } else { "" }.to_string(),
+            comparison.attr.clone(),
+        ];
+        row.extend(comparison.values.iter().map(|values| values.join("; ")));
+        rows.push(row);
+    }
+    println!("{}", format_table(&headers, &rows));
+    println!(
+        "{} of {} attribute(s) differ",
+        comparisons.iter().filter(|c| c.differs).count(),
+        comparisons.len()
+    );
+    Ok(())
+}
+
+#[derive_tool(
+    id = "CompareUserTool",
+    name = "Compare User Across Directories",
+    desc = "Compares a user's attributes and group memberships across configured LDAP targets"
+)]
+pub fn compare_user() {
+    if let Err(e) = run_compare_user() {
+        println!("Error: {}", e);
+    }
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Local, Utc};
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Scope, SearchEntry};
+
+use super::connection::{LdapToolError, base_dn, ldap_session, ldap_target};
+use super::ldif::split_dn;
+use super::search::{first_value, format_table, paged_search};
+use super::users::{escape_filter_value, optional_input};
+use super::{filetime_to_datetime, uac};
+use crate::io::{confirm, get_input};
+use crate::models::monitor::Host;
+use crate::modules::monitor::{inventory_path, merge_inventory, update_inventory};
+
+pub const COMPUTER_FILTER: &str = "(objectCategory=computer)";
+
+/// Source name of hosts exported to the host inventory from the default target.
+pub const INVENTORY_SOURCE: &str = "ldap";
+
+/// Source name of hosts exported from `target`, so that exports from different directories
+/// do not replace each other's hosts: `ldap` for the default target, `ldap:<name>` otherwise.
+pub fn inventory_source(target: Option<&str>) -> String {
+    match target {
+        Some(name) => format!("{}:{}", INVENTORY_SOURCE, name),
+        None => INVENTORY_SOURCE.to_string(),
+    }
+}
+
+const COMPUTER_ATTRS: [&str; 8] = [
+    "cn",
+    "sAMAccountName",
+    "dNSHostName",
+    "operatingSystem",
+    "operatingSystemVersion",
+    "lastLogonTimestamp",
+    "pwdLastSet",
+    "userAccountControl",
+];
+
+const INVENTORY_HEADERS: [&str; 7] = [
+    "name",
+    "dNSHostName",
+    "operatingSystem",
+    "version",
+    "lastLogon",
+    "OU",
+    "status",
+];
+
+/// A computer account in the directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Computer {
+    pub name: String,
+    pub dns_host_name: Option<String>,
+    pub operating_system: Option<String>,
+    pub operating_system_version: Option<String>,
+    pub last_logon: Option<DateTime<Utc>>,
+    /// DN of the OU or container the computer is in
+    pub ou: String,
+    pub enabled: bool,
+    /// Not logged on (or never) for at least the stale threshold
+    pub stale: bool,
+}
+
+impl Computer {
+    /// Reads a computer account. A machine that has never logged on counts as stale once its
+    /// account password (`pwdLastSet`, set when it was joined) is older than `stale_days`.
+    pub fn from_entry(entry: &SearchEntry, now: DateTime<Utc>, stale_days: i64) -> Self {
+        let text = |attr| first_value(entry, attr).map(str::to_string);
+        let number = |attr| first_value(entry, attr).and_then(|v| v.parse::<i64>().ok());
+        let last_logon = number("lastLogonTimestamp").and_then(filetime_to_datetime);
+        let seen = last_logon.or_else(|| number("pwdLastSet").and_then(filetime_to_datetime));
+        Self {
+            name: text("cn")
+                .or_else(|| {
+                    text("sAMAccountName").map(|name| name.trim_end_matches('$').to_string())
+                })
+                .unwrap_or_else(|| split_dn(&entry.dn).0.to_string()),
+            dns_host_name: text("dNSHostName"),
+            operating_system: text("operatingSystem"),
+            operating_system_version: text("operatingSystemVersion"),
+            last_logon,
+            ou: split_dn(&entry.dn).1.unwrap_or_default().to_string(),
+            enabled: number("userAccountControl").unwrap_or(0) as u32 & uac::ACCOUNTDISABLE == 0,
+            stale: seen.is_none_or(|seen| (now - seen).num_days() >= stale_days),
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        match (self.enabled, self.stale) {
+            (false, _) => "disabled",
+            (true, true) => "stale",
+            (true, false) => "active",
+        }
+    }
+
+    pub fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.dns_host_name.clone().unwrap_or_default(),
+            self.operating_system.clone().unwrap_or_default(),
+            self.operating_system_version.clone().unwrap_or_default(),
+            self.last_logon
+                .map(|time| time.with_timezone(&Local).format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "Never".to_string()),
+            self.ou.clone(),
+            self.status().to_string(),
+        ]
+    }
+
+    /// The computer as a host for the monitoring module, exported from `source`.
+    pub fn to_host(&self, source: &str) -> Host {
+        Host {
+            hostname: self
+                .dns_host_name
+                .clone()
+                .unwrap_or_else(|| self.name.clone()),
+            name: self.name.clone(),
+            os: self.operating_system.clone(),
+            os_version: self.operating_system_version.clone(),
+            location: Some(self.ou.clone()),
+            last_seen: self.last_logon,
+            enabled: self.enabled,
+            stale: self.stale,
+            source: source.to_string(),
+        }
+    }
+}
+
+fn run_computer_inventory() -> Result<(), LdapToolError> {
+    let stale_days = match get_input("Days without logon before a machine is stale [90]").as_str() {
+        "" => 90,
+        input => input
+            .parse()
+            .map_err(|_| LdapToolError::Config("Enter a number of days".to_string()))?,
+    };
+    let os = optional_input("Operating system (use * as a wildcard, empty for all)");
+    let filter = match &os {
+        Some(os) => format!(
+            "(&{}(operatingSystem={}))",
+            COMPUTER_FILTER,
+            escape_filter_value(os, true)
+        ),
+        None => COMPUTER_FILTER.to_string(),
+    };
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let now = Utc::now();
+    let mut computers = Vec::new();
+    paged_search(
+        &mut ldap,
+        &base,
+        Scope::Subtree,
+        &filter,
+        &COMPUTER_ATTRS,
+        |entry| computers.push(Computer::from_entry(&entry, now, stale_days)),
+    )?;
+    computers.sort_by_key(|c| c.name.to_lowercase());
+
+    let rows: Vec<Vec<String>> = computers.iter().map(Computer::row).collect();
+    print!("{}", format_table(&INVENTORY_HEADERS, &rows));
+    let stale = computers.iter().filter(|c| c.enabled && c.stale).count();
+    let disabled = computers.iter().filter(|c| !c.enabled).count();
+    println!(
+        "Total: {} computer(s), {} stale, {} disabled",
+        computers.len(),
+        stale,
+        disabled
+    );
+
+    let path = inventory_path();
+    if confirm(&format!("Export to the host inventory {}", path.display())) {
+        let source = inventory_source(ldap_target().as_deref());
+        let hosts = computers.iter().map(|c| c.to_host(&source)).collect();
+        // Only a full listing can tell which machines are gone
+        let total = if os.is_some() {
+            merge_inventory(&path, &source, hosts)
+        } else {
+            update_inventory(&path, &source, hosts)
+        }
+        .map_err(LdapToolError::Config)?;
+        println!("Host inventory updated, {} host(s)", total);
+    }
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ComputerInventoryTool",
+    name = "Computer Inventory",
+    desc = "Lists computer accounts with OS and last logon, flags stale machines and exports a host inventory"
+)]
+pub fn computer_inventory() {
+    if let Err(e) = run_computer_inventory() {
+        println!("Error: {}", e);
+    }
+}
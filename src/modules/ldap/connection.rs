@@ -0,0 +1,812 @@
+use std::cell::Cell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use ldap3::{LdapConn, LdapConnSettings, LdapError, Scope};
+use native_tls::{Certificate, Identity, TlsConnector};
+use sha2::{Digest, Sha256};
+
+use crate::io::verbose;
+
+/// LDAP result code returned when a server requires an encrypted or signed connection.
+const RC_STRONGER_AUTH_REQUIRED: u32 = 8;
+
+/// LDAP result code for a failed bind.
+const RC_INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(Debug)]
+pub enum LdapToolError {
+    Ldap(LdapError),
+    /// Missing or invalid configuration
+    Config(String),
+    /// The TLS connection could not be set up
+    Tls(String),
+    /// The server certificate does not match the pinned fingerprint
+    FingerprintMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// The server refused a bind over an unencrypted connection
+    EncryptionRequired(String),
+    /// An operation that sends secrets was attempted over an unencrypted connection
+    InsecureConnection(String),
+    /// The server rejected the bind credentials
+    InvalidCredentials(String),
+    /// No directory entry matched
+    NotFound(String),
+    /// None of the configured servers could be reached
+    Unavailable(String),
+    /// An LDIF file could not be parsed
+    Ldif {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for LdapToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LdapToolError::Ldap(e) => write!(f, "LDAP error: {}", e),
+            LdapToolError::Config(message) => write!(f, "Configuration error: {}", message),
+            LdapToolError::Tls(message) => write!(f, "TLS error: {}", message),
+            LdapToolError::FingerprintMismatch { expected, actual } => write!(
+                f,
+                "Server certificate fingerprint {} does not match the pinned fingerprint {}",
+                actual, expected
+            ),
+            LdapToolError::EncryptionRequired(message) => write!(
+                f,
+                "The server refused an unencrypted bind ({}). Use an ldaps:// URL or set LDAP_STARTTLS=true",
+                message
+            ),
+            LdapToolError::InsecureConnection(operation) => write!(
+                f,
+                "{} requires an encrypted connection. Use an ldaps:// URL or set LDAP_STARTTLS=true",
+                operation
+            ),
+            LdapToolError::InvalidCredentials(reason) => write!(f, "Bind failed: {}", reason),
+            LdapToolError::NotFound(what) => write!(f, "{} not found", what),
+            LdapToolError::Unavailable(servers) => {
+                write!(f, "No LDAP server could be reached: {}", servers)
+            }
+            LdapToolError::Ldif { line, message } => write!(f, "LDIF line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for LdapToolError {}
+
+impl LdapToolError {
+    /// Whether no server could be reached or the connection failed, which may not happen again
+    /// on the next attempt.
+    pub fn is_connection_failure(&self) -> bool {
+        match self {
+            LdapToolError::Ldap(e) => is_connection_error(e),
+            LdapToolError::Unavailable(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<LdapError> for LdapToolError {
+    fn from(e: LdapError) -> Self {
+        if is_connection_error(&e) {
+            CONNECTION_FAILURES.set(CONNECTION_FAILURES.get() + 1);
+        }
+        LdapToolError::Ldap(e)
+    }
+}
+
+/// Whether `e` means the connection itself failed, rather than the operation.
+fn is_connection_error(e: &LdapError) -> bool {
+    matches!(
+        e,
+        LdapError::Io { .. }
+            | LdapError::OpSend { .. }
+            | LdapError::ResultRecv { .. }
+            | LdapError::Timeout { .. }
+            | LdapError::EndOfStream
+    )
+}
+
+/// Explains the Active Directory sub-code in the diagnostic message of a failed bind, e.g.
+/// `80090308: LdapErr: DSID-0C090447, comment: AcceptSecurityContext error, data 52e, v3839`.
+pub fn describe_bind_error(text: &str) -> Option<&'static str> {
+    let code = text.split("data ").nth(1)?.split([',', ' ']).next()?;
+    Some(match code.to_ascii_lowercase().as_str() {
+        "525" => "user not found",
+        "52e" => "invalid username or password",
+        "530" => "logon not permitted at this time",
+        "531" => "logon not permitted from this workstation",
+        "532" => "password expired",
+        "533" => "account disabled",
+        "701" => "account expired",
+        "773" => "the password must be changed before logging on",
+        "775" => "account locked out",
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// Upgrade a plain `ldap://` connection with StartTLS
+    pub starttls: bool,
+    /// PEM file with the CA certificate(s) used to verify the server
+    pub ca_bundle: Option<PathBuf>,
+    /// SHA-256 fingerprint of the server certificate (DER) the connection must present
+    pub fingerprint: Option<Vec<u8>>,
+}
+
+/// How to authenticate after connecting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindMode {
+    Anonymous,
+    /// Simple bind with a DN, a UPN (`user@example.com`) or a down-level name (`EXAMPLE\user`)
+    Simple {
+        username: String,
+        password: String,
+    },
+    /// SASL EXTERNAL, authenticating with a TLS client certificate
+    External {
+        /// PEM file with the client certificate
+        certificate: PathBuf,
+        /// PEM file with the PKCS#8 private key of the certificate
+        key: PathBuf,
+    },
+}
+
+impl BindMode {
+    /// Reads `LDAP_BIND` (`simple`, the default, `anonymous` or `external`) and the
+    /// credentials that mode needs.
+    fn from_settings(settings: &TargetSettings) -> Result<Self, LdapToolError> {
+        match settings
+            .optional("BIND")
+            .unwrap_or_else(|| "simple".to_string())
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "simple" => Ok(BindMode::Simple {
+                username: settings.required("USERNAME")?,
+                password: settings.required("PASSWORD")?,
+            }),
+            "anonymous" => Ok(BindMode::Anonymous),
+            "external" => Ok(BindMode::External {
+                certificate: settings.required("CLIENT_CERT")?.into(),
+                key: settings.required("CLIENT_KEY")?.into(),
+            }),
+            other => Err(LdapToolError::Config(format!(
+                "{} must be simple, anonymous or external, got {}",
+                target_var(settings.target, "BIND"),
+                other
+            ))),
+        }
+    }
+
+    /// Checks the settings before anything is sent to the server.
+    pub fn validate(&self, encrypted: bool) -> Result<(), LdapToolError> {
+        match self {
+            BindMode::Anonymous => Ok(()),
+            // An empty password makes a simple bind unauthenticated, which AD accepts
+            // without checking anything
+            BindMode::Simple { password, .. } if password.is_empty() => Err(LdapToolError::Config(
+                "LDAP_PASSWORD is empty. Set LDAP_BIND=anonymous for an anonymous bind".to_string(),
+            )),
+            BindMode::Simple { username, .. }
+                if !(username.contains('=')
+                    || username.contains('@')
+                    || username.contains('\\')) =>
+            {
+                Err(LdapToolError::Config(format!(
+                    "LDAP_USERNAME must be a DN, a UPN (user@example.com) or DOMAIN\\user, got {}",
+                    username
+                )))
+            }
+            BindMode::Simple { .. } => Ok(()),
+            BindMode::External { .. } if !encrypted => Err(LdapToolError::Config(
+                "LDAP_BIND=external requires an ldaps:// URL or LDAP_STARTTLS=true".to_string(),
+            )),
+            BindMode::External { .. } => Ok(()),
+        }
+    }
+
+    /// Name used in messages, e.g. for the unencrypted bind warning.
+    fn describe(&self) -> String {
+        match self {
+            BindMode::Anonymous => "anonymously".to_string(),
+            BindMode::Simple { username, .. } => format!("as {}", username),
+            BindMode::External { certificate, .. } => {
+                format!("with the certificate {}", certificate.display())
+            }
+        }
+    }
+}
+
+/// Connection settings for the LDAP server.
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    /// Servers to try in order; the next one is used when a server cannot be reached
+    pub servers: Vec<String>,
+    pub bind: BindMode,
+    pub tls: TlsConfig,
+    pub connect_timeout: Option<Duration>,
+    pub operation_timeout: Option<Duration>,
+}
+
+fn optional_env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+/// The variable holding `setting` for a target: `LDAP_SERVER` for the default target and
+/// `LDAP_LAB_SERVER` for the target `lab`. The default target reads its base DN from `BASE_DN`.
+pub fn target_var(target: Option<&str>, setting: &str) -> String {
+    match target {
+        None if setting == "BASE_DN" => setting.to_string(),
+        None => format!("LDAP_{}", setting),
+        Some(name) => format!(
+            "LDAP_{}_{}",
+            name.to_ascii_uppercase().replace('-', "_"),
+            setting
+        ),
+    }
+}
+
+/// Reads the settings of one target from a variable lookup (the environment, outside tests).
+struct TargetSettings<'a> {
+    target: Option<&'a str>,
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl TargetSettings<'_> {
+    fn optional(&self, setting: &str) -> Option<String> {
+        (self.lookup)(&target_var(self.target, setting)).filter(|v| !v.trim().is_empty())
+    }
+
+    fn required(&self, setting: &str) -> Result<String, LdapToolError> {
+        self.optional(setting).ok_or_else(|| {
+            LdapToolError::Config(format!("{} not set", target_var(self.target, setting)))
+        })
+    }
+
+    fn flag(&self, setting: &str) -> bool {
+        self.optional(setting)
+            .is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+    }
+
+    fn seconds(&self, setting: &str) -> Result<Option<Duration>, LdapToolError> {
+        self.optional(setting)
+            .map(|v| {
+                v.trim()
+                    .parse::<u64>()
+                    .map(Duration::from_secs)
+                    .map_err(|_| {
+                        LdapToolError::Config(format!(
+                            "{} must be a number of seconds",
+                            target_var(self.target, setting)
+                        ))
+                    })
+            })
+            .transpose()
+    }
+}
+
+/// Splits a server list such as `ldaps://dc01.example.com, ldaps://dc02.example.com`.
+pub fn parse_server_list(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|server| !server.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl LdapConfig {
+    /// Reads the settings of the target selected with `--ldap-target` or `LDAP_TARGET`.
+    pub fn from_env() -> Result<Self, LdapToolError> {
+        let target = ldap_target();
+        Self::from_settings(&TargetSettings {
+            target: target.as_deref(),
+            lookup: &optional_env,
+        })
+    }
+
+    fn from_settings(settings: &TargetSettings) -> Result<Self, LdapToolError> {
+        let servers = parse_server_list(&settings.required("SERVER")?);
+        if servers.is_empty() {
+            return Err(LdapToolError::Config(format!(
+                "{} does not name a server",
+                target_var(settings.target, "SERVER")
+            )));
+        }
+        Ok(Self {
+            servers,
+            bind: BindMode::from_settings(settings)?,
+            tls: TlsConfig {
+                starttls: settings.flag("STARTTLS"),
+                ca_bundle: settings.optional("CA_BUNDLE").map(PathBuf::from),
+                fingerprint: settings
+                    .optional("TLS_FINGERPRINT")
+                    .map(|f| parse_fingerprint(&f))
+                    .transpose()?,
+            },
+            connect_timeout: settings.seconds("CONNECT_TIMEOUT")?,
+            operation_timeout: settings.seconds("TIMEOUT")?,
+        })
+    }
+
+    /// Whether connections are encrypted, either with `ldaps://` URLs or StartTLS.
+    pub fn is_encrypted(&self) -> bool {
+        self.tls.starttls
+            || self
+                .servers
+                .iter()
+                .all(|server| server.to_ascii_lowercase().starts_with("ldaps://"))
+    }
+}
+
+/// A named directory: its connection settings and search base.
+#[derive(Debug, Clone)]
+pub struct LdapTarget {
+    /// `None` for the default target, configured with the unprefixed variables
+    pub name: Option<String>,
+    pub base_dn: String,
+    pub config: LdapConfig,
+}
+
+impl LdapTarget {
+    /// Reads a target from the environment.
+    pub fn load(name: Option<&str>) -> Result<Self, LdapToolError> {
+        Self::load_with(name, &optional_env)
+    }
+
+    /// Reads a target with `lookup`, which maps variable names to values.
+    pub fn load_with(
+        name: Option<&str>,
+        lookup: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, LdapToolError> {
+        let name = parse_target_name(name.unwrap_or_default())?;
+        let settings = TargetSettings {
+            target: name.as_deref(),
+            lookup,
+        };
+        if name.is_some() && settings.optional("SERVER").is_none() {
+            return Err(LdapToolError::Config(format!(
+                "LDAP target {} is not configured ({} not set)",
+                name.as_deref().unwrap_or_default(),
+                target_var(settings.target, "SERVER")
+            )));
+        }
+        Ok(Self {
+            base_dn: settings.required("BASE_DN")?,
+            config: LdapConfig::from_settings(&settings)?,
+            name,
+        })
+    }
+
+    /// The name shown in messages and reports.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(DEFAULT_TARGET)
+    }
+}
+
+/// Name that selects the target configured with the unprefixed variables.
+pub const DEFAULT_TARGET: &str = "default";
+
+/// Checks a target name. An empty name and `default` select the default target.
+pub fn parse_target_name(name: &str) -> Result<Option<String>, LdapToolError> {
+    let name = name.trim();
+    if name.is_empty() || name.eq_ignore_ascii_case(DEFAULT_TARGET) {
+        return Ok(None);
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(LdapToolError::Config(format!(
+            "Invalid LDAP target name {}: use letters, digits, - and _",
+            name
+        )));
+    }
+    Ok(Some(name.to_ascii_lowercase()))
+}
+
+/// Target names listed in `LDAP_TARGETS`, for prompts.
+pub fn configured_targets() -> Vec<String> {
+    optional_env("LDAP_TARGETS")
+        .map(|targets| parse_server_list(&targets))
+        .unwrap_or_default()
+}
+
+/// The target chosen with `--ldap-target`, overriding `LDAP_TARGET`.
+static SELECTED_TARGET: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn set_ldap_target(name: Option<String>) {
+    *SELECTED_TARGET.lock().unwrap_or_else(|e| e.into_inner()) = name;
+}
+
+/// Name of the target the tools work on, `None` for the default target.
+pub fn ldap_target() -> Option<String> {
+    let selected = SELECTED_TARGET
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    selected
+        .or_else(|| optional_env("LDAP_TARGET"))
+        .filter(|name| !name.trim().eq_ignore_ascii_case(DEFAULT_TARGET))
+}
+
+/// Parses a SHA-256 fingerprint written as hex, with or without `:` separators.
+pub fn parse_fingerprint(value: &str) -> Result<Vec<u8>, LdapToolError> {
+    let hex: String = value
+        .chars()
+        .filter(|c| *c != ':' && !c.is_whitespace())
+        .collect();
+    let invalid = || {
+        LdapToolError::Config(format!(
+            "LDAP_TLS_FINGERPRINT must be a SHA-256 fingerprint in hex, got {}",
+            value
+        ))
+    };
+    if hex.len() != 64 {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+pub fn format_fingerprint(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Splits a PEM bundle into its individual certificates.
+pub fn split_pem_bundle(pem: &str) -> Vec<String> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut certificates = Vec::new();
+    let mut rest = pem;
+    while let Some(start) = rest.find(BEGIN) {
+        let Some(end) = rest[start..].find(END) else {
+            break;
+        };
+        let end = start + end + END.len();
+        certificates.push(rest[start..end].to_string());
+        rest = &rest[end..];
+    }
+    certificates
+}
+
+fn read_file(path: &Path, what: &str) -> Result<Vec<u8>, LdapToolError> {
+    std::fs::read(path).map_err(|e| {
+        LdapToolError::Config(format!("Failed to read {} {}: {}", what, path.display(), e))
+    })
+}
+
+/// Builds the TLS connector when the defaults are not enough: a custom CA bundle or a client
+/// certificate for SASL EXTERNAL.
+fn build_connector(config: &LdapConfig) -> Result<Option<TlsConnector>, LdapToolError> {
+    let identity = match &config.bind {
+        BindMode::External { certificate, key } => Some(
+            Identity::from_pkcs8(
+                &read_file(certificate, "client certificate")?,
+                &read_file(key, "client key")?,
+            )
+            .map_err(|e| LdapToolError::Tls(format!("Invalid client certificate or key: {}", e)))?,
+        ),
+        _ => None,
+    };
+    if config.tls.ca_bundle.is_none() && identity.is_none() {
+        return Ok(None);
+    }
+
+    let mut builder = TlsConnector::builder();
+    if let Some(ca_bundle) = &config.tls.ca_bundle {
+        let pem = String::from_utf8_lossy(&read_file(ca_bundle, "CA bundle")?).into_owned();
+        let certificates = split_pem_bundle(&pem);
+        if certificates.is_empty() {
+            return Err(LdapToolError::Config(format!(
+                "No certificates found in CA bundle {}",
+                ca_bundle.display()
+            )));
+        }
+        for certificate in certificates {
+            let certificate = Certificate::from_pem(certificate.as_bytes())
+                .map_err(|e| LdapToolError::Tls(format!("Invalid CA certificate: {}", e)))?;
+            builder.add_root_certificate(certificate);
+        }
+    }
+    if let Some(identity) = identity {
+        builder.identity(identity);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| LdapToolError::Tls(e.to_string()))
+}
+
+/// An open, bound LDAP connection.
+pub struct LdapConnection {
+    conn: LdapConn,
+    operation_timeout: Option<Duration>,
+    encrypted: bool,
+    /// The server from the configured list that answered
+    server: String,
+    /// When the connection was last handed out, to decide whether it needs a check
+    last_used: Instant,
+}
+
+impl LdapConnection {
+    /// The underlying connection, with the configured operation timeout applied to the next operation.
+    pub fn conn(&mut self) -> &mut LdapConn {
+        if let Some(timeout) = self.operation_timeout {
+            self.conn.with_timeout(timeout);
+        }
+        &mut self.conn
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+}
+
+/// Connects to the first reachable server described by `config`, verifying TLS settings, and
+/// binds. A server that cannot be reached is skipped; any other failure, such as rejected
+/// credentials, is returned without trying the remaining servers.
+pub fn connect(config: &LdapConfig) -> Result<LdapConnection, LdapToolError> {
+    let encrypted = config.is_encrypted();
+    if config.tls.fingerprint.is_some() && !encrypted {
+        return Err(LdapToolError::Config(
+            "LDAP_TLS_FINGERPRINT requires an ldaps:// URL or LDAP_STARTTLS=true".to_string(),
+        ));
+    }
+    config.bind.validate(encrypted)?;
+
+    let mut failures = Vec::new();
+    for server in &config.servers {
+        match connect_server(config, server, encrypted) {
+            Err(LdapToolError::Ldap(e)) if is_connection_error(&e) => {
+                if verbose() {
+                    eprintln!("[ldap] {} is unreachable: {}", server, e);
+                }
+                failures.push((server, e));
+            }
+            Ok(connection) => return Ok(connection),
+            Err(e) => return Err(e),
+        }
+    }
+    if failures.len() == 1 {
+        let (_, e) = failures.remove(0);
+        return Err(LdapToolError::Ldap(e));
+    }
+    Err(LdapToolError::Unavailable(
+        failures
+            .iter()
+            .map(|(server, e)| format!("{} ({})", server, e))
+            .collect::<Vec<_>>()
+            .join(", "),
+    ))
+}
+
+fn connect_server(
+    config: &LdapConfig,
+    server: &str,
+    encrypted: bool,
+) -> Result<LdapConnection, LdapToolError> {
+    let mut settings = LdapConnSettings::new().set_starttls(config.tls.starttls);
+    if let Some(timeout) = config.connect_timeout {
+        settings = settings.set_conn_timeout(timeout);
+    }
+    if let Some(connector) = build_connector(config)? {
+        settings = settings.set_connector(connector);
+    }
+    let mut conn = LdapConn::with_settings(settings, server)?;
+
+    if let Some(expected) = &config.tls.fingerprint {
+        let certificate = conn.get_peer_certificate()?.ok_or_else(|| {
+            LdapToolError::Tls("The server did not present a certificate".to_string())
+        })?;
+        let actual = Sha256::digest(&certificate).to_vec();
+        if &actual != expected {
+            let _ = conn.unbind();
+            return Err(LdapToolError::FingerprintMismatch {
+                expected: format_fingerprint(expected),
+                actual: format_fingerprint(&actual),
+            });
+        }
+    }
+
+    if !encrypted && verbose() {
+        eprintln!(
+            "[ldap] Warning: binding to {} over an unencrypted connection",
+            server
+        );
+    }
+    if verbose() {
+        eprintln!("[ldap] Binding to {} {}", server, config.bind.describe());
+    }
+
+    let mut connection = LdapConnection {
+        conn,
+        operation_timeout: config.operation_timeout,
+        encrypted,
+        server: server.to_string(),
+        last_used: Instant::now(),
+    };
+    let result = match &config.bind {
+        BindMode::Anonymous => connection.conn().simple_bind("", "")?,
+        BindMode::Simple { username, password } => {
+            connection.conn().simple_bind(username, password)?
+        }
+        BindMode::External { .. } => connection.conn().sasl_external_bind()?,
+    };
+    if result.rc == RC_STRONGER_AUTH_REQUIRED && !encrypted {
+        return Err(LdapToolError::EncryptionRequired(result.text));
+    }
+    if result.rc == RC_INVALID_CREDENTIALS {
+        let reason = describe_bind_error(&result.text).unwrap_or("invalid credentials");
+        return Err(LdapToolError::InvalidCredentials(format!(
+            "{} ({} {})",
+            reason,
+            server,
+            config.bind.describe()
+        )));
+    }
+    result.success()?;
+    Ok(connection)
+}
+
+/// Groups new users are added to, read from `LDAP_DEFAULT_GROUPS` (DNs separated by `;`) or
+/// the selected target's `DEFAULT_GROUPS`.
+pub fn default_groups() -> Vec<String> {
+    optional_env(&target_var(ldap_target().as_deref(), "DEFAULT_GROUPS"))
+        .map(|groups| split_dn_list(&groups))
+        .unwrap_or_default()
+}
+
+/// Splits a `;` separated list of DNs.
+pub fn split_dn_list(input: &str) -> Vec<String> {
+    input
+        .split(';')
+        .map(str::trim)
+        .filter(|dn| !dn.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The search base for directory operations, read from `BASE_DN` or the selected target's
+/// `BASE_DN`.
+pub fn base_dn() -> Result<String, LdapToolError> {
+    let target = ldap_target();
+    TargetSettings {
+        target: target.as_deref(),
+        lookup: &optional_env,
+    }
+    .required("BASE_DN")
+}
+
+/// Connects and binds using the settings from the environment.
+pub fn create_ldap_connection() -> Result<LdapConnection, LdapToolError> {
+    connect(&LdapConfig::from_env()?)
+}
+
+/// A reused connection that has been idle this long is checked before it is handed out.
+const IDLE_CHECK_AFTER: Duration = Duration::from_secs(60);
+
+thread_local! {
+    /// The connection shared by the LDAP tools, while no tool is using it.
+    static SESSION: Cell<Option<LdapConnection>> = const { Cell::new(None) };
+    /// Operations on this thread that failed because of the connection. A session that sees the
+    /// count change while it is borrowed does not hand its connection back.
+    static CONNECTION_FAILURES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The module's shared LDAP connection, borrowed by one tool at a time.
+///
+/// The connection is opened on first use and handed back when the session is dropped. A
+/// connection that was closed by the server, failed during the last tool or does not answer
+/// after being idle is replaced by a new one.
+pub struct LdapSession {
+    connection: Option<LdapConnection>,
+    /// `CONNECTION_FAILURES` when the session was handed out
+    failures: u64,
+}
+
+impl LdapSession {
+    fn connection(&self) -> &LdapConnection {
+        self.connection
+            .as_ref()
+            .expect("the session holds a connection until it is dropped")
+    }
+
+    fn connection_mut(&mut self) -> &mut LdapConnection {
+        self.connection
+            .as_mut()
+            .expect("the session holds a connection until it is dropped")
+    }
+}
+
+impl Deref for LdapSession {
+    type Target = LdapConnection;
+
+    fn deref(&self) -> &LdapConnection {
+        self.connection()
+    }
+}
+
+impl DerefMut for LdapSession {
+    fn deref_mut(&mut self) -> &mut LdapConnection {
+        self.connection_mut()
+    }
+}
+
+impl Drop for LdapSession {
+    fn drop(&mut self) {
+        let lost = CONNECTION_FAILURES.get() != self.failures;
+        if let Some(mut connection) = self.connection.take() {
+            if lost {
+                let _ = connection.conn.unbind();
+            } else {
+                connection.last_used = Instant::now();
+                SESSION.set(Some(connection));
+            }
+        }
+    }
+}
+
+/// Whether a cached connection can still be used.
+fn is_alive(connection: &mut LdapConnection) -> bool {
+    if connection.conn.is_closed() {
+        return false;
+    }
+    if connection.last_used.elapsed() < IDLE_CHECK_AFTER {
+        return true;
+    }
+    // Read the root DSE, which every server allows
+    connection
+        .conn()
+        .search("", Scope::Base, "(objectClass=*)", vec!["1.1"])
+        .and_then(|result| result.success())
+        .is_ok()
+}
+
+/// Borrows the shared connection, connecting (again) with the settings from the environment
+/// when there is none or it no longer works.
+pub fn ldap_session() -> Result<LdapSession, LdapToolError> {
+    ldap_session_with(create_ldap_connection)
+}
+
+/// Borrows the shared connection like [`ldap_session`], opening a new one with `connect`.
+pub fn ldap_session_with<F>(connect: F) -> Result<LdapSession, LdapToolError>
+where
+    F: FnOnce() -> Result<LdapConnection, LdapToolError>,
+{
+    let connection = match SESSION.take() {
+        Some(mut connection) => {
+            if is_alive(&mut connection) {
+                connection
+            } else {
+                if verbose() {
+                    eprintln!("[ldap] Connection lost, reconnecting");
+                }
+                connect()?
+            }
+        }
+        None => connect()?,
+    };
+    // Failures while connecting, such as unreachable servers skipped on the way, do not count
+    // against the new connection
+    Ok(LdapSession {
+        connection: Some(connection),
+        failures: CONNECTION_FAILURES.get(),
+    })
+}
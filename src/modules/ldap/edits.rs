@@ -0,0 +1,482 @@
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Mod, Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::groups::is_dn;
+use super::schema::{Schema, directory_schema};
+use super::search::{parse_csv, search_all, values};
+use super::snapshots::{AttributeChange, EntryChange, write_diff};
+use super::users::{
+    USER_FILTER, escape_filter_value, find_user, is_valid_attribute, required_input,
+};
+use crate::audit::{self, AuditEntry};
+use crate::io::confirm;
+
+/// Attribute the rows of an edit sheet are matched on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountKey {
+    AccountName,
+    Mail,
+}
+
+impl AccountKey {
+    pub fn attr(&self) -> &'static str {
+        match self {
+            AccountKey::AccountName => "sAMAccountName",
+            AccountKey::Mail => "mail",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditOp {
+    /// Set the attribute to the given values
+    Replace,
+    /// Add values, keeping the existing ones
+    Add,
+    /// Remove every value
+    Clear,
+}
+
+/// A change to one attribute, from one cell of an edit sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeEdit {
+    pub attr: String,
+    pub op: EditOp,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditRow {
+    /// Row number in the file, the header being row 1
+    pub row: usize,
+    pub key: String,
+    pub edits: Vec<AttributeEdit>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditSheet {
+    pub key: AccountKey,
+    pub rows: Vec<EditRow>,
+}
+
+fn sheet_error(row: usize, message: impl std::fmt::Display) -> LdapToolError {
+    LdapToolError::Config(format!("CSV row {}: {}", row, message))
+}
+
+/// Parses an edit sheet. The first column holds the `sAMAccountName` or `mail` of the account,
+/// the others are named `attribute` (replace), `attribute:add` or `attribute:clear`. Empty cells
+/// leave the attribute alone, several values in a cell are separated by `;`, and any value in
+/// a `clear` column clears the attribute.
+pub fn parse_edit_sheet(text: &str) -> Result<EditSheet, LdapToolError> {
+    let mut rows = parse_csv(text)?.into_iter();
+    let header = rows
+        .next()
+        .ok_or_else(|| LdapToolError::Config("The CSV file is empty".to_string()))?;
+    let key = match header[0].trim() {
+        name if name.eq_ignore_ascii_case("sAMAccountName") => AccountKey::AccountName,
+        name if name.eq_ignore_ascii_case("mail") => AccountKey::Mail,
+        name => {
+            return Err(sheet_error(
+                1,
+                format!(
+                    "the first column must be sAMAccountName or mail, not {}",
+                    name
+                ),
+            ));
+        }
+    };
+
+    let mut columns: Vec<(String, EditOp)> = Vec::new();
+    for column in &header[1..] {
+        let (attr, op) = match column.trim().split_once(':') {
+            None => (column.trim(), EditOp::Replace),
+            Some((attr, op)) => match op.to_ascii_lowercase().as_str() {
+                "replace" => (attr, EditOp::Replace),
+                "add" => (attr, EditOp::Add),
+                "clear" => (attr, EditOp::Clear),
+                _ => {
+                    return Err(sheet_error(
+                        1,
+                        format!("unknown operation {} (use replace, add or clear)", op),
+                    ));
+                }
+            },
+        };
+        if !is_valid_attribute(attr) {
+            return Err(sheet_error(1, format!("invalid attribute name {}", attr)));
+        }
+        if columns.iter().any(|(a, _)| a.eq_ignore_ascii_case(attr)) {
+            return Err(sheet_error(
+                1,
+                format!("{} appears in more than one column", attr),
+            ));
+        }
+        columns.push((attr.to_string(), op));
+    }
+    if columns.is_empty() {
+        return Err(sheet_error(1, "no attribute columns"));
+    }
+
+    let mut parsed = Vec::new();
+    for (index, cells) in rows.enumerate() {
+        let row = index + 2;
+        if cells.len() > header.len() {
+            return Err(sheet_error(row, "more fields than the header"));
+        }
+        let value = cells[0].trim();
+        if value.is_empty() {
+            return Err(sheet_error(row, format!("no {}", key.attr())));
+        }
+        let edits = columns
+            .iter()
+            .zip(cells.iter().skip(1))
+            .filter(|(_, cell)| !cell.trim().is_empty())
+            .map(|((attr, op), cell)| AttributeEdit {
+                attr: attr.clone(),
+                op: *op,
+                values: match op {
+                    EditOp::Clear => Vec::new(),
+                    _ => cell
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|v| !v.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                },
+            })
+            .collect();
+        parsed.push(EditRow {
+            row,
+            key: value.to_string(),
+            edits,
+        });
+    }
+    Ok(EditSheet { key, rows: parsed })
+}
+
+/// Checks an edit sheet against the directory schema: every attribute must exist and be
+/// writable, and a single-valued attribute takes at most one value per cell.
+pub fn check_edit_sheet(sheet: &EditSheet, schema: &Schema) -> Result<(), LdapToolError> {
+    for row in &sheet.rows {
+        for edit in &row.edits {
+            let Some(attribute) = schema.attribute(&edit.attr) else {
+                return Err(
+                    match schema.check_attributes(std::slice::from_ref(&edit.attr)) {
+                        Err(LdapToolError::Config(message)) => sheet_error(1, message),
+                        _ => sheet_error(1, format!("unknown attribute {}", edit.attr)),
+                    },
+                );
+            };
+            if attribute.no_user_modification {
+                return Err(sheet_error(
+                    1,
+                    format!("{} is maintained by the server", edit.attr),
+                ));
+            }
+            if attribute.single_value && edit.values.len() > 1 {
+                return Err(sheet_error(
+                    row.row,
+                    format!(
+                        "{} is single-valued but has {} values",
+                        edit.attr,
+                        edit.values.len()
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An attribute change worked out against the current values of an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedEdit {
+    pub attr: String,
+    pub op: EditOp,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl PlannedEdit {
+    fn added(&self) -> Vec<String> {
+        difference(&self.after, &self.before)
+    }
+
+    pub fn change(&self) -> AttributeChange {
+        AttributeChange {
+            attr: self.attr.clone(),
+            removed: difference(&self.before, &self.after),
+            added: self.added(),
+        }
+    }
+
+    /// The modification that makes the change. Adds only send the new values, so values added
+    /// by someone else in the meantime are kept.
+    pub fn apply_mod(&self) -> Mod<String> {
+        match self.op {
+            EditOp::Add => Mod::Add(self.attr.clone(), self.added().into_iter().collect()),
+            EditOp::Replace | EditOp::Clear => {
+                Mod::Replace(self.attr.clone(), self.after.iter().cloned().collect())
+            }
+        }
+    }
+
+    /// The modification that undoes [`PlannedEdit::apply_mod`].
+    pub fn rollback_mod(&self) -> Mod<String> {
+        match self.op {
+            EditOp::Add => Mod::Delete(self.attr.clone(), self.added().into_iter().collect()),
+            EditOp::Replace | EditOp::Clear => {
+                Mod::Replace(self.attr.clone(), self.before.iter().cloned().collect())
+            }
+        }
+    }
+}
+
+/// Values of `a` that are not in `b`.
+fn difference(a: &[String], b: &[String]) -> Vec<String> {
+    a.iter()
+        .filter(|value| !b.contains(value))
+        .cloned()
+        .collect()
+}
+
+/// Works out the changes `edits` make to `entry`, leaving out edits that change nothing.
+pub fn plan_edits(entry: &SearchEntry, edits: &[AttributeEdit]) -> Vec<PlannedEdit> {
+    edits
+        .iter()
+        .map(|edit| {
+            let before = values(entry, &edit.attr).to_vec();
+            let after = match edit.op {
+                EditOp::Replace => edit.values.clone(),
+                EditOp::Add => {
+                    let mut after = before.clone();
+                    // Most AD syntaxes compare case-insensitively, so the server would reject
+                    // a value that only differs in case as already present
+                    after.extend(
+                        edit.values
+                            .iter()
+                            .filter(|value| {
+                                !before.iter().any(|other| other.eq_ignore_ascii_case(value))
+                            })
+                            .cloned(),
+                    );
+                    after
+                }
+                EditOp::Clear => Vec::new(),
+            };
+            PlannedEdit {
+                attr: edit.attr.clone(),
+                op: edit.op,
+                before,
+                after,
+            }
+        })
+        .filter(|planned| {
+            let change = planned.change();
+            !change.added.is_empty() || !change.removed.is_empty()
+        })
+        .collect()
+}
+
+/// The planned changes for one account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountEdit {
+    pub row: usize,
+    pub dn: String,
+    pub edits: Vec<PlannedEdit>,
+}
+
+impl AccountEdit {
+    pub fn entry_change(&self) -> EntryChange {
+        EntryChange::Modified {
+            dn: self.dn.clone(),
+            changes: self.edits.iter().map(PlannedEdit::change).collect(),
+        }
+    }
+}
+
+/// Looks up the user an edit sheet row refers to, reading the attributes it edits.
+fn find_account(
+    ldap: &mut LdapConnection,
+    base: &str,
+    key: AccountKey,
+    value: &str,
+    attrs: &[&str],
+) -> Result<SearchEntry, LdapToolError> {
+    let filter = format!(
+        "(&{}({}={}))",
+        USER_FILTER,
+        key.attr(),
+        escape_filter_value(value, false)
+    );
+    let mut entries = search_all(ldap, base, Scope::Subtree, &filter, attrs)?;
+    match entries.len() {
+        0 => Err(LdapToolError::NotFound(format!("User {}", value))),
+        1 => Ok(entries.remove(0)),
+        n => Err(LdapToolError::Config(format!(
+            "{} {} matches {} accounts",
+            key.attr(),
+            value,
+            n
+        ))),
+    }
+}
+
+/// Turns `manager` values given as account names into DNs.
+fn resolve_managers(
+    ldap: &mut LdapConnection,
+    base: &str,
+    edits: &mut [AttributeEdit],
+) -> Result<(), LdapToolError> {
+    for edit in edits
+        .iter_mut()
+        .filter(|edit| edit.attr.eq_ignore_ascii_case("manager"))
+    {
+        for value in edit.values.iter_mut().filter(|value| !is_dn(value)) {
+            *value = find_user(ldap, base, value, &["1.1"])?.dn;
+        }
+    }
+    Ok(())
+}
+
+fn prepare_row(
+    ldap: &mut LdapConnection,
+    base: &str,
+    key: AccountKey,
+    row: &EditRow,
+) -> Result<AccountEdit, LdapToolError> {
+    let mut edits = row.edits.clone();
+    resolve_managers(ldap, base, &mut edits)?;
+    let attrs: Vec<&str> = edits.iter().map(|edit| edit.attr.as_str()).collect();
+    let entry = find_account(ldap, base, key, &row.key, &attrs)?;
+    Ok(AccountEdit {
+        row: row.row,
+        dn: entry.dn.clone(),
+        edits: plan_edits(&entry, &edits),
+    })
+}
+
+fn modify(
+    ldap: &mut LdapConnection,
+    dn: &str,
+    mods: Vec<Mod<String>>,
+) -> Result<(), LdapToolError> {
+    ldap.conn().modify(dn, mods)?.success()?;
+    Ok(())
+}
+
+fn record_edits(action: &str, edit: &AccountEdit, rollback: bool) {
+    for planned in &edit.edits {
+        let before = format!("{}: {}", planned.attr, planned.before.join("; "));
+        let after = format!("{}: {}", planned.attr, planned.after.join("; "));
+        let (before, after) = if rollback {
+            (after, before)
+        } else {
+            (before, after)
+        };
+        let entry =
+            AuditEntry::new("ldap", action, &edit.dn).with_change(Some(&before), Some(&after));
+        if let Err(e) = audit::record(&entry) {
+            println!("Warning: failed to write audit log: {}", e);
+        }
+    }
+}
+
+/// Undoes `applied` in reverse order.
+fn roll_back(ldap: &mut LdapConnection, applied: &[&AccountEdit]) {
+    for edit in applied.iter().rev() {
+        let mods = edit
+            .edits
+            .iter()
+            .rev()
+            .map(PlannedEdit::rollback_mod)
+            .collect();
+        match modify(ldap, &edit.dn, mods) {
+            Ok(()) => {
+                println!("Rolled back {}", edit.dn);
+                record_edits("bulk_edit_rollback", edit, true);
+            }
+            Err(e) => println!("Failed to roll back {}: {}", edit.dn, e),
+        }
+    }
+}
+
+fn run_bulk_edit() -> Result<(), LdapToolError> {
+    let path = required_input("CSV file")?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| LdapToolError::Config(format!("Failed to read {}: {}", path, e)))?;
+    let sheet = parse_edit_sheet(&text)?;
+    if let Some(schema) = directory_schema() {
+        check_edit_sheet(&sheet, &schema)?;
+    }
+    if sheet.rows.is_empty() {
+        println!("No rows in {}", path);
+        return Ok(());
+    }
+
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let mut planned = Vec::new();
+    let mut skipped = 0;
+    for row in &sheet.rows {
+        match prepare_row(&mut ldap, &base, sheet.key, row) {
+            Ok(edit) if edit.edits.is_empty() => {
+                println!("Row {} ({}): no changes", row.row, row.key)
+            }
+            Ok(edit) => planned.push(edit),
+            Err(e) => {
+                skipped += 1;
+                println!("Skipped row {} ({}): {}", row.row, row.key, e);
+            }
+        }
+    }
+    let changes: Vec<EntryChange> = planned.iter().map(AccountEdit::entry_change).collect();
+    write_diff(&mut std::io::stdout(), &changes)
+        .map_err(|e| LdapToolError::Config(e.to_string()))?;
+    if skipped > 0 {
+        println!("{} row(s) skipped", skipped);
+    }
+    if planned.is_empty() {
+        println!("Nothing to change");
+        return Ok(());
+    }
+
+    let rollback = confirm("Roll back all changes if a row fails");
+    if !confirm(&format!("Apply changes to {} account(s)", planned.len())) {
+        println!("Dry run only, nothing changed");
+        return Ok(());
+    }
+
+    let mut applied: Vec<&AccountEdit> = Vec::new();
+    for edit in &planned {
+        let mods = edit.edits.iter().map(PlannedEdit::apply_mod).collect();
+        match modify(&mut ldap, &edit.dn, mods) {
+            Ok(()) => {
+                println!("Updated {}", edit.dn);
+                record_edits("bulk_edit", edit, false);
+                applied.push(edit);
+            }
+            Err(e) if rollback => {
+                println!("Row {} ({}) failed: {}", edit.row, edit.dn, e);
+                roll_back(&mut ldap, &applied);
+                println!("Rolled back {} account(s)", applied.len());
+                return Ok(());
+            }
+            Err(e) => println!("Row {} ({}) failed: {}", edit.row, edit.dn, e),
+        }
+    }
+    println!("Updated {} of {} account(s)", applied.len(), planned.len());
+    Ok(())
+}
+
+#[derive_tool(
+    id = "BulkEditAttributesTool",
+    name = "Bulk Edit Attributes",
+    desc = "Applies attribute changes from a CSV file to user accounts, with a preview and optional rollback"
+)]
+pub fn bulk_edit_attributes() {
+    if let Err(e) = run_bulk_edit() {
+        println!("Error: {}", e);
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Mod, Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::display_values;
+use super::policies::domain_root;
+use super::search::{format_table, paged_search, search_all, values};
+use super::users::{escape_filter_value, optional_input, required_input};
+use crate::audit::{self, AuditEntry};
+use crate::io::{confirm, get_input};
+
+/// `LDAP_MATCHING_RULE_IN_CHAIN`: matches through nested group membership on the server.
+pub const MATCHING_RULE_IN_CHAIN: &str = "1.2.840.113556.1.4.1941";
+
+pub const GROUP_FILTER: &str = "(objectClass=group)";
+
+/// Result code AD returns when adding a value that is already present.
+const RC_ATTRIBUTE_OR_VALUE_EXISTS: u32 = 20;
+/// Result code AD returns when removing a member that is not in the group.
+const RC_UNWILLING_TO_PERFORM: u32 = 53;
+
+/// An object found while walking group memberships.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberNode {
+    pub dn: String,
+    pub is_group: bool,
+}
+
+impl MemberNode {
+    pub fn from_entry(entry: &SearchEntry) -> Self {
+        Self {
+            dn: entry.dn.clone(),
+            is_group: values(entry, "objectClass")
+                .iter()
+                .any(|class| class.eq_ignore_ascii_case("group")),
+        }
+    }
+}
+
+/// Everything reachable from a starting object through group memberships.
+#[derive(Debug, Default, PartialEq)]
+pub struct Expansion {
+    /// Objects that are not groups, in the order they were found
+    pub objects: Vec<String>,
+    /// Groups that were passed through, in the order they were found
+    pub groups: Vec<String>,
+    /// Edges that lead back to a group already being expanded, as `(from, to)`
+    pub cycles: Vec<(String, String)>,
+}
+
+/// Walks memberships depth-first from `start`, using `next` to list the neighbours of a group
+/// (its members, or the groups it is a member of).
+///
+/// Each group is expanded once, so cyclic nesting terminates. An edge back to a group that is
+/// still being expanded closes a cycle and is reported in [`Expansion::cycles`].
+pub fn expand<F>(start: &str, mut next: F) -> Result<Expansion, LdapToolError>
+where
+    F: FnMut(&str) -> Result<Vec<MemberNode>, LdapToolError>,
+{
+    let mut walk = Walk::default();
+    walk.visit(start, &mut next)?;
+    Ok(walk.expansion)
+}
+
+#[derive(Default)]
+struct Walk {
+    expansion: Expansion,
+    /// Lowercased DNs of every object reached so far
+    seen: HashSet<String>,
+    /// Lowercased DNs of the groups on the current path
+    path: HashSet<String>,
+}
+
+impl Walk {
+    fn visit<F>(&mut self, dn: &str, next: &mut F) -> Result<(), LdapToolError>
+    where
+        F: FnMut(&str) -> Result<Vec<MemberNode>, LdapToolError>,
+    {
+        let key = dn.to_ascii_lowercase();
+        self.seen.insert(key.clone());
+        self.path.insert(key.clone());
+        for node in next(dn)? {
+            let node_key = node.dn.to_ascii_lowercase();
+            if self.path.contains(&node_key) {
+                self.expansion.cycles.push((dn.to_string(), node.dn));
+            } else if self.seen.contains(&node_key) {
+                continue;
+            } else if node.is_group {
+                self.expansion.groups.push(node.dn.clone());
+                self.visit(&node.dn, next)?;
+            } else {
+                self.seen.insert(node_key);
+                self.expansion.objects.push(node.dn);
+            }
+        }
+        self.path.remove(&key);
+        Ok(())
+    }
+}
+
+/// Finds a group by `cn` or `sAMAccountName`, or by DN when `name` is one.
+pub fn find_group(
+    ldap: &mut LdapConnection,
+    base: &str,
+    name: &str,
+) -> Result<SearchEntry, LdapToolError> {
+    find_object(ldap, base, name, GROUP_FILTER, "Group")
+}
+
+/// Finds a user, group or computer by `sAMAccountName`, or by DN when `name` is one.
+pub fn find_member(
+    ldap: &mut LdapConnection,
+    base: &str,
+    name: &str,
+) -> Result<SearchEntry, LdapToolError> {
+    find_object(ldap, base, name, "(objectClass=*)", "Object")
+}
+
+/// Finds an object matching `class_filter` by `sAMAccountName` or `cn`, or by DN when `name` is
+/// one. `kind` names the object in the not found error.
+pub fn find_object(
+    ldap: &mut LdapConnection,
+    base: &str,
+    name: &str,
+    class_filter: &str,
+    kind: &str,
+) -> Result<SearchEntry, LdapToolError> {
+    let attrs = ["objectClass", "sAMAccountName", "cn"];
+    let entries = if is_dn(name) {
+        search_all(ldap, name, Scope::Base, class_filter, &attrs)?
+    } else {
+        let value = escape_filter_value(name, false);
+        let filter = format!("(&{}(|(sAMAccountName={1})(cn={1})))", class_filter, value);
+        search_all(ldap, base, Scope::Subtree, &filter, &attrs)?
+    };
+    match entries.len() {
+        0 => Err(LdapToolError::NotFound(format!("{} {}", kind, name))),
+        1 => Ok(entries.into_iter().next().unwrap()),
+        n => Err(LdapToolError::Config(format!(
+            "{} matches {} entries, use the DN instead",
+            name, n
+        ))),
+    }
+}
+
+/// Whether `input` looks like a DN rather than an account name.
+pub fn is_dn(input: &str) -> bool {
+    input.contains('=') && input.contains(',')
+}
+
+/// Filter for the members of `group_dn`; nested members too when `recursive`.
+pub fn members_filter(group_dn: &str, recursive: bool) -> String {
+    let rule = if recursive {
+        format!(":{}:", MATCHING_RULE_IN_CHAIN)
+    } else {
+        String::new()
+    };
+    format!(
+        "(memberOf{}={})",
+        rule,
+        escape_filter_value(group_dn, false)
+    )
+}
+
+/// Filter for the groups `member_dn` belongs to; through nesting too when `recursive`.
+pub fn groups_of_filter(member_dn: &str, recursive: bool) -> String {
+    let rule = if recursive {
+        format!(":{}:", MATCHING_RULE_IN_CHAIN)
+    } else {
+        String::new()
+    };
+    format!(
+        "(&{}(member{}={}))",
+        GROUP_FILTER,
+        rule,
+        escape_filter_value(member_dn, false)
+    )
+}
+
+/// Direct members of a group. Members are found with a `memberOf` search rather than by
+/// reading `member`, which AD returns in ranges of 1500 values for large groups. `base` should be
+/// the domain root (see `domain_root`), so that members outside `BASE_DN` and foreign security
+/// principals are found too.
+pub fn direct_members(
+    ldap: &mut LdapConnection,
+    base: &str,
+    group_dn: &str,
+) -> Result<Vec<MemberNode>, LdapToolError> {
+    Ok(search_all(
+        ldap,
+        base,
+        Scope::Subtree,
+        &members_filter(group_dn, false),
+        &["objectClass"],
+    )?
+    .iter()
+    .map(MemberNode::from_entry)
+    .collect())
+}
+
+/// Groups an object is a direct member of, searched for below `base`.
+pub fn direct_groups(
+    ldap: &mut LdapConnection,
+    base: &str,
+    member_dn: &str,
+) -> Result<Vec<MemberNode>, LdapToolError> {
+    Ok(search_all(
+        ldap,
+        base,
+        Scope::Subtree,
+        &groups_of_filter(member_dn, false),
+        &["objectClass"],
+    )?
+    .iter()
+    .map(MemberNode::from_entry)
+    .collect())
+}
+
+/// Adds `member_dn` to the `member` attribute of `group_dn`.
+pub fn add_member(
+    ldap: &mut LdapConnection,
+    group_dn: &str,
+    member_dn: &str,
+) -> Result<(), LdapToolError> {
+    let result = ldap.conn().modify(
+        group_dn,
+        vec![Mod::Add("member", HashSet::from([member_dn]))],
+    )?;
+    if result.rc == RC_ATTRIBUTE_OR_VALUE_EXISTS {
+        return Err(LdapToolError::Config(format!(
+            "{} is already a member of {}",
+            member_dn, group_dn
+        )));
+    }
+    result.success()?;
+    Ok(())
+}
+
+/// Removes `member_dn` from the `member` attribute of `group_dn`.
+pub fn remove_member(
+    ldap: &mut LdapConnection,
+    group_dn: &str,
+    member_dn: &str,
+) -> Result<(), LdapToolError> {
+    let result = ldap.conn().modify(
+        group_dn,
+        vec![Mod::Delete("member", HashSet::from([member_dn]))],
+    )?;
+    if result.rc == RC_UNWILLING_TO_PERFORM {
+        return Err(LdapToolError::Config(format!(
+            "{} is not a direct member of {}",
+            member_dn, group_dn
+        )));
+    }
+    result.success()?;
+    Ok(())
+}
+
+/// Reads account names or DNs from a member list, one per line. Empty lines and lines
+/// starting with `#` are skipped.
+pub fn parse_member_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// How nested group memberships are resolved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expand {
+    /// Direct memberships only
+    None,
+    /// On the server with `LDAP_MATCHING_RULE_IN_CHAIN` (Active Directory only)
+    InChain,
+    /// By walking the groups from the client, reporting cycles
+    Client,
+}
+
+pub fn parse_expand(input: &str) -> Option<Expand> {
+    match input.trim().to_ascii_lowercase().as_str() {
+        "" | "chain" => Some(Expand::InChain),
+        "client" => Some(Expand::Client),
+        "no" | "none" | "direct" => Some(Expand::None),
+        _ => None,
+    }
+}
+
+fn prompt_expand() -> Result<Expand, LdapToolError> {
+    parse_expand(&get_input(
+        "Expand nested groups (chain, client, no) [chain]",
+    ))
+    .ok_or_else(|| LdapToolError::Config("Expansion must be chain, client or no".to_string()))
+}
+
+pub fn write_members(out: &mut impl Write, expansion: &Expansion) -> std::io::Result<()> {
+    for dn in &expansion.objects {
+        writeln!(out, "{}", dn)?;
+    }
+    for dn in &expansion.groups {
+        writeln!(out, "{} (group)", dn)?;
+    }
+    for (from, to) in &expansion.cycles {
+        writeln!(out, "Cycle: {} contains {}", from, to)?;
+    }
+    writeln!(
+        out,
+        "Total: {} member(s), {} group(s)",
+        expansion.objects.len(),
+        expansion.groups.len()
+    )
+}
+
+fn run_list_groups() -> Result<(), LdapToolError> {
+    let base = base_dn()?;
+    let filter = match optional_input("Group name (use * as a wildcard, empty for all)") {
+        Some(name) => format!(
+            "(&{}(|(cn={1})(sAMAccountName={1})))",
+            GROUP_FILTER,
+            escape_filter_value(&name, true)
+        ),
+        None => GROUP_FILTER.to_string(),
+    };
+    let mut ldap = ldap_session()?;
+    let columns = ["sAMAccountName", "groupType", "description"];
+    let mut rows = Vec::new();
+    paged_search(
+        &mut ldap,
+        &base,
+        Scope::Subtree,
+        &filter,
+        &columns,
+        |entry| {
+            rows.push(
+                columns
+                    .iter()
+                    .map(|column| display_values(&entry, column).join("; "))
+                    .collect(),
+            );
+        },
+    )?;
+    print!("{}", format_table(&columns, &rows));
+    println!("Total: {} group(s)", rows.len());
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ListGroupsTool",
+    name = "List Groups",
+    desc = "Lists groups, optionally filtered by name"
+)]
+pub fn list_groups() {
+    if let Err(e) = run_list_groups() {
+        println!("Error: {}", e);
+    }
+}
+
+/// The members of `group_dn`, resolved as `mode` asks. Members are searched for in the whole
+/// domain rather than below `BASE_DN`, so members in other OUs and foreign security principals
+/// (`CN=ForeignSecurityPrincipals`) are listed too.
+pub fn group_members(
+    ldap: &mut LdapConnection,
+    group_dn: &str,
+    mode: Expand,
+) -> Result<Expansion, LdapToolError> {
+    let root = domain_root(ldap)?;
+    if mode == Expand::Client {
+        return expand(group_dn, |dn| direct_members(ldap, &root, dn));
+    }
+    let mut expansion = Expansion::default();
+    for node in search_all(
+        ldap,
+        &root,
+        Scope::Subtree,
+        &members_filter(group_dn, mode == Expand::InChain),
+        &["objectClass"],
+    )?
+    .iter()
+    .map(MemberNode::from_entry)
+    {
+        if node.is_group {
+            expansion.groups.push(node.dn);
+        } else {
+            expansion.objects.push(node.dn);
+        }
+    }
+    Ok(expansion)
+}
+
+fn run_list_group_members() -> Result<(), LdapToolError> {
+    let name = required_input("Group (name or DN)")?;
+    let expand_mode = prompt_expand()?;
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let group = find_group(&mut ldap, &base, &name)?;
+    let expansion = group_members(&mut ldap, &group.dn, expand_mode)?;
+    let _ = write_members(&mut std::io::stdout(), &expansion);
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ListGroupMembersTool",
+    name = "List Group Members",
+    desc = "Lists the members of a group, including members of nested groups"
+)]
+pub fn list_group_members() {
+    if let Err(e) = run_list_group_members() {
+        println!("Error: {}", e);
+    }
+}
+
+fn run_show_user_groups() -> Result<(), LdapToolError> {
+    let name = required_input("User (sAMAccountName or DN)")?;
+    let expand_mode = prompt_expand()?;
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let member = find_member(&mut ldap, &base, &name)?;
+
+    let groups = match expand_mode {
+        Expand::Client => {
+            let expansion = expand(&member.dn, |dn| direct_groups(&mut ldap, &base, dn))?;
+            for (from, to) in &expansion.cycles {
+                println!("Cycle: {} is a member of {}", from, to);
+            }
+            expansion.groups
+        }
+        Expand::None | Expand::InChain => search_all(
+            &mut ldap,
+            &base,
+            Scope::Subtree,
+            &groups_of_filter(&member.dn, expand_mode == Expand::InChain),
+            &["cn"],
+        )?
+        .into_iter()
+        .map(|entry| entry.dn)
+        .collect(),
+    };
+    for group in &groups {
+        println!("{}", group);
+    }
+    println!(
+        "Total: {} group(s), not counting the primary group",
+        groups.len()
+    );
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ShowUserGroupsTool",
+    name = "Show User Groups",
+    desc = "Shows the groups a user is a member of, including through nested groups"
+)]
+pub fn show_user_groups() {
+    if let Err(e) = run_show_user_groups() {
+        println!("Error: {}", e);
+    }
+}
+
+fn record_membership(action: &str, group_dn: &str, member_dn: &str) {
+    let entry = AuditEntry::new("ldap", action, group_dn).with_change(
+        (action == "remove_group_member").then_some(member_dn),
+        (action == "add_group_member").then_some(member_dn),
+    );
+    if let Err(e) = audit::record(&entry) {
+        println!("Warning: failed to write audit log: {}", e);
+    }
+}
+
+fn run_change_membership(add: bool) -> Result<(), LdapToolError> {
+    let group_name = required_input("Group (name or DN)")?;
+    let member_name = required_input("Member (sAMAccountName or DN)")?;
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let group = find_group(&mut ldap, &base, &group_name)?;
+    let member = find_member(&mut ldap, &base, &member_name)?;
+
+    let (verb, action) = if add {
+        ("Add", "add_group_member")
+    } else {
+        ("Remove", "remove_group_member")
+    };
+    if !confirm(&format!(
+        "{} {} {} {}",
+        verb,
+        member.dn,
+        if add { "to" } else { "from" },
+        group.dn
+    )) {
+        println!("Aborted");
+        return Ok(());
+    }
+    if add {
+        add_member(&mut ldap, &group.dn, &member.dn)?;
+    } else {
+        remove_member(&mut ldap, &group.dn, &member.dn)?;
+    }
+    println!("Done");
+    record_membership(action, &group.dn, &member.dn);
+    Ok(())
+}
+
+#[derive_tool(
+    id = "AddGroupMemberTool",
+    name = "Add Group Member",
+    desc = "Adds a user, group or computer to a group"
+)]
+pub fn add_group_member() {
+    if let Err(e) = run_change_membership(true) {
+        println!("Error: {}", e);
+    }
+}
+
+#[derive_tool(
+    id = "RemoveGroupMemberTool",
+    name = "Remove Group Member",
+    desc = "Removes a direct member from a group"
+)]
+pub fn remove_group_member() {
+    if let Err(e) = run_change_membership(false) {
+        println!("Error: {}", e);
+    }
+}
+
+fn run_bulk_add_members() -> Result<(), LdapToolError> {
+    let group_name = required_input("Group (name or DN)")?;
+    let path = required_input("File with one sAMAccountName or DN per line")?;
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| LdapToolError::Config(format!("Failed to read {}: {}", path, e)))?;
+    let names = parse_member_list(&contents);
+    if names.is_empty() {
+        return Err(LdapToolError::Config(format!(
+            "No members listed in {}",
+            path
+        )));
+    }
+
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let group = find_group(&mut ldap, &base, &group_name)?;
+    if !confirm(&format!("Add {} member(s) to {}", names.len(), group.dn)) {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    let mut added = 0;
+    for name in &names {
+        let result = find_member(&mut ldap, &base, name)
+            .and_then(|member| add_member(&mut ldap, &group.dn, &member.dn).map(|_| member.dn));
+        match result {
+            Ok(dn) => {
+                println!("Added {}", dn);
+                record_membership("add_group_member", &group.dn, &dn);
+                added += 1;
+            }
+            Err(e) => println!("Skipped {}: {}", name, e),
+        }
+    }
+    println!("Added {} of {} member(s)", added, names.len());
+    Ok(())
+}
+
+#[derive_tool(
+    id = "BulkAddGroupMembersTool",
+    name = "Bulk Add Group Members",
+    desc = "Adds the accounts listed in a file to a group"
+)]
+pub fn bulk_add_group_members() {
+    if let Err(e) = run_bulk_add_members() {
+        println!("Error: {}", e);
+    }
+}
@@ -0,0 +1,711 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Mod, Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::schema::attribute_list_input;
+use super::search::{paged_search, search_all};
+use super::users::{parse_scope, resolve_base};
+use crate::audit::{self, AuditEntry};
+use crate::io::{confirm, get_input};
+
+/// Lines longer than this are folded (RFC 2849 recommends 76 columns).
+const LINE_WIDTH: usize = 76;
+
+/// Whether `value` can be written as-is (`SAFE-STRING` in RFC 2849). Other values are
+/// base64-encoded. Values with trailing spaces are encoded too so they survive editors.
+pub fn is_safe_string(value: &[u8]) -> bool {
+    match value.first() {
+        None => true,
+        Some(b' ' | b':' | b'<') => false,
+        Some(_) => {
+            value.last() != Some(&b' ')
+                && value
+                    .iter()
+                    .all(|b| b.is_ascii() && !matches!(b, b'\0' | b'\n' | b'\r'))
+        }
+    }
+}
+
+/// Writes `name: value`, or `name:: base64` when needed, folded to [`LINE_WIDTH`].
+fn write_line(out: &mut impl Write, name: &str, value: &[u8]) -> std::io::Result<()> {
+    let line = if is_safe_string(value) {
+        format!("{}: {}", name, String::from_utf8_lossy(value))
+    } else {
+        format!("{}:: {}", name, BASE64.encode(value))
+    };
+    // Every character is ASCII at this point, so byte offsets are character offsets
+    let (first, mut rest) = line.split_at(line.len().min(LINE_WIDTH));
+    writeln!(out, "{}", first)?;
+    while !rest.is_empty() {
+        let (chunk, remainder) = rest.split_at(rest.len().min(LINE_WIDTH - 1));
+        writeln!(out, " {}", chunk)?;
+        rest = remainder;
+    }
+    Ok(())
+}
+
+/// Writes `entry` as an LDIF content record followed by a blank line. Attributes are
+/// sorted by name so exports of the same data compare equal.
+pub fn write_ldif_entry(out: &mut impl Write, entry: &SearchEntry) -> std::io::Result<()> {
+    write_line(out, "dn", entry.dn.as_bytes())?;
+    let mut attrs: Vec<(&String, Vec<&[u8]>)> = entry
+        .attrs
+        .iter()
+        .map(|(name, values)| (name, values.iter().map(|v| v.as_bytes()).collect()))
+        .chain(
+            entry
+                .bin_attrs
+                .iter()
+                .map(|(name, values)| (name, values.iter().map(Vec::as_slice).collect())),
+        )
+        .collect();
+    attrs.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+    for (name, values) in attrs {
+        for value in values {
+            write_line(out, name, value)?;
+        }
+    }
+    writeln!(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModOp {
+    Add,
+    Delete,
+    Replace,
+}
+
+/// One `add:`/`delete:`/`replace:` block of a modify record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdifMod {
+    pub op: ModOp,
+    pub attr: String,
+    pub values: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LdifChange {
+    Add(Vec<(String, Vec<Vec<u8>>)>),
+    Modify(Vec<LdifMod>),
+    Delete,
+    ModRdn {
+        new_rdn: String,
+        delete_old_rdn: bool,
+        new_superior: Option<String>,
+    },
+}
+
+/// A change record from an LDIF file. Content records (without `changetype`) are adds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdifRecord {
+    /// Line the record starts on, for messages
+    pub line: usize,
+    pub dn: String,
+    pub change: LdifChange,
+}
+
+impl LdifRecord {
+    pub fn kind(&self) -> &'static str {
+        match self.change {
+            LdifChange::Add(_) => "add",
+            LdifChange::Modify(_) => "modify",
+            LdifChange::Delete => "delete",
+            LdifChange::ModRdn { .. } => "modrdn",
+        }
+    }
+}
+
+/// An unfolded `name: value` line.
+struct Line {
+    number: usize,
+    name: String,
+    value: Vec<u8>,
+}
+
+fn ldif_error(line: usize, message: impl Into<String>) -> LdapToolError {
+    LdapToolError::Ldif {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Splits LDIF text into records of unfolded lines, dropping comments.
+fn split_records(text: &str) -> Result<Vec<Vec<Line>>, LdapToolError> {
+    let mut records = Vec::new();
+    let mut record: Vec<(usize, String)> = Vec::new();
+    let mut in_comment = false;
+
+    for (index, raw) in text.lines().enumerate() {
+        let number = index + 1;
+        if let Some(continuation) = raw.strip_prefix(' ') {
+            if in_comment {
+                continue;
+            }
+            match record.last_mut() {
+                Some((_, line)) => line.push_str(continuation),
+                None => {
+                    return Err(ldif_error(
+                        number,
+                        "Continuation line without a preceding line",
+                    ));
+                }
+            }
+            continue;
+        }
+        in_comment = raw.starts_with('#');
+        if in_comment {
+            continue;
+        }
+        if raw.trim().is_empty() {
+            if !record.is_empty() {
+                records.push(std::mem::take(&mut record));
+            }
+            continue;
+        }
+        record.push((number, raw.to_string()));
+    }
+    if !record.is_empty() {
+        records.push(record);
+    }
+
+    records
+        .into_iter()
+        .map(|record| record.into_iter().map(|(n, l)| parse_line(n, &l)).collect())
+        .collect()
+}
+
+fn parse_line(number: usize, line: &str) -> Result<Line, LdapToolError> {
+    // Separator between the blocks of a modify record
+    if line.trim_end() == "-" {
+        return Ok(Line {
+            number,
+            name: "-".to_string(),
+            value: Vec::new(),
+        });
+    }
+    let (name, rest) = line.split_once(':').ok_or_else(|| {
+        ldif_error(
+            number,
+            format!("Expected \"attribute: value\", got {}", line),
+        )
+    })?;
+    let value = if let Some(encoded) = rest.strip_prefix(':') {
+        BASE64
+            .decode(encoded.trim())
+            .map_err(|e| ldif_error(number, format!("Invalid base64 value: {}", e)))?
+    } else if rest.starts_with('<') {
+        return Err(ldif_error(number, "URL values (:<) are not supported"));
+    } else {
+        rest.trim_start_matches(' ').as_bytes().to_vec()
+    };
+    Ok(Line {
+        number,
+        name: name.trim().to_string(),
+        value,
+    })
+}
+
+fn text_value(line: &Line) -> Result<String, LdapToolError> {
+    String::from_utf8(line.value.clone())
+        .map_err(|_| ldif_error(line.number, format!("{} is not valid UTF-8", line.name)))
+}
+
+/// Parses an LDIF file into change records.
+pub fn parse_ldif(text: &str) -> Result<Vec<LdifRecord>, LdapToolError> {
+    let mut records = Vec::new();
+    for (index, lines) in split_records(text)?.into_iter().enumerate() {
+        let mut lines = lines.into_iter().peekable();
+        if index == 0
+            && lines
+                .peek()
+                .is_some_and(|l| l.name.eq_ignore_ascii_case("version"))
+        {
+            lines.next();
+            if lines.peek().is_none() {
+                continue;
+            }
+        }
+        let first = lines.next().expect("records are not empty");
+        if !first.name.eq_ignore_ascii_case("dn") {
+            return Err(ldif_error(first.number, "A record must start with dn:"));
+        }
+        let dn = text_value(&first)?;
+        if lines
+            .peek()
+            .is_some_and(|l| l.name.eq_ignore_ascii_case("control"))
+        {
+            let line = lines.next().unwrap();
+            return Err(ldif_error(line.number, "Controls are not supported"));
+        }
+        let changetype = match lines.peek() {
+            Some(l) if l.name.eq_ignore_ascii_case("changetype") => {
+                let line = lines.next().unwrap();
+                text_value(&line)?.trim().to_ascii_lowercase()
+            }
+            _ => "add".to_string(),
+        };
+        let rest: Vec<Line> = lines.collect();
+        let change = match changetype.as_str() {
+            "add" => parse_add(first.number, &rest)?,
+            "delete" => match rest.first() {
+                Some(line) => {
+                    return Err(ldif_error(
+                        line.number,
+                        "Unexpected line in a delete record",
+                    ));
+                }
+                None => LdifChange::Delete,
+            },
+            "modify" => parse_modify(first.number, &rest)?,
+            "modrdn" | "moddn" => parse_modrdn(first.number, &rest)?,
+            other => {
+                return Err(ldif_error(
+                    first.number,
+                    format!("Unknown changetype {}", other),
+                ));
+            }
+        };
+        records.push(LdifRecord {
+            line: first.number,
+            dn,
+            change,
+        });
+    }
+    Ok(records)
+}
+
+fn parse_add(start: usize, lines: &[Line]) -> Result<LdifChange, LdapToolError> {
+    let mut attrs: Vec<(String, Vec<Vec<u8>>)> = Vec::new();
+    for line in lines {
+        match attrs
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&line.name))
+        {
+            Some((_, values)) => values.push(line.value.clone()),
+            None => attrs.push((line.name.clone(), vec![line.value.clone()])),
+        }
+    }
+    if attrs.is_empty() {
+        return Err(ldif_error(start, "An add record needs attributes"));
+    }
+    Ok(LdifChange::Add(attrs))
+}
+
+fn parse_modify(start: usize, lines: &[Line]) -> Result<LdifChange, LdapToolError> {
+    let mut mods = Vec::new();
+    let mut lines = lines.iter();
+    while let Some(line) = lines.next() {
+        let op = match line.name.to_ascii_lowercase().as_str() {
+            "add" => ModOp::Add,
+            "delete" => ModOp::Delete,
+            "replace" => ModOp::Replace,
+            _ => {
+                return Err(ldif_error(
+                    line.number,
+                    format!("Expected add:, delete: or replace:, got {}:", line.name),
+                ));
+            }
+        };
+        let attr = text_value(line)?.trim().to_string();
+        let mut values = Vec::new();
+        let mut terminated = false;
+        for value_line in lines.by_ref() {
+            if value_line.name == "-" {
+                terminated = true;
+                break;
+            }
+            if !value_line.name.eq_ignore_ascii_case(&attr) {
+                return Err(ldif_error(
+                    value_line.number,
+                    format!("Expected a value for {}, got {}:", attr, value_line.name),
+                ));
+            }
+            values.push(value_line.value.clone());
+        }
+        if !terminated {
+            return Err(ldif_error(
+                line.number,
+                format!("Missing - after the {} block", attr),
+            ));
+        }
+        if op == ModOp::Add && values.is_empty() {
+            return Err(ldif_error(
+                line.number,
+                format!("add: {} needs values", attr),
+            ));
+        }
+        mods.push(LdifMod { op, attr, values });
+    }
+    if mods.is_empty() {
+        return Err(ldif_error(start, "A modify record needs changes"));
+    }
+    Ok(LdifChange::Modify(mods))
+}
+
+fn parse_modrdn(start: usize, lines: &[Line]) -> Result<LdifChange, LdapToolError> {
+    let find = |name: &str| lines.iter().find(|l| l.name.eq_ignore_ascii_case(name));
+    let new_rdn = find("newrdn").ok_or_else(|| ldif_error(start, "modrdn needs newrdn:"))?;
+    let delete_old =
+        find("deleteoldrdn").ok_or_else(|| ldif_error(start, "modrdn needs deleteoldrdn:"))?;
+    let delete_old_rdn = match text_value(delete_old)?.trim() {
+        "0" => false,
+        "1" => true,
+        other => {
+            return Err(ldif_error(
+                delete_old.number,
+                format!("deleteoldrdn must be 0 or 1, got {}", other),
+            ));
+        }
+    };
+    if let Some(line) = lines.iter().find(|l| {
+        !["newrdn", "deleteoldrdn", "newsuperior"]
+            .iter()
+            .any(|name| l.name.eq_ignore_ascii_case(name))
+    }) {
+        return Err(ldif_error(
+            line.number,
+            format!("Unexpected {}: in a modrdn record", line.name),
+        ));
+    }
+    Ok(LdifChange::ModRdn {
+        new_rdn: text_value(new_rdn)?,
+        delete_old_rdn,
+        new_superior: find("newsuperior").map(text_value).transpose()?,
+    })
+}
+
+/// Splits a DN into its first RDN and the parent DN, honouring escaped commas.
+pub fn split_dn(dn: &str) -> (&str, Option<&str>) {
+    let mut escaped = false;
+    for (index, c) in dn.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => return (&dn[..index], Some(dn[index + 1..].trim_start())),
+            _ => escaped = false,
+        }
+    }
+    (dn, None)
+}
+
+/// The DN an entry has after a modrdn.
+pub fn renamed_dn(dn: &str, new_rdn: &str, new_superior: Option<&str>) -> String {
+    match new_superior.or(split_dn(dn).1) {
+        Some(parent) => format!("{},{}", new_rdn, parent),
+        None => new_rdn.to_string(),
+    }
+}
+
+fn has_value(entry: &SearchEntry, attr: &str, value: &[u8]) -> bool {
+    let text = std::str::from_utf8(value).ok();
+    entry
+        .attrs
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(attr))
+        .flat_map(|(_, values)| values)
+        .any(|v| text.is_some_and(|t| t.eq_ignore_ascii_case(v)))
+        || entry
+            .bin_attrs
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(attr))
+            .flat_map(|(_, values)| values)
+            .any(|v| v == value)
+}
+
+fn has_attribute(entry: &SearchEntry, attr: &str) -> bool {
+    entry
+        .attrs
+        .keys()
+        .chain(entry.bin_attrs.keys())
+        .any(|name| name.eq_ignore_ascii_case(attr))
+}
+
+/// Checks a record against the directory without changing anything. `lookup` reads an entry
+/// by DN (with all user attributes) and returns `None` when it does not exist.
+///
+/// Returns the problems found; an empty list means the record is expected to apply. Value
+/// comparisons are case-insensitive, which matches most AD string syntaxes.
+pub fn validate_record<F>(record: &LdifRecord, mut lookup: F) -> Result<Vec<String>, LdapToolError>
+where
+    F: FnMut(&str) -> Result<Option<SearchEntry>, LdapToolError>,
+{
+    let mut problems = Vec::new();
+    let current = lookup(&record.dn)?;
+    match &record.change {
+        LdifChange::Add(_) => {
+            if current.is_some() {
+                problems.push("the entry already exists".to_string());
+            }
+            if let Some(parent) = split_dn(&record.dn).1
+                && lookup(parent)?.is_none()
+            {
+                problems.push(format!("the parent {} does not exist", parent));
+            }
+        }
+        LdifChange::Delete => {
+            if current.is_none() {
+                problems.push("the entry does not exist".to_string());
+            }
+        }
+        LdifChange::Modify(mods) => match &current {
+            None => problems.push("the entry does not exist".to_string()),
+            Some(entry) => {
+                for m in mods {
+                    match m.op {
+                        ModOp::Add => {
+                            for value in &m.values {
+                                if has_value(entry, &m.attr, value) {
+                                    problems.push(format!(
+                                        "{} already has the value {}",
+                                        m.attr,
+                                        String::from_utf8_lossy(value)
+                                    ));
+                                }
+                            }
+                        }
+                        ModOp::Delete if m.values.is_empty() => {
+                            if !has_attribute(entry, &m.attr) {
+                                problems.push(format!("{} has no values to delete", m.attr));
+                            }
+                        }
+                        ModOp::Delete => {
+                            for value in &m.values {
+                                if !has_value(entry, &m.attr, value) {
+                                    problems.push(format!(
+                                        "{} does not have the value {}",
+                                        m.attr,
+                                        String::from_utf8_lossy(value)
+                                    ));
+                                }
+                            }
+                        }
+                        ModOp::Replace => {}
+                    }
+                }
+            }
+        },
+        LdifChange::ModRdn {
+            new_rdn,
+            new_superior,
+            ..
+        } => {
+            if current.is_none() {
+                problems.push("the entry does not exist".to_string());
+            }
+            if let Some(superior) = new_superior
+                && lookup(superior)?.is_none()
+            {
+                problems.push(format!("the new superior {} does not exist", superior));
+            }
+            let target = renamed_dn(&record.dn, new_rdn, new_superior.as_deref());
+            if !target.eq_ignore_ascii_case(&record.dn) && lookup(&target)?.is_some() {
+                problems.push(format!("{} already exists", target));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+fn to_mod(m: &LdifMod) -> Mod<Vec<u8>> {
+    let values: HashSet<Vec<u8>> = m.values.iter().cloned().collect();
+    let attr = m.attr.as_bytes().to_vec();
+    match m.op {
+        ModOp::Add => Mod::Add(attr, values),
+        ModOp::Delete => Mod::Delete(attr, values),
+        ModOp::Replace => Mod::Replace(attr, values),
+    }
+}
+
+/// Applies one record to the directory.
+pub fn apply_record(ldap: &mut LdapConnection, record: &LdifRecord) -> Result<(), LdapToolError> {
+    let result = match &record.change {
+        LdifChange::Add(attrs) => ldap.conn().add(
+            &record.dn,
+            attrs
+                .iter()
+                .map(|(name, values)| {
+                    (
+                        name.as_bytes().to_vec(),
+                        values.iter().cloned().collect::<HashSet<_>>(),
+                    )
+                })
+                .collect(),
+        )?,
+        LdifChange::Modify(mods) => ldap
+            .conn()
+            .modify(&record.dn, mods.iter().map(to_mod).collect())?,
+        LdifChange::Delete => ldap.conn().delete(&record.dn)?,
+        LdifChange::ModRdn {
+            new_rdn,
+            delete_old_rdn,
+            new_superior,
+        } => ldap.conn().modifydn(
+            &record.dn,
+            new_rdn,
+            *delete_old_rdn,
+            new_superior.as_deref(),
+        )?,
+    };
+    result.success()?;
+    Ok(())
+}
+
+/// Reads an entry by DN, returning `None` when it does not exist.
+pub fn lookup_entry(
+    ldap: &mut LdapConnection,
+    dn: &str,
+) -> Result<Option<SearchEntry>, LdapToolError> {
+    match search_all(ldap, dn, Scope::Base, "(objectClass=*)", &["*"]) {
+        Ok(entries) => Ok(entries.into_iter().next()),
+        // noSuchObject
+        Err(LdapToolError::Ldap(ldap3::LdapError::LdapResult { result })) if result.rc == 32 => {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn run_export_ldif() -> Result<(), LdapToolError> {
+    let base = resolve_base(
+        &get_input("Base DN (e.g. OU=Staff, empty for BASE_DN)"),
+        &base_dn()?,
+    );
+    let scope = parse_scope(&get_input("Scope (sub, one, base) [sub]"))
+        .ok_or_else(|| LdapToolError::Config("Scope must be sub, one or base".to_string()))?;
+    let filter = Some(get_input("Filter [(objectClass=*)]"))
+        .filter(|f| !f.is_empty())
+        .unwrap_or_else(|| "(objectClass=*)".to_string());
+    let attrs = attribute_list_input("Attributes [*]", &["*"])?;
+    let path = get_input("Output file");
+    if path.is_empty() {
+        return Err(LdapToolError::Config(
+            "An output file is required".to_string(),
+        ));
+    }
+
+    let mut file = std::io::BufWriter::new(
+        std::fs::File::create(&path)
+            .map_err(|e| LdapToolError::Config(format!("Failed to create {}: {}", path, e)))?,
+    );
+    let write_error =
+        |e: std::io::Error| LdapToolError::Config(format!("Failed to write {}: {}", path, e));
+    writeln!(file, "version: 1\n").map_err(write_error)?;
+
+    let mut ldap = ldap_session()?;
+    let attrs: Vec<&str> = attrs.iter().map(String::as_str).collect();
+    let mut failed = None;
+    let count = paged_search(&mut ldap, &base, scope, &filter, &attrs, |entry| {
+        if failed.is_none()
+            && let Err(e) = write_ldif_entry(&mut file, &entry)
+        {
+            failed = Some(e);
+        }
+    })?;
+    if let Some(e) = failed {
+        return Err(write_error(e));
+    }
+    file.flush().map_err(write_error)?;
+    println!("Exported {} entries to {}", count, path);
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ExportLdifTool",
+    name = "Export LDIF",
+    desc = "Exports the results of a search to an LDIF file"
+)]
+pub fn export_ldif() {
+    if let Err(e) = run_export_ldif() {
+        println!("Error: {}", e);
+    }
+}
+
+fn run_import_ldif() -> Result<(), LdapToolError> {
+    let path = get_input("LDIF file");
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| LdapToolError::Config(format!("Failed to read {}: {}", path, e)))?;
+    let records = parse_ldif(&text)?;
+    if records.is_empty() {
+        println!("No records in {}", path);
+        return Ok(());
+    }
+
+    let mut ldap = ldap_session()?;
+    println!("Checking {} record(s) against the directory", records.len());
+    let mut invalid = 0;
+    for record in &records {
+        let problems = validate_record(record, |dn| lookup_entry(&mut ldap, dn))?;
+        if problems.is_empty() {
+            println!(
+                "OK    line {}: {} {}",
+                record.line,
+                record.kind(),
+                record.dn
+            );
+        } else {
+            invalid += 1;
+            println!(
+                "FAIL  line {}: {} {}",
+                record.line,
+                record.kind(),
+                record.dn
+            );
+            for problem in problems {
+                println!("        {}", problem);
+            }
+        }
+    }
+    // Later records may depend on earlier ones (e.g. adding an OU and then its users), so a
+    // failed check is not always fatal
+    if invalid > 0 {
+        println!(
+            "{} record(s) failed the check. Records that depend on earlier ones in the file are expected to fail here.",
+            invalid
+        );
+    }
+    if !confirm(&format!("Apply {} record(s)", records.len())) {
+        println!("Dry run only, nothing changed");
+        return Ok(());
+    }
+
+    for (index, record) in records.iter().enumerate() {
+        if let Err(e) = apply_record(&mut ldap, record) {
+            println!(
+                "Stopped at line {} ({} {}): {}",
+                record.line,
+                record.kind(),
+                record.dn,
+                e
+            );
+            println!("Applied {} of {} record(s)", index, records.len());
+            return Ok(());
+        }
+        println!(
+            "Applied line {}: {} {}",
+            record.line,
+            record.kind(),
+            record.dn
+        );
+        let entry = AuditEntry::new("ldap", &format!("ldif_{}", record.kind()), &record.dn);
+        if let Err(e) = audit::record(&entry) {
+            println!("Warning: failed to write audit log: {}", e);
+        }
+    }
+    println!("Applied {} record(s)", records.len());
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ImportLdifTool",
+    name = "Import LDIF",
+    desc = "Checks an LDIF change file against the directory and applies it"
+)]
+pub fn import_ldif() {
+    if let Err(e) = run_import_ldif() {
+        println!("Error: {}", e);
+    }
+}
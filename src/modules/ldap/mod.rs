@@ -0,0 +1,126 @@
+#![allow(non_snake_case, dead_code)]
+use jh_admin_cli_macros::Module;
+use jh_admin_cli_macros::derive_tool;
+
+use ldap3::Scope;
+
+use crate::io::get_input;
+
+mod accounts;
+mod attributes;
+mod compare;
+mod computers;
+mod connection;
+mod edits;
+mod groups;
+mod ldif;
+mod orgchart;
+mod ous;
+mod passwords;
+mod policies;
+mod reports;
+mod schema;
+mod search;
+mod snapshots;
+mod users;
+
+pub use accounts::*;
+pub use attributes::*;
+pub use compare::*;
+pub use computers::*;
+pub use connection::*;
+pub use edits::*;
+pub use groups::*;
+pub use ldif::*;
+pub use orgchart::*;
+pub use ous::*;
+pub use passwords::*;
+pub use policies::*;
+pub use reports::*;
+pub use schema::*;
+pub use search::*;
+pub use snapshots::*;
+pub use users::*;
+
+#[derive(Module)]
+#[module(name = "LDAP Management Module", desc = "Manage LDAP users and groups")]
+pub struct LDAPModule;
+
+#[derive_tool(
+    id = "FetchUsersTool",
+    name = "Fetch Users",
+    desc = "Lists all users in the system"
+)]
+pub fn fetch_all_users() {
+    let mut ldap = match ldap_session() {
+        Ok(ldap) => ldap,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let base_dn = match base_dn() {
+        Ok(base_dn) => base_dn,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+
+    let result = paged_search(
+        &mut ldap,
+        &base_dn,
+        Scope::Subtree,
+        USER_FILTER,
+        &["sAMAccountName", "name"],
+        |entry| {
+            println!(
+                "{} - {}",
+                first_value(&entry, "sAMAccountName").unwrap_or_default(),
+                first_value(&entry, "name").unwrap_or_default()
+            );
+        },
+    );
+    match result {
+        Ok(count) => println!("Total: {} user(s)", count),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+fn run_show_entry() -> Result<(), LdapToolError> {
+    let name = get_input("Object (sAMAccountName, group name or DN)");
+    if name.is_empty() {
+        return Err(LdapToolError::Config("An object is required".to_string()));
+    }
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let dn = find_member(&mut ldap, &base, &name)?.dn;
+    // Constructed attributes are only returned when asked for by name
+    let entry = search_all(
+        &mut ldap,
+        &dn,
+        Scope::Base,
+        "(objectClass=*)",
+        &[
+            "*",
+            "msDS-User-Account-Control-Computed",
+            "msDS-UserPasswordExpiryTimeComputed",
+        ],
+    )?
+    .into_iter()
+    .next()
+    .ok_or_else(|| LdapToolError::NotFound(dn.clone()))?;
+    let _ = write_entry(&mut std::io::stdout(), &entry);
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ShowEntryTool",
+    name = "Show Entry",
+    desc = "Shows all attributes of a directory object, decoded for display"
+)]
+pub fn show_entry() {
+    if let Err(e) = run_show_entry() {
+        println!("Error: {}", e);
+    }
+}
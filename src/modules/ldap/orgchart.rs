@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Scope, SearchEntry};
+use serde::Serialize;
+
+use super::connection::{LdapToolError, base_dn, ldap_session};
+use super::search::{first_value, paged_search, values};
+use super::uac;
+use super::users::{USER_FILTER, optional_input, resolve_base};
+use crate::io::get_input;
+
+const PERSON_ATTRS: [&str; 7] = [
+    "sAMAccountName",
+    "displayName",
+    "title",
+    "department",
+    "manager",
+    "directReports",
+    "userAccountControl",
+];
+
+/// A user account as it appears in the org chart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Person {
+    pub dn: String,
+    pub account_name: String,
+    pub display_name: String,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub manager: Option<String>,
+    pub direct_reports: Vec<String>,
+    pub enabled: bool,
+}
+
+impl Person {
+    pub fn from_entry(entry: &SearchEntry) -> Self {
+        let text = |attr| first_value(entry, attr).map(str::to_string);
+        let account_name = text("sAMAccountName").unwrap_or_default();
+        Self {
+            dn: entry.dn.clone(),
+            display_name: text("displayName").unwrap_or_else(|| account_name.clone()),
+            account_name,
+            title: text("title"),
+            department: text("department"),
+            manager: text("manager"),
+            direct_reports: values(entry, "directReports").to_vec(),
+            enabled: first_value(entry, "userAccountControl")
+                .and_then(|v| v.parse::<u32>().ok())
+                .is_none_or(|flags| flags & uac::ACCOUNTDISABLE == 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanReason {
+    /// The manager is not among the users read, e.g. deleted or outside the search base
+    Missing,
+    Disabled,
+}
+
+/// An enabled user whose manager is missing or disabled.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Orphan {
+    pub dn: String,
+    pub manager: String,
+    pub reason: OrphanReason,
+}
+
+/// One person and everyone below them, for the JSON export.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OrgNode {
+    pub dn: String,
+    pub account_name: String,
+    pub name: String,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub enabled: bool,
+    pub reports: Vec<OrgNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OrgChartExport {
+    pub roots: Vec<OrgNode>,
+    pub orphans: Vec<Orphan>,
+    pub cycles: Vec<Vec<String>>,
+}
+
+/// The reporting hierarchy built from `manager` and `directReports`.
+///
+/// Disabled users are only shown when someone still reports to them.
+#[derive(Debug)]
+pub struct OrgChart {
+    /// Sorted by display name, so reports are listed alphabetically
+    people: Vec<Person>,
+    manager_of: Vec<Option<usize>>,
+    reports: Vec<Vec<usize>>,
+    /// People without a known manager, plus one member of every cycle
+    roots: Vec<usize>,
+    pub orphans: Vec<Orphan>,
+    /// DNs of the people in each management cycle, each reporting to the next
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl OrgChart {
+    pub fn build(mut people: Vec<Person>) -> Self {
+        people.sort_by_key(|p| (p.display_name.to_lowercase(), p.dn.to_lowercase()));
+        // DNs in `manager` and `directReports` may differ in case from the entry DN
+        let index: HashMap<String, usize> = people
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.dn.to_lowercase(), i))
+            .collect();
+        let find = |dn: &str| index.get(&dn.to_lowercase()).copied();
+
+        let mut manager_of: Vec<Option<usize>> = people
+            .iter()
+            .map(|p| p.manager.as_deref().and_then(find))
+            .collect();
+        // `directReports` is the back link of `manager`; it only adds links for reports whose
+        // `manager` could not be read
+        for (i, person) in people.iter().enumerate() {
+            for report in person.direct_reports.iter().filter_map(|dn| find(dn)) {
+                if people[report].manager.is_none() {
+                    manager_of[report] = Some(i);
+                }
+            }
+        }
+        let mut reports = vec![Vec::new(); people.len()];
+        for (i, manager) in manager_of.iter().enumerate() {
+            if let Some(manager) = manager {
+                reports[*manager].push(i);
+            }
+        }
+
+        let orphans = people
+            .iter()
+            .zip(&manager_of)
+            .filter(|(person, _)| person.enabled)
+            .filter_map(|(person, manager)| {
+                let reason = match manager {
+                    None => OrphanReason::Missing,
+                    Some(m) if !people[*m].enabled => OrphanReason::Disabled,
+                    Some(_) => return None,
+                };
+                Some(Orphan {
+                    dn: person.dn.clone(),
+                    manager: person.manager.clone()?,
+                    reason,
+                })
+            })
+            .collect();
+
+        // Every person has at most one manager, so following the manager links from each
+        // person either ends at the top or runs into a cycle
+        const NEW: u8 = 0;
+        const ON_PATH: u8 = 1;
+        const DONE: u8 = 2;
+        let mut state = vec![NEW; people.len()];
+        let mut cycles: Vec<Vec<usize>> = Vec::new();
+        for start in 0..people.len() {
+            let mut path = Vec::new();
+            let mut current = Some(start);
+            while let Some(i) = current {
+                match state[i] {
+                    NEW => {
+                        state[i] = ON_PATH;
+                        path.push(i);
+                        current = manager_of[i];
+                    }
+                    ON_PATH => {
+                        let at = path.iter().position(|&p| p == i).unwrap_or_default();
+                        // Start at the first person by name, each reporting to the next
+                        let mut cycle = path[at..].to_vec();
+                        let first = (0..cycle.len()).min_by_key(|&c| cycle[c]).unwrap_or(0);
+                        cycle.rotate_left(first);
+                        cycles.push(cycle);
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+            for i in path {
+                state[i] = DONE;
+            }
+        }
+
+        let mut roots: Vec<usize> = (0..people.len())
+            .filter(|&i| manager_of[i].is_none())
+            .chain(cycles.iter().map(|cycle| cycle[0]))
+            .collect();
+        roots.sort_unstable();
+        let cycles = cycles
+            .iter()
+            .map(|cycle| cycle.iter().map(|&i| people[i].dn.clone()).collect())
+            .collect();
+        Self {
+            people,
+            manager_of,
+            reports,
+            roots,
+            orphans,
+            cycles,
+        }
+    }
+
+    fn is_shown(&self, i: usize) -> bool {
+        self.people[i].enabled || !self.reports[i].is_empty()
+    }
+
+    /// Visits the shown people depth-first from the roots, each one once.
+    fn walk(&self, mut visit: impl FnMut(usize, usize)) {
+        fn step(
+            chart: &OrgChart,
+            i: usize,
+            depth: usize,
+            seen: &mut [bool],
+            visit: &mut dyn FnMut(usize, usize),
+        ) {
+            if seen[i] || !chart.is_shown(i) {
+                return;
+            }
+            seen[i] = true;
+            visit(i, depth);
+            for &report in &chart.reports[i] {
+                step(chart, report, depth + 1, seen, visit);
+            }
+        }
+        let mut seen = vec![false; self.people.len()];
+        for &root in &self.roots {
+            step(self, root, 0, &mut seen, &mut visit);
+        }
+    }
+
+    fn label(&self, i: usize) -> String {
+        let person = &self.people[i];
+        let mut label = format!("{} ({})", person.display_name, person.account_name);
+        if let Some(title) = &person.title {
+            label.push_str(&format!(", {}", title));
+        }
+        if !person.enabled {
+            label.push_str(" [disabled]");
+        }
+        if let Some(orphan) = self.orphans.iter().find(|o| o.dn == person.dn) {
+            label.push_str(match orphan.reason {
+                OrphanReason::Missing => " [manager not found]",
+                OrphanReason::Disabled => " [manager disabled]",
+            });
+        }
+        if self.cycles.iter().any(|cycle| cycle.contains(&person.dn)) {
+            label.push_str(" [cycle]");
+        }
+        label
+    }
+
+    /// Writes the hierarchy as an indented tree.
+    pub fn write_tree(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let mut result = Ok(());
+        self.walk(|i, depth| {
+            if result.is_ok() {
+                result = writeln!(out, "{}{}", "    ".repeat(depth), self.label(i));
+            }
+        });
+        result
+    }
+
+    /// The hierarchy as a Graphviz digraph, with an edge from each manager to each report.
+    pub fn to_dot(&self) -> String {
+        fn quote(value: &str) -> String {
+            format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        let mut dot = String::from("digraph orgchart {\n    node [shape=box];\n");
+        for (i, person) in self.people.iter().enumerate() {
+            if !self.is_shown(i) {
+                continue;
+            }
+            let mut label = person.display_name.clone();
+            if let Some(title) = &person.title {
+                label.push('\n');
+                label.push_str(title);
+            }
+            let style = if person.enabled { "" } else { ", style=dashed" };
+            dot.push_str(&format!(
+                "    {} [label={}{}];\n",
+                quote(&person.dn),
+                quote(&label).replace('\n', "\\n"),
+                style
+            ));
+        }
+        for (i, manager) in self.manager_of.iter().enumerate() {
+            if let Some(manager) = manager.filter(|_| self.is_shown(i)) {
+                dot.push_str(&format!(
+                    "    {} -> {};\n",
+                    quote(&self.people[manager].dn),
+                    quote(&self.people[i].dn)
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn export(&self) -> OrgChartExport {
+        // The open path from a root down to the person visited last
+        let mut path: Vec<OrgNode> = Vec::new();
+        let mut roots = Vec::new();
+        // Closes the subtrees at `depth` and below, moving each into its manager
+        let mut close = |path: &mut Vec<OrgNode>, depth: usize| {
+            while path.len() > depth {
+                let Some(node) = path.pop() else { break };
+                match path.last_mut() {
+                    Some(manager) => manager.reports.push(node),
+                    None => roots.push(node),
+                }
+            }
+        };
+        self.walk(|i, depth| {
+            close(&mut path, depth);
+            let person = &self.people[i];
+            path.push(OrgNode {
+                dn: person.dn.clone(),
+                account_name: person.account_name.clone(),
+                name: person.display_name.clone(),
+                title: person.title.clone(),
+                department: person.department.clone(),
+                enabled: person.enabled,
+                reports: Vec::new(),
+            });
+        });
+        close(&mut path, 0);
+        OrgChartExport {
+            roots,
+            orphans: self.orphans.clone(),
+            cycles: self.cycles.clone(),
+        }
+    }
+}
+
+fn run_org_chart() -> Result<(), LdapToolError> {
+    let base = resolve_base(
+        &get_input("Base DN (e.g. OU=Staff, empty for BASE_DN)"),
+        &base_dn()?,
+    );
+    let mut ldap = ldap_session()?;
+    let mut people = Vec::new();
+    paged_search(
+        &mut ldap,
+        &base,
+        Scope::Subtree,
+        USER_FILTER,
+        &PERSON_ATTRS,
+        |entry| people.push(Person::from_entry(&entry)),
+    )?;
+    let chart = OrgChart::build(people);
+
+    chart
+        .write_tree(&mut std::io::stdout())
+        .map_err(|e| LdapToolError::Config(e.to_string()))?;
+    for orphan in &chart.orphans {
+        let reason = match orphan.reason {
+            OrphanReason::Missing => "not found",
+            OrphanReason::Disabled => "disabled",
+        };
+        println!(
+            "Orphan: {} (manager {} {})",
+            orphan.dn, orphan.manager, reason
+        );
+    }
+    for cycle in &chart.cycles {
+        println!("Cycle: {} -> {}", cycle.join(" -> "), cycle[0]);
+    }
+    println!(
+        "{} orphan(s), {} cycle(s)",
+        chart.orphans.len(),
+        chart.cycles.len()
+    );
+
+    let write = |path: String, contents: String| {
+        std::fs::write(&path, contents)
+            .map(|_| println!("Saved {}", path))
+            .map_err(|e| LdapToolError::Config(format!("Failed to write {}: {}", path, e)))
+    };
+    if let Some(path) = optional_input("Graphviz DOT file (empty to skip)") {
+        write(path, chart.to_dot())?;
+    }
+    if let Some(path) = optional_input("JSON file (empty to skip)") {
+        let json = serde_json::to_string_pretty(&chart.export())
+            .map_err(|e| LdapToolError::Config(format!("Failed to encode org chart: {}", e)))?;
+        write(path, json)?;
+    }
+    Ok(())
+}
+
+#[derive_tool(
+    id = "OrgChartTool",
+    name = "Org Chart",
+    desc = "Builds the reporting hierarchy from manager attributes, reports orphans and cycles, and exports DOT or JSON"
+)]
+pub fn org_chart() {
+    if let Err(e) = run_org_chart() {
+        println!("Error: {}", e);
+    }
+}
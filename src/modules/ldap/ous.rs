@@ -0,0 +1,438 @@
+use std::io::Write;
+
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::groups::find_object;
+use super::ldif::{renamed_dn, split_dn};
+use super::search::{first_value, paged_search, search_all, values};
+use super::users::{required_input, resolve_base};
+use crate::audit::{self, AuditEntry};
+use crate::io::{clear_screen, confirm, get_input, is_quit, select_index};
+
+/// Number of matches listed before a bulk move asks for confirmation.
+const PREVIEW_LIMIT: usize = 20;
+
+/// The objects a move may touch. OUs and containers are left out: moving one would also move
+/// everything below it, including matches a bulk move collected before it.
+pub const MOVABLE_FILTER: &str =
+    "(|(objectCategory=person)(objectCategory=computer)(objectClass=group))";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectKind {
+    /// An OU or container that can hold other objects
+    Container,
+    User,
+    Group,
+    Computer,
+    Other,
+}
+
+impl ObjectKind {
+    /// Classifies an object by its `objectClass` values. Computers are checked before users
+    /// since computer objects are also of class `user`.
+    pub fn from_classes(classes: &[String]) -> Self {
+        let has = |class: &str| classes.iter().any(|c| c.eq_ignore_ascii_case(class));
+        if has("organizationalUnit") || has("container") || has("builtinDomain") {
+            ObjectKind::Container
+        } else if has("computer") {
+            ObjectKind::Computer
+        } else if has("group") {
+            ObjectKind::Group
+        } else if has("user") || has("inetOrgPerson") {
+            ObjectKind::User
+        } else {
+            ObjectKind::Other
+        }
+    }
+}
+
+/// A direct child of an OU.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChildObject {
+    pub dn: String,
+    pub name: String,
+    pub kind: ObjectKind,
+}
+
+/// Number of objects directly in an OU, by kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectCounts {
+    pub users: usize,
+    pub groups: usize,
+    pub computers: usize,
+    pub other: usize,
+}
+
+impl std::fmt::Display for ObjectCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} users, {} groups, {} computers",
+            self.users, self.groups, self.computers
+        )?;
+        if self.other > 0 {
+            write!(f, ", {} other", self.other)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OuNode {
+    dn: String,
+    name: String,
+    depth: usize,
+    expanded: bool,
+    /// Child containers (as node indexes) and object counts, once loaded
+    contents: Option<(Vec<usize>, ObjectCounts)>,
+}
+
+/// The OU tree shown by the browser. Containers are loaded lazily, one level at a time.
+#[derive(Debug, Clone)]
+pub struct OuTree {
+    nodes: Vec<OuNode>,
+}
+
+impl OuTree {
+    pub fn new(root_dn: &str) -> Self {
+        Self {
+            nodes: vec![OuNode {
+                dn: root_dn.to_string(),
+                name: root_dn.to_string(),
+                depth: 0,
+                expanded: false,
+                contents: None,
+            }],
+        }
+    }
+
+    /// Node indexes in display order: each expanded node is followed by its children.
+    pub fn visible(&self) -> Vec<usize> {
+        let mut visible = Vec::new();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            visible.push(index);
+            let node = &self.nodes[index];
+            if let (true, Some((children, _))) = (node.expanded, &node.contents) {
+                stack.extend(children.iter().rev());
+            }
+        }
+        visible
+    }
+
+    pub fn dn(&self, index: usize) -> &str {
+        &self.nodes[index].dn
+    }
+
+    /// Loads the children of `index` with `load` (unless already loaded) and counts them.
+    fn load<F>(&mut self, index: usize, load: &mut F) -> Result<(), LdapToolError>
+    where
+        F: FnMut(&str) -> Result<Vec<ChildObject>, LdapToolError>,
+    {
+        if self.nodes[index].contents.is_some() {
+            return Ok(());
+        }
+        let mut objects = load(&self.nodes[index].dn)?;
+        objects.sort_by_key(|o| o.name.to_lowercase());
+        let mut counts = ObjectCounts::default();
+        let mut children = Vec::new();
+        for object in objects {
+            match object.kind {
+                ObjectKind::Container => {
+                    children.push(self.nodes.len());
+                    self.nodes.push(OuNode {
+                        dn: object.dn,
+                        name: object.name,
+                        depth: self.nodes[index].depth + 1,
+                        expanded: false,
+                        contents: None,
+                    });
+                }
+                ObjectKind::User => counts.users += 1,
+                ObjectKind::Group => counts.groups += 1,
+                ObjectKind::Computer => counts.computers += 1,
+                ObjectKind::Other => counts.other += 1,
+            }
+        }
+        self.nodes[index].contents = Some((children, counts));
+        Ok(())
+    }
+
+    /// Expands or collapses a node, loading its children the first time.
+    pub fn toggle<F>(&mut self, index: usize, load: &mut F) -> Result<(), LdapToolError>
+    where
+        F: FnMut(&str) -> Result<Vec<ChildObject>, LdapToolError>,
+    {
+        self.load(index, load)?;
+        self.nodes[index].expanded = !self.nodes[index].expanded;
+        Ok(())
+    }
+
+    /// Loads every visible node so counts can be shown for all of them.
+    pub fn load_visible<F>(&mut self, load: &mut F) -> Result<(), LdapToolError>
+    where
+        F: FnMut(&str) -> Result<Vec<ChildObject>, LdapToolError>,
+    {
+        for index in self.visible() {
+            self.load(index, load)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the visible nodes, numbered for selection.
+    pub fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        for (number, index) in self.visible().into_iter().enumerate() {
+            let node = &self.nodes[index];
+            let marker = match &node.contents {
+                Some((children, _)) if children.is_empty() => " ",
+                _ if node.expanded => "-",
+                _ => "+",
+            };
+            write!(
+                out,
+                "{:>3}. {}[{}] {}",
+                number + 1,
+                "  ".repeat(node.depth),
+                marker,
+                node.name
+            )?;
+            match &node.contents {
+                Some((_, counts)) => writeln!(out, " ({})", counts)?,
+                None => writeln!(out)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists the direct children of `dn`.
+pub fn list_children(
+    ldap: &mut LdapConnection,
+    dn: &str,
+) -> Result<Vec<ChildObject>, LdapToolError> {
+    Ok(search_all(
+        ldap,
+        dn,
+        Scope::OneLevel,
+        "(objectClass=*)",
+        &["objectClass", "name"],
+    )?
+    .into_iter()
+    .map(|entry| ChildObject {
+        name: first_value(&entry, "name")
+            .map(str::to_string)
+            .unwrap_or_else(|| split_dn(&entry.dn).0.to_string()),
+        kind: ObjectKind::from_classes(values(&entry, "objectClass")),
+        dn: entry.dn,
+    })
+    .collect())
+}
+
+fn run_browse_ous() -> Result<(), LdapToolError> {
+    let root = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let mut load = |dn: &str| list_children(&mut ldap, dn);
+    let mut tree = OuTree::new(&root);
+    tree.toggle(0, &mut load)?;
+
+    loop {
+        clear_screen();
+        tree.load_visible(&mut load)?;
+        println!("OU tree (counts are objects directly in each OU)");
+        println!("Enter a number to expand or collapse it, Q to go back");
+        let _ = tree.write(&mut std::io::stdout());
+
+        let input = get_input("Select");
+        if is_quit(&input) {
+            return Ok(());
+        }
+        let visible = tree.visible();
+        match select_index(&input, visible.len()) {
+            Some(number) => tree.toggle(visible[number - 1], &mut load)?,
+            None => println!("Invalid selection"),
+        }
+    }
+}
+
+#[derive_tool(
+    id = "BrowseOusTool",
+    name = "Browse OUs",
+    desc = "Browses the OU tree with the number of users, groups and computers in each OU"
+)]
+pub fn browse_ous() {
+    if let Err(e) = run_browse_ous() {
+        println!("Error: {}", e);
+    }
+}
+
+/// Whether `target` is `dn` itself or lies below it, which would make a move impossible.
+pub fn is_within(target: &str, dn: &str) -> bool {
+    let (target, dn) = (target.to_ascii_lowercase(), dn.to_ascii_lowercase());
+    target == dn || target.ends_with(&format!(",{}", dn))
+}
+
+/// Moves an object to `new_parent`, keeping its RDN. Returns the new DN.
+pub fn move_object(
+    ldap: &mut LdapConnection,
+    dn: &str,
+    new_parent: &str,
+) -> Result<String, LdapToolError> {
+    if is_within(new_parent, dn) {
+        return Err(LdapToolError::Config(format!(
+            "Cannot move {} into itself",
+            dn
+        )));
+    }
+    let rdn = split_dn(dn).0;
+    ldap.conn()
+        .modifydn(dn, rdn, true, Some(new_parent))?
+        .success()?;
+    Ok(renamed_dn(dn, rdn, Some(new_parent)))
+}
+
+fn record_move(before: &str, after: &str) {
+    let entry =
+        AuditEntry::new("ldap", "move_object", after).with_change(Some(before), Some(after));
+    if let Err(e) = audit::record(&entry) {
+        println!("Warning: failed to write audit log: {}", e);
+    }
+}
+
+/// Looks up the target OU and checks that it exists.
+fn resolve_target(ldap: &mut LdapConnection, base: &str) -> Result<String, LdapToolError> {
+    let target = resolve_base(
+        &required_input("Target OU (e.g. OU=Leavers or a full DN)")?,
+        base,
+    );
+    search_all(ldap, &target, Scope::Base, "(objectClass=*)", &["1.1"])
+        .map_err(|_| LdapToolError::NotFound(format!("OU {}", target)))?;
+    Ok(target)
+}
+
+/// Finds a user, group or computer to move by `sAMAccountName` or `cn`, or by DN when `name` is
+/// one.
+pub fn find_movable(
+    ldap: &mut LdapConnection,
+    base: &str,
+    name: &str,
+) -> Result<SearchEntry, LdapToolError> {
+    find_object(ldap, base, name, MOVABLE_FILTER, "User, group or computer")
+}
+
+fn run_move_object() -> Result<(), LdapToolError> {
+    let name = required_input("Object (sAMAccountName, group name or DN)")?;
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let object = find_movable(&mut ldap, &base, &name)?;
+    let target = resolve_target(&mut ldap, &base)?;
+    if !confirm(&format!("Move {} to {}", object.dn, target)) {
+        println!("Aborted");
+        return Ok(());
+    }
+    let new_dn = move_object(&mut ldap, &object.dn, &target)?;
+    println!("Moved to {}", new_dn);
+    record_move(&object.dn, &new_dn);
+    Ok(())
+}
+
+#[derive_tool(
+    id = "MoveObjectTool",
+    name = "Move Object",
+    desc = "Moves a user, group or computer to another OU"
+)]
+pub fn move_directory_object() {
+    if let Err(e) = run_move_object() {
+        println!("Error: {}", e);
+    }
+}
+
+/// DNs of the users, groups and computers below `search_base` that match `filter` and are not
+/// already in `target`.
+pub fn bulk_move_candidates(
+    ldap: &mut LdapConnection,
+    search_base: &str,
+    filter: &str,
+    target: &str,
+) -> Result<Vec<String>, LdapToolError> {
+    let mut matches = Vec::new();
+    paged_search(
+        ldap,
+        search_base,
+        Scope::Subtree,
+        &format!("(&{}{})", MOVABLE_FILTER, filter),
+        &["objectClass"],
+        |entry| {
+            let kind = ObjectKind::from_classes(values(&entry, "objectClass"));
+            // Objects already in the target OU stay where they are
+            let in_target = split_dn(&entry.dn)
+                .1
+                .is_some_and(|parent| parent.eq_ignore_ascii_case(target));
+            if matches!(
+                kind,
+                ObjectKind::User | ObjectKind::Group | ObjectKind::Computer
+            ) && !in_target
+            {
+                matches.push(entry.dn);
+            }
+        },
+    )?;
+    Ok(matches)
+}
+
+fn run_bulk_move() -> Result<(), LdapToolError> {
+    let base = base_dn()?;
+    let search_base = resolve_base(
+        &get_input("Search base (e.g. OU=Staff, empty for BASE_DN)"),
+        &base,
+    );
+    let filter = required_input("Filter (e.g. (&(objectClass=user)(department=Sales)))")?;
+    if !filter.starts_with('(') || !filter.ends_with(')') {
+        return Err(LdapToolError::Config(
+            "The filter must be enclosed in parentheses".to_string(),
+        ));
+    }
+    let mut ldap = ldap_session()?;
+    let target = resolve_target(&mut ldap, &base)?;
+
+    let matches = bulk_move_candidates(&mut ldap, &search_base, &filter, &target)?;
+    if matches.is_empty() {
+        println!("No objects to move");
+        return Ok(());
+    }
+    for dn in matches.iter().take(PREVIEW_LIMIT) {
+        println!("  {}", dn);
+    }
+    if matches.len() > PREVIEW_LIMIT {
+        println!("  ... and {} more", matches.len() - PREVIEW_LIMIT);
+    }
+    if !confirm(&format!("Move {} object(s) to {}", matches.len(), target)) {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    let mut moved = 0;
+    for dn in &matches {
+        match move_object(&mut ldap, dn, &target) {
+            Ok(new_dn) => {
+                record_move(dn, &new_dn);
+                moved += 1;
+            }
+            Err(e) => println!("Skipped {}: {}", dn, e),
+        }
+    }
+    println!("Moved {} of {} object(s)", moved, matches.len());
+    Ok(())
+}
+
+#[derive_tool(
+    id = "BulkMoveTool",
+    name = "Bulk Move Objects",
+    desc = "Moves every user, group and computer matching a filter to another OU"
+)]
+pub fn bulk_move_objects() {
+    if let Err(e) = run_bulk_move() {
+        println!("Error: {}", e);
+    }
+}
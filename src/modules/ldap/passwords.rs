@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+
+use chrono::TimeDelta;
+use jh_admin_cli_macros::derive_tool;
+use ldap3::Mod;
+
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::policies::{RESULTANT_PSO, resultant_policy};
+use super::search::first_value;
+use super::users::{find_user, required_input, set_password};
+use crate::audit::{self, AuditEntry};
+use crate::io::{confirm, get_secret_input};
+
+/// Length of generated passwords when the domain asks for less.
+pub const GENERATED_LENGTH: usize = 16;
+
+const UPPER: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijkmnopqrstuvwxyz";
+const DIGITS: &str = "23456789";
+const SYMBOLS: &str = "!#$%&*+-=?@^_";
+
+/// The parts of a password policy that matter when choosing a password; see
+/// `PolicySettings::password_policy`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub complexity: bool,
+    /// `maxPwdAge`, `None` when passwords never expire
+    pub max_age: Option<TimeDelta>,
+}
+
+impl PasswordPolicy {
+    /// Checks `password` the way AD does before sending it, so obvious rejections get a clear
+    /// message. The DC still applies history and minimum age checks on its own.
+    ///
+    /// With complexity enabled a password needs three of: uppercase, lowercase, digits,
+    /// symbols and other (non-ASCII) letters, and must not contain the account name or any
+    /// part of the display name that is at least three characters long.
+    pub fn check(
+        &self,
+        password: &str,
+        account_name: &str,
+        display_name: &str,
+    ) -> Result<(), String> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(format!(
+                "The password must be at least {} characters long",
+                self.min_length
+            ));
+        }
+        if !self.complexity {
+            return Ok(());
+        }
+
+        let categories = [
+            password.chars().any(|c| c.is_ascii_uppercase()),
+            password.chars().any(|c| c.is_ascii_lowercase()),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+            password.chars().any(|c| c.is_alphabetic() && !c.is_ascii()),
+        ];
+        if categories.iter().filter(|c| **c).count() < 3 {
+            return Err(
+                "The password must contain three of: uppercase letters, lowercase letters, digits and symbols"
+                    .to_string(),
+            );
+        }
+
+        let lower = password.to_lowercase();
+        if account_name.chars().count() >= 3 && lower.contains(&account_name.to_lowercase()) {
+            return Err("The password must not contain the account name".to_string());
+        }
+        let separators = [',', '.', '-', '_', '#', ' ', '\t'];
+        if let Some(part) = display_name
+            .split(separators)
+            .find(|part| part.chars().count() >= 3 && lower.contains(&part.to_lowercase()))
+        {
+            return Err(format!(
+                "The password must not contain \"{}\" from the display name",
+                part
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns a uniformly random index below `n`.
+fn random_index(n: usize) -> Result<usize, LdapToolError> {
+    const RANGE: usize = 1 << 16;
+    // Reject values above the largest multiple of `n` to avoid modulo bias
+    let limit = RANGE - RANGE % n;
+    loop {
+        let mut bytes = [0u8; 2];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| LdapToolError::Config(format!("No random source available: {}", e)))?;
+        let value = u16::from_le_bytes(bytes) as usize;
+        if value < limit {
+            return Ok(value % n);
+        }
+    }
+}
+
+fn random_char(set: &str) -> Result<char, LdapToolError> {
+    Ok(set.as_bytes()[random_index(set.len())?] as char)
+}
+
+/// Generates a random password satisfying `policy`, with at least one character of each class.
+///
+/// Ambiguous characters (`0`/`O`, `1`/`l`/`I`) are left out so passwords can be read out.
+pub fn generate_password(policy: &PasswordPolicy) -> Result<String, LdapToolError> {
+    let length = policy.min_length.max(GENERATED_LENGTH);
+    let all: String = [UPPER, LOWER, DIGITS, SYMBOLS].concat();
+
+    let mut chars = Vec::with_capacity(length);
+    for set in [UPPER, LOWER, DIGITS, SYMBOLS] {
+        chars.push(random_char(set)?);
+    }
+    while chars.len() < length {
+        chars.push(random_char(&all)?);
+    }
+    // Fisher-Yates, so the guaranteed characters are not always at the start
+    for i in (1..chars.len()).rev() {
+        chars.swap(i, random_index(i + 1)?);
+    }
+    Ok(chars.into_iter().collect())
+}
+
+/// Sets a new password and optionally requires a change at next logon.
+pub fn reset_password(
+    ldap: &mut LdapConnection,
+    dn: &str,
+    password: &str,
+    must_change: bool,
+) -> Result<(), LdapToolError> {
+    set_password(ldap, dn, password)?;
+    if must_change {
+        ldap.conn()
+            .modify(dn, vec![Mod::Replace("pwdLastSet", HashSet::from(["0"]))])?
+            .success()?;
+    }
+    Ok(())
+}
+
+fn run_reset_password() -> Result<(), LdapToolError> {
+    let account_name = required_input("sAMAccountName")?;
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    if !ldap.is_encrypted() {
+        return Err(LdapToolError::InsecureConnection(
+            "Resetting a password".to_string(),
+        ));
+    }
+
+    let user = find_user(
+        &mut ldap,
+        &base,
+        &account_name,
+        &["displayName", RESULTANT_PSO],
+    )?;
+    let display_name = first_value(&user, "displayName")
+        .unwrap_or_default()
+        .to_string();
+    let settings = resultant_policy(&mut ldap, &user)?;
+    let policy = settings.password_policy();
+    println!(
+        "{}: minimum length {}, complexity {}",
+        settings.name(),
+        policy.min_length,
+        if policy.complexity {
+            "required"
+        } else {
+            "not required"
+        }
+    );
+
+    let input = get_secret_input("New password (leave empty to generate one)");
+    let generated = input.is_empty();
+    let password = if generated {
+        generate_password(&policy)?
+    } else {
+        input
+    };
+    policy
+        .check(&password, &account_name, &display_name)
+        .map_err(LdapToolError::Config)?;
+    let must_change = confirm("Require a password change at next logon");
+
+    println!("Resetting the password of {}", user.dn);
+    if !confirm("Continue") {
+        println!("Aborted");
+        return Ok(());
+    }
+    reset_password(&mut ldap, &user.dn, &password, must_change)?;
+    println!("Password reset");
+    if generated {
+        println!("New password: {}", password);
+    }
+
+    let entry = AuditEntry::new("ldap", "reset_password", &user.dn)
+        .with_change(None, must_change.then_some("pwdLastSet=0"));
+    if let Err(e) = audit::record(&entry) {
+        println!("Warning: failed to write audit log: {}", e);
+    }
+    Ok(())
+}
+
+#[derive_tool(
+    id = "ResetPasswordTool",
+    name = "Reset Password",
+    desc = "Resets a user's password, optionally generating one that meets the domain policy"
+)]
+pub fn reset_user_password() {
+    if let Err(e) = run_reset_password() {
+        println!("Error: {}", e);
+    }
+}
@@ -0,0 +1,365 @@
+//! Password and lockout policies: the domain policy that Group Policy writes to the domain root,
+//! and fine-grained Password Settings Objects (PSOs) that override it for users and groups.
+
+use chrono::TimeDelta;
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::ldif::split_dn;
+use super::passwords::PasswordPolicy;
+use super::schema::read_root_dse;
+use super::search::{first_value, format_table, search_all, values};
+use super::users::{find_user, optional_input};
+use super::{format_interval, parse_interval, pwd_properties};
+
+/// Location of the PSOs below the domain root.
+pub const PSO_CONTAINER: &str = "CN=Password Settings Container,CN=System";
+/// Constructed attribute naming the PSO that applies to a user, absent when the domain policy
+/// applies. It is only returned when asked for by name.
+pub const RESULTANT_PSO: &str = "msDS-ResultantPSO";
+
+const DOMAIN_POLICY_ATTRS: [&str; 8] = [
+    "minPwdLength",
+    "pwdHistoryLength",
+    "pwdProperties",
+    "minPwdAge",
+    "maxPwdAge",
+    "lockoutThreshold",
+    "lockoutDuration",
+    "lockOutObservationWindow",
+];
+
+const PSO_ATTRS: [&str; 12] = [
+    "cn",
+    "msDS-PasswordSettingsPrecedence",
+    "msDS-MinimumPasswordLength",
+    "msDS-PasswordHistoryLength",
+    "msDS-PasswordComplexityEnabled",
+    "msDS-PasswordReversibleEncryptionEnabled",
+    "msDS-MinimumPasswordAge",
+    "msDS-MaximumPasswordAge",
+    "msDS-LockoutThreshold",
+    "msDS-LockoutDuration",
+    "msDS-LockoutObservationWindow",
+    "msDS-PSOAppliesTo",
+];
+
+/// Where a policy comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicySource {
+    /// The domain root, set by the Default Domain Policy GPO
+    Domain,
+    /// A Password Settings Object; the lowest precedence wins
+    Pso {
+        precedence: u32,
+        /// Users and groups the PSO is linked to
+        applies_to: Vec<String>,
+    },
+}
+
+/// The password and lockout settings of one policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicySettings {
+    pub dn: String,
+    pub source: PolicySource,
+    pub min_length: u32,
+    pub history_length: u32,
+    pub complexity: bool,
+    pub reversible_encryption: bool,
+    /// `None` when passwords can be changed again straight away
+    pub min_age: Option<TimeDelta>,
+    /// `None` when passwords never expire
+    pub max_age: Option<TimeDelta>,
+    /// Failed attempts before the account is locked out, 0 for never
+    pub lockout_threshold: u32,
+    /// `None` when a locked account stays locked until an administrator unlocks it
+    pub lockout_duration: Option<TimeDelta>,
+    /// Period after which the failed attempt count is reset
+    pub lockout_window: Option<TimeDelta>,
+}
+
+fn number(entry: &SearchEntry, attr: &str) -> u32 {
+    first_value(entry, attr)
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+fn flag(entry: &SearchEntry, attr: &str) -> bool {
+    first_value(entry, attr).is_some_and(|v| v.eq_ignore_ascii_case("TRUE"))
+}
+
+fn interval(entry: &SearchEntry, attr: &str) -> Option<TimeDelta> {
+    first_value(entry, attr).and_then(parse_interval)
+}
+
+impl PolicySettings {
+    /// Reads the policy from the attributes of the domain root.
+    pub fn from_domain(entry: &SearchEntry) -> Self {
+        let properties = number(entry, "pwdProperties");
+        Self {
+            dn: entry.dn.clone(),
+            source: PolicySource::Domain,
+            min_length: number(entry, "minPwdLength"),
+            history_length: number(entry, "pwdHistoryLength"),
+            complexity: properties & pwd_properties::DOMAIN_PASSWORD_COMPLEX != 0,
+            reversible_encryption: properties & pwd_properties::DOMAIN_PASSWORD_STORE_CLEARTEXT
+                != 0,
+            min_age: interval(entry, "minPwdAge"),
+            max_age: interval(entry, "maxPwdAge"),
+            lockout_threshold: number(entry, "lockoutThreshold"),
+            lockout_duration: interval(entry, "lockoutDuration"),
+            lockout_window: interval(entry, "lockOutObservationWindow"),
+        }
+    }
+
+    /// Reads the policy from an `msDS-PasswordSettings` object.
+    pub fn from_pso(entry: &SearchEntry) -> Self {
+        Self {
+            dn: entry.dn.clone(),
+            source: PolicySource::Pso {
+                precedence: number(entry, "msDS-PasswordSettingsPrecedence"),
+                applies_to: values(entry, "msDS-PSOAppliesTo").to_vec(),
+            },
+            min_length: number(entry, "msDS-MinimumPasswordLength"),
+            history_length: number(entry, "msDS-PasswordHistoryLength"),
+            complexity: flag(entry, "msDS-PasswordComplexityEnabled"),
+            reversible_encryption: flag(entry, "msDS-PasswordReversibleEncryptionEnabled"),
+            min_age: interval(entry, "msDS-MinimumPasswordAge"),
+            max_age: interval(entry, "msDS-MaximumPasswordAge"),
+            lockout_threshold: number(entry, "msDS-LockoutThreshold"),
+            lockout_duration: interval(entry, "msDS-LockoutDuration"),
+            lockout_window: interval(entry, "msDS-LockoutObservationWindow"),
+        }
+    }
+
+    /// `Domain policy`, or the PSO's name.
+    pub fn name(&self) -> String {
+        match self.source {
+            PolicySource::Domain => "Domain policy".to_string(),
+            PolicySource::Pso { .. } => {
+                let rdn = split_dn(&self.dn).0;
+                rdn.split_once('=')
+                    .map_or(rdn, |(_, value)| value)
+                    .to_string()
+            }
+        }
+    }
+
+    /// The settings that password checks and generation need.
+    pub fn password_policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: self.min_length as usize,
+            complexity: self.complexity,
+            max_age: self.max_age,
+        }
+    }
+
+    /// The settings as `(setting, value)` rows for display.
+    pub fn describe(&self) -> Vec<Vec<String>> {
+        let duration = |interval: Option<TimeDelta>, none: &str| {
+            interval.map_or(none.to_string(), format_interval)
+        };
+        let lockout = |value: String| {
+            if self.lockout_threshold == 0 {
+                "-".to_string()
+            } else {
+                value
+            }
+        };
+        let mut rows = vec![
+            ("Minimum password length", self.min_length.to_string()),
+            (
+                "Password history",
+                format!("{} passwords remembered", self.history_length),
+            ),
+            (
+                "Complexity",
+                if self.complexity {
+                    "required"
+                } else {
+                    "not required"
+                }
+                .to_string(),
+            ),
+            (
+                "Reversible encryption",
+                if self.reversible_encryption {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+                .to_string(),
+            ),
+            ("Minimum password age", duration(self.min_age, "none")),
+            (
+                "Maximum password age",
+                duration(self.max_age, "never expires"),
+            ),
+            (
+                "Lockout threshold",
+                match self.lockout_threshold {
+                    0 => "never locks out".to_string(),
+                    n => format!("{} failed attempts", n),
+                },
+            ),
+            (
+                "Lockout duration",
+                lockout(duration(
+                    self.lockout_duration,
+                    "until an administrator unlocks the account",
+                )),
+            ),
+            (
+                "Reset failed attempts after",
+                lockout(duration(self.lockout_window, "none")),
+            ),
+        ];
+        if let PolicySource::Pso {
+            precedence,
+            applies_to,
+        } = &self.source
+        {
+            rows.insert(0, ("Precedence", precedence.to_string()));
+            rows.push(("Applies to", applies_to.join("; ")));
+        }
+        rows.into_iter()
+            .map(|(setting, value)| vec![setting.to_string(), value])
+            .collect()
+    }
+}
+
+/// DN of the domain root, from `defaultNamingContext` in the rootDSE. The policy is read from
+/// there rather than from `BASE_DN`, which may name an OU.
+pub fn domain_root(ldap: &mut LdapConnection) -> Result<String, LdapToolError> {
+    let root = read_root_dse(ldap)?;
+    first_value(&root, "defaultNamingContext")
+        .map(str::to_string)
+        .ok_or_else(|| {
+            LdapToolError::Config(
+                "The server does not advertise a defaultNamingContext".to_string(),
+            )
+        })
+}
+
+/// Reads the domain password and lockout policy from the domain root.
+pub fn domain_policy(ldap: &mut LdapConnection) -> Result<PolicySettings, LdapToolError> {
+    let domain_dn = domain_root(ldap)?;
+    search_all(
+        ldap,
+        &domain_dn,
+        Scope::Base,
+        "(objectClass=*)",
+        &DOMAIN_POLICY_ATTRS,
+    )?
+    .into_iter()
+    .next()
+    .map(|entry| PolicySettings::from_domain(&entry))
+    .ok_or_else(|| LdapToolError::NotFound(format!("Domain {}", domain_dn)))
+}
+
+/// Reads the PSOs of the domain, lowest precedence first. Only administrators can read them by
+/// default, so an empty list may also mean they are hidden.
+pub fn password_settings(ldap: &mut LdapConnection) -> Result<Vec<PolicySettings>, LdapToolError> {
+    let container = format!("{},{}", PSO_CONTAINER, domain_root(ldap)?);
+    let entries = match search_all(
+        ldap,
+        &container,
+        Scope::Subtree,
+        "(objectClass=msDS-PasswordSettings)",
+        &PSO_ATTRS,
+    ) {
+        Ok(entries) => entries,
+        // noSuchObject: no container, e.g. a directory that is not AD
+        Err(LdapToolError::Ldap(ldap3::LdapError::LdapResult { result })) if result.rc == 32 => {
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    let mut psos: Vec<PolicySettings> = entries.iter().map(PolicySettings::from_pso).collect();
+    psos.sort_by_key(|pso| match pso.source {
+        PolicySource::Pso { precedence, .. } => (precedence, pso.name().to_lowercase()),
+        PolicySource::Domain => (u32::MAX, String::new()),
+    });
+    Ok(psos)
+}
+
+/// The policy that applies to `user`, which must have been read with `msDS-ResultantPSO`:
+/// the PSO it names, or the domain policy.
+pub fn resultant_policy(
+    ldap: &mut LdapConnection,
+    user: &SearchEntry,
+) -> Result<PolicySettings, LdapToolError> {
+    let Some(pso) = first_value(user, RESULTANT_PSO) else {
+        return domain_policy(ldap);
+    };
+    search_all(ldap, pso, Scope::Base, "(objectClass=*)", &PSO_ATTRS)?
+        .into_iter()
+        .next()
+        .map(|entry| PolicySettings::from_pso(&entry))
+        .ok_or_else(|| LdapToolError::NotFound(format!("Password Settings Object {}", pso)))
+}
+
+fn run_password_policy_report() -> Result<(), LdapToolError> {
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let domain = domain_policy(&mut ldap)?;
+    let psos = password_settings(&mut ldap)?;
+
+    let mut headers = vec!["Setting".to_string(), domain.name()];
+    headers.extend(psos.iter().map(PolicySettings::name));
+    let columns: Vec<Vec<Vec<String>>> = std::iter::once(&domain)
+        .chain(&psos)
+        .map(PolicySettings::describe)
+        .collect();
+    // Settings in the order PSOs list them, with blanks where the domain policy has none
+    let rows: Vec<Vec<String>> = columns
+        .iter()
+        .max_by_key(|rows| rows.len())
+        .into_iter()
+        .flatten()
+        .map(|row| {
+            let setting = &row[0];
+            let mut cells = vec![setting.clone()];
+            cells.extend(columns.iter().map(|rows| {
+                rows.iter()
+                    .find(|other| &other[0] == setting)
+                    .map(|other| other[1].clone())
+                    .unwrap_or_default()
+            }));
+            cells
+        })
+        .collect();
+    print!("{}", format_table(&headers, &rows));
+    if psos.is_empty() {
+        println!("No Password Settings Objects found (reading them needs administrator rights)");
+    }
+
+    let Some(account_name) =
+        optional_input("sAMAccountName to show the policy for (empty to skip)")
+    else {
+        return Ok(());
+    };
+    let user = find_user(&mut ldap, &base, &account_name, &[RESULTANT_PSO])?;
+    let policy = resultant_policy(&mut ldap, &user)?;
+    match policy.source {
+        PolicySource::Domain => println!("{} uses the domain policy", user.dn),
+        PolicySource::Pso { .. } => println!("{} uses {} ({})", user.dn, policy.name(), policy.dn),
+    }
+    print!(
+        "{}",
+        format_table(&["Setting", "Value"], &policy.describe())
+    );
+    Ok(())
+}
+
+#[derive_tool(
+    id = "PasswordPolicyTool",
+    name = "Password Policies",
+    desc = "Shows the domain password policy and Password Settings Objects, and the policy that applies to a user"
+)]
+pub fn password_policy_report() {
+    if let Err(e) = run_password_policy_report() {
+        println!("Error: {}", e);
+    }
+}
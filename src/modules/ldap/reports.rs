@@ -0,0 +1,268 @@
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Scope, SearchEntry};
+
+use super::connection::{LdapToolError, base_dn, ldap_session};
+use super::policies::domain_policy;
+use super::search::{first_value, format_csv, format_table, paged_search};
+use super::users::USER_FILTER;
+use super::{filetime_to_datetime, uac};
+use crate::io::{confirm, get_input};
+
+const REPORT_ATTRS: [&str; 7] = [
+    "sAMAccountName",
+    "displayName",
+    "userAccountControl",
+    "lastLogonTimestamp",
+    "pwdLastSet",
+    "accountExpires",
+    "msDS-UserPasswordExpiryTimeComputed",
+];
+
+const REPORT_HEADERS: [&str; 8] = [
+    "sAMAccountName",
+    "displayName",
+    "enabled",
+    "lastLogon",
+    "passwordLastSet",
+    "passwordExpires",
+    "accountExpires",
+    "findings",
+];
+
+/// Thresholds for the account report.
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    /// Accounts without a logon for this many days are stale
+    pub stale_days: i64,
+    /// Passwords expiring within this many days are reported
+    pub expiry_days: i64,
+    pub include_disabled: bool,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self {
+            stale_days: 90,
+            expiry_days: 14,
+            include_disabled: false,
+        }
+    }
+}
+
+/// Something in an account that needs attention.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Finding {
+    NeverLoggedIn,
+    Stale {
+        days: i64,
+    },
+    PasswordExpiring {
+        days: i64,
+    },
+    PasswordExpired,
+    /// `pwdLastSet` is 0: the password must be changed at next logon
+    PasswordMustChange,
+    PasswordNeverExpires,
+    AccountExpired,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finding::NeverLoggedIn => write!(f, "never logged in"),
+            Finding::Stale { days } => write!(f, "no logon for {} days", days),
+            Finding::PasswordExpiring { days } => write!(f, "password expires in {} days", days),
+            Finding::PasswordExpired => write!(f, "password expired"),
+            Finding::PasswordMustChange => write!(f, "must change password"),
+            Finding::PasswordNeverExpires => write!(f, "password never expires"),
+            Finding::AccountExpired => write!(f, "account expired"),
+        }
+    }
+}
+
+/// The logon, password and expiry state of one user account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountReport {
+    pub account_name: String,
+    pub display_name: String,
+    pub enabled: bool,
+    /// `lastLogonTimestamp`, which AD only replicates when it is more than ~14 days old
+    pub last_logon: Option<DateTime<Utc>>,
+    pub password_last_set: Option<DateTime<Utc>>,
+    pub password_expires: Option<DateTime<Utc>>,
+    pub account_expires: Option<DateTime<Utc>>,
+    pub findings: Vec<Finding>,
+}
+
+fn filetime(entry: &SearchEntry, attr: &str) -> Option<i64> {
+    first_value(entry, attr).and_then(|v| v.parse().ok())
+}
+
+impl AccountReport {
+    /// Evaluates an account at `now`.
+    ///
+    /// The password expiry is taken from `msDS-UserPasswordExpiryTimeComputed` when the server
+    /// provides it, since that accounts for fine-grained password policies. Otherwise it is
+    /// `pwdLastSet` plus the domain `maxPwdAge`.
+    pub fn evaluate(
+        entry: &SearchEntry,
+        now: DateTime<Utc>,
+        max_password_age: Option<TimeDelta>,
+        options: &ReportOptions,
+    ) -> Self {
+        let account_control = filetime(entry, "userAccountControl").unwrap_or(0) as u32;
+        let never_expires = account_control & uac::DONT_EXPIRE_PASSWORD != 0;
+        let pwd_last_set = filetime(entry, "pwdLastSet");
+        let last_logon = filetime(entry, "lastLogonTimestamp").and_then(filetime_to_datetime);
+        let password_last_set = pwd_last_set.and_then(filetime_to_datetime);
+        let password_expires = if never_expires {
+            None
+        } else {
+            match filetime(entry, "msDS-UserPasswordExpiryTimeComputed") {
+                Some(computed) => filetime_to_datetime(computed),
+                None => password_last_set
+                    .zip(max_password_age)
+                    .map(|(set, age)| set + age),
+            }
+        };
+        let account_expires = filetime(entry, "accountExpires").and_then(filetime_to_datetime);
+
+        let mut findings = Vec::new();
+        match last_logon {
+            None => findings.push(Finding::NeverLoggedIn),
+            Some(logon) => {
+                let days = (now - logon).num_days();
+                if days >= options.stale_days {
+                    findings.push(Finding::Stale { days });
+                }
+            }
+        }
+        if never_expires {
+            findings.push(Finding::PasswordNeverExpires);
+        } else if pwd_last_set == Some(0) {
+            findings.push(Finding::PasswordMustChange);
+        } else if let Some(expires) = password_expires {
+            if expires <= now {
+                findings.push(Finding::PasswordExpired);
+            } else {
+                let days = (expires - now).num_days();
+                if days < options.expiry_days {
+                    findings.push(Finding::PasswordExpiring { days });
+                }
+            }
+        }
+        if account_expires.is_some_and(|expires| expires <= now) {
+            findings.push(Finding::AccountExpired);
+        }
+
+        Self {
+            account_name: first_value(entry, "sAMAccountName")
+                .unwrap_or_default()
+                .to_string(),
+            display_name: first_value(entry, "displayName")
+                .unwrap_or_default()
+                .to_string(),
+            enabled: account_control & uac::ACCOUNTDISABLE == 0,
+            last_logon,
+            password_last_set,
+            password_expires,
+            account_expires,
+            findings,
+        }
+    }
+
+    pub fn row(&self) -> Vec<String> {
+        let date = |time: Option<DateTime<Utc>>| match time {
+            Some(time) => time
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => String::new(),
+        };
+        vec![
+            self.account_name.clone(),
+            self.display_name.clone(),
+            if self.enabled { "yes" } else { "no" }.to_string(),
+            date(self.last_logon),
+            date(self.password_last_set),
+            date(self.password_expires),
+            date(self.account_expires),
+            self.findings
+                .iter()
+                .map(Finding::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ]
+    }
+}
+
+/// Evaluates all accounts and keeps the ones with findings.
+pub fn account_report(
+    entries: impl IntoIterator<Item = SearchEntry>,
+    now: DateTime<Utc>,
+    max_password_age: Option<TimeDelta>,
+    options: &ReportOptions,
+) -> Vec<AccountReport> {
+    entries
+        .into_iter()
+        .map(|entry| AccountReport::evaluate(&entry, now, max_password_age, options))
+        .filter(|report| options.include_disabled || report.enabled)
+        .filter(|report| !report.findings.is_empty())
+        .collect()
+}
+
+fn prompt_days(message: &str, default: i64) -> Result<i64, LdapToolError> {
+    let input = get_input(&format!("{} [{}]", message, default));
+    if input.is_empty() {
+        return Ok(default);
+    }
+    input
+        .parse()
+        .map_err(|_| LdapToolError::Config(format!("{} must be a number of days", message)))
+}
+
+fn run_account_report() -> Result<(), LdapToolError> {
+    let defaults = ReportOptions::default();
+    let options = ReportOptions {
+        stale_days: prompt_days("Days without logon", defaults.stale_days)?,
+        expiry_days: prompt_days("Days until password expiry", defaults.expiry_days)?,
+        include_disabled: confirm("Include disabled accounts"),
+    };
+    let base = base_dn()?;
+    let mut ldap = ldap_session()?;
+    let policy = domain_policy(&mut ldap)?.password_policy();
+
+    let mut entries = Vec::new();
+    paged_search(
+        &mut ldap,
+        &base,
+        Scope::Subtree,
+        USER_FILTER,
+        &REPORT_ATTRS,
+        |entry| entries.push(entry),
+    )?;
+    let reports = account_report(entries, Utc::now(), policy.max_age, &options);
+    let rows: Vec<Vec<String>> = reports.iter().map(AccountReport::row).collect();
+    print!("{}", format_table(&REPORT_HEADERS, &rows));
+    println!("Total: {} account(s) with findings", reports.len());
+
+    let path = get_input("Export to CSV file (leave empty to skip)");
+    if !path.is_empty() {
+        std::fs::write(&path, format_csv(&REPORT_HEADERS, &rows))
+            .map_err(|e| LdapToolError::Config(format!("Failed to write {}: {}", path, e)))?;
+        println!("Wrote {}", path);
+    }
+    Ok(())
+}
+
+#[derive_tool(
+    id = "AccountReportTool",
+    name = "Stale Account and Password Report",
+    desc = "Reports stale accounts, expiring or never-expiring passwords and expired accounts"
+)]
+pub fn stale_account_report() {
+    if let Err(e) = run_account_report() {
+        println!("Error: {}", e);
+    }
+}
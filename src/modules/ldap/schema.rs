@@ -0,0 +1,823 @@
+//! The directory schema, read from the subschema subentry advertised in the rootDSE, used to
+//! look up attributes and object classes and to check and complete attribute names.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError, ldap_session};
+use super::search::{first_value, format_table, search_all, values};
+use super::users::parse_columns;
+use crate::io::{get_completed_input, is_quit};
+
+/// Names of common LDAP and Active Directory attribute syntaxes.
+const SYNTAXES: [(&str, &str); 18] = [
+    ("1.3.6.1.4.1.1466.115.121.1.5", "Binary"),
+    ("1.3.6.1.4.1.1466.115.121.1.7", "Boolean"),
+    ("1.3.6.1.4.1.1466.115.121.1.12", "DN"),
+    ("1.3.6.1.4.1.1466.115.121.1.15", "Directory String"),
+    ("1.3.6.1.4.1.1466.115.121.1.24", "Generalized Time"),
+    ("1.3.6.1.4.1.1466.115.121.1.26", "IA5 String"),
+    ("1.3.6.1.4.1.1466.115.121.1.27", "Integer"),
+    ("1.3.6.1.4.1.1466.115.121.1.36", "Numeric String"),
+    ("1.3.6.1.4.1.1466.115.121.1.38", "OID"),
+    ("1.3.6.1.4.1.1466.115.121.1.40", "Octet String"),
+    ("1.3.6.1.4.1.1466.115.121.1.44", "Printable String"),
+    ("1.3.6.1.4.1.1466.115.121.1.50", "Telephone Number"),
+    ("1.3.6.1.4.1.1466.115.121.1.53", "UTC Time"),
+    ("1.2.840.113556.1.4.903", "DN-Binary"),
+    ("1.2.840.113556.1.4.904", "DN-String"),
+    ("1.2.840.113556.1.4.905", "Teletex String"),
+    ("1.2.840.113556.1.4.906", "Large Integer"),
+    ("1.2.840.113556.1.4.907", "NT Security Descriptor"),
+];
+
+/// Attribute names accepted in attribute lists that are not attribute types.
+const SPECIAL_ATTRIBUTES: [&str; 4] = ["*", "+", "1.1", "dn"];
+
+/// An attribute type definition (RFC 4512 section 4.1.2).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AttributeType {
+    pub oid: String,
+    pub names: Vec<String>,
+    pub description: Option<String>,
+    pub superior: Option<String>,
+    /// Syntax OID, without a length bound such as `{64}`
+    pub syntax: Option<String>,
+    pub single_value: bool,
+    /// Set for attributes the server maintains, such as `memberOf` in AD
+    pub no_user_modification: bool,
+}
+
+impl AttributeType {
+    /// The first name, or the OID of an unnamed attribute.
+    pub fn name(&self) -> &str {
+        self.names.first().unwrap_or(&self.oid)
+    }
+
+    /// The syntax by name where it is a well-known one, otherwise its OID.
+    pub fn syntax_name(&self) -> &str {
+        match &self.syntax {
+            Some(oid) => SYNTAXES
+                .iter()
+                .find(|(syntax, _)| syntax == oid)
+                .map_or(oid.as_str(), |(_, name)| name),
+            None => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ClassKind {
+    Abstract,
+    #[default]
+    Structural,
+    Auxiliary,
+}
+
+impl ClassKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ClassKind::Abstract => "abstract",
+            ClassKind::Structural => "structural",
+            ClassKind::Auxiliary => "auxiliary",
+        }
+    }
+}
+
+/// An object class definition (RFC 4512 section 4.1.1).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjectClass {
+    pub oid: String,
+    pub names: Vec<String>,
+    pub description: Option<String>,
+    pub superiors: Vec<String>,
+    pub kind: ClassKind,
+    pub must: Vec<String>,
+    pub may: Vec<String>,
+    /// Auxiliary classes allowed by the DIT content rule for this class, as AD publishes them
+    pub auxiliary: Vec<String>,
+}
+
+impl ObjectClass {
+    pub fn name(&self) -> &str {
+        self.names.first().unwrap_or(&self.oid)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Dollar,
+    Word(String),
+    Quoted(String),
+}
+
+/// Decodes the `\27` and `\5C` escapes of a quoted string.
+fn unescape(value: &str) -> String {
+    value
+        .replace("\\27", "'")
+        .replace("\\5C", "\\")
+        .replace("\\5c", "\\")
+}
+
+fn tokenize(definition: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = definition.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => tokens.push(Token::Dollar),
+            '\'' => {
+                let end = definition[start + 1..]
+                    .find('\'')
+                    .map(|i| start + 1 + i)
+                    .ok_or("unterminated quoted string")?;
+                tokens.push(Token::Quoted(unescape(&definition[start + 1..end])));
+                while chars.next_if(|(i, _)| *i <= end).is_some() {}
+            }
+            _ => {
+                let mut end = definition.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '$' | '\'') {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                tokens.push(Token::Word(definition[start..end].to_string()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Walks the tokens of one definition: `( oid KEYWORD value ... )`.
+struct Definition {
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl Definition {
+    fn parse(definition: &str) -> Result<(String, Self), String> {
+        let mut tokens = tokenize(definition)?.into_iter();
+        if tokens.next() != Some(Token::Open) || tokens.as_slice().last() != Some(&Token::Close) {
+            return Err("not enclosed in parentheses".to_string());
+        }
+        let mut definition = Self { tokens };
+        let oid = definition.single()?;
+        Ok((oid, definition))
+    }
+
+    /// The next keyword, or `None` at the closing parenthesis.
+    fn keyword(&mut self) -> Result<Option<String>, String> {
+        match self.tokens.next() {
+            Some(Token::Word(keyword)) => Ok(Some(keyword.to_ascii_uppercase())),
+            Some(Token::Close) => Ok(None),
+            other => Err(format!("expected a keyword, found {:?}", other)),
+        }
+    }
+
+    /// A single name, OID or quoted string.
+    fn single(&mut self) -> Result<String, String> {
+        match self.tokens.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => Ok(value),
+            other => Err(format!("expected a value, found {:?}", other)),
+        }
+    }
+
+    /// One value, or a parenthesized list separated by `$` or spaces.
+    fn list(&mut self) -> Result<Vec<String>, String> {
+        if self.tokens.as_slice().first() != Some(&Token::Open) {
+            return Ok(vec![self.single()?]);
+        }
+        self.tokens.next();
+        let mut values = Vec::new();
+        loop {
+            match self.tokens.next() {
+                Some(Token::Word(value) | Token::Quoted(value)) => values.push(value),
+                Some(Token::Dollar) => {}
+                Some(Token::Close) => return Ok(values),
+                other => return Err(format!("unterminated list, found {:?}", other)),
+            }
+        }
+    }
+}
+
+/// Parses an `attributeTypes` value.
+pub fn parse_attribute_type(definition: &str) -> Result<AttributeType, LdapToolError> {
+    let invalid = |reason: String| {
+        LdapToolError::Config(format!("Invalid attribute type {}: {}", definition, reason))
+    };
+    let (oid, mut parts) = Definition::parse(definition).map_err(invalid)?;
+    let mut attribute = AttributeType {
+        oid,
+        ..AttributeType::default()
+    };
+    while let Some(keyword) = parts.keyword().map_err(invalid)? {
+        match keyword.as_str() {
+            "NAME" => attribute.names = parts.list().map_err(invalid)?,
+            "DESC" => attribute.description = Some(parts.single().map_err(invalid)?),
+            "SUP" => attribute.superior = Some(parts.single().map_err(invalid)?),
+            "SYNTAX" => {
+                let syntax = parts.single().map_err(invalid)?;
+                let oid = syntax.split('{').next().unwrap_or_default();
+                attribute.syntax = Some(oid.to_string());
+            }
+            "SINGLE-VALUE" => attribute.single_value = true,
+            "NO-USER-MODIFICATION" => attribute.no_user_modification = true,
+            "OBSOLETE" | "COLLECTIVE" => {}
+            _ => {
+                parts.list().map_err(invalid)?;
+            }
+        }
+    }
+    Ok(attribute)
+}
+
+/// Parses an `objectClasses` value.
+pub fn parse_object_class(definition: &str) -> Result<ObjectClass, LdapToolError> {
+    let invalid = |reason: String| {
+        LdapToolError::Config(format!("Invalid object class {}: {}", definition, reason))
+    };
+    let (oid, mut parts) = Definition::parse(definition).map_err(invalid)?;
+    let mut class = ObjectClass {
+        oid,
+        ..ObjectClass::default()
+    };
+    while let Some(keyword) = parts.keyword().map_err(invalid)? {
+        match keyword.as_str() {
+            "NAME" => class.names = parts.list().map_err(invalid)?,
+            "DESC" => class.description = Some(parts.single().map_err(invalid)?),
+            "SUP" => class.superiors = parts.list().map_err(invalid)?,
+            "MUST" => class.must = parts.list().map_err(invalid)?,
+            "MAY" => class.may = parts.list().map_err(invalid)?,
+            "AUX" => class.auxiliary = parts.list().map_err(invalid)?,
+            "ABSTRACT" => class.kind = ClassKind::Abstract,
+            "STRUCTURAL" => class.kind = ClassKind::Structural,
+            "AUXILIARY" => class.kind = ClassKind::Auxiliary,
+            "OBSOLETE" => {}
+            _ => {
+                parts.list().map_err(invalid)?;
+            }
+        }
+    }
+    Ok(class)
+}
+
+/// An attribute an object class requires or allows, directly or through its superiors and
+/// auxiliary classes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassAttribute {
+    pub name: String,
+    pub required: bool,
+    /// The class that lists the attribute
+    pub class: String,
+}
+
+/// The attribute types and object classes of a directory.
+#[derive(Debug, Default)]
+pub struct Schema {
+    attributes: Vec<AttributeType>,
+    classes: Vec<ObjectClass>,
+    /// Lowercased names and OIDs to positions in `attributes` and `classes`
+    attribute_index: HashMap<String, usize>,
+    class_index: HashMap<String, usize>,
+    /// Definitions that could not be parsed and were left out
+    pub skipped: Vec<String>,
+}
+
+impl Schema {
+    /// Builds the schema from the `attributeTypes`, `objectClasses` and `dITContentRules`
+    /// values of a subschema subentry. Definitions that cannot be parsed are skipped.
+    pub fn from_definitions(
+        attribute_types: &[String],
+        object_classes: &[String],
+        content_rules: &[String],
+    ) -> Self {
+        let mut schema = Schema::default();
+        for definition in attribute_types {
+            match parse_attribute_type(definition) {
+                Ok(attribute) => schema.attributes.push(attribute),
+                Err(_) => schema.skipped.push(definition.clone()),
+            }
+        }
+        for definition in object_classes {
+            match parse_object_class(definition) {
+                Ok(class) => schema.classes.push(class),
+                Err(_) => schema.skipped.push(definition.clone()),
+            }
+        }
+        schema
+            .attributes
+            .sort_by_key(|a| a.name().to_ascii_lowercase());
+        schema
+            .classes
+            .sort_by_key(|c| c.name().to_ascii_lowercase());
+        schema.reindex();
+        // A content rule has the OID of its structural class and lists its auxiliary classes
+        for definition in content_rules {
+            match parse_object_class(definition) {
+                Ok(rule) => {
+                    if let Some(&position) = schema.class_index.get(&rule.oid.to_ascii_lowercase())
+                    {
+                        schema.classes[position].auxiliary.extend(rule.auxiliary);
+                    }
+                }
+                Err(_) => schema.skipped.push(definition.clone()),
+            }
+        }
+        schema
+    }
+
+    fn reindex(&mut self) {
+        self.attribute_index.clear();
+        for (position, attribute) in self.attributes.iter().enumerate() {
+            for key in attribute.names.iter().chain([&attribute.oid]) {
+                self.attribute_index
+                    .insert(key.to_ascii_lowercase(), position);
+            }
+        }
+        self.class_index.clear();
+        for (position, class) in self.classes.iter().enumerate() {
+            for key in class.names.iter().chain([&class.oid]) {
+                self.class_index.insert(key.to_ascii_lowercase(), position);
+            }
+        }
+    }
+
+    /// Attribute types, sorted by name.
+    pub fn attributes(&self) -> &[AttributeType] {
+        &self.attributes
+    }
+
+    /// Object classes, sorted by name.
+    pub fn classes(&self) -> &[ObjectClass] {
+        &self.classes
+    }
+
+    /// Looks up an attribute type by any of its names or its OID, ignoring case and options
+    /// such as `;binary`.
+    pub fn attribute(&self, name: &str) -> Option<&AttributeType> {
+        let name = name.split(';').next().unwrap_or_default();
+        self.attribute_index
+            .get(&name.to_ascii_lowercase())
+            .map(|&position| &self.attributes[position])
+    }
+
+    pub fn class(&self, name: &str) -> Option<&ObjectClass> {
+        self.class_index
+            .get(&name.to_ascii_lowercase())
+            .map(|&position| &self.classes[position])
+    }
+
+    /// Every attribute of `class`, including those of its superiors and auxiliary classes.
+    /// An attribute required anywhere is required. Required attributes come first.
+    pub fn class_attributes(&self, class: &str) -> Result<Vec<ClassAttribute>, LdapToolError> {
+        let start = self
+            .class(class)
+            .ok_or_else(|| LdapToolError::NotFound(format!("Object class {}", class)))?;
+        let mut attributes: Vec<ClassAttribute> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![start];
+        while let Some(class) = pending.pop() {
+            if !visited.insert(class.oid.clone()) {
+                continue;
+            }
+            let listed = class
+                .must
+                .iter()
+                .map(|name| (name, true))
+                .chain(class.may.iter().map(|name| (name, false)));
+            for (name, required) in listed {
+                let name = self.attribute(name).map_or(name.as_str(), |a| a.name());
+                match attributes
+                    .iter_mut()
+                    .find(|a| a.name.eq_ignore_ascii_case(name))
+                {
+                    Some(existing) if required && !existing.required => {
+                        existing.required = true;
+                        existing.class = class.name().to_string();
+                    }
+                    Some(_) => {}
+                    None => attributes.push(ClassAttribute {
+                        name: name.to_string(),
+                        required,
+                        class: class.name().to_string(),
+                    }),
+                }
+            }
+            pending.extend(
+                class
+                    .superiors
+                    .iter()
+                    .chain(&class.auxiliary)
+                    .filter_map(|name| self.class(name)),
+            );
+        }
+        attributes.sort_by(|a, b| {
+            b.required.cmp(&a.required).then_with(|| {
+                a.name
+                    .to_ascii_lowercase()
+                    .cmp(&b.name.to_ascii_lowercase())
+            })
+        });
+        Ok(attributes)
+    }
+
+    /// Classes that list `attribute` in MUST or MAY, directly rather than through a superior.
+    pub fn classes_with(&self, attribute: &str) -> Vec<(&ObjectClass, bool)> {
+        let Some(attribute) = self.attribute(attribute) else {
+            return Vec::new();
+        };
+        let is_it = |name: &String| self.attribute(name).is_some_and(|a| a.oid == attribute.oid);
+        self.classes
+            .iter()
+            .filter_map(|class| {
+                if class.must.iter().any(is_it) {
+                    Some((class, true))
+                } else if class.may.iter().any(is_it) {
+                    Some((class, false))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Attribute names, for completion.
+    pub fn attribute_names(&self) -> Vec<String> {
+        self.attributes
+            .iter()
+            .flat_map(|a| a.names.iter().cloned())
+            .collect()
+    }
+
+    /// The known attribute closest to `name`, allowing a couple of typos.
+    pub fn suggest(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.attributes
+            .iter()
+            .flat_map(|a| a.names.iter())
+            .map(|candidate| {
+                (
+                    edit_distance(&name, &candidate.to_ascii_lowercase()),
+                    candidate,
+                )
+            })
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate.as_str())
+    }
+
+    /// Checks that each name is an attribute type of this schema.
+    pub fn check_attributes(&self, names: &[String]) -> Result<(), LdapToolError> {
+        for name in names {
+            if SPECIAL_ATTRIBUTES
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(name))
+                || self.attribute(name).is_some()
+            {
+                continue;
+            }
+            return Err(LdapToolError::Config(match self.suggest(name) {
+                Some(suggestion) => {
+                    format!("Unknown attribute {} (did you mean {}?)", name, suggestion)
+                }
+                None => format!("Unknown attribute {}", name),
+            }));
+        }
+        Ok(())
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Reads the rootDSE with its operational attributes.
+pub fn read_root_dse(ldap: &mut LdapConnection) -> Result<SearchEntry, LdapToolError> {
+    search_all(ldap, "", Scope::Base, "(objectClass=*)", &["*", "+"])?
+        .into_iter()
+        .next()
+        .ok_or_else(|| LdapToolError::NotFound("rootDSE".to_string()))
+}
+
+/// Reads the schema from the subschema subentry named in the rootDSE.
+pub fn read_schema(ldap: &mut LdapConnection) -> Result<Schema, LdapToolError> {
+    let root = read_root_dse(ldap)?;
+    let subentry = first_value(&root, "subschemaSubentry").ok_or_else(|| {
+        LdapToolError::Config("The server does not advertise a subschema subentry".to_string())
+    })?;
+    let entry = search_all(
+        ldap,
+        subentry,
+        Scope::Base,
+        "(objectClass=subschema)",
+        &["attributeTypes", "objectClasses", "dITContentRules"],
+    )?
+    .into_iter()
+    .next()
+    .ok_or_else(|| LdapToolError::NotFound(format!("Subschema subentry {}", subentry)))?;
+    Ok(Schema::from_definitions(
+        values(&entry, "attributeTypes"),
+        values(&entry, "objectClasses"),
+        values(&entry, "dITContentRules"),
+    ))
+}
+
+/// The schema of the selected directory, read once per run.
+static SCHEMA: Mutex<Option<Arc<Schema>>> = Mutex::new(None);
+/// Set when the server answered but the schema could not be read, so prompts do not try again.
+static SCHEMA_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Reads the schema with `ldap`, or returns the copy read earlier.
+pub fn load_schema(ldap: &mut LdapConnection) -> Result<Arc<Schema>, LdapToolError> {
+    let mut cached = SCHEMA.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(schema) = cached.as_ref() {
+        return Ok(Arc::clone(schema));
+    }
+    let schema = Arc::new(read_schema(ldap)?);
+    *cached = Some(Arc::clone(&schema));
+    Ok(schema)
+}
+
+/// The schema for completing and checking attribute names, or `None` when it cannot be read,
+/// in which case names are only checked for valid characters.
+///
+/// A connection failure is tried again the next time; any other failure is remembered for the
+/// rest of the run.
+pub(super) fn directory_schema() -> Option<Arc<Schema>> {
+    if let Some(schema) = SCHEMA.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return Some(Arc::clone(schema));
+    }
+    if SCHEMA_UNAVAILABLE.load(Ordering::Relaxed) {
+        return None;
+    }
+    match ldap_session().and_then(|mut ldap| load_schema(&mut ldap)) {
+        Ok(schema) => Some(schema),
+        Err(e) => {
+            if !e.is_connection_failure() {
+                SCHEMA_UNAVAILABLE.store(true, Ordering::Relaxed);
+            }
+            println!(
+                "Warning: the directory schema could not be read, attribute names are not checked: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Prompts for input in which Tab completes attribute names from the directory schema.
+pub(super) fn attribute_input(message: &str) -> String {
+    let names = directory_schema()
+        .map(|schema| schema.attribute_names())
+        .unwrap_or_default();
+    get_completed_input(message, &names)
+}
+
+/// Checks attribute names against the directory schema, when it can be read.
+pub(super) fn check_attributes(names: &[String]) -> Result<(), LdapToolError> {
+    match directory_schema() {
+        Some(schema) => schema.check_attributes(names),
+        None => Ok(()),
+    }
+}
+
+/// Prompts for a comma separated attribute list with completion, falling back to `default`
+/// when empty, and checks the names against the schema.
+pub(super) fn attribute_list_input(
+    message: &str,
+    default: &[&str],
+) -> Result<Vec<String>, LdapToolError> {
+    let attributes = parse_columns(&attribute_input(message), default)?;
+    check_attributes(&attributes)?;
+    Ok(attributes)
+}
+
+fn print_root_dse(root: &SearchEntry) {
+    let rows: Vec<Vec<String>> = [
+        "dnsHostName",
+        "defaultNamingContext",
+        "schemaNamingContext",
+        "subschemaSubentry",
+        "supportedLDAPVersion",
+        "supportedSASLMechanisms",
+        "domainControllerFunctionality",
+    ]
+    .iter()
+    .filter(|attr| !values(root, attr).is_empty())
+    .map(|attr| vec![attr.to_string(), values(root, attr).join(", ")])
+    .collect();
+    print!("{}", format_table(&["rootDSE", "Value"], &rows));
+}
+
+fn print_attribute(schema: &Schema, attribute: &AttributeType) {
+    println!(
+        "Attribute {} ({})",
+        attribute.names.join(", "),
+        attribute.oid
+    );
+    if let Some(description) = &attribute.description {
+        println!("  {}", description);
+    }
+    println!(
+        "  Syntax: {} ({})",
+        attribute.syntax_name(),
+        attribute.syntax.as_deref().unwrap_or("none")
+    );
+    println!(
+        "  {}{}",
+        if attribute.single_value {
+            "single-valued"
+        } else {
+            "multi-valued"
+        },
+        if attribute.no_user_modification {
+            ", maintained by the server"
+        } else {
+            ""
+        }
+    );
+    for (class, required) in schema.classes_with(attribute.name()) {
+        println!(
+            "  {} by {}",
+            if required { "Required" } else { "Allowed" },
+            class.name()
+        );
+    }
+}
+
+fn print_class(schema: &Schema, class: &ObjectClass) -> Result<(), LdapToolError> {
+    println!(
+        "Object class {} ({}), {}",
+        class.names.join(", "),
+        class.oid,
+        class.kind.label()
+    );
+    if let Some(description) = &class.description {
+        println!("  {}", description);
+    }
+    if !class.superiors.is_empty() {
+        println!("  Superior: {}", class.superiors.join(", "));
+    }
+    if !class.auxiliary.is_empty() {
+        println!("  Auxiliary: {}", class.auxiliary.join(", "));
+    }
+    let rows: Vec<Vec<String>> = schema
+        .class_attributes(class.name())?
+        .into_iter()
+        .map(|attribute| {
+            let definition = schema.attribute(&attribute.name);
+            vec![
+                attribute.name,
+                definition.map_or("", |a| a.syntax_name()).to_string(),
+                match definition {
+                    Some(a) if a.single_value => "single",
+                    Some(_) => "multi",
+                    None => "",
+                }
+                .to_string(),
+                if attribute.required {
+                    "required"
+                } else {
+                    "optional"
+                }
+                .to_string(),
+                attribute.class,
+            ]
+        })
+        .collect();
+    print!(
+        "{}",
+        format_table(&["Attribute", "Syntax", "Values", "Status", "From"], &rows)
+    );
+    Ok(())
+}
+
+/// Lists the classes and attributes whose name starts with `prefix`.
+fn print_matches(schema: &Schema, prefix: &str) {
+    let prefix = prefix.to_ascii_lowercase();
+    let matching = |name: &str| name.to_ascii_lowercase().starts_with(&prefix);
+    let classes: Vec<Vec<String>> = schema
+        .classes()
+        .iter()
+        .filter(|c| matching(c.name()))
+        .map(|c| {
+            vec![
+                c.name().to_string(),
+                c.kind.label().to_string(),
+                c.superiors.join(", "),
+            ]
+        })
+        .collect();
+    let attributes: Vec<Vec<String>> = schema
+        .attributes()
+        .iter()
+        .filter(|a| matching(a.name()))
+        .map(|a| {
+            vec![
+                a.name().to_string(),
+                a.syntax_name().to_string(),
+                if a.single_value { "single" } else { "multi" }.to_string(),
+            ]
+        })
+        .collect();
+    if !classes.is_empty() {
+        print!(
+            "{}",
+            format_table(&["Object class", "Kind", "Superior"], &classes)
+        );
+    }
+    if !attributes.is_empty() {
+        print!(
+            "{}",
+            format_table(&["Attribute", "Syntax", "Values"], &attributes)
+        );
+    }
+    println!(
+        "{} object class(es), {} attribute(s)",
+        classes.len(),
+        attributes.len()
+    );
+}
+
+fn run_schema_browser() -> Result<(), LdapToolError> {
+    let mut ldap = ldap_session()?;
+    print_root_dse(&read_root_dse(&mut ldap)?);
+    let schema = load_schema(&mut ldap)?;
+    drop(ldap);
+    println!(
+        "{} object classes, {} attributes",
+        schema.classes().len(),
+        schema.attributes().len()
+    );
+    if !schema.skipped.is_empty() {
+        println!(
+            "{} definition(s) could not be parsed and are not shown",
+            schema.skipped.len()
+        );
+    }
+
+    let mut names = schema.attribute_names();
+    names.extend(
+        schema
+            .classes()
+            .iter()
+            .flat_map(|c| c.names.iter().cloned()),
+    );
+    loop {
+        let input = get_completed_input(
+            "Object class or attribute (prefix* to list, Tab to complete, Q to go back)",
+            &names,
+        );
+        if input.is_empty() || is_quit(&input) {
+            return Ok(());
+        }
+        if let Some(prefix) = input.strip_suffix('*') {
+            print_matches(&schema, prefix);
+            continue;
+        }
+        let class = schema.class(&input);
+        let attribute = schema.attribute(&input);
+        if let Some(class) = class {
+            print_class(&schema, class)?;
+        }
+        if let Some(attribute) = attribute {
+            print_attribute(&schema, attribute);
+        }
+        if class.is_none() && attribute.is_none() {
+            match schema.suggest(&input) {
+                Some(suggestion) => println!("{} not found, did you mean {}?", input, suggestion),
+                None => println!("{} not found", input),
+            }
+        }
+    }
+}
+
+#[derive_tool(
+    id = "SchemaBrowserTool",
+    name = "Schema Browser",
+    desc = "Shows the rootDSE and looks up object classes and attributes in the directory schema"
+)]
+pub fn schema_browser() {
+    if let Err(e) = run_schema_browser() {
+        println!("Error: {}", e);
+    }
+}
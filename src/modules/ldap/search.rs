@@ -0,0 +1,244 @@
+use ldap3::adapters::{Adapter, EntriesOnly, PagedResults};
+use ldap3::{Scope, SearchEntry};
+
+use super::connection::{LdapConnection, LdapToolError};
+
+/// Number of entries requested per page with the Simple Paged Results control.
+///
+/// Active Directory caps a single search at `MaxPageSize` (1000 by default), so pages must stay
+/// below it.
+pub const PAGE_SIZE: i32 = 500;
+
+/// Runs a paged, streaming search and hands every entry to `on_entry` as it arrives.
+///
+/// Referrals and intermediate messages are skipped. Returns the number of entries seen.
+pub fn paged_search<F>(
+    ldap: &mut LdapConnection,
+    base: &str,
+    scope: Scope,
+    filter: &str,
+    attrs: &[&str],
+    mut on_entry: F,
+) -> Result<usize, LdapToolError>
+where
+    F: FnMut(SearchEntry),
+{
+    let attrs: Vec<String> = attrs.iter().map(|a| a.to_string()).collect();
+    let adapters: Vec<Box<dyn Adapter<String, Vec<String>>>> = vec![
+        Box::new(EntriesOnly::new()),
+        Box::new(PagedResults::new(PAGE_SIZE)),
+    ];
+    let mut stream = ldap
+        .conn()
+        .streaming_search_with(adapters, base, scope, filter, attrs)?;
+
+    let mut count = 0;
+    while let Some(entry) = stream.next()? {
+        on_entry(SearchEntry::construct(entry));
+        count += 1;
+    }
+    stream.result().success()?;
+    Ok(count)
+}
+
+/// Collects the results of a paged search.
+pub fn search_all(
+    ldap: &mut LdapConnection,
+    base: &str,
+    scope: Scope,
+    filter: &str,
+    attrs: &[&str],
+) -> Result<Vec<SearchEntry>, LdapToolError> {
+    let mut entries = Vec::new();
+    paged_search(ldap, base, scope, filter, attrs, |entry| {
+        entries.push(entry)
+    })?;
+    Ok(entries)
+}
+
+/// Splits an attribute name that carries a range of values, such as `member;range=0-1499`, into
+/// the attribute name and the index of the last value returned. The index is `None` in the final
+/// range (`member;range=1500-*`).
+pub fn parse_range(name: &str) -> Option<(&str, Option<usize>)> {
+    let (attr, range) = name.split_once(";range=")?;
+    let (_, end) = range.split_once('-')?;
+    match end {
+        "*" => Some((attr, None)),
+        end => Some((attr, Some(end.parse().ok()?))),
+    }
+}
+
+/// Reads the values of `attr` on `dn` that follow index `end`. AD returns at most
+/// `MaxValRange` values (1500 by default) of an attribute in one search, so the rest are
+/// requested as `attr;range=N-*` until the final range comes back.
+pub fn remaining_values(
+    ldap: &mut LdapConnection,
+    dn: &str,
+    attr: &str,
+    mut end: usize,
+) -> Result<Vec<String>, LdapToolError> {
+    let mut values = Vec::new();
+    loop {
+        let requested = format!("{};range={}-*", attr, end + 1);
+        let entry = search_all(ldap, dn, Scope::Base, "(objectClass=*)", &[&requested])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| LdapToolError::NotFound(dn.to_string()))?;
+        let Some((next, chunk)) = entry.attrs.into_iter().find_map(|(name, chunk)| {
+            parse_range(&name)
+                .filter(|(name, _)| name.eq_ignore_ascii_case(attr))
+                .map(|(_, next)| (next, chunk))
+        }) else {
+            return Ok(values);
+        };
+        values.extend(chunk);
+        match next {
+            Some(next) if next > end => end = next,
+            _ => return Ok(values),
+        }
+    }
+}
+
+/// Replaces the partial values of ranged attributes in `entry` (`member;range=0-1499`) with
+/// all of their values, stored under the plain attribute name.
+pub fn complete_ranges(
+    ldap: &mut LdapConnection,
+    entry: &mut SearchEntry,
+) -> Result<(), LdapToolError> {
+    let ranged: Vec<String> = entry
+        .attrs
+        .keys()
+        .filter(|name| parse_range(name).is_some())
+        .cloned()
+        .collect();
+    for name in ranged {
+        let mut all = entry.attrs.remove(&name).unwrap_or_default();
+        let Some((attr, end)) = parse_range(&name) else {
+            continue;
+        };
+        if let Some(end) = end {
+            all.extend(remaining_values(ldap, &entry.dn, attr, end)?);
+        }
+        entry.attrs.entry(attr.to_string()).or_default().extend(all);
+    }
+    Ok(())
+}
+
+/// Returns all values of `attr`, matching the attribute name case-insensitively.
+pub fn values<'a>(entry: &'a SearchEntry, attr: &str) -> &'a [String] {
+    entry
+        .attrs
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(attr))
+        .map(|(_, values)| values.as_slice())
+        .unwrap_or_default()
+}
+
+/// Returns the first value of `attr`, matching the attribute name case-insensitively.
+pub fn first_value<'a>(entry: &'a SearchEntry, attr: &str) -> Option<&'a str> {
+    values(entry, attr).first().map(String::as_str)
+}
+
+/// Formats rows as a left-aligned table with a header line.
+pub fn format_table<H: AsRef<str>>(headers: &[H], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.as_ref().chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: &[&str]| {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        format!("{}\n", line.trim_end())
+    };
+
+    let header: Vec<&str> = headers.iter().map(|h| h.as_ref()).collect();
+    let separator: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    let mut table = format_row(&header);
+    table.push_str(&format_row(
+        &separator.iter().map(String::as_str).collect::<Vec<_>>(),
+    ));
+    for row in rows {
+        table.push_str(&format_row(
+            &row.iter().map(String::as_str).collect::<Vec<_>>(),
+        ));
+    }
+    table
+}
+
+/// Formats rows as CSV (RFC 4180), quoting fields that contain commas, quotes or line breaks.
+pub fn format_csv<H: AsRef<str>>(headers: &[H], rows: &[Vec<String>]) -> String {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+    let mut csv = headers
+        .iter()
+        .map(|h| field(h.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",");
+    csv.push_str("\r\n");
+    for row in rows {
+        csv.push_str(&row.iter().map(|v| field(v)).collect::<Vec<_>>().join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Parses CSV (RFC 4180) into rows of fields. Quoted fields may contain commas, quotes (`""`)
+/// and line breaks. Empty lines are skipped.
+pub fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, LdapToolError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text
+        .strip_prefix('\u{feff}')
+        .unwrap_or(text)
+        .chars()
+        .peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.next_if_eq(&'"').is_some() {
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            _ if quoted => field.push(c),
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(LdapToolError::Config(format!(
+            "CSV row {}: unterminated quoted field",
+            rows.len() + 1
+        )));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
@@ -0,0 +1,510 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Scope, SearchEntry};
+use serde::{Deserialize, Serialize};
+
+use super::attributes::{AttributeKind, attribute_kind, decode_guid, decode_sid, display_value};
+use super::connection::{LdapConnection, LdapToolError, base_dn, ldap_session};
+use super::schema::attribute_list_input;
+use super::search::{complete_ranges, first_value, paged_search};
+use super::users::resolve_base;
+use crate::io::{get_input, select_index};
+
+const SNAPSHOT_DIR: &str = "snapshots";
+
+/// Users and groups: enough to see new accounts, disabled accounts and membership changes.
+pub const DEFAULT_SNAPSHOT_FILTER: &str =
+    "(|(&(objectCategory=person)(objectClass=user))(objectClass=group))";
+
+pub const DEFAULT_SNAPSHOT_ATTRS: [&str; 7] = [
+    "objectClass",
+    "sAMAccountName",
+    "displayName",
+    "mail",
+    "userAccountControl",
+    "member",
+    "manager",
+];
+
+/// How far before a snapshot `whenChanged` is checked when the `uSNChanged` of the snapshot
+/// cannot be used, to allow for replication delay between DCs.
+const REPLICATION_MARGIN: TimeDelta = TimeDelta::hours(1);
+
+/// Attribute values of one object, keyed by attribute name.
+pub type SnapshotEntry = BTreeMap<String, Vec<String>>;
+
+/// Selected objects and attributes of the directory at one point in time.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Snapshot {
+    pub created: DateTime<Utc>,
+    /// `dnsHostName` of the DC the snapshot was taken from. USNs are only comparable on one DC.
+    pub server: Option<String>,
+    /// `highestCommittedUSN` of that DC before the snapshot was taken
+    pub highest_usn: Option<u64>,
+    pub base: String,
+    pub filter: String,
+    pub attributes: Vec<String>,
+    /// Entries keyed by DN
+    pub entries: BTreeMap<String, SnapshotEntry>,
+}
+
+/// How a binary value of `name` is stored: SIDs and GUIDs in their string form, anything else
+/// base64-encoded.
+fn stored_binary(name: &str, value: &[u8]) -> String {
+    let decoded = match attribute_kind(name) {
+        AttributeKind::Sid => decode_sid(value),
+        AttributeKind::Guid => decode_guid(value),
+        _ => None,
+    };
+    decoded.unwrap_or_else(|| BASE64.encode(value))
+}
+
+/// Converts a search entry for storage. Binary SIDs and GUIDs are stored in their string form,
+/// other binary values base64-encoded. ldap3 returns binary values that happen to be valid
+/// UTF-8 as text, so those are converted the same way to store every object alike.
+pub fn snapshot_entry(entry: &SearchEntry, attributes: &[String]) -> SnapshotEntry {
+    let wanted = |name: &str| {
+        attributes
+            .iter()
+            .any(|a| a == "*" || a.eq_ignore_ascii_case(name))
+    };
+    let mut stored = SnapshotEntry::new();
+    for (name, values) in entry.attrs.iter().filter(|(name, _)| wanted(name)) {
+        let binary = matches!(
+            attribute_kind(name),
+            AttributeKind::Sid | AttributeKind::Guid | AttributeKind::Binary
+        );
+        let values = values.iter().map(|value| {
+            if binary {
+                stored_binary(name, value.as_bytes())
+            } else {
+                value.clone()
+            }
+        });
+        stored.entry(name.clone()).or_default().extend(values);
+    }
+    for (name, values) in entry.bin_attrs.iter().filter(|(name, _)| wanted(name)) {
+        let values = values.iter().map(|value| stored_binary(name, value));
+        stored.entry(name.clone()).or_default().extend(values);
+    }
+    for values in stored.values_mut() {
+        values.sort();
+    }
+    stored
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeChange {
+    pub attr: String,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryChange {
+    Added(String),
+    Removed(String),
+    Modified {
+        dn: String,
+        changes: Vec<AttributeChange>,
+    },
+}
+
+fn diff_entry(old: &SnapshotEntry, new: &SnapshotEntry) -> Vec<AttributeChange> {
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let before: BTreeSet<&String> = old.get(name).into_iter().flatten().collect();
+            let after: BTreeSet<&String> = new.get(name).into_iter().flatten().collect();
+            let change = AttributeChange {
+                attr: name.clone(),
+                removed: before.difference(&after).map(|v| v.to_string()).collect(),
+                added: after.difference(&before).map(|v| v.to_string()).collect(),
+            };
+            (!change.removed.is_empty() || !change.added.is_empty()).then_some(change)
+        })
+        .collect()
+}
+
+/// Compares two sets of entries. DNs are compared case-insensitively.
+pub fn diff_entries(
+    old: &BTreeMap<String, SnapshotEntry>,
+    new: &BTreeMap<String, SnapshotEntry>,
+) -> Vec<EntryChange> {
+    let lower = |entries: &BTreeMap<String, SnapshotEntry>| -> BTreeMap<String, String> {
+        entries
+            .keys()
+            .map(|dn| (dn.to_ascii_lowercase(), dn.clone()))
+            .collect()
+    };
+    let (old_dns, new_dns) = (lower(old), lower(new));
+    let mut changes = Vec::new();
+    for (key, dn) in &old_dns {
+        match new_dns.get(key) {
+            None => changes.push(EntryChange::Removed(dn.clone())),
+            Some(new_dn) => {
+                let attribute_changes = diff_entry(&old[dn], &new[new_dn]);
+                if !attribute_changes.is_empty() {
+                    changes.push(EntryChange::Modified {
+                        dn: new_dn.clone(),
+                        changes: attribute_changes,
+                    });
+                }
+            }
+        }
+    }
+    for (key, dn) in &new_dns {
+        if !old_dns.contains_key(key) {
+            changes.push(EntryChange::Added(dn.clone()));
+        }
+    }
+    changes
+}
+
+/// Writes a diff report. Single-valued changes are shown as `before -> after`, with flags and
+/// timestamps decoded.
+pub fn write_diff(out: &mut impl Write, changes: &[EntryChange]) -> std::io::Result<()> {
+    let (mut added, mut removed, mut modified) = (0, 0, 0);
+    for change in changes {
+        match change {
+            EntryChange::Added(dn) => {
+                added += 1;
+                writeln!(out, "+ {}", dn)?;
+            }
+            EntryChange::Removed(dn) => {
+                removed += 1;
+                writeln!(out, "- {}", dn)?;
+            }
+            EntryChange::Modified { dn, changes } => {
+                modified += 1;
+                writeln!(out, "~ {}", dn)?;
+                for change in changes {
+                    match (&change.removed[..], &change.added[..]) {
+                        ([before], [after]) => writeln!(
+                            out,
+                            "    {}: {} -> {}",
+                            change.attr,
+                            display_value(&change.attr, before),
+                            display_value(&change.attr, after)
+                        )?,
+                        _ => {
+                            for value in &change.removed {
+                                writeln!(
+                                    out,
+                                    "    {}: - {}",
+                                    change.attr,
+                                    display_value(&change.attr, value)
+                                )?;
+                            }
+                            for value in &change.added {
+                                writeln!(
+                                    out,
+                                    "    {}: + {}",
+                                    change.attr,
+                                    display_value(&change.attr, value)
+                                )?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    writeln!(
+        out,
+        "{} added, {} removed, {} modified",
+        added, removed, modified
+    )
+}
+
+/// Directory snapshots are stored in, overridable through `JH_SNAPSHOT_DIR`.
+pub fn snapshot_dir() -> PathBuf {
+    std::env::var("JH_SNAPSHOT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(SNAPSHOT_DIR))
+}
+
+pub fn save_snapshot(dir: &Path, snapshot: &Snapshot) -> Result<PathBuf, LdapToolError> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| LdapToolError::Config(format!("Failed to create {}: {}", dir.display(), e)))?;
+    let path = dir.join(format!(
+        "snapshot-{}.json",
+        snapshot.created.format("%Y%m%d-%H%M%S")
+    ));
+    let json = serde_json::to_string_pretty(snapshot)
+        .map_err(|e| LdapToolError::Config(format!("Failed to encode snapshot: {}", e)))?;
+    std::fs::write(&path, json)
+        .map_err(|e| LdapToolError::Config(format!("Failed to write {}: {}", path.display(), e)))?;
+    Ok(path)
+}
+
+pub fn load_snapshot(path: &Path) -> Result<Snapshot, LdapToolError> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| LdapToolError::Config(format!("Failed to read {}: {}", path.display(), e)))?;
+    serde_json::from_str(&json)
+        .map_err(|e| LdapToolError::Config(format!("Invalid snapshot {}: {}", path.display(), e)))
+}
+
+/// Snapshot files in `dir`, oldest first.
+pub fn list_snapshots(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| n.starts_with("snapshot-") && n.ends_with(".json"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
+
+/// Reads `dnsHostName` and `highestCommittedUSN` from the rootDSE.
+fn server_state(ldap: &mut LdapConnection) -> Result<(Option<String>, Option<u64>), LdapToolError> {
+    let mut state = (None, None);
+    paged_search(
+        ldap,
+        "",
+        Scope::Base,
+        "(objectClass=*)",
+        &["dnsHostName", "highestCommittedUSN"],
+        |entry| {
+            state = (
+                first_value(&entry, "dnsHostName").map(str::to_string),
+                first_value(&entry, "highestCommittedUSN").and_then(|v| v.parse().ok()),
+            );
+        },
+    )?;
+    Ok(state)
+}
+
+fn fetch_entries(
+    ldap: &mut LdapConnection,
+    base: &str,
+    filter: &str,
+    attributes: &[String],
+) -> Result<BTreeMap<String, SnapshotEntry>, LdapToolError> {
+    let attrs: Vec<&str> = attributes.iter().map(String::as_str).collect();
+    let mut found = Vec::new();
+    paged_search(ldap, base, Scope::Subtree, filter, &attrs, |entry| {
+        found.push(entry)
+    })?;
+    // Groups with more than 1500 members come back with only the first range of `member`
+    let mut entries = BTreeMap::new();
+    for mut entry in found {
+        complete_ranges(ldap, &mut entry)?;
+        entries.insert(entry.dn.clone(), snapshot_entry(&entry, attributes));
+    }
+    Ok(entries)
+}
+
+pub fn take_snapshot(
+    ldap: &mut LdapConnection,
+    base: &str,
+    filter: &str,
+    attributes: &[String],
+) -> Result<Snapshot, LdapToolError> {
+    let created = Utc::now();
+    // Read the USN first so changes made while fetching are picked up by the next comparison
+    let (server, highest_usn) = server_state(ldap)?;
+    Ok(Snapshot {
+        created,
+        server,
+        highest_usn,
+        base: base.to_string(),
+        filter: filter.to_string(),
+        attributes: attributes.to_vec(),
+        entries: fetch_entries(ldap, base, filter, attributes)?,
+    })
+}
+
+/// Filter for the objects changed since `snapshot`: by `uSNChanged` when connected to the same
+/// DC, otherwise by `whenChanged` (which is replicated) with a margin for replication delay.
+pub fn changed_since_filter(snapshot: &Snapshot, server: Option<&str>) -> String {
+    let condition = match (snapshot.highest_usn, &snapshot.server, server) {
+        (Some(usn), Some(old), Some(current)) if old.eq_ignore_ascii_case(current) => {
+            format!("(uSNChanged>={})", usn + 1)
+        }
+        _ => format!(
+            "(whenChanged>={})",
+            (snapshot.created - REPLICATION_MARGIN).format("%Y%m%d%H%M%S.0Z")
+        ),
+    };
+    format!("(&{}{})", snapshot.filter, condition)
+}
+
+/// The current state of the objects in `snapshot`. Only objects changed since the snapshot are
+/// fetched with all attributes; the rest are taken from the snapshot after a DN-only search
+/// that finds deleted and moved objects.
+pub fn live_entries(
+    ldap: &mut LdapConnection,
+    snapshot: &Snapshot,
+) -> Result<BTreeMap<String, SnapshotEntry>, LdapToolError> {
+    let (server, _) = server_state(ldap)?;
+    let mut present = BTreeSet::new();
+    // "1.1" requests no attributes
+    paged_search(
+        ldap,
+        &snapshot.base,
+        Scope::Subtree,
+        &snapshot.filter,
+        &["1.1"],
+        |entry| {
+            present.insert(entry.dn.to_ascii_lowercase());
+        },
+    )?;
+    let changed = fetch_entries(
+        ldap,
+        &snapshot.base,
+        &changed_since_filter(snapshot, server.as_deref()),
+        &snapshot.attributes,
+    )?;
+    Ok(merge_changes(&snapshot.entries, changed, &present))
+}
+
+/// Applies fetched changes to the entries of a snapshot and drops entries that no longer exist.
+/// `present` holds the lowercased DNs of every matching object in the directory.
+pub fn merge_changes(
+    entries: &BTreeMap<String, SnapshotEntry>,
+    changed: BTreeMap<String, SnapshotEntry>,
+    present: &BTreeSet<String>,
+) -> BTreeMap<String, SnapshotEntry> {
+    let changed_dns: BTreeSet<String> = changed.keys().map(|dn| dn.to_ascii_lowercase()).collect();
+    let mut merged: BTreeMap<String, SnapshotEntry> = entries
+        .iter()
+        .filter(|(dn, _)| {
+            let key = dn.to_ascii_lowercase();
+            present.contains(&key) && !changed_dns.contains(&key)
+        })
+        .map(|(dn, entry)| (dn.clone(), entry.clone()))
+        .collect();
+    merged.extend(changed);
+    merged
+}
+
+fn describe(path: &Path, snapshot: &Snapshot) -> String {
+    format!(
+        "{} ({} entries, {})",
+        snapshot
+            .created
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S"),
+        snapshot.entries.len(),
+        path.file_name().unwrap_or_default().to_string_lossy()
+    )
+}
+
+fn prompt_snapshot(message: &str, paths: &[PathBuf]) -> Result<(PathBuf, Snapshot), LdapToolError> {
+    for (index, path) in paths.iter().enumerate() {
+        match load_snapshot(path) {
+            Ok(snapshot) => println!("{}. {}", index + 1, describe(path, &snapshot)),
+            Err(e) => println!("{}. {}", index + 1, e),
+        }
+    }
+    let index = select_index(&get_input(message), paths.len())
+        .ok_or_else(|| LdapToolError::Config("Invalid selection".to_string()))?;
+    let path = paths[index - 1].clone();
+    let snapshot = load_snapshot(&path)?;
+    Ok((path, snapshot))
+}
+
+fn run_take_snapshot() -> Result<(), LdapToolError> {
+    let base = resolve_base(
+        &get_input("Base DN (e.g. OU=Staff, empty for BASE_DN)"),
+        &base_dn()?,
+    );
+    let filter = Some(get_input(&format!("Filter [{}]", DEFAULT_SNAPSHOT_FILTER)))
+        .filter(|f| !f.is_empty())
+        .unwrap_or_else(|| DEFAULT_SNAPSHOT_FILTER.to_string());
+    let attributes = attribute_list_input(
+        &format!("Attributes [{}]", DEFAULT_SNAPSHOT_ATTRS.join(",")),
+        &DEFAULT_SNAPSHOT_ATTRS,
+    )?;
+    let mut ldap = ldap_session()?;
+    let snapshot = take_snapshot(&mut ldap, &base, &filter, &attributes)?;
+    let path = save_snapshot(&snapshot_dir(), &snapshot)?;
+    println!("Saved {}", describe(&path, &snapshot));
+    Ok(())
+}
+
+#[derive_tool(
+    id = "TakeSnapshotTool",
+    name = "Take Directory Snapshot",
+    desc = "Saves selected objects and attributes of the directory to a local snapshot"
+)]
+pub fn take_directory_snapshot() {
+    if let Err(e) = run_take_snapshot() {
+        println!("Error: {}", e);
+    }
+}
+
+fn snapshot_paths() -> Result<Vec<PathBuf>, LdapToolError> {
+    let dir = snapshot_dir();
+    let paths = list_snapshots(&dir);
+    if paths.is_empty() {
+        return Err(LdapToolError::NotFound(format!(
+            "Snapshots in {}",
+            dir.display()
+        )));
+    }
+    Ok(paths)
+}
+
+fn run_diff_snapshots() -> Result<(), LdapToolError> {
+    let paths = snapshot_paths()?;
+    let (_, old) = prompt_snapshot("Older snapshot", &paths)?;
+    let (_, new) = prompt_snapshot("Newer snapshot", &paths)?;
+    if old.base != new.base || old.filter != new.filter || old.attributes != new.attributes {
+        println!("Warning: the snapshots cover different objects or attributes");
+    }
+    let _ = write_diff(
+        &mut std::io::stdout(),
+        &diff_entries(&old.entries, &new.entries),
+    );
+    Ok(())
+}
+
+#[derive_tool(
+    id = "DiffSnapshotsTool",
+    name = "Compare Snapshots",
+    desc = "Shows what changed between two directory snapshots"
+)]
+pub fn diff_snapshots() {
+    if let Err(e) = run_diff_snapshots() {
+        println!("Error: {}", e);
+    }
+}
+
+fn run_diff_snapshot_live() -> Result<(), LdapToolError> {
+    let paths = snapshot_paths()?;
+    let (_, snapshot) = prompt_snapshot("Snapshot", &paths)?;
+    let mut ldap = ldap_session()?;
+    let live = live_entries(&mut ldap, &snapshot)?;
+    let _ = write_diff(
+        &mut std::io::stdout(),
+        &diff_entries(&snapshot.entries, &live),
+    );
+    Ok(())
+}
+
+#[derive_tool(
+    id = "DiffSnapshotLiveTool",
+    name = "Compare Snapshot with Directory",
+    desc = "Shows what changed in the directory since a snapshot"
+)]
+pub fn diff_snapshot_live() {
+    if let Err(e) = run_diff_snapshot_live() {
+        println!("Error: {}", e);
+    }
+}
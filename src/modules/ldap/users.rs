@@ -0,0 +1,470 @@
+use std::collections::HashSet;
+
+use jh_admin_cli_macros::derive_tool;
+use ldap3::{Mod, Scope, SearchEntry, dn_escape};
+
+use super::connection::{
+    LdapConnection, LdapToolError, base_dn, default_groups, ldap_session, split_dn_list,
+};
+use super::groups::add_member;
+use super::passwords::generate_password;
+use super::policies::domain_policy;
+use super::schema::{attribute_input, attribute_list_input};
+use super::search::{format_table, paged_search, search_all};
+use super::{display_values, uac};
+use crate::audit::{self, AuditEntry};
+use crate::io::{confirm, get_input, get_secret_input};
+
+/// Filter matching user accounts (and not computers, which are also `objectClass=user` in AD).
+pub const USER_FILTER: &str = "(&(objectCategory=person)(objectClass=user))";
+
+const DEFAULT_COLUMNS: [&str; 4] = ["sAMAccountName", "displayName", "mail", "department"];
+
+/// Criteria for finding users; every criterion that is set must match.
+///
+/// Values may contain `*` wildcards, everything else is escaped.
+#[derive(Debug, Default)]
+pub struct UserQuery {
+    /// Matched against `cn`, `displayName`, `givenName` and `sn`
+    pub name: Option<String>,
+    pub account_name: Option<String>,
+    pub mail: Option<String>,
+    pub department: Option<String>,
+    /// Extra `attribute=value` (or `>=`, `<=`, `~=`) conditions
+    pub fragments: Vec<String>,
+}
+
+impl UserQuery {
+    pub fn to_filter(&self) -> Result<String, LdapToolError> {
+        let mut filter = String::from(USER_FILTER);
+        if let Some(name) = &self.name {
+            let value = escape_filter_value(name, true);
+            filter.push_str(&format!(
+                "(|(cn={0})(displayName={0})(givenName={0})(sn={0}))",
+                value
+            ));
+        }
+        for (attr, value) in [
+            ("sAMAccountName", &self.account_name),
+            ("mail", &self.mail),
+            ("department", &self.department),
+        ] {
+            if let Some(value) = value {
+                filter.push_str(&format!("({}={})", attr, escape_filter_value(value, true)));
+            }
+        }
+        for fragment in &self.fragments {
+            filter.push_str(&parse_fragment(fragment)?);
+        }
+        Ok(format!("(&{})", filter))
+    }
+}
+
+/// Escapes a value for use in a search filter (RFC 4515), optionally keeping `*` as a wildcard.
+pub fn escape_filter_value(value: &str, allow_wildcards: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '*' if allow_wildcards => escaped.push('*'),
+            '*' | '(' | ')' | '\\' | '\0' => escaped.push_str(&format!("\\{:02x}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether `name` is a valid attribute description (name or OID, with optional options).
+pub fn is_valid_attribute(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ';' || c == '.')
+}
+
+/// Turns an `attribute=value` fragment into an escaped filter item.
+pub fn parse_fragment(fragment: &str) -> Result<String, LdapToolError> {
+    let invalid = || LdapToolError::Config(format!("Invalid filter fragment: {}", fragment));
+    // Split at the first operator by position so that values may contain operators too; on a
+    // tie the two-character form, listed first, wins
+    let (index, operator) = ["~=", ">=", "<=", "="]
+        .into_iter()
+        .filter_map(|op| fragment.find(op).map(|index| (index, op)))
+        .min_by_key(|(index, _)| *index)
+        .ok_or_else(invalid)?;
+    let attr = fragment[..index].trim();
+    let value = fragment[index + operator.len()..].trim();
+    if !is_valid_attribute(attr) || value.is_empty() {
+        return Err(invalid());
+    }
+    // Wildcards only have a meaning in equality matches
+    Ok(format!(
+        "({}{}{})",
+        attr,
+        operator,
+        escape_filter_value(value, operator == "=")
+    ))
+}
+
+/// Resolves a search base: a full DN is used as-is, a relative one (e.g. `OU=Sales`) is
+/// placed under `base_dn`.
+pub fn resolve_base(input: &str, base_dn: &str) -> String {
+    let input = input.trim();
+    if input.is_empty() {
+        base_dn.to_string()
+    } else if input.to_ascii_lowercase().contains("dc=") {
+        input.to_string()
+    } else {
+        format!("{},{}", input, base_dn)
+    }
+}
+
+pub fn parse_scope(input: &str) -> Option<Scope> {
+    match input.trim().to_ascii_lowercase().as_str() {
+        "" | "sub" | "subtree" => Some(Scope::Subtree),
+        "one" | "onelevel" => Some(Scope::OneLevel),
+        "base" => Some(Scope::Base),
+        _ => None,
+    }
+}
+
+/// Parses a comma separated attribute list, falling back to `default` when empty.
+pub fn parse_columns(input: &str, default: &[&str]) -> Result<Vec<String>, LdapToolError> {
+    let columns: Vec<String> = input
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+        .collect();
+    if columns.is_empty() {
+        return Ok(default.iter().map(|c| c.to_string()).collect());
+    }
+    match columns.iter().find(|c| !is_valid_attribute(c)) {
+        Some(invalid) => Err(LdapToolError::Config(format!(
+            "Invalid attribute name: {}",
+            invalid
+        ))),
+        None => Ok(columns),
+    }
+}
+
+pub(super) fn optional_input(message: &str) -> Option<String> {
+    Some(get_input(message)).filter(|v| !v.is_empty())
+}
+
+fn run_user_search() -> Result<(), LdapToolError> {
+    println!("Leave a field empty to skip it. Use * as a wildcard.");
+    let query = UserQuery {
+        name: optional_input("Name"),
+        account_name: optional_input("sAMAccountName"),
+        mail: optional_input("Mail"),
+        department: optional_input("Department"),
+        fragments: attribute_input("Extra conditions (e.g. title=*Manager*, comma separated)")
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(str::to_string)
+            .collect(),
+    };
+    let filter = query.to_filter()?;
+    let base = resolve_base(
+        &get_input("Search base or OU (e.g. OU=Sales, empty for BASE_DN)"),
+        &base_dn()?,
+    );
+    let scope = parse_scope(&get_input("Scope (sub, one, base) [sub]"))
+        .ok_or_else(|| LdapToolError::Config("Scope must be sub, one or base".to_string()))?;
+    let columns = attribute_list_input(
+        &format!("Columns [{}]", DEFAULT_COLUMNS.join(",")),
+        &DEFAULT_COLUMNS,
+    )?;
+
+    let mut ldap = ldap_session()?;
+    let attrs: Vec<&str> = columns.iter().map(String::as_str).collect();
+    let mut rows = Vec::new();
+    paged_search(&mut ldap, &base, scope, &filter, &attrs, |entry| {
+        rows.push(
+            columns
+                .iter()
+                .map(|column| display_values(&entry, column).join("; "))
+                .collect(),
+        );
+    })?;
+
+    print!("{}", format_table(&attrs, &rows));
+    println!("Total: {} user(s)", rows.len());
+    Ok(())
+}
+
+#[derive_tool(
+    id = "SearchUsersTool",
+    name = "Search Users",
+    desc = "Finds users by name, account name, mail, department, OU or custom conditions"
+)]
+pub fn search_users() {
+    if let Err(e) = run_user_search() {
+        println!("Error: {}", e);
+    }
+}
+
+/// Looks up a single user by `sAMAccountName` below `base`.
+pub fn find_user(
+    ldap: &mut LdapConnection,
+    base: &str,
+    account_name: &str,
+    attrs: &[&str],
+) -> Result<SearchEntry, LdapToolError> {
+    let filter = format!(
+        "(&{}(sAMAccountName={}))",
+        USER_FILTER,
+        escape_filter_value(account_name, false)
+    );
+    search_all(ldap, base, Scope::Subtree, &filter, attrs)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| LdapToolError::NotFound(format!("User {}", account_name)))
+}
+
+/// A user account to create in Active Directory.
+#[derive(Debug, Default)]
+pub struct NewUser {
+    /// DN of the OU (or container) the user is created in
+    pub ou: String,
+    pub account_name: String,
+    pub user_principal_name: String,
+    pub given_name: String,
+    pub surname: String,
+    pub display_name: String,
+    pub mail: Option<String>,
+    pub department: Option<String>,
+    pub title: Option<String>,
+    pub password: String,
+    /// `userAccountControl` set once the password is in place
+    pub account_control: u32,
+    /// Require a password change at next logon (`pwdLastSet=0`)
+    pub must_change_password: bool,
+    pub groups: Vec<String>,
+}
+
+impl NewUser {
+    pub fn dn(&self) -> String {
+        format!("CN={},{}", dn_escape(self.display_name.as_str()), self.ou)
+    }
+
+    /// Attributes of the add request. The account starts disabled until a password is set.
+    pub fn attributes(&self) -> Vec<(String, HashSet<String>)> {
+        let mut attrs = vec![
+            (
+                "objectClass".to_string(),
+                ["top", "person", "organizationalPerson", "user"]
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            ("cn".to_string(), HashSet::from([self.display_name.clone()])),
+            (
+                "sAMAccountName".to_string(),
+                HashSet::from([self.account_name.clone()]),
+            ),
+            (
+                "userPrincipalName".to_string(),
+                HashSet::from([self.user_principal_name.clone()]),
+            ),
+            (
+                "givenName".to_string(),
+                HashSet::from([self.given_name.clone()]),
+            ),
+            ("sn".to_string(), HashSet::from([self.surname.clone()])),
+            (
+                "displayName".to_string(),
+                HashSet::from([self.display_name.clone()]),
+            ),
+            (
+                "userAccountControl".to_string(),
+                HashSet::from([(uac::NORMAL_ACCOUNT | uac::ACCOUNTDISABLE).to_string()]),
+            ),
+        ];
+        for (name, value) in [
+            ("mail", &self.mail),
+            ("department", &self.department),
+            ("title", &self.title),
+        ] {
+            if let Some(value) = value.as_ref().filter(|v| !v.is_empty()) {
+                attrs.push((name.to_string(), HashSet::from([value.clone()])));
+            }
+        }
+        attrs
+    }
+}
+
+/// Encodes a password for `unicodePwd`: the quoted password as UTF-16LE.
+pub fn encode_unicode_pwd(password: &str) -> Vec<u8> {
+    format!("\"{}\"", password)
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Sets a user's password. AD only accepts `unicodePwd` over an encrypted connection.
+pub fn set_password(
+    ldap: &mut LdapConnection,
+    dn: &str,
+    password: &str,
+) -> Result<(), LdapToolError> {
+    if !ldap.is_encrypted() {
+        return Err(LdapToolError::InsecureConnection(
+            "Setting a password".to_string(),
+        ));
+    }
+    ldap.conn()
+        .modify(
+            dn,
+            vec![Mod::Replace(
+                b"unicodePwd".to_vec(),
+                HashSet::from([encode_unicode_pwd(password)]),
+            )],
+        )?
+        .success()?;
+    Ok(())
+}
+
+/// Creates the user, sets its password and flags, and adds it to its groups.
+///
+/// If any step after the add fails, the new object is deleted again so that no half-configured
+/// account (without a password, still disabled or missing some of its groups) is left behind.
+/// Returns the DN of the new user.
+pub fn create_user(ldap: &mut LdapConnection, user: &NewUser) -> Result<String, LdapToolError> {
+    if !ldap.is_encrypted() {
+        return Err(LdapToolError::InsecureConnection(
+            "Creating a user with a password".to_string(),
+        ));
+    }
+    let dn = user.dn();
+    ldap.conn().add(&dn, user.attributes())?.success()?;
+
+    if let Err(e) = configure_new_user(ldap, &dn, user) {
+        let _ = ldap.conn().delete(&dn);
+        return Err(e);
+    }
+    Ok(dn)
+}
+
+fn configure_new_user(
+    ldap: &mut LdapConnection,
+    dn: &str,
+    user: &NewUser,
+) -> Result<(), LdapToolError> {
+    set_password(ldap, dn, &user.password)?;
+
+    let mut mods = vec![Mod::Replace(
+        "userAccountControl".to_string(),
+        HashSet::from([user.account_control.to_string()]),
+    )];
+    if user.must_change_password {
+        mods.push(Mod::Replace(
+            "pwdLastSet".to_string(),
+            HashSet::from(["0".to_string()]),
+        ));
+    }
+    ldap.conn().modify(dn, mods)?.success()?;
+
+    for group in &user.groups {
+        add_member(ldap, group, dn)?;
+    }
+    Ok(())
+}
+
+pub(super) fn required_input(message: &str) -> Result<String, LdapToolError> {
+    Some(get_input(message))
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| LdapToolError::Config(format!("{} is required", message)))
+}
+
+fn run_create_user() -> Result<(), LdapToolError> {
+    let base = base_dn()?;
+    let ou = resolve_base(&get_input("OU (e.g. OU=Staff, empty for BASE_DN)"), &base);
+    let given_name = required_input("First name (givenName)")?;
+    let surname = required_input("Last name (sn)")?;
+    let display_name = Some(get_input(&format!(
+        "Display name [{} {}]",
+        given_name, surname
+    )))
+    .filter(|v| !v.is_empty())
+    .unwrap_or_else(|| format!("{} {}", given_name, surname));
+    let account_name = required_input("sAMAccountName")?;
+    let user_principal_name = required_input("userPrincipalName (e.g. jdoe@example.com)")?;
+    let mail = optional_input("Mail");
+    let department = optional_input("Department");
+    let title = optional_input("Title");
+    let password = get_secret_input("Initial password (leave empty to generate one)");
+
+    let mut account_control = uac::NORMAL_ACCOUNT;
+    if !confirm("Enable the account now") {
+        account_control |= uac::ACCOUNTDISABLE;
+    }
+    if confirm("Password never expires") {
+        account_control |= uac::DONT_EXPIRE_PASSWORD;
+    }
+    let must_change_password = !confirm("Skip password change at next logon");
+
+    let defaults = default_groups();
+    let groups = match optional_input(&format!(
+        "Groups (DNs separated by ;) [{}]",
+        defaults.join("; ")
+    )) {
+        Some(groups) => split_dn_list(&groups),
+        None => defaults,
+    };
+
+    let generated = password.is_empty();
+    let mut user = NewUser {
+        ou,
+        account_name,
+        user_principal_name,
+        given_name,
+        surname,
+        display_name,
+        mail,
+        department,
+        title,
+        password,
+        account_control,
+        must_change_password,
+        groups,
+    };
+    println!("Creating {}", user.dn());
+    for group in &user.groups {
+        println!("  member of {}", group);
+    }
+    if !confirm("Create this user") {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    let mut ldap = ldap_session()?;
+    if generated {
+        user.password = generate_password(&domain_policy(&mut ldap)?.password_policy())?;
+    }
+    let dn = create_user(&mut ldap, &user)?;
+    println!("Created {}", dn);
+    if generated {
+        println!("Initial password: {}", user.password);
+    }
+    let entry = AuditEntry::new("ldap", "create_user", &dn).with_change(
+        None,
+        Some(&format!("userAccountControl={}", user.account_control)),
+    );
+    if let Err(e) = audit::record(&entry) {
+        println!("Warning: failed to write audit log: {}", e);
+    }
+    Ok(())
+}
+
+#[derive_tool(
+    id = "CreateUserTool",
+    name = "Create User",
+    desc = "Creates an Active Directory user with an initial password and default groups"
+)]
+pub fn create_ad_user() {
+    if let Err(e) = run_create_user() {
+        println!("Error: {}", e);
+    }
+}
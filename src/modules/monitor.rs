@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+
+use crate::models::monitor::{Host, HostInventory};
+
+const HOST_INVENTORY_PATH: &str = "hosts.json";
+
+/// Location of the host inventory, overridable through `JH_HOST_INVENTORY`.
+pub fn inventory_path() -> PathBuf {
+    std::env::var("JH_HOST_INVENTORY")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(HOST_INVENTORY_PATH))
+}
+
+/// Reads the inventory at `path`. A missing file is an empty inventory.
+pub fn load_inventory(path: &Path) -> Result<HostInventory, String> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid host inventory {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HostInventory::default()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+pub fn save_inventory(path: &Path, inventory: &HostInventory) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(inventory)
+        .map_err(|e| format!("Failed to encode host inventory: {}", e))?;
+    std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Replaces the hosts from `source` in the inventory at `path`, keeping hosts from other
+/// sources. Returns the number of hosts in the inventory.
+pub fn update_inventory(path: &Path, source: &str, hosts: Vec<Host>) -> Result<usize, String> {
+    store_hosts(path, hosts, |host| host.source != source)
+}
+
+/// Adds `hosts` to the inventory at `path`, replacing hosts of `source` with the same hostname
+/// and keeping every other host. Used for partial exports, which must not drop the hosts they
+/// did not include. Returns the number of hosts in the inventory.
+pub fn merge_inventory(path: &Path, source: &str, hosts: Vec<Host>) -> Result<usize, String> {
+    let replaced: Vec<String> = hosts.iter().map(|h| h.hostname.to_lowercase()).collect();
+    store_hosts(path, hosts, |host| {
+        host.source != source || !replaced.contains(&host.hostname.to_lowercase())
+    })
+}
+
+fn store_hosts(
+    path: &Path,
+    hosts: Vec<Host>,
+    keep: impl Fn(&Host) -> bool,
+) -> Result<usize, String> {
+    let mut inventory = load_inventory(path)?;
+    inventory.hosts.retain(|host| keep(host));
+    inventory.hosts.extend(hosts);
+    inventory
+        .hosts
+        .sort_by_key(|host| host.hostname.to_lowercase());
+    inventory.updated = Some(Utc::now());
+    save_inventory(path, &inventory)?;
+    Ok(inventory.hosts.len())
+}
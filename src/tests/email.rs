@@ -0,0 +1,456 @@
+use std::time::{Duration, Instant};
+
+use chrono::TimeZone;
+use serde_json::Value;
+
+use crate::audit::{self, AuditEntry};
+use crate::http::{HttpError, RetryPolicy};
+use crate::models::email::{Data, RespData, ZohoApiResponse};
+use crate::modules::email::{
+    NewAccount, ZohoClient, change_account_role, find_account_by_email, refresh_access_token,
+    request_tokens, write_account_details, write_admins, write_email_users, write_policies,
+};
+
+use super::zoho_mock::{self, MockResponse, ZohoMock};
+
+#[test]
+fn fixtures_deserialize_into_api_response() {
+    let list: ZohoApiResponse = serde_json::from_str(zoho_mock::ACCOUNTS_FIXTURE).unwrap();
+    let Some(RespData::Vector(Some(accounts))) = list.data else {
+        panic!("expected a list of accounts");
+    };
+    assert_eq!(accounts.len(), 3);
+    assert_eq!(accounts[0].display_name.as_deref(), Some("Ahmad Urabi"));
+    assert_eq!(accounts[0].email_address.as_ref().unwrap().len(), 2);
+    assert_eq!(
+        accounts[0].address.as_ref().unwrap().city.as_deref(),
+        Some("Amman")
+    );
+
+    let single: ZohoApiResponse = serde_json::from_str(zoho_mock::ACCOUNT_FIXTURE).unwrap();
+    let Some(RespData::Item(Some(account))) = single.data else {
+        panic!("expected a single account");
+    };
+    assert_eq!(account.zuid, Some(700001004));
+
+    let error: ZohoApiResponse = serde_json::from_str(zoho_mock::INVALID_TOKEN_FIXTURE).unwrap();
+    assert_eq!(error.status.unwrap().code, Some(404));
+}
+
+#[test]
+fn exchanges_auth_code_for_tokens() {
+    let mock = ZohoMock::start();
+    let tokens = request_tokens(&mock.endpoints(), zoho_mock::AUTH_CODE).unwrap();
+    assert_eq!(tokens.access_token, zoho_mock::ACCESS_TOKEN);
+    assert_eq!(tokens.refresh_token, zoho_mock::REFRESH_TOKEN);
+
+    let request = &mock.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/oauth/v2/token");
+    assert!(request.body.contains("grant_type=authorization_code"));
+}
+
+#[test]
+fn rejects_invalid_auth_code() {
+    let mock = ZohoMock::start();
+    match request_tokens(&mock.endpoints(), "wrong-code") {
+        Err(HttpError::Api(message)) => assert_eq!(message, "invalid_code"),
+        other => panic!("unexpected result: {:?}", other.map(|t| t.access_token)),
+    }
+}
+
+#[test]
+fn refreshed_token_is_accepted() {
+    let mock = ZohoMock::start();
+    let token = refresh_access_token(&mock.endpoints(), zoho_mock::REFRESH_TOKEN).unwrap();
+    assert_ne!(token, zoho_mock::ACCESS_TOKEN);
+
+    let client = ZohoClient::new(mock.endpoints(), zoho_mock::ZOID, &token);
+    assert_eq!(client.list_accounts().unwrap().len(), 3);
+}
+
+#[test]
+fn invalid_token_is_reported() {
+    let mock = ZohoMock::start();
+    let client = ZohoClient::new(mock.endpoints(), zoho_mock::ZOID, "expired");
+    match client.list_accounts() {
+        Err(HttpError::Status { status, body }) => {
+            assert_eq!(status.as_u16(), 404);
+            assert!(body.contains("INVALID_OAUTHTOKEN"));
+        }
+        other => panic!("unexpected result: {:?}", other.map(|a| a.len())),
+    }
+}
+
+#[test]
+fn list_accounts_follows_pagination() {
+    let accounts = (0..250)
+        .map(|i| zoho_mock::account(1000 + i, &format!("user{}@example.com", i), "User"))
+        .collect();
+    let mock = ZohoMock::with_accounts(accounts);
+
+    let listed = mock.client().list_accounts().unwrap();
+    assert_eq!(listed.len(), 250);
+    assert_eq!(listed[249].zuid, Some(1249));
+
+    let starts: Vec<String> = mock
+        .requests()
+        .iter()
+        .map(|r| r.query["start"].clone())
+        .collect();
+    assert_eq!(starts, ["0", "100", "200"]);
+}
+
+#[test]
+fn list_email_users_prints_every_account() {
+    let mock = ZohoMock::start();
+    let mut out = Vec::new();
+    write_email_users(&mock.client(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(
+        out.lines().collect::<Vec<_>>(),
+        [
+            "User: Ahmad Urabi, Email(s): ahmad@example.com, it@example.com (Alias)",
+            "User: Lina Haddad, Email(s): lina@example.com",
+            "User: Omar Khalil, Email(s): omar@example.com",
+        ]
+    );
+}
+
+#[test]
+fn creates_and_deletes_accounts() {
+    let mock = ZohoMock::start();
+    let client = mock.client();
+    let new_account = NewAccount {
+        primary_email_address: "sara@example.com".to_string(),
+        password: "Initial#Pass1".to_string(),
+        first_name: "Sara".to_string(),
+        last_name: "Nasser".to_string(),
+        display_name: "Sara Nasser".to_string(),
+    };
+
+    let created = client.create_account(&new_account).unwrap();
+    assert_eq!(created.display_name.as_deref(), Some("Sara Nasser"));
+    match client.create_account(&new_account) {
+        Err(HttpError::Status { status, body }) => {
+            assert_eq!(status.as_u16(), 400);
+            assert!(body.contains("EMAIL_ALREADY_EXISTS"));
+        }
+        other => panic!("unexpected result: {:?}", other.map(|a| a.zuid)),
+    }
+
+    let accounts = client.list_accounts().unwrap();
+    let sara = find_account_by_email(&accounts, "SARA@example.com").unwrap();
+    client.delete_accounts(&[sara.zuid.unwrap()]).unwrap();
+    assert_eq!(mock.accounts().len(), 3);
+}
+
+#[test]
+fn finds_accounts_by_alias() {
+    let mock = ZohoMock::start();
+    let accounts = mock.client().list_accounts().unwrap();
+    let account = find_account_by_email(&accounts, "it@example.com").unwrap();
+    assert_eq!(account.zuid, Some(700001001));
+    assert!(find_account_by_email(&accounts, "nobody@example.com").is_none());
+}
+
+#[test]
+fn retries_rate_limited_and_failed_requests() {
+    let mock = ZohoMock::start();
+    mock.queue(MockResponse::json(429, serde_json::json!({})).with_header("Retry-After", "0"));
+    mock.queue(MockResponse::json(503, serde_json::json!({})));
+
+    assert_eq!(mock.client().list_accounts().unwrap().len(), 3);
+    assert_eq!(mock.requests().len(), 3);
+}
+
+#[test]
+fn gives_up_after_max_retries() {
+    let mock = ZohoMock::start();
+    for _ in 0..10 {
+        mock.queue(MockResponse::json(500, serde_json::json!({})));
+    }
+
+    match mock.client().list_accounts() {
+        Err(HttpError::Status { status, .. }) => assert_eq!(status.as_u16(), 500),
+        other => panic!("unexpected result: {:?}", other.map(|a| a.len())),
+    }
+    assert_eq!(
+        mock.requests().len(),
+        1 + zoho_mock::fast_retries().max_retries as usize
+    );
+}
+
+#[test]
+fn does_not_retry_client_errors() {
+    let mock = ZohoMock::start();
+    mock.queue(MockResponse::json(400, serde_json::json!({})));
+
+    assert!(mock.client().list_accounts().is_err());
+    assert_eq!(mock.requests().len(), 1);
+}
+
+#[test]
+fn retries_posts_only_when_they_were_not_processed() {
+    let mock = ZohoMock::start();
+    let client = mock.client();
+    let new_account = NewAccount {
+        primary_email_address: "sara@example.com".to_string(),
+        password: "Initial#Pass1".to_string(),
+        first_name: "Sara".to_string(),
+        last_name: "Nasser".to_string(),
+        display_name: "Sara Nasser".to_string(),
+    };
+
+    // The account may have been created before the server failed
+    mock.queue(MockResponse::json(503, serde_json::json!({})));
+    match client.create_account(&new_account) {
+        Err(HttpError::Status { status, .. }) => assert_eq!(status.as_u16(), 503),
+        other => panic!("unexpected result: {:?}", other.map(|a| a.zuid)),
+    }
+    assert_eq!(mock.requests().len(), 1);
+
+    mock.queue(MockResponse::json(429, serde_json::json!({})).with_header("Retry-After", "0"));
+    assert!(client.create_account(&new_account).is_ok());
+    assert_eq!(mock.requests().len(), 3);
+}
+
+#[test]
+fn gives_up_when_retry_after_exceeds_the_budget() {
+    let mock = ZohoMock::start();
+    // Longer than the policy's max_delay but within max_total: waited for as sent
+    mock.queue(MockResponse::json(429, serde_json::json!({})).with_header("Retry-After", "1"));
+    let policy = RetryPolicy {
+        max_total: Duration::from_secs(2),
+        ..zoho_mock::fast_retries()
+    };
+    let client = mock.client().with_retry_policy(policy);
+    let started = Instant::now();
+    assert_eq!(client.list_accounts().unwrap().len(), 3);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+
+    mock.queue(MockResponse::json(429, serde_json::json!({})).with_header("Retry-After", "60"));
+    let before = mock.requests().len();
+    match mock.client().list_accounts() {
+        Err(HttpError::Status { status, .. }) => assert_eq!(status.as_u16(), 429),
+        other => panic!("unexpected result: {:?}", other.map(|a| a.len())),
+    }
+    assert_eq!(mock.requests().len(), before + 1);
+}
+
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, strip_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(strip_nulls).collect()),
+        other => other,
+    }
+}
+
+#[test]
+fn account_data_round_trips_for_every_org() {
+    for fixture in zoho_mock::ORG_FIXTURES {
+        let payload: Value = serde_json::from_str(fixture).unwrap();
+        for account in payload["data"].as_array().unwrap() {
+            let data: Data = serde_json::from_value(account.clone()).unwrap();
+            let serialized = strip_nulls(serde_json::to_value(&data).unwrap());
+            assert_eq!(&serialized, account);
+        }
+    }
+}
+
+#[test]
+fn policy_ids_are_read_for_any_org() {
+    let policies: Vec<String> = zoho_mock::ORG_FIXTURES
+        .iter()
+        .flat_map(|fixture| {
+            let response: ZohoApiResponse = serde_json::from_str(fixture).unwrap();
+            let Some(RespData::Vector(Some(accounts))) = response.data else {
+                panic!("expected a list of accounts");
+            };
+            accounts
+        })
+        .filter_map(|account| account.policy_id.map(|p| p.describe()))
+        .collect();
+    assert_eq!(
+        policies,
+        [
+            "Business Policy (1082700000192558003)",
+            "Business Policy (1082700000192558003)",
+            "Default Policy (2911000000002005)",
+            "Executives (55100000000777001)",
+            "Contractors (55100000000777002)",
+        ]
+    );
+}
+
+#[test]
+fn lists_mail_policies() {
+    let mock = ZohoMock::start();
+    let mut out = Vec::new();
+    write_policies(&mock.client(), &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap().lines().collect::<Vec<_>>(),
+        [
+            "Business Policy (1082700000192558003) [Default]",
+            "Contractors (1082700000192558101)",
+        ]
+    );
+}
+
+#[test]
+fn assigns_mail_policy_to_accounts() {
+    let mock = ZohoMock::start();
+    let client = mock.client();
+    client
+        .assign_policy(zoho_mock::CONTRACTORS_POLICY_ID, &[700001002, 700001003])
+        .unwrap();
+
+    let accounts = client.list_accounts().unwrap();
+    let policy = |zuid| {
+        accounts
+            .iter()
+            .find(|a| a.zuid == Some(zuid))
+            .and_then(|a| a.policy_id.as_ref())
+            .map(|p| p.describe())
+    };
+    assert_eq!(
+        policy(700001001).as_deref(),
+        Some("Business Policy (1082700000192558003)")
+    );
+    assert_eq!(
+        policy(700001002).as_deref(),
+        Some("Contractors (1082700000192558101)")
+    );
+    assert_eq!(
+        policy(700001003).as_deref(),
+        Some("Contractors (1082700000192558101)")
+    );
+
+    match client.assign_policy("unknown", &[700001001]) {
+        Err(HttpError::Status { status, .. }) => assert_eq!(status.as_u16(), 404),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn fetches_a_single_account_by_email_or_id() {
+    let mock = ZohoMock::start();
+    let client = mock.client();
+    assert_eq!(
+        client.get_account("it@example.com").unwrap().zuid,
+        Some(700001001)
+    );
+    assert_eq!(
+        client.get_account("6000000001002").unwrap().zuid,
+        Some(700001002)
+    );
+    match client.get_account("nobody@example.com") {
+        Err(HttpError::Status { status, body }) => {
+            assert_eq!(status.as_u16(), 404);
+            assert!(body.contains("USER_NOT_EXIST"));
+        }
+        other => panic!("unexpected result: {:?}", other.map(|a| a.zuid)),
+    }
+
+    // Characters with a meaning in URLs stay part of the id
+    assert!(
+        client
+            .get_account("it@example.com/../../policy?x#y")
+            .is_err()
+    );
+    assert_eq!(
+        mock.requests().last().unwrap().path,
+        format!(
+            "/api/organization/{}/accounts/it@example.com%2F..%2F..%2Fpolicy%3Fx%23y",
+            zoho_mock::ZOID
+        )
+    );
+}
+
+#[test]
+fn account_details_are_grouped() {
+    let mock = ZohoMock::start();
+    let account = mock.client().get_account("ahmad@example.com").unwrap();
+    let mut out = Vec::new();
+    write_account_details(&account, &mut out);
+    let out = String::from_utf8(out).unwrap();
+
+    let sections: Vec<&str> = out.lines().filter(|l| !l.starts_with(' ')).collect();
+    assert_eq!(
+        sections,
+        [
+            "Identity",
+            "Addresses",
+            "Security",
+            "Storage",
+            "Protocols",
+            "Send Mail Details"
+        ]
+    );
+    assert!(out.contains("  Email address:          it@example.com (alias)\n"));
+    assert!(out.contains("  Postal address:         1 Rainbow St, Amman, Amman, 11181, Jordan\n"));
+    assert!(out.contains("  Used:                   1.8 GB\n"));
+    assert!(out.contains("  Mail policy:            Business Policy (1082700000192558003)\n"));
+    assert!(out.contains("  POP fetch time:         Never\n"));
+    assert!(out.contains("    Server:               smtp.zoho.com:465\n"));
+
+    let created = chrono::Local.timestamp_millis_opt(1651752845000).unwrap();
+    assert!(out.contains(&format!(
+        "  Created:                {}\n",
+        created.format("%Y-%m-%d %H:%M:%S %:z")
+    )));
+}
+
+#[test]
+fn lists_admins_by_privilege() {
+    let mock = ZohoMock::start();
+    let mut out = Vec::new();
+    write_admins(&mock.client(), &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap().lines().collect::<Vec<_>>(),
+        [
+            "super_admin  User: Ahmad Urabi, Email(s): ahmad@example.com, it@example.com (Alias)",
+            "admin        User: Lina Haddad, Email(s): lina@example.com [Custom admin]",
+            "2 admin account(s)",
+        ]
+    );
+}
+
+#[test]
+fn role_changes_are_applied_and_audited() {
+    let mock = ZohoMock::start();
+    let client = mock.client();
+    let account = client.get_account("omar@example.com").unwrap();
+
+    let entry = change_account_role(&client, &account, "admin").unwrap();
+    assert_eq!(entry.action, "change_role");
+    assert_eq!(entry.target, "omar@example.com");
+    assert_eq!(entry.before.as_deref(), Some("member"));
+    assert_eq!(entry.after.as_deref(), Some("admin"));
+    assert_eq!(
+        client
+            .get_account("omar@example.com")
+            .unwrap()
+            .role
+            .as_deref(),
+        Some("admin")
+    );
+
+    assert!(change_account_role(&client, &account, "owner").is_err());
+
+    let path = std::env::temp_dir().join(format!("jh_admin_audit_{}.log", std::process::id()));
+    audit::append(&path, &entry).unwrap();
+    audit::append(&path, &entry).unwrap();
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let entries: Vec<AuditEntry> = log
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(entries, [entry.clone(), entry]);
+}
@@ -0,0 +1,2741 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, TimeDelta};
+use ldap3::{Mod, Scope, SearchEntry};
+
+use crate::models::monitor::Host;
+use crate::modules::ldap::{
+    AccountAction, AccountKey, AccountReport, AccountState, AttributeChange, AttributeEdit,
+    BindMode, ChildObject, ClassKind, Computer, EditOp, EntryChange, Expand, Finding,
+    GENERATED_LENGTH, LdapConfig, LdapConnection, LdapTarget, LdapToolError, LdifChange, LdifMod,
+    LdifRecord, MemberNode, ModOp, NewUser, ObjectKind, OrgChart, OrphanReason, OuTree,
+    PasswordPolicy, Person, PlannedEdit, PolicySettings, PolicySource, RESULTANT_PSO,
+    ReportOptions, Schema, Snapshot, SnapshotEntry, TlsConfig, UAC_FLAGS, USER_FILTER, UserQuery,
+    account_mods, account_report, add_member, apply_account_action, apply_record,
+    bulk_move_candidates, changed_since_filter, check_edit_sheet, compare_users, connect,
+    create_user, decode_flags, decode_guid, decode_sid, describe_bind_error, diff_entries,
+    direct_members, display_value, display_values, domain_policy, domain_root, encode_unicode_pwd,
+    expand, filetime_to_datetime, find_movable, find_user, format_byte_size, format_csv,
+    format_filetime, format_fingerprint, format_interval, format_table, format_uac,
+    generate_password, group_members, groups_of_filter, inventory_source, is_dn, is_safe_string,
+    is_within, ldap_session_with, list_snapshots, live_entries, load_snapshot, locked_out_accounts,
+    lookup_entry, members_filter, merge_changes, move_object, parse_attribute_type, parse_columns,
+    parse_csv, parse_edit_sheet, parse_expand, parse_fingerprint, parse_fragment,
+    parse_generalized_time, parse_interval, parse_ldif, parse_member_list, parse_object_class,
+    parse_scope, parse_server_list, parse_target_list, password_settings, plan_edits,
+    read_root_dse, read_schema, remove_member, renamed_dn, reset_password, resolve_base,
+    resultant_policy, save_snapshot, search_all, set_password, snapshot_entry, split_dn,
+    split_dn_list, split_pem_bundle, take_snapshot, target_var, uac, validate_record, write_diff,
+    write_entry, write_ldif_entry, write_members,
+};
+use crate::modules::monitor::{load_inventory, merge_inventory, update_inventory};
+
+use super::ldap_mock::{self, LdapMock};
+
+fn config(server: &str) -> LdapConfig {
+    LdapConfig {
+        servers: vec![server.to_string()],
+        bind: BindMode::Simple {
+            username: "CN=svc,DC=example,DC=com".to_string(),
+            password: "secret".to_string(),
+        },
+        tls: TlsConfig::default(),
+        connect_timeout: Some(Duration::from_secs(2)),
+        operation_timeout: Some(Duration::from_secs(2)),
+    }
+}
+
+#[test]
+fn parses_fingerprints_with_and_without_separators() {
+    let colons = "AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89";
+    let bytes = parse_fingerprint(colons).unwrap();
+    assert_eq!(bytes.len(), 32);
+    assert_eq!(
+        parse_fingerprint(&colons.replace(':', "").to_lowercase()).unwrap(),
+        bytes
+    );
+    assert_eq!(format_fingerprint(&bytes), colons);
+
+    assert!(matches!(
+        parse_fingerprint("AB:CD"),
+        Err(LdapToolError::Config(_))
+    ));
+    assert!(matches!(
+        parse_fingerprint(&"ZZ".repeat(32)),
+        Err(LdapToolError::Config(_))
+    ));
+}
+
+#[test]
+fn splits_pem_bundles() {
+    let bundle = "subject=Root\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+                  subject=Issuing\n-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+    assert_eq!(
+        split_pem_bundle(bundle),
+        [
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----",
+            "-----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----",
+        ]
+    );
+    assert!(split_pem_bundle("not a certificate").is_empty());
+}
+
+#[test]
+fn detects_encrypted_connections() {
+    assert!(config("ldaps://dc01.example.com").is_encrypted());
+    assert!(!config("ldap://dc01.example.com").is_encrypted());
+
+    let mut starttls = config("ldap://dc01.example.com");
+    starttls.tls.starttls = true;
+    assert!(starttls.is_encrypted());
+}
+
+#[test]
+fn reads_named_targets() {
+    let vars: HashMap<&str, &str> = [
+        ("LDAP_SERVER", "ldap://dc01.example.com"),
+        ("BASE_DN", "DC=example,DC=com"),
+        ("LDAP_BIND", "anonymous"),
+        (
+            "LDAP_LAB_EAST_SERVER",
+            "ldaps://dc1.lab.test, ldaps://dc2.lab.test",
+        ),
+        ("LDAP_LAB_EAST_BASE_DN", "DC=lab,DC=test"),
+        ("LDAP_LAB_EAST_USERNAME", "CN=svc,DC=lab,DC=test"),
+        ("LDAP_LAB_EAST_PASSWORD", "secret"),
+        ("LDAP_LAB_EAST_TIMEOUT", "10"),
+        ("LDAP_BROKEN_SERVER", "ldap://dc.broken.test"),
+        ("LDAP_BROKEN_BASE_DN", "DC=broken,DC=test"),
+    ]
+    .into_iter()
+    .collect();
+    let lookup = |name: &str| vars.get(name).map(|v| v.to_string());
+
+    let lab = LdapTarget::load_with(Some("Lab-East"), &lookup).unwrap();
+    assert_eq!(lab.label(), "lab-east");
+    assert_eq!(lab.base_dn, "DC=lab,DC=test");
+    assert_eq!(
+        lab.config.servers,
+        ["ldaps://dc1.lab.test", "ldaps://dc2.lab.test"]
+    );
+    assert!(lab.config.is_encrypted());
+    assert_eq!(lab.config.operation_timeout, Some(Duration::from_secs(10)));
+    assert!(matches!(lab.config.bind, BindMode::Simple { .. }));
+
+    for name in [None, Some("default"), Some("")] {
+        let default = LdapTarget::load_with(name, &lookup).unwrap();
+        assert_eq!(default.name, None);
+        assert_eq!(default.label(), "default");
+        assert_eq!(default.base_dn, "DC=example,DC=com");
+        assert_eq!(default.config.servers, ["ldap://dc01.example.com"]);
+        assert!(matches!(default.config.bind, BindMode::Anonymous));
+    }
+
+    let message = |name| match LdapTarget::load_with(Some(name), &lookup) {
+        Err(LdapToolError::Config(message)) => message,
+        other => panic!("expected a configuration error, got {:?}", other),
+    };
+    assert!(message("prod").contains("LDAP_PROD_SERVER"));
+    assert!(message("broken").contains("LDAP_BROKEN_USERNAME"));
+    assert!(message("lab east").contains("Invalid LDAP target name"));
+
+    assert_eq!(target_var(None, "BASE_DN"), "BASE_DN");
+    assert_eq!(target_var(None, "STARTTLS"), "LDAP_STARTTLS");
+    assert_eq!(
+        target_var(Some("lab-east"), "BASE_DN"),
+        "LDAP_LAB_EAST_BASE_DN"
+    );
+    assert_eq!(
+        parse_server_list("ldap://a ldap://b,,ldap://c"),
+        ["ldap://a", "ldap://b", "ldap://c"]
+    );
+    assert_eq!(
+        parse_target_list("default, lab-east, LAB-EAST").unwrap(),
+        [None, Some("lab-east".to_string())]
+    );
+    assert!(parse_target_list("lab-east").is_err());
+}
+
+#[test]
+fn compares_user_attributes() {
+    let user = |base: &str, title: &str, groups: &[&str]| SearchEntry {
+        dn: format!("CN=John Doe,OU=Staff,{}", base),
+        attrs: HashMap::from([
+            ("title".to_string(), vec![title.to_string()]),
+            (
+                "manager".to_string(),
+                vec![format!("CN=Carol Boss,OU=Staff,{}", base)],
+            ),
+            (
+                "memberOf".to_string(),
+                groups.iter().map(|g| format!("{},{}", g, base)).collect(),
+            ),
+        ]),
+        bin_attrs: HashMap::new(),
+    };
+    let prod = user("DC=example,DC=com", "Engineer", &["CN=Staff", "CN=VPN"]);
+    let lab = user("DC=lab,DC=test", "engineer", &["CN=vpn", "CN=Staff"]);
+    let drifted = user("DC=example,DC=com", "Manager", &["CN=Staff"]);
+    let attrs = ["title", "manager", "memberOf", "mail"].map(str::to_string);
+
+    let same = compare_users(
+        &attrs,
+        &[
+            ("DC=example,DC=com", Some(&prod)),
+            ("DC=lab,DC=test", Some(&lab)),
+        ],
+    );
+    assert!(same.iter().all(|c| !c.differs), "{:?}", same);
+    assert_eq!(same[1].values[1], ["CN=Carol Boss,OU=Staff"]);
+    assert_eq!(same[2].values[0], ["CN=Staff", "CN=VPN"]);
+    assert!(same[3].values.iter().all(Vec::is_empty));
+
+    let differ = compare_users(
+        &attrs,
+        &[
+            ("DC=example,DC=com", Some(&prod)),
+            ("DC=example,DC=com", Some(&drifted)),
+        ],
+    );
+    let differing: Vec<&str> = differ
+        .iter()
+        .filter(|c| c.differs)
+        .map(|c| c.attr.as_str())
+        .collect();
+    assert_eq!(differing, ["title", "memberOf"]);
+
+    let missing = compare_users(
+        &attrs,
+        &[("DC=example,DC=com", Some(&prod)), ("DC=lab,DC=test", None)],
+    );
+    assert!(missing.iter().all(|c| c.differs));
+    assert!(missing[0].values[1].is_empty());
+}
+
+#[test]
+fn pinning_requires_an_encrypted_connection() {
+    let mut pinned = config("ldap://127.0.0.1:1");
+    pinned.tls.fingerprint = Some(vec![0; 32]);
+    assert!(matches!(connect(&pinned), Err(LdapToolError::Config(_))));
+}
+
+#[test]
+fn missing_ca_bundle_is_a_configuration_error() {
+    let mut with_bundle = config("ldaps://127.0.0.1:1");
+    with_bundle.tls.ca_bundle = Some("/nonexistent/ca.pem".into());
+    assert!(matches!(
+        connect(&with_bundle),
+        Err(LdapToolError::Config(_))
+    ));
+}
+
+#[test]
+fn validates_bind_settings() {
+    let simple = |username: &str, password: &str| BindMode::Simple {
+        username: username.to_string(),
+        password: password.to_string(),
+    };
+    for username in [
+        "CN=svc,DC=example,DC=com",
+        "svc@example.com",
+        "EXAMPLE\\svc",
+    ] {
+        assert!(simple(username, "secret").validate(false).is_ok());
+    }
+    assert!(matches!(
+        simple("svc", "secret").validate(true),
+        Err(LdapToolError::Config(_))
+    ));
+    assert!(matches!(
+        simple("svc@example.com", "").validate(true),
+        Err(LdapToolError::Config(_))
+    ));
+    assert!(BindMode::Anonymous.validate(false).is_ok());
+
+    let external = BindMode::External {
+        certificate: "/nonexistent/client.pem".into(),
+        key: "/nonexistent/client.key".into(),
+    };
+    assert!(external.validate(true).is_ok());
+    let mut plain = config("ldap://127.0.0.1:1");
+    plain.bind = external.clone();
+    assert!(matches!(connect(&plain), Err(LdapToolError::Config(_))));
+    let mut missing_certificate = config("ldaps://127.0.0.1:1");
+    missing_certificate.bind = external;
+    assert!(matches!(
+        connect(&missing_certificate),
+        Err(LdapToolError::Config(_))
+    ));
+}
+
+#[test]
+fn describes_ad_bind_errors() {
+    let text = |code: &str| {
+        format!(
+            "80090308: LdapErr: DSID-0C090447, comment: AcceptSecurityContext error, data {}, v3839\0",
+            code
+        )
+    };
+    assert_eq!(
+        describe_bind_error(&text("52e")),
+        Some("invalid username or password")
+    );
+    assert_eq!(
+        describe_bind_error(&text("775")),
+        Some("account locked out")
+    );
+    assert_eq!(describe_bind_error(&text("532")), Some("password expired"));
+    assert_eq!(describe_bind_error(&text("999")), None);
+    assert_eq!(describe_bind_error("Invalid credentials"), None);
+}
+
+#[test]
+fn builds_escaped_user_filters() {
+    let query = UserQuery {
+        name: Some("Jo*".to_string()),
+        department: Some("R&D (Labs)".to_string()),
+        fragments: vec!["title=*Manager*".to_string(), "employeeID>=100".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        query.to_filter().unwrap(),
+        "(&(&(objectCategory=person)(objectClass=user))\
+         (|(cn=Jo*)(displayName=Jo*)(givenName=Jo*)(sn=Jo*))\
+         (department=R&D \\28Labs\\29)(title=*Manager*)(employeeID>=100))"
+    );
+
+    let injection = UserQuery {
+        account_name: Some("*)(objectClass=*".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        injection.to_filter().unwrap(),
+        "(&(&(objectCategory=person)(objectClass=user))(sAMAccountName=*\\29\\28objectClass=*))"
+    );
+}
+
+#[test]
+fn rejects_invalid_fragments() {
+    assert_eq!(parse_fragment("mail=a\\b").unwrap(), "(mail=a\\5cb)");
+    assert_eq!(
+        parse_fragment("badPwdCount>=*").unwrap(),
+        "(badPwdCount>=\\2a)"
+    );
+    assert_eq!(parse_fragment("title=a>=b").unwrap(), "(title=a>=b)");
+    assert_eq!(
+        parse_fragment("description=x~=y").unwrap(),
+        "(description=x~=y)"
+    );
+    assert!(parse_fragment("(objectClass=*)").is_err());
+    assert!(parse_fragment("title").is_err());
+    assert!(parse_fragment("title=").is_err());
+}
+
+#[test]
+fn resolves_search_bases_and_scopes() {
+    let base = "DC=example,DC=com";
+    assert_eq!(resolve_base("", base), base);
+    assert_eq!(resolve_base("OU=Sales", base), "OU=Sales,DC=example,DC=com");
+    assert_eq!(
+        resolve_base("OU=Lab,DC=lab,DC=local", base),
+        "OU=Lab,DC=lab,DC=local"
+    );
+
+    assert!(matches!(parse_scope(""), Some(Scope::Subtree)));
+    assert!(matches!(parse_scope("one"), Some(Scope::OneLevel)));
+    assert!(matches!(parse_scope("BASE"), Some(Scope::Base)));
+    assert!(parse_scope("everything").is_none());
+}
+
+#[test]
+fn parses_columns_and_formats_tables() {
+    assert_eq!(parse_columns("", &["cn"]).unwrap(), ["cn"]);
+    assert_eq!(
+        parse_columns("mail, title", &["cn"]).unwrap(),
+        ["mail", "title"]
+    );
+    assert!(parse_columns("mail,(cn)", &["cn"]).is_err());
+
+    let table = format_table(
+        &["sAMAccountName", "mail"],
+        &[
+            vec!["jdoe".to_string(), "jdoe@example.com".to_string()],
+            vec!["administrator".to_string(), String::new()],
+        ],
+    );
+    assert_eq!(
+        table,
+        "sAMAccountName  mail\n\
+         --------------  ----------------\n\
+         jdoe            jdoe@example.com\n\
+         administrator\n"
+    );
+}
+
+fn new_user() -> NewUser {
+    NewUser {
+        ou: "OU=Staff,DC=example,DC=com".to_string(),
+        account_name: "jdoe".to_string(),
+        user_principal_name: "jdoe@example.com".to_string(),
+        given_name: "John".to_string(),
+        surname: "Doe, Jr.".to_string(),
+        display_name: "Doe, Jr. John".to_string(),
+        mail: Some("jdoe@example.com".to_string()),
+        department: Some(String::new()),
+        password: "Secret1!".to_string(),
+        account_control: uac::NORMAL_ACCOUNT,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn new_user_starts_disabled_with_escaped_dn() {
+    let user = new_user();
+    assert_eq!(user.dn(), "CN=Doe\\2c Jr. John,OU=Staff,DC=example,DC=com");
+
+    let attrs: HashMap<String, HashSet<String>> = user.attributes().into_iter().collect();
+    assert_eq!(attrs["objectClass"].len(), 4);
+    assert!(attrs["objectClass"].contains("user"));
+    assert_eq!(attrs["sAMAccountName"], HashSet::from(["jdoe".to_string()]));
+    assert_eq!(
+        attrs["userPrincipalName"],
+        HashSet::from(["jdoe@example.com".to_string()])
+    );
+    assert_eq!(
+        attrs["mail"],
+        HashSet::from(["jdoe@example.com".to_string()])
+    );
+    assert_eq!(
+        attrs["userAccountControl"],
+        HashSet::from([(uac::NORMAL_ACCOUNT | uac::ACCOUNTDISABLE).to_string()])
+    );
+    // Empty optional values are left out rather than sent as empty attributes
+    assert!(!attrs.contains_key("department"));
+    assert!(!attrs.contains_key("title"));
+    assert!(!attrs.contains_key("unicodePwd"));
+}
+
+#[test]
+fn encodes_unicode_pwd_as_quoted_utf16le() {
+    assert_eq!(
+        encode_unicode_pwd("ab"),
+        [0x22, 0, 0x61, 0, 0x62, 0, 0x22, 0]
+    );
+    assert_eq!(encode_unicode_pwd("é"), [0x22, 0, 0xE9, 0, 0x22, 0]);
+}
+
+#[test]
+fn splits_dn_lists() {
+    assert_eq!(
+        split_dn_list(" CN=Staff,OU=Groups,DC=example,DC=com ;; CN=VPN,DC=example,DC=com;"),
+        [
+            "CN=Staff,OU=Groups,DC=example,DC=com",
+            "CN=VPN,DC=example,DC=com"
+        ]
+    );
+    assert!(split_dn_list("").is_empty());
+}
+
+fn account_entry(attrs: &[(&str, &str)]) -> SearchEntry {
+    SearchEntry {
+        dn: "CN=John Doe,OU=Staff,DC=example,DC=com".to_string(),
+        attrs: attrs
+            .iter()
+            .map(|(name, value)| (name.to_string(), vec![value.to_string()]))
+            .collect(),
+        bin_attrs: HashMap::new(),
+    }
+}
+
+#[test]
+fn decodes_account_control_flags() {
+    assert_eq!(format_uac(0x202), "ACCOUNTDISABLE | NORMAL_ACCOUNT (0x202)");
+    assert_eq!(
+        format_uac(uac::NORMAL_ACCOUNT | uac::DONT_EXPIRE_PASSWORD),
+        "NORMAL_ACCOUNT | DONT_EXPIRE_PASSWORD (0x10200)"
+    );
+    assert_eq!(format_uac(0), "none (0x0)");
+    assert_eq!(
+        decode_flags(0x8000_0200, UAC_FLAGS),
+        ["NORMAL_ACCOUNT", "0x80000000"]
+    );
+}
+
+#[test]
+fn converts_filetimes() {
+    // 2024-01-01T00:00:00Z
+    let time = filetime_to_datetime(133_485_408_000_000_000).unwrap();
+    assert_eq!(time.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+    assert!(filetime_to_datetime(0).is_none());
+    assert!(filetime_to_datetime(i64::MAX).is_none());
+    assert_eq!(format_filetime(0), "Never");
+}
+
+#[test]
+fn toggles_account_disable_and_unlocks() {
+    let account = AccountState::from_entry(&account_entry(&[
+        ("sAMAccountName", "jdoe"),
+        ("userAccountControl", "512"),
+        ("lockoutTime", "133485408000000000"),
+        ("msDS-User-Account-Control-Computed", "16"),
+    ]));
+    assert!(account.locked_out);
+    assert!(!account.is_disabled());
+    assert_eq!(account.describe_flags(), "LOCKOUT | NORMAL_ACCOUNT (0x210)");
+
+    let disabled = account.after(AccountAction::Disable);
+    assert!(disabled.is_disabled());
+    assert_eq!(disabled.after(AccountAction::Enable).account_control, 512);
+
+    let unlocked = account.after(AccountAction::Unlock);
+    assert!(!unlocked.locked_out);
+    assert_eq!(unlocked.describe_flags(), "NORMAL_ACCOUNT (0x200)");
+    match &account_mods(&account, AccountAction::Unlock)[..] {
+        [Mod::Replace(attr, values)] => {
+            assert_eq!(attr, "lockoutTime");
+            assert_eq!(values, &HashSet::from(["0".to_string()]));
+        }
+        _ => panic!("expected a single replace"),
+    }
+    match &account_mods(&account, AccountAction::Disable)[..] {
+        [Mod::Replace(attr, values)] => {
+            assert_eq!(attr, "userAccountControl");
+            assert_eq!(values, &HashSet::from(["514".to_string()]));
+        }
+        _ => panic!("expected a single replace"),
+    }
+}
+
+#[test]
+fn expired_lockouts_are_not_reported_as_locked() {
+    // lockoutTime stays set after the lockout duration passes; the computed flag is cleared
+    let expired = AccountState::from_entry(&account_entry(&[
+        ("userAccountControl", "512"),
+        ("lockoutTime", "133485408000000000"),
+        ("msDS-User-Account-Control-Computed", "0"),
+    ]));
+    assert!(!expired.locked_out);
+
+    let without_computed = AccountState::from_entry(&account_entry(&[
+        ("userAccountControl", "512"),
+        ("lockoutTime", "133485408000000000"),
+    ]));
+    assert!(without_computed.locked_out);
+}
+
+#[test]
+fn generated_passwords_meet_the_policy() {
+    let policy = PasswordPolicy {
+        min_length: 20,
+        complexity: true,
+        ..Default::default()
+    };
+    for _ in 0..50 {
+        let password = generate_password(&policy).unwrap();
+        assert_eq!(password.chars().count(), 20);
+        assert!(policy.check(&password, "", "").is_ok(), "{}", password);
+        assert!(password.chars().any(|c| c.is_ascii_uppercase()));
+        assert!(password.chars().any(|c| c.is_ascii_lowercase()));
+        assert!(password.chars().any(|c| c.is_ascii_digit()));
+        assert!(password.chars().any(|c| !c.is_alphanumeric()));
+    }
+
+    let short_policy = PasswordPolicy {
+        min_length: 7,
+        complexity: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        generate_password(&short_policy).unwrap().len(),
+        GENERATED_LENGTH
+    );
+}
+
+#[test]
+fn checks_passwords_like_the_domain() {
+    let policy = PasswordPolicy {
+        min_length: 8,
+        complexity: true,
+        ..Default::default()
+    };
+    assert!(policy.check("Winter#2026", "jdoe", "John Doe").is_ok());
+    assert!(policy.check("Sh0rt!", "jdoe", "John Doe").is_err());
+    // Only two categories
+    assert!(policy.check("winter2026", "jdoe", "John Doe").is_err());
+    assert!(policy.check("MyJDoe#2026", "jdoe", "John Doe").is_err());
+    assert!(policy.check("Johnny#2026", "jdoe", "John Doe").is_err());
+    // Parts of the display name shorter than three characters are allowed
+    assert!(policy.check("Lo#Winter2026", "jdoe", "Lo Xi").is_ok());
+
+    let simple = PasswordPolicy {
+        min_length: 8,
+        complexity: false,
+        ..Default::default()
+    };
+    assert!(simple.check("winter2026", "jdoe", "John Doe").is_ok());
+}
+
+fn group_graph(
+    edges: &[(&str, &str)],
+) -> impl FnMut(&str) -> Result<Vec<MemberNode>, LdapToolError> {
+    let edges: Vec<(String, String)> = edges
+        .iter()
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect();
+    move |dn| {
+        Ok(edges
+            .iter()
+            .filter(|(from, _)| from.eq_ignore_ascii_case(dn))
+            .map(|(_, to)| MemberNode {
+                dn: to.clone(),
+                is_group: to.starts_with("CN=G"),
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn expands_nested_groups_once() {
+    let expansion = expand(
+        "CN=GAll",
+        group_graph(&[
+            ("CN=GAll", "CN=GSales"),
+            ("CN=GAll", "CN=GEngineering"),
+            ("CN=GSales", "CN=alice"),
+            ("CN=GEngineering", "CN=bob"),
+            ("CN=GEngineering", "CN=GSales"),
+            ("CN=GSales", "CN=bob"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(expansion.objects, ["CN=alice", "CN=bob"]);
+    assert_eq!(expansion.groups, ["CN=GSales", "CN=GEngineering"]);
+    assert!(expansion.cycles.is_empty());
+}
+
+#[test]
+fn reports_membership_cycles() {
+    // GA -> GB -> GC -> GB, and GC -> GA through a differently cased DN
+    let expansion = expand(
+        "CN=GA",
+        group_graph(&[
+            ("CN=GA", "CN=GB"),
+            ("CN=GB", "CN=GC"),
+            ("CN=GC", "CN=GB"),
+            ("CN=GC", "cn=ga"),
+            ("CN=GC", "CN=carol"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(expansion.objects, ["CN=carol"]);
+    assert_eq!(expansion.groups, ["CN=GB", "CN=GC"]);
+    assert_eq!(
+        expansion.cycles,
+        [
+            ("CN=GC".to_string(), "CN=GB".to_string()),
+            ("CN=GC".to_string(), "cn=ga".to_string()),
+        ]
+    );
+
+    let mut out = Vec::new();
+    write_members(&mut out, &expansion).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("CN=GB (group)\n"));
+    assert!(out.contains("Cycle: CN=GC contains CN=GB\n"));
+    assert!(out.ends_with("Total: 1 member(s), 2 group(s)\n"));
+}
+
+#[test]
+fn builds_membership_filters() {
+    let group = "CN=Sales (EU),OU=Groups,DC=example,DC=com";
+    assert_eq!(
+        members_filter(group, false),
+        "(memberOf=CN=Sales \\28EU\\29,OU=Groups,DC=example,DC=com)"
+    );
+    assert_eq!(
+        members_filter(group, true),
+        "(memberOf:1.2.840.113556.1.4.1941:=CN=Sales \\28EU\\29,OU=Groups,DC=example,DC=com)"
+    );
+    assert_eq!(
+        groups_of_filter("CN=Doe\\2c John,DC=example,DC=com", true),
+        "(&(objectClass=group)(member:1.2.840.113556.1.4.1941:=CN=Doe\\5c2c John,DC=example,DC=com))"
+    );
+}
+
+#[test]
+fn parses_member_lists_and_options() {
+    let list = "# new starters\njdoe\n\n  CN=Jane Roe,OU=Staff,DC=example,DC=com  \n#asmith\n";
+    assert_eq!(
+        parse_member_list(list),
+        ["jdoe", "CN=Jane Roe,OU=Staff,DC=example,DC=com"]
+    );
+    assert!(is_dn("CN=Jane Roe,DC=example,DC=com"));
+    assert!(!is_dn("jdoe"));
+
+    assert_eq!(parse_expand(""), Some(Expand::InChain));
+    assert_eq!(parse_expand("Client"), Some(Expand::Client));
+    assert_eq!(parse_expand("no"), Some(Expand::None));
+    assert_eq!(parse_expand("deep"), None);
+}
+
+fn filetime_at(time: &str) -> String {
+    let time = DateTime::parse_from_rfc3339(time).unwrap();
+    ((time.timestamp() + 11_644_473_600) * 10_000_000).to_string()
+}
+
+#[test]
+fn parses_intervals() {
+    assert_eq!(parse_interval("-36288000000000"), Some(TimeDelta::days(42)));
+    assert_eq!(parse_interval("0"), None);
+    assert_eq!(parse_interval("-9223372036854775808"), None);
+    assert_eq!(parse_interval("forever"), None);
+}
+
+#[test]
+fn reports_stale_accounts_and_password_expiry() {
+    let now = DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z")
+        .unwrap()
+        .to_utc();
+    let max_age = Some(TimeDelta::days(42));
+    let options = ReportOptions::default();
+    let evaluate = |attrs: &[(&str, &str)]| {
+        AccountReport::evaluate(&account_entry(attrs), now, max_age, &options).findings
+    };
+
+    let recent_logon = filetime_at("2026-02-20T08:00:00Z");
+    let stale_logon = filetime_at("2025-10-01T08:00:00Z");
+    let recent_password = filetime_at("2026-02-15T08:00:00Z");
+    let old_password = filetime_at("2026-01-30T08:00:00Z");
+
+    assert!(
+        evaluate(&[
+            ("userAccountControl", "512"),
+            ("lastLogonTimestamp", &recent_logon),
+            ("pwdLastSet", &recent_password),
+            ("accountExpires", "9223372036854775807"),
+        ])
+        .is_empty()
+    );
+    assert_eq!(
+        evaluate(&[
+            ("userAccountControl", "512"),
+            ("lastLogonTimestamp", &stale_logon),
+            ("pwdLastSet", &old_password),
+        ]),
+        [
+            Finding::Stale { days: 151 },
+            Finding::PasswordExpiring { days: 11 }
+        ]
+    );
+    assert_eq!(
+        evaluate(&[
+            ("userAccountControl", "66048"),
+            ("pwdLastSet", "0"),
+            ("accountExpires", &filetime_at("2026-02-28T00:00:00Z")),
+        ]),
+        [
+            Finding::NeverLoggedIn,
+            Finding::PasswordNeverExpires,
+            Finding::AccountExpired
+        ]
+    );
+    assert_eq!(
+        evaluate(&[
+            ("userAccountControl", "512"),
+            ("lastLogonTimestamp", &recent_logon),
+            ("pwdLastSet", "0"),
+        ]),
+        [Finding::PasswordMustChange]
+    );
+    // The computed expiry (which includes fine-grained policies) wins over maxPwdAge
+    assert_eq!(
+        evaluate(&[
+            ("userAccountControl", "512"),
+            ("lastLogonTimestamp", &recent_logon),
+            ("pwdLastSet", &recent_password),
+            (
+                "msDS-UserPasswordExpiryTimeComputed",
+                &filetime_at("2026-02-27T00:00:00Z")
+            ),
+        ]),
+        [Finding::PasswordExpired]
+    );
+}
+
+#[test]
+fn report_skips_clean_and_disabled_accounts() {
+    let now = DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z")
+        .unwrap()
+        .to_utc();
+    let entries = vec![
+        account_entry(&[("sAMAccountName", "never"), ("userAccountControl", "512")]),
+        account_entry(&[
+            ("sAMAccountName", "disabled"),
+            ("userAccountControl", "514"),
+        ]),
+        account_entry(&[
+            ("sAMAccountName", "clean"),
+            ("userAccountControl", "512"),
+            ("lastLogonTimestamp", &filetime_at("2026-02-28T08:00:00Z")),
+            ("pwdLastSet", &filetime_at("2026-02-28T08:00:00Z")),
+        ]),
+    ];
+    let report = account_report(
+        entries.clone(),
+        now,
+        Some(TimeDelta::days(42)),
+        &ReportOptions::default(),
+    );
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].account_name, "never");
+    assert_eq!(report[0].row()[7], "never logged in");
+
+    let options = ReportOptions {
+        include_disabled: true,
+        ..Default::default()
+    };
+    assert_eq!(account_report(entries, now, None, &options).len(), 2);
+}
+
+#[test]
+fn formats_csv() {
+    let csv = format_csv(
+        &["name", "findings"],
+        &[
+            vec!["Doe, John".to_string(), "no logon for 91 days".to_string()],
+            vec!["Jane \"JR\" Roe".to_string(), String::new()],
+        ],
+    );
+    assert_eq!(
+        csv,
+        "name,findings\r\n\"Doe, John\",no logon for 91 days\r\n\"Jane \"\"JR\"\" Roe\",\r\n"
+    );
+}
+
+const ADMIN_SID: [u8; 28] = [
+    0x01, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x15, 0x00, 0x00, 0x00, 0xB6, 0x55, 0x0E, 0x2C,
+    0x64, 0xB5, 0x0B, 0x3C, 0x7A, 0x6F, 0x8C, 0x4E, 0xF4, 0x01, 0x00, 0x00,
+];
+
+#[test]
+fn decodes_sids_and_guids() {
+    assert_eq!(
+        decode_sid(&ADMIN_SID).unwrap(),
+        "S-1-5-21-739136950-1007400292-1317826426-500"
+    );
+    // S-1-5-32-544 (BUILTIN\Administrators)
+    assert_eq!(
+        decode_sid(&[1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 0x20, 0x02, 0, 0]).unwrap(),
+        "S-1-5-32-544"
+    );
+    assert!(decode_sid(&ADMIN_SID[..27]).is_none());
+    // Its bytes are valid UTF-8, so ldap3 returns it as text rather than binary
+    let builtin_admins = [1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 0x20, 0x02, 0, 0];
+    let text = String::from_utf8(builtin_admins.to_vec()).unwrap();
+    assert_eq!(display_value("objectSid", &text), "S-1-5-32-544");
+    let entry = SearchEntry {
+        dn: "CN=Administrators,CN=Builtin,DC=example,DC=com".to_string(),
+        attrs: HashMap::from([("objectSid".to_string(), vec![text.clone()])]),
+        bin_attrs: HashMap::new(),
+    };
+    assert_eq!(display_values(&entry, "objectsid"), ["S-1-5-32-544"]);
+    assert_eq!(
+        display_value("thumbnailPhoto", "GIF89a"),
+        "<binary, 6 bytes>"
+    );
+
+    let guid = [
+        0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE,
+        0xFF,
+    ];
+    assert_eq!(
+        decode_guid(&guid).unwrap(),
+        "00112233-4455-6677-8899-aabbccddeeff"
+    );
+    assert!(decode_guid(&guid[..15]).is_none());
+}
+
+#[test]
+fn decodes_special_attribute_values() {
+    assert_eq!(
+        display_value("groupType", "-2147483646"),
+        "GLOBAL | SECURITY (0x80000002)"
+    );
+    assert_eq!(display_value("grouptype", "8"), "UNIVERSAL (0x8)");
+    assert_eq!(
+        display_value("sAMAccountType", "805306368"),
+        "USER_OBJECT (0x30000000)"
+    );
+    assert_eq!(
+        display_value("userAccountControl", "66050"),
+        "ACCOUNTDISABLE | NORMAL_ACCOUNT | DONT_EXPIRE_PASSWORD (0x10202)"
+    );
+    assert_eq!(display_value("maxPwdAge", "-36288000000000"), "42 days");
+    assert_eq!(
+        display_value("lockoutDuration", "-18000000000"),
+        "30 minutes"
+    );
+    assert_eq!(
+        display_value("accountExpires", "9223372036854775807"),
+        "Never"
+    );
+    let local = |utc: &str| {
+        DateTime::parse_from_rfc3339(utc)
+            .unwrap()
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S %:z")
+            .to_string()
+    };
+    assert_eq!(
+        display_value("whenCreated", "20240101120000.0Z"),
+        local("2024-01-01T12:00:00Z")
+    );
+    assert_eq!(
+        display_value("pwdLastSet", "133485408000000000"),
+        local("2024-01-01T00:00:00Z")
+    );
+    // Values that do not parse are shown unchanged
+    assert_eq!(display_value("whenCreated", "yesterday"), "yesterday");
+    assert_eq!(display_value("department", "42"), "42");
+
+    assert_eq!(
+        parse_generalized_time("20240229235959.0Z")
+            .unwrap()
+            .to_rfc3339(),
+        "2024-02-29T23:59:59+00:00"
+    );
+    assert_eq!(format_interval(TimeDelta::minutes(1500)), "1 day 1 hour");
+    assert_eq!(format_byte_size(512), "512 bytes");
+    assert_eq!(format_byte_size(4300), "4.2 KB");
+    assert_eq!(format_byte_size(3 * 1024 * 1024), "3.0 MB");
+}
+
+#[test]
+fn writes_entries_with_binary_attributes() {
+    let mut entry = account_entry(&[("sAMAccountName", "administrator"), ("groupType", "4")]);
+    entry.bin_attrs = HashMap::from([
+        ("objectSid".to_string(), vec![ADMIN_SID.to_vec()]),
+        ("thumbnailPhoto".to_string(), vec![vec![0xFF; 2048]]),
+    ]);
+    assert_eq!(
+        display_values(&entry, "OBJECTSID"),
+        ["S-1-5-21-739136950-1007400292-1317826426-500"]
+    );
+
+    let mut out = Vec::new();
+    write_entry(&mut out, &entry).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "dn: CN=John Doe,OU=Staff,DC=example,DC=com\n\
+         groupType: DOMAIN_LOCAL (0x4)\n\
+         objectSid: S-1-5-21-739136950-1007400292-1317826426-500\n\
+         sAMAccountName: administrator\n\
+         thumbnailPhoto: <binary, 2.0 KB>\n"
+    );
+}
+
+const CHANGES_LDIF: &str = include_str!("fixtures/ldap/changes.ldif");
+
+#[test]
+fn parses_ldif_change_records() {
+    let records = parse_ldif(CHANGES_LDIF).unwrap();
+    let kinds: Vec<&str> = records.iter().map(LdifRecord::kind).collect();
+    assert_eq!(kinds, ["add", "add", "modify", "modrdn", "delete"]);
+
+    assert_eq!(records[1].line, 10);
+    assert_eq!(
+        records[1].dn,
+        "CN=Zoë Müller,OU=Contractors,DC=example,DC=com"
+    );
+    let LdifChange::Add(attrs) = &records[1].change else {
+        panic!("expected an add");
+    };
+    assert_eq!(
+        attrs[1],
+        ("cn".to_string(), vec!["Zoë Müller".as_bytes().to_vec()])
+    );
+    assert_eq!(
+        attrs[3].1[0],
+        b"Contractor for the 2026 migration project, expected to stay until the end of the year"
+    );
+
+    let LdifChange::Modify(mods) = &records[2].change else {
+        panic!("expected a modify");
+    };
+    assert_eq!(
+        mods,
+        &[
+            LdifMod {
+                op: ModOp::Replace,
+                attr: "title".to_string(),
+                values: vec![b"Senior Engineer".to_vec()],
+            },
+            LdifMod {
+                op: ModOp::Add,
+                attr: "otherTelephone".to_string(),
+                values: vec![b"+1 555 0100".to_vec(), b"+1 555 0101".to_vec()],
+            },
+            LdifMod {
+                op: ModOp::Delete,
+                attr: "info".to_string(),
+                values: vec![],
+            },
+        ]
+    );
+
+    assert_eq!(
+        records[3].change,
+        LdifChange::ModRdn {
+            new_rdn: "CN=Jane Smith".to_string(),
+            delete_old_rdn: true,
+            new_superior: Some("OU=Contractors,DC=example,DC=com".to_string()),
+        }
+    );
+}
+
+#[test]
+fn rejects_invalid_ldif_with_line_numbers() {
+    let error = |text: &str| match parse_ldif(text) {
+        Err(LdapToolError::Ldif { line, message }) => (line, message),
+        other => panic!("expected an LDIF error, got {:?}", other),
+    };
+    assert_eq!(
+        error("dn: CN=x,DC=example,DC=com\nchangetype: modify\nreplace: title\ntitle: x\n").0,
+        3
+    );
+    assert_eq!(error("cn: x\n").1, "A record must start with dn:");
+    assert_eq!(
+        error("dn: CN=x,DC=example,DC=com\nchangetype: modrdn\nnewrdn: CN=y\ndeleteoldrdn: 2\n"),
+        (4, "deleteoldrdn must be 0 or 1, got 2".to_string())
+    );
+    assert_eq!(
+        error("dn: CN=x,DC=example,DC=com\nchangetype: rename\n").1,
+        "Unknown changetype rename"
+    );
+    assert_eq!(
+        error("dn: CN=x,DC=example,DC=com\njpegPhoto:< file:///tmp/x.jpg\n").0,
+        2
+    );
+}
+
+#[test]
+fn exported_entries_parse_back() {
+    let mut entry = account_entry(&[
+        ("cn", "Zoë Müller"),
+        ("description", &"x".repeat(100)),
+        ("info", " leading space"),
+        ("sAMAccountName", "zmuller"),
+    ]);
+    entry.bin_attrs = HashMap::from([("objectSid".to_string(), vec![ADMIN_SID.to_vec()])]);
+
+    let mut out = Vec::new();
+    write_ldif_entry(&mut out, &entry).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.lines().all(|line| line.len() <= 76), "{}", text);
+    assert!(text.contains("\ncn:: Wm/DqyBNw7xsbGVy\n"));
+    assert!(text.contains("\ninfo:: IGxlYWRpbmcgc3BhY2U=\n"));
+    assert!(text.contains("\nsAMAccountName: zmuller\n"));
+
+    let records = parse_ldif(&text).unwrap();
+    let LdifChange::Add(attrs) = &records[0].change else {
+        panic!("expected an add");
+    };
+    let attrs: HashMap<&str, &Vec<Vec<u8>>> = attrs.iter().map(|(n, v)| (n.as_str(), v)).collect();
+    assert_eq!(records[0].dn, entry.dn);
+    assert_eq!(attrs["cn"][0], "Zoë Müller".as_bytes());
+    assert_eq!(attrs["description"][0], "x".repeat(100).as_bytes());
+    assert_eq!(attrs["info"][0], b" leading space");
+    assert_eq!(attrs["objectSid"][0], ADMIN_SID);
+
+    assert!(is_safe_string(b"plain value"));
+    assert!(!is_safe_string(b":colon"));
+    assert!(!is_safe_string(b"<angle"));
+    assert!(!is_safe_string(b"trailing "));
+    assert!(!is_safe_string(b"line\nbreak"));
+}
+
+#[test]
+fn validates_ldif_records_against_the_directory() {
+    let directory: HashMap<String, SearchEntry> = [
+        account_entry(&[("cn", "John Doe"), ("info", "old note")]),
+        SearchEntry {
+            dn: "DC=example,DC=com".to_string(),
+            attrs: HashMap::new(),
+            bin_attrs: HashMap::new(),
+        },
+        SearchEntry {
+            dn: "OU=Staff,DC=example,DC=com".to_string(),
+            attrs: HashMap::new(),
+            bin_attrs: HashMap::new(),
+        },
+        SearchEntry {
+            dn: "CN=Jane Roe,OU=Staff,DC=example,DC=com".to_string(),
+            attrs: HashMap::new(),
+            bin_attrs: HashMap::new(),
+        },
+    ]
+    .into_iter()
+    .map(|entry| (entry.dn.to_ascii_lowercase(), entry))
+    .collect();
+    let check = |ldif: &str| {
+        let record = &parse_ldif(ldif).unwrap()[0];
+        validate_record(record, |dn| {
+            Ok(directory.get(&dn.to_ascii_lowercase()).cloned())
+        })
+        .unwrap()
+    };
+
+    let records = parse_ldif(CHANGES_LDIF).unwrap();
+    let problems: Vec<Vec<String>> = records
+        .iter()
+        .map(|record| {
+            validate_record(record, |dn| {
+                Ok(directory.get(&dn.to_ascii_lowercase()).cloned())
+            })
+            .unwrap()
+        })
+        .collect();
+    assert!(problems[0].is_empty());
+    // The OU is only created by the first record
+    assert_eq!(
+        problems[1],
+        ["the parent OU=Contractors,DC=example,DC=com does not exist"]
+    );
+    assert!(problems[2].is_empty());
+    assert_eq!(
+        problems[3],
+        ["the new superior OU=Contractors,DC=example,DC=com does not exist"]
+    );
+    assert_eq!(problems[4], ["the entry does not exist"]);
+
+    assert_eq!(
+        check("dn: CN=John Doe,OU=Staff,DC=example,DC=com\nchangetype: add\ncn: John Doe\n"),
+        ["the entry already exists"]
+    );
+    assert_eq!(
+        check(
+            "dn: CN=John Doe,OU=Staff,DC=example,DC=com\nchangetype: modify\nadd: info\ninfo: OLD NOTE\n-\ndelete: mail\n-\ndelete: cn\ncn: Jane\n-\n"
+        ),
+        [
+            "info already has the value OLD NOTE",
+            "mail has no values to delete",
+            "cn does not have the value Jane"
+        ]
+    );
+    assert_eq!(
+        check(
+            "dn: CN=John Doe,OU=Staff,DC=example,DC=com\nchangetype: modrdn\nnewrdn: CN=Jane Roe\ndeleteoldrdn: 1\n"
+        ),
+        ["CN=Jane Roe,OU=Staff,DC=example,DC=com already exists"]
+    );
+}
+
+#[test]
+fn splits_dns() {
+    assert_eq!(
+        split_dn("CN=Doe\\, John,OU=Staff,DC=example,DC=com"),
+        ("CN=Doe\\, John", Some("OU=Staff,DC=example,DC=com"))
+    );
+    assert_eq!(split_dn("DC=com"), ("DC=com", None));
+    assert_eq!(
+        renamed_dn("CN=a,OU=Staff,DC=example,DC=com", "CN=b", None),
+        "CN=b,OU=Staff,DC=example,DC=com"
+    );
+    assert_eq!(
+        renamed_dn(
+            "CN=a,OU=Staff,DC=example,DC=com",
+            "CN=a",
+            Some("OU=Old,DC=example,DC=com")
+        ),
+        "CN=a,OU=Old,DC=example,DC=com"
+    );
+}
+
+fn stored(attrs: &[(&str, &[&str])]) -> SnapshotEntry {
+    attrs
+        .iter()
+        .map(|(name, values)| {
+            (
+                name.to_string(),
+                values.iter().map(|v| v.to_string()).collect(),
+            )
+        })
+        .collect()
+}
+
+fn snapshot_of(entries: &[(&str, SnapshotEntry)]) -> BTreeMap<String, SnapshotEntry> {
+    entries
+        .iter()
+        .map(|(dn, entry)| (dn.to_string(), entry.clone()))
+        .collect()
+}
+
+#[test]
+fn stores_requested_attributes_in_snapshots() {
+    let mut entry = account_entry(&[
+        ("sAMAccountName", "jdoe"),
+        ("whenChanged", "20260301120000.0Z"),
+    ]);
+    entry.attrs.insert(
+        "member".to_string(),
+        vec!["CN=b".to_string(), "CN=a".to_string()],
+    );
+    entry.bin_attrs = HashMap::from([
+        ("objectSid".to_string(), vec![ADMIN_SID.to_vec()]),
+        ("thumbnailPhoto".to_string(), vec![vec![0xFF, 0xD8]]),
+    ]);
+    let attributes: Vec<String> = ["samaccountname", "member", "objectSid", "thumbnailPhoto"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    assert_eq!(
+        snapshot_entry(&entry, &attributes),
+        stored(&[
+            ("member", &["CN=a", "CN=b"]),
+            (
+                "objectSid",
+                &["S-1-5-21-739136950-1007400292-1317826426-500"]
+            ),
+            ("sAMAccountName", &["jdoe"]),
+            ("thumbnailPhoto", &["/9g="]),
+        ])
+    );
+
+    // A SID whose bytes are valid UTF-8 comes back as text and is stored the same way
+    let builtin_admins = [1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 0x20, 0x02, 0, 0];
+    let as_text = SearchEntry {
+        attrs: HashMap::from([(
+            "objectSid".to_string(),
+            vec![String::from_utf8(builtin_admins.to_vec()).unwrap()],
+        )]),
+        ..account_entry(&[])
+    };
+    let as_binary = SearchEntry {
+        bin_attrs: HashMap::from([("objectSid".to_string(), vec![builtin_admins.to_vec()])]),
+        ..account_entry(&[])
+    };
+    let expected = stored(&[("objectSid", &["S-1-5-32-544"])]);
+    assert_eq!(snapshot_entry(&as_text, &attributes), expected);
+    assert_eq!(snapshot_entry(&as_binary, &attributes), expected);
+}
+
+#[test]
+fn diffs_snapshots() {
+    let old = snapshot_of(&[
+        (
+            "CN=John Doe,OU=Staff,DC=example,DC=com",
+            stored(&[
+                ("userAccountControl", &["512"]),
+                ("mail", &["jdoe@example.com"]),
+            ]),
+        ),
+        (
+            "CN=Staff,OU=Groups,DC=example,DC=com",
+            stored(&[("member", &["CN=a", "CN=b"])]),
+        ),
+        ("CN=Leaver,OU=Staff,DC=example,DC=com", stored(&[])),
+    ]);
+    let new = snapshot_of(&[
+        (
+            "cn=john doe,ou=staff,dc=example,dc=com",
+            stored(&[
+                ("userAccountControl", &["514"]),
+                ("mail", &["jdoe@example.com"]),
+            ]),
+        ),
+        (
+            "CN=Staff,OU=Groups,DC=example,DC=com",
+            stored(&[("member", &["CN=b", "CN=c", "CN=d"])]),
+        ),
+        ("CN=Starter,OU=Staff,DC=example,DC=com", stored(&[])),
+    ]);
+
+    let changes = diff_entries(&old, &new);
+    assert_eq!(
+        changes,
+        [
+            EntryChange::Modified {
+                dn: "cn=john doe,ou=staff,dc=example,dc=com".to_string(),
+                changes: vec![AttributeChange {
+                    attr: "userAccountControl".to_string(),
+                    removed: vec!["512".to_string()],
+                    added: vec!["514".to_string()],
+                }],
+            },
+            EntryChange::Removed("CN=Leaver,OU=Staff,DC=example,DC=com".to_string()),
+            EntryChange::Modified {
+                dn: "CN=Staff,OU=Groups,DC=example,DC=com".to_string(),
+                changes: vec![AttributeChange {
+                    attr: "member".to_string(),
+                    removed: vec!["CN=a".to_string()],
+                    added: vec!["CN=c".to_string(), "CN=d".to_string()],
+                }],
+            },
+            EntryChange::Added("CN=Starter,OU=Staff,DC=example,DC=com".to_string()),
+        ]
+    );
+
+    let mut out = Vec::new();
+    write_diff(&mut out, &changes).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "~ cn=john doe,ou=staff,dc=example,dc=com\n\
+         \x20   userAccountControl: NORMAL_ACCOUNT (0x200) -> ACCOUNTDISABLE | NORMAL_ACCOUNT (0x202)\n\
+         - CN=Leaver,OU=Staff,DC=example,DC=com\n\
+         ~ CN=Staff,OU=Groups,DC=example,DC=com\n\
+         \x20   member: - CN=a\n\
+         \x20   member: + CN=c\n\
+         \x20   member: + CN=d\n\
+         + CN=Starter,OU=Staff,DC=example,DC=com\n\
+         1 added, 1 removed, 2 modified\n"
+    );
+}
+
+fn snapshot_at(created: &str) -> Snapshot {
+    Snapshot {
+        created: DateTime::parse_from_rfc3339(created).unwrap().to_utc(),
+        server: Some("dc01.example.com".to_string()),
+        highest_usn: Some(41_000),
+        base: "DC=example,DC=com".to_string(),
+        filter: "(objectClass=group)".to_string(),
+        attributes: vec!["member".to_string()],
+        entries: BTreeMap::new(),
+    }
+}
+
+#[test]
+fn limits_live_fetches_to_changed_objects() {
+    let snapshot = snapshot_at("2026-03-01T12:00:00Z");
+    assert_eq!(
+        changed_since_filter(&snapshot, Some("DC01.example.com")),
+        "(&(objectClass=group)(uSNChanged>=41001))"
+    );
+    // USNs are local to a DC, so another DC falls back to whenChanged
+    assert_eq!(
+        changed_since_filter(&snapshot, Some("dc02.example.com")),
+        "(&(objectClass=group)(whenChanged>=20260301110000.0Z))"
+    );
+
+    let entries = snapshot_of(&[
+        ("CN=Unchanged", stored(&[("member", &["CN=a"])])),
+        ("CN=Changed", stored(&[("member", &["CN=a"])])),
+        ("CN=Deleted", stored(&[("member", &["CN=a"])])),
+    ]);
+    let changed = snapshot_of(&[
+        ("CN=Changed", stored(&[("member", &["CN=b"])])),
+        ("CN=New", stored(&[])),
+    ]);
+    let present: BTreeSet<String> = ["cn=unchanged", "cn=changed", "cn=new"]
+        .iter()
+        .map(|dn| dn.to_string())
+        .collect();
+    assert_eq!(
+        merge_changes(&entries, changed, &present),
+        snapshot_of(&[
+            ("CN=Unchanged", stored(&[("member", &["CN=a"])])),
+            ("CN=Changed", stored(&[("member", &["CN=b"])])),
+            ("CN=New", stored(&[])),
+        ])
+    );
+}
+
+#[test]
+fn saves_and_lists_snapshots() {
+    let dir = std::env::temp_dir().join(format!("jh_admin_snapshots_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(list_snapshots(&dir).is_empty());
+
+    let mut older = snapshot_at("2026-03-01T12:00:00Z");
+    older
+        .entries
+        .insert("CN=Staff".to_string(), stored(&[("member", &["CN=a"])]));
+    let newer = snapshot_at("2026-03-08T12:00:00Z");
+    let newer_path = save_snapshot(&dir, &newer).unwrap();
+    let older_path = save_snapshot(&dir, &older).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a snapshot").unwrap();
+
+    assert_eq!(list_snapshots(&dir), [older_path.clone(), newer_path]);
+    assert!(older_path.ends_with("snapshot-20260301-120000.json"));
+    assert_eq!(load_snapshot(&older_path).unwrap(), older);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn child(dn: &str, kind: ObjectKind) -> ChildObject {
+    ChildObject {
+        dn: dn.to_string(),
+        name: split_dn(dn).0.split_once('=').unwrap().1.to_string(),
+        kind,
+    }
+}
+
+#[test]
+fn classifies_objects_by_class() {
+    let classes = |names: &[&str]| -> Vec<String> { names.iter().map(|n| n.to_string()).collect() };
+    assert_eq!(
+        ObjectKind::from_classes(&classes(&[
+            "top",
+            "person",
+            "organizationalPerson",
+            "user",
+            "computer"
+        ])),
+        ObjectKind::Computer
+    );
+    assert_eq!(
+        ObjectKind::from_classes(&classes(&["top", "person", "organizationalPerson", "user"])),
+        ObjectKind::User
+    );
+    assert_eq!(
+        ObjectKind::from_classes(&classes(&["top", "group"])),
+        ObjectKind::Group
+    );
+    assert_eq!(
+        ObjectKind::from_classes(&classes(&["top", "organizationalUnit"])),
+        ObjectKind::Container
+    );
+    assert_eq!(
+        ObjectKind::from_classes(&classes(&["top", "contact"])),
+        ObjectKind::Other
+    );
+}
+
+#[test]
+fn browses_the_ou_tree_lazily() {
+    let mut loaded = Vec::new();
+    let mut load = |dn: &str| {
+        loaded.push(dn.to_string());
+        Ok(match dn {
+            "DC=example,DC=com" => vec![
+                child("OU=Staff,DC=example,DC=com", ObjectKind::Container),
+                child("CN=Users,DC=example,DC=com", ObjectKind::Container),
+                child("CN=Administrator,DC=example,DC=com", ObjectKind::User),
+            ],
+            "OU=Staff,DC=example,DC=com" => vec![
+                child("OU=Sales,OU=Staff,DC=example,DC=com", ObjectKind::Container),
+                child("CN=jdoe,OU=Staff,DC=example,DC=com", ObjectKind::User),
+                child("CN=jroe,OU=Staff,DC=example,DC=com", ObjectKind::User),
+                child("CN=Staff,OU=Staff,DC=example,DC=com", ObjectKind::Group),
+                child("CN=WS01,OU=Staff,DC=example,DC=com", ObjectKind::Computer),
+                child("CN=Printer,OU=Staff,DC=example,DC=com", ObjectKind::Other),
+            ],
+            _ => vec![],
+        })
+    };
+
+    let mut tree = OuTree::new("DC=example,DC=com");
+    tree.toggle(0, &mut load).unwrap();
+    tree.load_visible(&mut load).unwrap();
+    let visible = tree.visible();
+    assert_eq!(visible.len(), 3);
+    // Children are sorted by name
+    assert_eq!(tree.dn(visible[1]), "OU=Staff,DC=example,DC=com");
+
+    tree.toggle(visible[1], &mut load).unwrap();
+    tree.load_visible(&mut load).unwrap();
+    let mut out = Vec::new();
+    tree.write(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "  1. [-] DC=example,DC=com (1 users, 0 groups, 0 computers)\n  \
+         2.   [-] Staff (2 users, 1 groups, 1 computers, 1 other)\n  \
+         3.     [ ] Sales (0 users, 0 groups, 0 computers)\n  \
+         4.   [ ] Users (0 users, 0 groups, 0 computers)\n"
+    );
+
+    // Collapsing hides the children without loading anything again
+    tree.toggle(visible[1], &mut load).unwrap();
+    assert_eq!(tree.visible().len(), 3);
+    tree.load_visible(&mut load).unwrap();
+    assert_eq!(
+        loaded,
+        [
+            "DC=example,DC=com",
+            "OU=Staff,DC=example,DC=com",
+            "CN=Users,DC=example,DC=com",
+            "OU=Sales,OU=Staff,DC=example,DC=com",
+        ]
+    );
+}
+
+#[test]
+fn refuses_moves_into_the_object_itself() {
+    let ou = "OU=Staff,DC=example,DC=com";
+    assert!(is_within("OU=Sales,OU=Staff,DC=example,DC=com", ou));
+    assert!(is_within("ou=staff,dc=example,dc=com", ou));
+    assert!(!is_within("OU=Leavers,DC=example,DC=com", ou));
+    assert!(!is_within(
+        "OU=OldStaff,DC=example,DC=com",
+        "Staff,DC=example,DC=com"
+    ));
+}
+
+fn computer_entry(dn: &str, attrs: &[(&str, &str)]) -> SearchEntry {
+    SearchEntry {
+        dn: dn.to_string(),
+        ..account_entry(attrs)
+    }
+}
+
+#[test]
+fn reads_computer_accounts() {
+    let now = DateTime::parse_from_rfc3339("2024-06-30T12:00:00Z")
+        .unwrap()
+        .to_utc();
+    let entry = computer_entry(
+        "CN=WS01,OU=Workstations,DC=example,DC=com",
+        &[
+            ("cn", "WS01"),
+            ("dNSHostName", "ws01.example.com"),
+            ("operatingSystem", "Windows 11 Enterprise"),
+            ("operatingSystemVersion", "10.0 (22631)"),
+            ("lastLogonTimestamp", &filetime_at("2024-06-20T08:00:00Z")),
+            ("userAccountControl", "4096"),
+        ],
+    );
+    let computer = Computer::from_entry(&entry, now, 90);
+    assert_eq!(computer.ou, "OU=Workstations,DC=example,DC=com");
+    assert!(computer.enabled);
+    assert!(!computer.stale);
+    assert_eq!(computer.status(), "active");
+    assert_eq!(
+        computer.to_host(&inventory_source(None)),
+        Host {
+            hostname: "ws01.example.com".to_string(),
+            name: "WS01".to_string(),
+            os: Some("Windows 11 Enterprise".to_string()),
+            os_version: Some("10.0 (22631)".to_string()),
+            location: Some("OU=Workstations,DC=example,DC=com".to_string()),
+            last_seen: computer.last_logon,
+            enabled: true,
+            stale: false,
+            source: "ldap".to_string(),
+        }
+    );
+    assert!(Computer::from_entry(&entry, now, 5).stale);
+
+    // Never logged on: stale once the join (pwdLastSet) is older than the threshold
+    let joined = |time: &str| {
+        computer_entry(
+            "CN=SRV02,CN=Computers,DC=example,DC=com",
+            &[
+                ("sAMAccountName", "SRV02$"),
+                ("pwdLastSet", &filetime_at(time)),
+                ("userAccountControl", "4098"),
+            ],
+        )
+    };
+    let old = Computer::from_entry(&joined("2024-01-01T00:00:00Z"), now, 90);
+    assert_eq!(old.name, "SRV02");
+    assert!(old.stale && !old.enabled);
+    assert_eq!(old.status(), "disabled");
+    assert_eq!(old.row()[4], "Never");
+    assert_eq!(old.to_host("ldap:lab").hostname, "SRV02");
+    assert_eq!(inventory_source(Some("lab")), "ldap:lab");
+    assert!(!Computer::from_entry(&joined("2024-06-01T00:00:00Z"), now, 90).stale);
+}
+
+#[test]
+fn updates_host_inventory_per_source() {
+    let path = std::env::temp_dir().join(format!("jh_admin_hosts_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let host = |hostname: &str, source: &str| Host {
+        hostname: hostname.to_string(),
+        name: hostname.to_string(),
+        os: None,
+        os_version: None,
+        location: None,
+        last_seen: None,
+        enabled: true,
+        stale: false,
+        source: source.to_string(),
+    };
+
+    assert!(load_inventory(&path).unwrap().hosts.is_empty());
+    let hosts = vec![host("web01", "manual"), host("ws02", "ldap")];
+    assert_eq!(update_inventory(&path, "manual", hosts).unwrap(), 2);
+    assert_eq!(
+        update_inventory(&path, "ldap", vec![host("ws01", "ldap")]).unwrap(),
+        2
+    );
+
+    let inventory = load_inventory(&path).unwrap();
+    let names: Vec<&str> = inventory
+        .hosts
+        .iter()
+        .map(|h| h.hostname.as_str())
+        .collect();
+    assert_eq!(names, ["web01", "ws01"]);
+    assert!(inventory.updated.is_some());
+
+    // A filtered export updates its hosts and leaves the other LDAP hosts in place, and
+    // exports from another target have their own source
+    let hosts = vec![host("ws02", "ldap"), host("WS01", "ldap")];
+    assert_eq!(update_inventory(&path, "ldap", hosts).unwrap(), 3);
+    let mut updated = host("ws01", "ldap");
+    updated.stale = true;
+    assert_eq!(merge_inventory(&path, "ldap", vec![updated]).unwrap(), 3);
+    let lab = vec![host("ws02", "ldap:lab")];
+    assert_eq!(update_inventory(&path, "ldap:lab", lab).unwrap(), 4);
+    let inventory = load_inventory(&path).unwrap();
+    let hosts: Vec<(&str, &str, bool)> = inventory
+        .hosts
+        .iter()
+        .map(|h| (h.hostname.as_str(), h.source.as_str(), h.stale))
+        .collect();
+    assert_eq!(
+        hosts,
+        [
+            ("web01", "manual", false),
+            ("ws01", "ldap", true),
+            ("ws02", "ldap", false),
+            ("ws02", "ldap:lab", false),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn parses_csv() {
+    let text = "\u{feff}a,b,c\r\n1,\"two, \"\"quoted\"\"\",\"multi\nline\"\r\n\r\n,,\n4";
+    assert_eq!(
+        parse_csv(text).unwrap(),
+        vec![
+            vec!["a", "b", "c"],
+            vec!["1", "two, \"quoted\"", "multi\nline"],
+            vec!["", "", ""],
+            vec!["4"],
+        ]
+    );
+    assert!(matches!(
+        parse_csv("a,\"b\n"),
+        Err(LdapToolError::Config(_))
+    ));
+    let rows = vec![vec!["x,y".to_string(), "say \"hi\"".to_string()]];
+    assert_eq!(
+        parse_csv(&format_csv(&["a", "b"], &rows)).unwrap()[1],
+        rows[0]
+    );
+}
+
+#[test]
+fn parses_edit_sheets() {
+    let sheet = parse_edit_sheet(
+        "mail,title,otherTelephone:add,manager:replace,facsimileTelephoneNumber:clear\n\
+         jdoe@example.com,Senior Engineer,,,\n\
+         asmith@example.com,,+1 555 0101; +1 555 0102,jdoe,x\n",
+    )
+    .unwrap();
+    assert_eq!(sheet.key, AccountKey::Mail);
+    assert_eq!(sheet.rows.len(), 2);
+    assert_eq!(sheet.rows[0].row, 2);
+    assert_eq!(sheet.rows[0].key, "jdoe@example.com");
+    assert_eq!(
+        sheet.rows[0].edits,
+        [AttributeEdit {
+            attr: "title".to_string(),
+            op: EditOp::Replace,
+            values: vec!["Senior Engineer".to_string()],
+        }]
+    );
+    let ops: Vec<(&str, EditOp, usize)> = sheet.rows[1]
+        .edits
+        .iter()
+        .map(|edit| (edit.attr.as_str(), edit.op, edit.values.len()))
+        .collect();
+    assert_eq!(
+        ops,
+        [
+            ("otherTelephone", EditOp::Add, 2),
+            ("manager", EditOp::Replace, 1),
+            ("facsimileTelephoneNumber", EditOp::Clear, 0),
+        ]
+    );
+
+    for invalid in [
+        "cn,title\njdoe,x\n",
+        "sAMAccountName\njdoe\n",
+        "sAMAccountName,title:append\njdoe,x\n",
+        "sAMAccountName,title,Title\njdoe,x,y\n",
+        "sAMAccountName,title\n,x\n",
+        "sAMAccountName,title\njdoe,x,y\n",
+    ] {
+        assert!(
+            matches!(parse_edit_sheet(invalid), Err(LdapToolError::Config(_))),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn plans_attribute_edits() {
+    let mut entry = account_entry(&[("title", "Engineer"), ("department", "IT")]);
+    entry.attrs.insert(
+        "otherTelephone".to_string(),
+        vec!["+1 555 0100".to_string()],
+    );
+    let edit = |attr: &str, op, values: &[&str]| AttributeEdit {
+        attr: attr.to_string(),
+        op,
+        values: values.iter().map(|v| v.to_string()).collect(),
+    };
+    let planned = plan_edits(
+        &entry,
+        &[
+            edit("title", EditOp::Replace, &["Senior Engineer"]),
+            edit("department", EditOp::Replace, &["IT"]),
+            edit(
+                "otherTelephone",
+                EditOp::Add,
+                &["+1 555 0100", "+1 555 0199"],
+            ),
+            edit("mobile", EditOp::Clear, &[]),
+            edit("department", EditOp::Clear, &[]),
+        ],
+    );
+    let attrs: Vec<&str> = planned.iter().map(|p| p.attr.as_str()).collect();
+    assert_eq!(attrs, ["title", "otherTelephone", "department"]);
+    assert_eq!(planned[0].before, ["Engineer"]);
+    assert_eq!(planned[0].change().removed, ["Engineer"]);
+    assert_eq!(planned[0].change().added, ["Senior Engineer"]);
+    assert_eq!(planned[1].after, ["+1 555 0100", "+1 555 0199"]);
+    assert!(planned[1].change().removed.is_empty());
+    assert!(planned[2].after.is_empty());
+
+    let set = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<HashSet<_>>();
+    let title: &PlannedEdit = &planned[0];
+    assert_eq!(
+        title.apply_mod(),
+        Mod::Replace("title".to_string(), set(&["Senior Engineer"]))
+    );
+    assert_eq!(
+        title.rollback_mod(),
+        Mod::Replace("title".to_string(), set(&["Engineer"]))
+    );
+    assert_eq!(
+        planned[1].apply_mod(),
+        Mod::Add("otherTelephone".to_string(), set(&["+1 555 0199"]))
+    );
+    assert_eq!(
+        planned[1].rollback_mod(),
+        Mod::Delete("otherTelephone".to_string(), set(&["+1 555 0199"]))
+    );
+    assert_eq!(
+        planned[2].apply_mod(),
+        Mod::Replace("department".to_string(), set(&[]))
+    );
+    assert_eq!(
+        planned[2].rollback_mod(),
+        Mod::Replace("department".to_string(), set(&["IT"]))
+    );
+}
+
+#[test]
+fn parses_schema_definitions() {
+    let cn = parse_attribute_type(
+        "( 2.5.4.3 NAME ( 'cn' 'commonName' ) DESC 'RFC4519: common name\\27s value' \
+         SUP name EQUALITY caseIgnoreMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15{64} \
+         SINGLE-VALUE X-ORIGIN ( 'RFC 4519' 'RFC 2256' ) )",
+    )
+    .unwrap();
+    assert_eq!(cn.oid, "2.5.4.3");
+    assert_eq!(cn.names, ["cn", "commonName"]);
+    assert_eq!(
+        cn.description.as_deref(),
+        Some("RFC4519: common name's value")
+    );
+    assert_eq!(cn.superior.as_deref(), Some("name"));
+    assert_eq!(cn.syntax.as_deref(), Some("1.3.6.1.4.1.1466.115.121.1.15"));
+    assert_eq!(cn.syntax_name(), "Directory String");
+    assert!(cn.single_value && !cn.no_user_modification);
+
+    let ad = parse_attribute_type(
+        "( 1.2.840.113556.1.4.96 NAME 'pwdLastSet' SYNTAX '1.2.840.113556.1.4.906' SINGLE-VALUE )",
+    )
+    .unwrap();
+    assert_eq!(ad.syntax_name(), "Large Integer");
+    let unnamed = parse_attribute_type("( 1.2.3.4 SYNTAX 1.2.3.5 )").unwrap();
+    assert_eq!(unnamed.name(), "1.2.3.4");
+    assert_eq!(unnamed.syntax_name(), "1.2.3.5");
+
+    let user = parse_object_class(
+        "( 1.2.840.113556.1.5.9 NAME 'user' SUP ( organizationalPerson $ top ) STRUCTURAL \
+         MUST cn MAY ( mail $ userPrincipalName ) )",
+    )
+    .unwrap();
+    assert_eq!(user.superiors, ["organizationalPerson", "top"]);
+    assert_eq!(user.kind, ClassKind::Structural);
+    assert_eq!(user.must, ["cn"]);
+    assert_eq!(user.may, ["mail", "userPrincipalName"]);
+
+    for invalid in [
+        "2.5.4.3 NAME 'cn'",
+        "( 2.5.4.3 NAME 'cn )",
+        "( 2.5.4.3 NAME ( 'cn' )",
+    ] {
+        assert!(
+            matches!(parse_attribute_type(invalid), Err(LdapToolError::Config(_))),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn resolves_class_attributes_through_the_hierarchy() {
+    let definitions = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    let schema = Schema::from_definitions(
+        &definitions(&[
+            "( 2.5.4.0 NAME 'objectClass' SYNTAX 1.3.6.1.4.1.1466.115.121.1.38 )",
+            "( 2.5.4.3 NAME ( 'cn' 'commonName' ) SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 SINGLE-VALUE )",
+            "( 2.5.4.4 NAME 'sn' SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 SINGLE-VALUE )",
+            "( 2.5.4.12 NAME 'title' SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 SINGLE-VALUE )",
+            "( 2.5.4.31 NAME 'member' SYNTAX 1.3.6.1.4.1.1466.115.121.1.12 )",
+            "( 1.2.840.113556.1.4.221 NAME 'sAMAccountName' SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 )",
+            "not a definition",
+        ]),
+        &definitions(&[
+            "( 2.5.6.0 NAME 'top' ABSTRACT MUST objectClass MAY commonName )",
+            "( 2.5.6.6 NAME 'person' SUP top STRUCTURAL MUST cn MAY ( sn $ title ) )",
+            "( 1.2.3.1 NAME 'employee' SUP person STRUCTURAL MUST title )",
+            "( 1.2.3.2 NAME 'principal' SUP top AUXILIARY MUST sAMAccountName )",
+        ]),
+        &definitions(&["( 1.2.3.1 NAME 'employee' AUX principal )"]),
+    );
+    assert_eq!(schema.skipped, ["not a definition"]);
+    assert_eq!(schema.attributes().len(), 6);
+    assert_eq!(schema.attribute("COMMONNAME").unwrap().oid, "2.5.4.3");
+    assert_eq!(
+        schema.attribute("member;range=0-1499").unwrap().name(),
+        "member"
+    );
+    assert_eq!(schema.class("Employee").unwrap().auxiliary, ["principal"]);
+
+    let attributes: Vec<(String, bool, String)> = schema
+        .class_attributes("employee")
+        .unwrap()
+        .into_iter()
+        .map(|a| (a.name, a.required, a.class))
+        .collect();
+    let expected = [
+        ("cn", true, "person"),
+        ("objectClass", true, "top"),
+        ("sAMAccountName", true, "principal"),
+        ("title", true, "employee"),
+        ("sn", false, "person"),
+    ];
+    assert_eq!(
+        attributes,
+        expected.map(|(name, required, class)| (name.to_string(), required, class.to_string()))
+    );
+    assert!(matches!(
+        schema.class_attributes("nobody"),
+        Err(LdapToolError::NotFound(_))
+    ));
+
+    let with_title: Vec<(&str, bool)> = schema
+        .classes_with("title")
+        .into_iter()
+        .map(|(class, required)| (class.name(), required))
+        .collect();
+    assert_eq!(with_title, [("employee", true), ("person", false)]);
+
+    let names = |list: &[&str]| list.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    assert!(
+        schema
+            .check_attributes(&names(&["cn", "TITLE", "*", "+", "dn"]))
+            .is_ok()
+    );
+    match schema.check_attributes(&names(&["cn", "titel"])) {
+        Err(LdapToolError::Config(message)) => {
+            assert_eq!(message, "Unknown attribute titel (did you mean title?)")
+        }
+        other => panic!("expected an unknown attribute, got {:?}", other),
+    }
+    match schema.check_attributes(&names(&["department"])) {
+        Err(LdapToolError::Config(message)) => assert_eq!(message, "Unknown attribute department"),
+        other => panic!("expected an unknown attribute, got {:?}", other),
+    }
+}
+
+#[test]
+fn reads_password_policies() {
+    let domain = PolicySettings::from_domain(&SearchEntry {
+        dn: "DC=example,DC=com".to_string(),
+        ..account_entry(&[
+            ("minPwdLength", "8"),
+            ("pwdProperties", "17"),
+            ("pwdHistoryLength", "24"),
+            ("minPwdAge", "-864000000000"),
+            ("maxPwdAge", "-9223372036854775808"),
+            ("lockoutThreshold", "0"),
+            ("lockoutDuration", "-18000000000"),
+        ])
+    });
+    assert_eq!(domain.source, PolicySource::Domain);
+    assert_eq!(domain.name(), "Domain policy");
+    assert!(domain.complexity && domain.reversible_encryption);
+    assert_eq!(domain.min_age, Some(TimeDelta::days(1)));
+    assert_eq!(domain.max_age, None);
+    assert_eq!(domain.lockout_window, None);
+    let rows = domain.describe();
+    let value = |rows: &[Vec<String>], setting: &str| {
+        rows.iter()
+            .find(|row| row[0] == setting)
+            .map(|row| row[1].clone())
+    };
+    assert_eq!(
+        value(&rows, "Maximum password age").as_deref(),
+        Some("never expires")
+    );
+    assert_eq!(
+        value(&rows, "Lockout threshold").as_deref(),
+        Some("never locks out")
+    );
+    assert_eq!(value(&rows, "Lockout duration").as_deref(), Some("-"));
+    assert_eq!(value(&rows, "Precedence"), None);
+
+    let mut entry = SearchEntry {
+        dn: "CN=Admins PSO,CN=Password Settings Container,CN=System,DC=example,DC=com".to_string(),
+        ..account_entry(&[
+            ("msDS-PasswordSettingsPrecedence", "10"),
+            ("msDS-PasswordComplexityEnabled", "TRUE"),
+            ("msDS-PasswordReversibleEncryptionEnabled", "FALSE"),
+            ("msDS-MinimumPasswordLength", "15"),
+            ("msDS-MaximumPasswordAge", "-77760000000000"),
+            ("msDS-LockoutThreshold", "3"),
+            ("msDS-LockoutDuration", "-9223372036854775808"),
+            ("msDS-LockoutObservationWindow", "-18000000000"),
+        ])
+    };
+    entry.attrs.insert(
+        "msDS-PSOAppliesTo".to_string(),
+        vec![JANE_ROE.to_string(), STAFF_GROUP.to_string()],
+    );
+    let pso = PolicySettings::from_pso(&entry);
+    assert_eq!(pso.name(), "Admins PSO");
+    assert_eq!(
+        pso.source,
+        PolicySource::Pso {
+            precedence: 10,
+            applies_to: vec![JANE_ROE.to_string(), STAFF_GROUP.to_string()],
+        }
+    );
+    assert!(pso.complexity && !pso.reversible_encryption);
+    assert_eq!(
+        pso.password_policy(),
+        PasswordPolicy {
+            min_length: 15,
+            complexity: true,
+            max_age: Some(TimeDelta::days(90)),
+        }
+    );
+    let rows = pso.describe();
+    assert_eq!(value(&rows, "Precedence").as_deref(), Some("10"));
+    assert_eq!(
+        value(&rows, "Lockout duration").as_deref(),
+        Some("until an administrator unlocks the account")
+    );
+    assert_eq!(
+        value(&rows, "Reset failed attempts after").as_deref(),
+        Some("30 minutes")
+    );
+    assert_eq!(
+        value(&rows, "Applies to"),
+        Some(format!("{}; {}", JANE_ROE, STAFF_GROUP))
+    );
+}
+
+fn person(name: &str, manager: Option<&str>, enabled: bool) -> Person {
+    Person {
+        dn: format!("CN={},OU=Staff,DC=example,DC=com", name),
+        account_name: name.to_lowercase(),
+        display_name: name.to_string(),
+        title: None,
+        department: None,
+        manager: manager.map(|m| format!("CN={},OU=Staff,DC=example,DC=com", m)),
+        direct_reports: Vec::new(),
+        enabled,
+    }
+}
+
+#[test]
+fn builds_org_chart() {
+    let mut ceo = person("Carol", None, true);
+    ceo.title = Some("CEO".to_string());
+    // Managers are linked through `manager`, or only through the manager's `directReports`
+    ceo.direct_reports
+        .push("cn=Dave,ou=staff,dc=example,dc=com".to_string());
+    let people = vec![
+        person("Erin", Some("Dave"), true),
+        person("Dave", None, true),
+        ceo,
+        person("Frank", Some("Gone"), true),
+        person("Oscar", Some("Carol"), false),
+        person("Grace", Some("Oscar"), true),
+        person("Leaver", Some("Dave"), false),
+        person("Xena", Some("Yuri"), true),
+        person("Yuri", Some("Zoe"), true),
+        person("Zoe", Some("Xena"), true),
+        person("Walt", Some("Yuri"), true),
+    ];
+    let chart = OrgChart::build(people);
+
+    let orphans: Vec<(&str, OrphanReason)> = chart
+        .orphans
+        .iter()
+        .map(|o| (o.dn.as_str(), o.reason))
+        .collect();
+    assert_eq!(
+        orphans,
+        [
+            ("CN=Frank,OU=Staff,DC=example,DC=com", OrphanReason::Missing),
+            (
+                "CN=Grace,OU=Staff,DC=example,DC=com",
+                OrphanReason::Disabled
+            ),
+        ]
+    );
+    assert_eq!(
+        chart.cycles,
+        [[
+            "CN=Xena,OU=Staff,DC=example,DC=com",
+            "CN=Yuri,OU=Staff,DC=example,DC=com",
+            "CN=Zoe,OU=Staff,DC=example,DC=com",
+        ]]
+    );
+
+    let mut tree = Vec::new();
+    chart.write_tree(&mut tree).unwrap();
+    assert_eq!(
+        String::from_utf8(tree).unwrap(),
+        "Carol (carol), CEO\n\
+         \x20   Dave (dave)\n\
+         \x20       Erin (erin)\n\
+         \x20   Oscar (oscar) [disabled]\n\
+         \x20       Grace (grace) [manager disabled]\n\
+         Frank (frank) [manager not found]\n\
+         Xena (xena) [cycle]\n\
+         \x20   Zoe (zoe) [cycle]\n\
+         \x20       Yuri (yuri) [cycle]\n\
+         \x20           Walt (walt)\n"
+    );
+
+    let dot = chart.to_dot();
+    assert!(dot.starts_with("digraph orgchart {\n"));
+    assert!(dot.contains("    \"CN=Carol,OU=Staff,DC=example,DC=com\" [label=\"Carol\\nCEO\"];\n"));
+    assert!(dot.contains("[label=\"Oscar\", style=dashed]"));
+    assert!(dot.contains(
+        "    \"CN=Dave,OU=Staff,DC=example,DC=com\" -> \"CN=Erin,OU=Staff,DC=example,DC=com\";\n"
+    ));
+    assert!(!dot.contains("Leaver"));
+
+    let export = chart.export();
+    let roots: Vec<&str> = export.roots.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(roots, ["Carol", "Frank", "Xena"]);
+    assert_eq!(export.roots[0].reports[0].reports[0].name, "Erin");
+    let json = serde_json::to_value(&export).unwrap();
+    assert_eq!(json["orphans"][1]["reason"], "disabled");
+    assert_eq!(
+        json["roots"][2]["reports"][0]["reports"][0]["account_name"],
+        "yuri"
+    );
+}
+
+const JOHN_DOE: &str = "CN=John Doe,OU=Staff,DC=example,DC=com";
+const JANE_ROE: &str = "CN=Jane Roe,OU=Staff,DC=example,DC=com";
+const STAFF_GROUP: &str = "CN=Staff,OU=Groups,DC=example,DC=com";
+const ENGINEERING_GROUP: &str = "CN=Engineering,OU=Groups,DC=example,DC=com";
+const VPN_GROUP: &str = "CN=VPN Users,OU=Groups,DC=example,DC=com";
+
+fn account_names(entries: &[SearchEntry]) -> Vec<&str> {
+    let mut names: Vec<&str> = entries
+        .iter()
+        .map(|e| e.attrs["sAMAccountName"][0].as_str())
+        .collect();
+    names.sort();
+    names
+}
+
+fn bind_as(mock: &LdapMock, username: &str, password: &str) -> Result<(), LdapToolError> {
+    let mut config = mock.config();
+    config.bind = BindMode::Simple {
+        username: username.to_string(),
+        password: password.to_string(),
+    };
+    connect(&config).map(|_| ())
+}
+
+fn reason_of(result: Result<(), LdapToolError>) -> String {
+    match result {
+        Err(LdapToolError::InvalidCredentials(reason)) => reason,
+        other => panic!("expected invalid credentials, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn pages_searches_against_the_directory() {
+    let mock = LdapMock::start();
+    mock.set_size_limit(2);
+    let mut ldap = mock.connect().unwrap();
+
+    let users = search_all(
+        &mut ldap,
+        ldap_mock::BASE_DN,
+        Scope::Subtree,
+        USER_FILTER,
+        &["sAMAccountName"],
+    )
+    .unwrap();
+    assert_eq!(
+        account_names(&users),
+        ["cboss", "jdoe", "jroe", "llocked", "otimer", "svc-admin"]
+    );
+    // Six users in pages of two
+    let searches = mock
+        .operations()
+        .iter()
+        .filter(|op| op.kind == "search")
+        .count();
+    assert_eq!(searches, 3);
+    assert!(!users[0].attrs.contains_key("unicodePwd"));
+
+    let disabled = search_all(
+        &mut ldap,
+        ldap_mock::BASE_DN,
+        Scope::Subtree,
+        &format!(
+            "(&{}(userAccountControl:1.2.840.113556.1.4.803:=2))",
+            USER_FILTER
+        ),
+        &["sAMAccountName"],
+    )
+    .unwrap();
+    assert_eq!(account_names(&disabled), ["otimer"]);
+
+    let staff = search_all(
+        &mut ldap,
+        "OU=Staff,DC=example,DC=com",
+        Scope::OneLevel,
+        "(&(department=Eng*)(!(title=*)))",
+        &["sAMAccountName"],
+    )
+    .unwrap();
+    assert_eq!(account_names(&staff), ["otimer"]);
+}
+
+#[test]
+fn binds_like_active_directory() {
+    let mock = LdapMock::start();
+    assert!(bind_as(&mock, "jdoe@example.com", ldap_mock::PASSWORD).is_ok());
+    assert!(bind_as(&mock, "EXAMPLE\\jdoe", ldap_mock::PASSWORD).is_ok());
+
+    let reason = |username: &str, password: &str| reason_of(bind_as(&mock, username, password));
+    assert!(reason(JOHN_DOE, "wrong").starts_with("invalid username or password"));
+    assert!(reason("nobody@example.com", "wrong").starts_with("invalid username or password"));
+    assert!(reason("otimer@example.com", ldap_mock::PASSWORD).starts_with("account disabled"));
+    assert!(reason("llocked@example.com", ldap_mock::PASSWORD).starts_with("account locked out"));
+
+    // Anonymous sessions may only read the rootDSE
+    let mut config = mock.config();
+    config.bind = BindMode::Anonymous;
+    let mut ldap = connect(&config).unwrap();
+    let root = search_all(
+        &mut ldap,
+        "",
+        Scope::Base,
+        "(objectClass=*)",
+        &["dnsHostName"],
+    )
+    .unwrap();
+    assert_eq!(root[0].attrs["dnsHostName"], [ldap_mock::DNS_HOST_NAME]);
+    assert!(find_user(&mut ldap, ldap_mock::BASE_DN, "jdoe", &["cn"]).is_err());
+}
+
+#[test]
+fn creates_users_and_resets_passwords_over_tls() {
+    let mock = LdapMock::start();
+    let mut plain = mock.connect().unwrap();
+    assert!(matches!(
+        create_user(&mut plain, &new_user()),
+        Err(LdapToolError::InsecureConnection(_))
+    ));
+
+    let mut ldap = mock.connect_tls().unwrap();
+    assert!(ldap.is_encrypted());
+    let mut user = new_user();
+    user.account_name = "jdoe2".to_string();
+    user.user_principal_name = "jdoe2@example.com".to_string();
+    user.must_change_password = true;
+    user.groups = vec![VPN_GROUP.to_string()];
+    let dn = create_user(&mut ldap, &user).unwrap();
+    assert_eq!(dn, user.dn());
+    assert_eq!(mock.password(&dn).as_deref(), Some("Secret1!"));
+    assert_eq!(mock.values(&dn, "userAccountControl"), ["512"]);
+    assert_eq!(mock.values(&dn, "pwdLastSet"), ["0"]);
+    assert_eq!(mock.values(&dn, "name"), ["Doe, Jr. John"]);
+    let found = find_user(&mut ldap, ldap_mock::BASE_DN, "jdoe2", &["memberOf"]).unwrap();
+    assert_eq!(found.dn, dn);
+    assert_eq!(found.attrs["memberOf"], [VPN_GROUP]);
+    assert!(
+        reason_of(bind_as(&mock, "jdoe2@example.com", "Secret1!"))
+            .starts_with("the password must be changed")
+    );
+
+    // The DC refuses the short password and the half-created account is removed again
+    let mut weak = new_user();
+    weak.account_name = "weak".to_string();
+    weak.display_name = "Weak User".to_string();
+    weak.password = "Sh0rt!".to_string();
+    assert!(matches!(
+        create_user(&mut ldap, &weak),
+        Err(LdapToolError::Ldap(ldap3::LdapError::LdapResult { result })) if result.rc == 19
+    ));
+    assert!(!mock.exists(&weak.dn()));
+
+    // So is an account whose group cannot be added
+    let mut orphan = new_user();
+    orphan.account_name = "orphan".to_string();
+    orphan.display_name = "Orphan User".to_string();
+    orphan.groups = vec![
+        VPN_GROUP.to_string(),
+        "CN=Missing,OU=Groups,DC=example,DC=com".to_string(),
+    ];
+    assert!(create_user(&mut ldap, &orphan).is_err());
+    assert!(!mock.exists(&orphan.dn()));
+    assert!(
+        !mock
+            .values(VPN_GROUP, "member")
+            .iter()
+            .any(|member| member.eq_ignore_ascii_case(&orphan.dn()))
+    );
+
+    let policy = domain_policy(&mut ldap).unwrap().password_policy();
+    assert_eq!(policy.min_length, 8);
+    assert!(policy.complexity);
+    assert_eq!(policy.max_age, Some(TimeDelta::days(42)));
+    let password = generate_password(&policy).unwrap();
+    reset_password(&mut ldap, JOHN_DOE, &password, false).unwrap();
+    assert_eq!(mock.password(JOHN_DOE), Some(password.clone()));
+    assert!(bind_as(&mock, "jdoe@example.com", &password).is_ok());
+    assert!(bind_as(&mock, "jdoe@example.com", ldap_mock::PASSWORD).is_err());
+    assert!(matches!(
+        set_password(&mut plain, JOHN_DOE, &password),
+        Err(LdapToolError::InsecureConnection(_))
+    ));
+}
+
+#[test]
+fn changes_accounts_and_groups_in_the_directory() {
+    let mock = LdapMock::start();
+    let mut ldap = mock.connect().unwrap();
+    let base = ldap_mock::BASE_DN;
+
+    let locked = locked_out_accounts(&mut ldap, base).unwrap();
+    assert_eq!(
+        locked
+            .iter()
+            .map(|a| a.account_name.as_str())
+            .collect::<Vec<_>>(),
+        ["llocked"]
+    );
+    let (before, after) =
+        apply_account_action(&mut ldap, base, "llocked", AccountAction::Unlock).unwrap();
+    assert!(before.locked_out);
+    assert!(!after.locked_out);
+    assert!(locked_out_accounts(&mut ldap, base).unwrap().is_empty());
+
+    let (_, after) = apply_account_action(&mut ldap, base, "jdoe", AccountAction::Disable).unwrap();
+    assert!(after.is_disabled());
+    assert_eq!(mock.values(JOHN_DOE, "userAccountControl"), ["514"]);
+    let (_, after) = apply_account_action(&mut ldap, base, "jdoe", AccountAction::Enable).unwrap();
+    assert!(!after.is_disabled());
+
+    let mut members: Vec<MemberNode> = direct_members(&mut ldap, base, STAFF_GROUP).unwrap();
+    members.sort_by(|a, b| a.dn.cmp(&b.dn));
+    assert_eq!(
+        members,
+        [
+            MemberNode {
+                dn: "CN=Carol Boss,OU=Staff,DC=example,DC=com".to_string(),
+                is_group: false
+            },
+            MemberNode {
+                dn: ENGINEERING_GROUP.to_string(),
+                is_group: true
+            },
+            MemberNode {
+                dn: JANE_ROE.to_string(),
+                is_group: false
+            },
+        ]
+    );
+    let nested = search_all(
+        &mut ldap,
+        base,
+        Scope::Subtree,
+        &format!("(&{}{})", USER_FILTER, members_filter(STAFF_GROUP, true)),
+        &["sAMAccountName"],
+    )
+    .unwrap();
+    assert_eq!(account_names(&nested), ["cboss", "jdoe", "jroe", "otimer"]);
+    let groups = search_all(
+        &mut ldap,
+        base,
+        Scope::Subtree,
+        &groups_of_filter(JOHN_DOE, true),
+        &["cn"],
+    )
+    .unwrap();
+    assert_eq!(groups.len(), 2);
+
+    add_member(&mut ldap, VPN_GROUP, JOHN_DOE).unwrap();
+    assert_eq!(mock.values(VPN_GROUP, "member"), [JOHN_DOE]);
+    assert!(matches!(
+        add_member(&mut ldap, VPN_GROUP, JOHN_DOE),
+        Err(LdapToolError::Config(message)) if message.contains("already a member")
+    ));
+    assert!(matches!(
+        remove_member(&mut ldap, VPN_GROUP, JANE_ROE),
+        Err(LdapToolError::Config(message)) if message.contains("not a direct member")
+    ));
+    remove_member(&mut ldap, VPN_GROUP, JOHN_DOE).unwrap();
+    assert!(mock.values(VPN_GROUP, "member").is_empty());
+}
+
+#[test]
+fn applies_ldif_moves_and_snapshots_against_the_directory() {
+    let mock = LdapMock::start();
+    let mut ldap = mock.connect().unwrap();
+    let snapshot = take_snapshot(
+        &mut ldap,
+        ldap_mock::BASE_DN,
+        USER_FILTER,
+        &["title".to_string(), "department".to_string()],
+    )
+    .unwrap();
+    assert_eq!(snapshot.server.as_deref(), Some(ldap_mock::DNS_HOST_NAME));
+    assert!(snapshot.highest_usn.is_some());
+    assert_eq!(snapshot.entries.len(), 6);
+
+    let ldif = "dn: CN=Jane Roe,OU=Staff,DC=example,DC=com\n\
+                changetype: modify\n\
+                replace: title\n\
+                title: Controller\n\
+                -\n\
+                \n\
+                dn: OU=Finance,DC=example,DC=com\n\
+                changetype: add\n\
+                objectClass: organizationalUnit\n\
+                ou: Finance\n";
+    for record in parse_ldif(ldif).unwrap() {
+        apply_record(&mut ldap, &record).unwrap();
+    }
+    let jane = lookup_entry(&mut ldap, JANE_ROE).unwrap().unwrap();
+    assert_eq!(jane.attrs["title"], ["Controller"]);
+    assert!(
+        lookup_entry(&mut ldap, "CN=Nobody,OU=Staff,DC=example,DC=com")
+            .unwrap()
+            .is_none()
+    );
+
+    let moved = move_object(&mut ldap, JANE_ROE, "OU=Finance,DC=example,DC=com").unwrap();
+    assert_eq!(moved, "CN=Jane Roe,OU=Finance,DC=example,DC=com");
+    assert!(mock.values(STAFF_GROUP, "member").contains(&moved));
+    assert!(!mock.exists(JANE_ROE));
+    // The OU is no longer empty
+    let delete = LdifRecord {
+        line: 1,
+        dn: "OU=Finance,DC=example,DC=com".to_string(),
+        change: LdifChange::Delete,
+    };
+    assert!(apply_record(&mut ldap, &delete).is_err());
+
+    let live = live_entries(&mut ldap, &snapshot).unwrap();
+    let changes = diff_entries(&snapshot.entries, &live);
+    assert_eq!(
+        changes,
+        [
+            EntryChange::Removed(JANE_ROE.to_string()),
+            EntryChange::Added(moved.clone()),
+        ]
+    );
+}
+
+#[test]
+fn lists_group_members_from_the_whole_domain() {
+    let fsp = "CN=S-1-5-21-1004336348-1177238915-682003330-1105,\
+               CN=ForeignSecurityPrincipals,DC=example,DC=com";
+    let mock = LdapMock::with_ldif(&format!(
+        "{}\n\
+         dn: CN=ForeignSecurityPrincipals,DC=example,DC=com\n\
+         objectClass: top\n\
+         objectClass: container\n\
+         cn: ForeignSecurityPrincipals\n\n\
+         dn: {}\n\
+         objectClass: top\n\
+         objectClass: foreignSecurityPrincipal\n\
+         cn: S-1-5-21-1004336348-1177238915-682003330-1105\n",
+        ldap_mock::DIRECTORY_FIXTURE,
+        fsp
+    ));
+    let mut ldap = mock.connect().unwrap();
+    add_member(&mut ldap, ENGINEERING_GROUP, fsp).unwrap();
+
+    let direct = group_members(&mut ldap, ENGINEERING_GROUP, Expand::None).unwrap();
+    assert!(direct.objects.iter().any(|dn| dn == fsp));
+    for mode in [Expand::InChain, Expand::Client] {
+        let nested = group_members(&mut ldap, STAFF_GROUP, mode).unwrap();
+        assert!(nested.objects.iter().any(|dn| dn == fsp));
+        assert_eq!(nested.groups, [ENGINEERING_GROUP]);
+    }
+}
+
+#[test]
+fn snapshots_read_every_member_of_large_groups() {
+    let mock = LdapMock::with_ldif(&format!(
+        "{}\n{}",
+        ldap_mock::DIRECTORY_FIXTURE,
+        ldap_mock::large_group_ldif(3200)
+    ));
+    let mut ldap = mock.connect().unwrap();
+    let last = "CN=Contact 3200,OU=Contacts,DC=example,DC=com";
+    // AD returns `member;range=0-1499` whether `member` is listed or all attributes are asked for
+    for attributes in [["member".to_string()], ["*".to_string()]] {
+        let snapshot = take_snapshot(
+            &mut ldap,
+            ldap_mock::BASE_DN,
+            "(objectClass=group)",
+            &attributes,
+        )
+        .unwrap();
+        let group = &snapshot.entries[ldap_mock::LARGE_GROUP];
+        assert_eq!(group["member"].len(), 3200);
+        assert!(group["member"].iter().any(|member| member == last));
+        assert!(group.keys().all(|name| !name.contains(";range=")));
+    }
+
+    let snapshot = take_snapshot(
+        &mut ldap,
+        ldap_mock::BASE_DN,
+        "(objectClass=group)",
+        &["member".to_string()],
+    )
+    .unwrap();
+    remove_member(&mut ldap, ldap_mock::LARGE_GROUP, last).unwrap();
+    let live = live_entries(&mut ldap, &snapshot).unwrap();
+    assert_eq!(
+        diff_entries(&snapshot.entries, &live),
+        [EntryChange::Modified {
+            dn: ldap_mock::LARGE_GROUP.to_string(),
+            changes: vec![AttributeChange {
+                attr: "member".to_string(),
+                removed: vec![last.to_string()],
+                added: Vec::new(),
+            }],
+        }]
+    );
+}
+
+#[test]
+fn fails_over_to_the_next_server() {
+    let mock = LdapMock::start();
+    let dead = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("ldap://{}", listener.local_addr().unwrap())
+    };
+    let live = mock.config().servers[0].clone();
+
+    let mut config = mock.config();
+    config.servers = vec![dead.clone(), live.clone()];
+    let ldap = connect(&config).unwrap();
+    assert_eq!(ldap.server(), live);
+
+    config.servers = vec![dead.clone()];
+    assert!(matches!(connect(&config), Err(LdapToolError::Ldap(_))));
+    config.servers = vec![dead.clone(), dead.clone()];
+    match connect(&config) {
+        Err(LdapToolError::Unavailable(servers)) => assert!(servers.contains(&dead)),
+        other => panic!("expected no server to be reachable, got {:?}", other.err()),
+    }
+
+    // Rejected credentials are not retried on the next server
+    let other = LdapMock::start();
+    config.servers = vec![live, other.config().servers[0].clone()];
+    config.bind = BindMode::Simple {
+        username: ldap_mock::ADMIN_DN.to_string(),
+        password: "wrong".to_string(),
+    };
+    assert!(matches!(
+        connect(&config),
+        Err(LdapToolError::InvalidCredentials(_))
+    ));
+    assert!(other.operations().is_empty());
+}
+
+/// Binds the mock has answered, one per connection opened.
+fn binds(mock: &LdapMock) -> usize {
+    mock.operations()
+        .iter()
+        .filter(|op| op.kind == "bind")
+        .count()
+}
+
+fn read_domain(ldap: &mut LdapConnection) -> Result<Vec<SearchEntry>, LdapToolError> {
+    search_all(
+        ldap,
+        ldap_mock::BASE_DN,
+        Scope::Base,
+        "(objectClass=*)",
+        &["1.1"],
+    )
+}
+
+#[test]
+fn sessions_reuse_the_connection() {
+    let mock = LdapMock::start();
+    for _ in 0..3 {
+        let mut ldap = ldap_session_with(|| mock.connect()).unwrap();
+        assert_eq!(read_domain(&mut ldap).unwrap().len(), 1);
+    }
+    assert_eq!(binds(&mock), 1);
+
+    // A failed operation that leaves the connection working does not discard it
+    {
+        let mut ldap = ldap_session_with(|| mock.connect()).unwrap();
+        let missing = search_all(
+            &mut ldap,
+            "CN=Nobody,DC=example,DC=com",
+            Scope::Base,
+            "(objectClass=*)",
+            &["1.1"],
+        );
+        assert!(missing.is_err());
+    }
+    let mut ldap = ldap_session_with(|| mock.connect()).unwrap();
+    assert_eq!(read_domain(&mut ldap).unwrap().len(), 1);
+    assert_eq!(binds(&mock), 1);
+}
+
+#[test]
+fn sessions_reconnect_after_the_connection_drops() {
+    let mock = LdapMock::start();
+    {
+        let mut ldap = ldap_session_with(|| mock.connect()).unwrap();
+        read_domain(&mut ldap).unwrap();
+    }
+    mock.drop_connections();
+    {
+        let mut ldap = ldap_session_with(|| mock.connect()).unwrap();
+        assert!(matches!(
+            read_domain(&mut ldap),
+            Err(LdapToolError::Ldap(_))
+        ));
+    }
+    let mut ldap = ldap_session_with(|| mock.connect()).unwrap();
+    assert_eq!(read_domain(&mut ldap).unwrap().len(), 1);
+    assert_eq!(binds(&mock), 2);
+}
+
+#[test]
+fn sessions_keep_a_connection_made_after_skipping_a_server() {
+    let mock = LdapMock::start();
+    let dead = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("ldap://{}", listener.local_addr().unwrap())
+    };
+    let mut config = mock.config();
+    config.servers.insert(0, dead);
+
+    for _ in 0..2 {
+        let mut ldap = ldap_session_with(|| connect(&config)).unwrap();
+        read_domain(&mut ldap).unwrap();
+    }
+    assert_eq!(binds(&mock), 1);
+}
+
+#[test]
+fn compares_users_across_directories() {
+    let prod = LdapMock::start();
+    let lab = LdapMock::start();
+    let mut lab_ldap = lab.connect().unwrap();
+    remove_member(&mut lab_ldap, ENGINEERING_GROUP, JOHN_DOE).unwrap();
+    lab_ldap
+        .conn()
+        .modify(
+            JOHN_DOE,
+            vec![Mod::Replace("title", HashSet::from(["Intern"]))],
+        )
+        .unwrap()
+        .success()
+        .unwrap();
+
+    let attrs = ["title", "department", "manager", "memberOf"].map(str::to_string);
+    let attr_refs = ["title", "department", "manager", "memberOf"];
+    let mut prod_ldap = prod.connect().unwrap();
+    let prod_user = find_user(&mut prod_ldap, ldap_mock::BASE_DN, "jdoe", &attr_refs).unwrap();
+    let lab_user = find_user(&mut lab_ldap, ldap_mock::BASE_DN, "jdoe", &attr_refs).unwrap();
+
+    let comparisons = compare_users(
+        &attrs,
+        &[
+            (ldap_mock::BASE_DN, Some(&prod_user)),
+            (ldap_mock::BASE_DN, Some(&lab_user)),
+        ],
+    );
+    let differing: Vec<&str> = comparisons
+        .iter()
+        .filter(|c| c.differs)
+        .map(|c| c.attr.as_str())
+        .collect();
+    assert_eq!(differing, ["title", "memberOf"]);
+    assert_eq!(comparisons[1].values[0], ["Engineering"]);
+    assert_eq!(comparisons[2].values[1], ["CN=Carol Boss,OU=Staff"]);
+    assert_eq!(comparisons[3].values[1], Vec::<String>::new());
+}
+
+#[test]
+fn reads_the_schema_from_the_directory() {
+    let mock = LdapMock::start();
+    let mut ldap = mock.connect().unwrap();
+    let root = read_root_dse(&mut ldap).unwrap();
+    assert_eq!(root.attrs["subschemaSubentry"], [ldap_mock::SUBSCHEMA_DN]);
+    assert_eq!(root.attrs["dnsHostName"], [ldap_mock::DNS_HOST_NAME]);
+
+    let schema = read_schema(&mut ldap).unwrap();
+    assert!(schema.skipped.is_empty(), "{:?}", schema.skipped);
+    let mail = schema.attribute("mail").unwrap();
+    assert_eq!(mail.syntax_name(), "Directory String");
+    assert!(mail.single_value);
+    assert!(schema.attribute("memberOf").unwrap().no_user_modification);
+
+    let user = schema.class_attributes("user").unwrap();
+    let status = |name: &str| {
+        user.iter()
+            .find(|a| a.name == name)
+            .map(|a| (a.required, a.class.as_str()))
+    };
+    assert_eq!(status("sAMAccountName"), Some((true, "securityPrincipal")));
+    assert_eq!(status("cn"), Some((true, "person")));
+    assert_eq!(status("manager"), Some((false, "organizationalPerson")));
+    assert_eq!(status("dNSHostName"), None);
+    assert_eq!(schema.class("computer").unwrap().superiors, ["user"]);
+
+    let check = |csv: &str| match check_edit_sheet(&parse_edit_sheet(csv).unwrap(), &schema) {
+        Ok(()) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    assert_eq!(
+        check("sAMAccountName,title,manager\njdoe,Engineer,cboss\n"),
+        ""
+    );
+    assert!(check("sAMAccountName,titel\njdoe,Engineer\n").contains("did you mean title?"));
+    assert!(
+        check("sAMAccountName,memberOf:add\njdoe,CN=Staff\n").contains("maintained by the server")
+    );
+    assert!(
+        check("sAMAccountName,title\njdoe,Engineer;Manager\n")
+            .contains("CSV row 2: title is single-valued but has 2 values")
+    );
+}
+
+#[test]
+fn resolves_password_settings_objects() {
+    let mock = LdapMock::with_ldif(&format!(
+        "{}\n{}",
+        ldap_mock::DIRECTORY_FIXTURE,
+        ldap_mock::PASSWORD_SETTINGS_FIXTURE
+    ));
+    let mut ldap = mock.connect_tls().unwrap();
+    let base = ldap_mock::BASE_DN;
+    // The domain root comes from the rootDSE, whatever BASE_DN is set to
+    assert_eq!(domain_root(&mut ldap).unwrap(), base);
+    let domain = domain_policy(&mut ldap).unwrap();
+    assert_eq!(domain.min_length, 8);
+    assert_eq!(domain.lockout_threshold, 5);
+    assert_eq!(domain.lockout_duration, Some(TimeDelta::minutes(30)));
+
+    let psos = password_settings(&mut ldap).unwrap();
+    let names: Vec<String> = psos.iter().map(PolicySettings::name).collect();
+    assert_eq!(names, ["Engineering PSO", "Staff PSO", "Admins PSO"]);
+    assert!(!psos[0].complexity);
+    assert_eq!(psos[2].lockout_duration, None);
+
+    let resultant = |ldap: &mut LdapConnection, account_name: &str| {
+        let user = find_user(ldap, base, account_name, &[RESULTANT_PSO]).unwrap();
+        resultant_policy(ldap, &user).unwrap().name()
+    };
+    for (account_name, policy) in [
+        ("jdoe", "Engineering PSO"),
+        ("otimer", "Engineering PSO"),
+        ("cboss", "Staff PSO"),
+        ("jroe", "Admins PSO"),
+        ("svc-admin", "Admins PSO"),
+        ("llocked", "Domain policy"),
+    ] {
+        assert_eq!(
+            resultant(&mut ldap, account_name),
+            policy,
+            "{}",
+            account_name
+        );
+    }
+    // The constructed attribute is not part of *
+    let user = find_user(&mut ldap, base, "jdoe", &["*"]).unwrap();
+    assert!(!user.attrs.contains_key(RESULTANT_PSO));
+
+    // The DC enforces the PSO, not the domain policy
+    assert!(matches!(
+        set_password(&mut ldap, JANE_ROE, "Short1!pass"),
+        Err(LdapToolError::Ldap(ldap3::LdapError::LdapResult { result })) if result.rc == 19
+    ));
+    set_password(&mut ldap, JANE_ROE, "Much-l0nger-secret").unwrap();
+    set_password(&mut ldap, JOHN_DOE, "lowercaseonly").unwrap();
+
+    // Without the container, as on a directory that is not AD, there are no PSOs
+    let mut plain = LdapMock::start().connect().unwrap();
+    assert!(password_settings(&mut plain).unwrap().is_empty());
+}
+
+#[test]
+fn bulk_moves_only_users_groups_and_computers() {
+    let mock = LdapMock::start();
+    let mut ldap = mock.connect().unwrap();
+    let mut candidates = bulk_move_candidates(
+        &mut ldap,
+        ldap_mock::BASE_DN,
+        "(objectClass=*)",
+        "OU=Groups,DC=example,DC=com",
+    )
+    .unwrap();
+    candidates.sort();
+    assert_eq!(
+        candidates,
+        [
+            "CN=Carol Boss,OU=Staff,DC=example,DC=com",
+            JANE_ROE,
+            JOHN_DOE,
+            "CN=Larry Locked,OU=Staff,DC=example,DC=com",
+            "CN=Old Timer,OU=Staff,DC=example,DC=com",
+            ldap_mock::ADMIN_DN,
+            "CN=WS01,OU=Workstations,OU=Computers,DC=example,DC=com",
+        ]
+    );
+
+    // A filter naming an OU matches nothing
+    let ous = bulk_move_candidates(
+        &mut ldap,
+        ldap_mock::BASE_DN,
+        "(ou=Staff)",
+        "OU=Groups,DC=example,DC=com",
+    )
+    .unwrap();
+    assert!(ous.is_empty());
+
+    // So are single moves
+    let jdoe = find_movable(&mut ldap, ldap_mock::BASE_DN, "jdoe").unwrap();
+    assert_eq!(jdoe.dn, JOHN_DOE);
+    let staff = find_movable(&mut ldap, ldap_mock::BASE_DN, "OU=Staff,DC=example,DC=com");
+    assert!(matches!(staff, Err(LdapToolError::NotFound(_))));
+}
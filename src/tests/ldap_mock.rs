@@ -0,0 +1,1691 @@
+//! An in-memory stand-in for an Active Directory domain controller.
+//!
+//! The server speaks enough LDAPv3 (BER over TCP, plain and `ldaps://`) for the real `ldap3`
+//! client to bind, search with paging, and add, modify, rename and delete entries. It is seeded
+//! from an LDIF fixture and behaves like AD where the tools depend on it: bind errors carry the
+//! `data XXX` sub-codes, `memberOf`, `directReports` and `msDS-ResultantPSO` are computed, the
+//! in-chain and bitwise matching rules are supported, and `unicodePwd` is only accepted over an
+//! encrypted connection and checked against the password policy that applies to the account.
+//! Attributes with more than 1500 values are returned in ranges. The subschema subentry named in
+//! the rootDSE is served from its own fixture.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use ldap3::asn1::{PL, StructureTag, TagClass, parse_tag};
+use native_tls::{Identity, TlsAcceptor};
+
+use crate::modules::ldap::{
+    BindMode, LdapConfig, LdapConnection, LdapToolError, LdifChange, TlsConfig, connect,
+    encode_unicode_pwd, parse_ldif, split_dn,
+};
+
+pub const DIRECTORY_FIXTURE: &str = include_str!("fixtures/ldap/directory.ldif");
+/// PSOs to append to the directory fixture.
+pub const PASSWORD_SETTINGS_FIXTURE: &str = include_str!("fixtures/ldap/password_settings.ldif");
+const SCHEMA_FIXTURE: &str = include_str!("fixtures/ldap/schema.ldif");
+const SERVER_CERT: &[u8] = include_bytes!("fixtures/ldap/server.pem");
+const SERVER_KEY: &[u8] = include_bytes!("fixtures/ldap/server.key");
+
+pub const BASE_DN: &str = "DC=example,DC=com";
+pub const DNS_HOST_NAME: &str = "dc01.example.com";
+pub const ADMIN_DN: &str = "CN=Service Admin,OU=Service Accounts,DC=example,DC=com";
+/// Password of every account in the fixture.
+pub const PASSWORD: &str = "Passw0rd!";
+
+/// A group with more members than a search returns at once; see [`large_group_ldif`].
+pub const LARGE_GROUP: &str = "CN=All Contacts,OU=Groups,DC=example,DC=com";
+
+const SCHEMA_DN: &str = "CN=Schema,CN=Configuration,DC=example,DC=com";
+pub const SUBSCHEMA_DN: &str = "CN=Aggregate,CN=Schema,CN=Configuration,DC=example,DC=com";
+const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+const MATCHING_RULE_BIT_AND: &str = "1.2.840.113556.1.4.803";
+const MATCHING_RULE_BIT_OR: &str = "1.2.840.113556.1.4.804";
+const MATCHING_RULE_IN_CHAIN: &str = "1.2.840.113556.1.4.1941";
+/// Attributes holding DNs that are rewritten when an object is renamed or deleted.
+const DN_ATTRIBUTES: [&str; 3] = ["member", "manager", "managedBy"];
+/// Most values of one attribute returned by a search, like AD's `MaxValRange`
+pub const MAX_VALUE_RANGE: usize = 1500;
+/// Seconds between 1601-01-01 (the FILETIME epoch) and 1970-01-01.
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+mod rc {
+    pub const SUCCESS: u32 = 0;
+    pub const OPERATIONS_ERROR: u32 = 1;
+    pub const PROTOCOL_ERROR: u32 = 2;
+    pub const SIZE_LIMIT_EXCEEDED: u32 = 4;
+    pub const AUTH_METHOD_NOT_SUPPORTED: u32 = 7;
+    pub const NO_SUCH_ATTRIBUTE: u32 = 16;
+    pub const CONSTRAINT_VIOLATION: u32 = 19;
+    pub const ATTRIBUTE_OR_VALUE_EXISTS: u32 = 20;
+    pub const NO_SUCH_OBJECT: u32 = 32;
+    pub const INVALID_CREDENTIALS: u32 = 49;
+    pub const UNWILLING_TO_PERFORM: u32 = 53;
+    pub const OBJECT_CLASS_VIOLATION: u32 = 65;
+    pub const NOT_ALLOWED_ON_NON_LEAF: u32 = 66;
+    pub const NOT_ALLOWED_ON_RDN: u32 = 67;
+    pub const ENTRY_ALREADY_EXISTS: u32 = 68;
+}
+
+/// A request the server handled, with the result code it answered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub kind: &'static str,
+    pub dn: String,
+    pub rc: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    dn: String,
+    attrs: Vec<(String, Vec<Vec<u8>>)>,
+}
+
+impl Entry {
+    fn get(&self, name: &str) -> Option<&Vec<Vec<u8>>> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, values)| values)
+    }
+
+    fn first(&self, name: &str) -> Option<String> {
+        self.get(name)
+            .and_then(|values| values.first())
+            .map(|v| String::from_utf8_lossy(v).into_owned())
+    }
+
+    fn number(&self, name: &str) -> Option<i64> {
+        self.first(name).and_then(|v| v.parse().ok())
+    }
+
+    /// Replaces the values of `name`; an empty list removes the attribute.
+    fn set(&mut self, name: &str, values: Vec<Vec<u8>>) {
+        match self
+            .attrs
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            Some(i) if values.is_empty() => {
+                self.attrs.remove(i);
+            }
+            Some(i) => self.attrs[i].1 = values,
+            None if values.is_empty() => {}
+            None => self.attrs.push((name.to_string(), values)),
+        }
+    }
+
+    fn has_class(&self, class: &str) -> bool {
+        self.get("objectClass")
+            .is_some_and(|values| values.iter().any(|v| eq_text(v, class.as_bytes())))
+    }
+}
+
+struct MockState {
+    /// Entries keyed by lowercased DN
+    entries: BTreeMap<String, Entry>,
+    usn: u64,
+    /// Largest page (or unpaged result) returned, like AD's `MaxPageSize`
+    size_limit: usize,
+    operations: Vec<Operation>,
+    /// The subschema subentry, only readable with a base search like in AD
+    subschema: Entry,
+    /// Bumped to close the connections opened before
+    generation: u64,
+}
+
+impl MockState {
+    fn entry(&self, dn: &str) -> Option<&Entry> {
+        self.entries.get(&dn.to_ascii_lowercase())
+    }
+
+    fn next_usn(&mut self) -> u64 {
+        self.usn += 1;
+        self.usn
+    }
+
+    /// Bumps `uSNChanged` and `whenChanged` of an entry that was just modified.
+    fn touch(&mut self, entry: &mut Entry) {
+        let usn = self.next_usn();
+        entry.set("uSNChanged", vec![usn.to_string().into_bytes()]);
+        entry.set("whenChanged", vec![generalized_now().into_bytes()]);
+    }
+
+    fn domain(&self) -> Option<&Entry> {
+        self.entry(BASE_DN)
+    }
+}
+
+/// LDIF for `LARGE_GROUP` and its `count` members, contacts in `OU=Contacts`, to append to the
+/// directory fixture.
+pub fn large_group_ldif(count: usize) -> String {
+    let members: Vec<String> = (1..=count)
+        .map(|i| format!("CN=Contact {:04},OU=Contacts,{}", i, BASE_DN))
+        .collect();
+    let mut ldif = format!(
+        "dn: OU=Contacts,{}\nobjectClass: top\nobjectClass: organizationalUnit\nou: Contacts\n\n",
+        BASE_DN
+    );
+    for dn in &members {
+        ldif.push_str(&format!(
+            "dn: {}\nobjectClass: top\nobjectClass: contact\ncn: {}\n\n",
+            dn,
+            rdn_value(dn)
+        ));
+    }
+    ldif.push_str(&format!(
+        "dn: {}\nobjectClass: top\nobjectClass: group\ncn: All Contacts\n\
+         sAMAccountName: All Contacts\ngroupType: -2147483646\n",
+        LARGE_GROUP
+    ));
+    for dn in &members {
+        ldif.push_str(&format!("member: {}\n", dn));
+    }
+    ldif
+}
+
+pub struct LdapMock {
+    addr: SocketAddr,
+    tls_addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl LdapMock {
+    /// Starts a server seeded with the directory fixture.
+    pub fn start() -> Self {
+        Self::with_ldif(DIRECTORY_FIXTURE)
+    }
+
+    pub fn with_ldif(ldif: &str) -> Self {
+        let mut state = MockState {
+            entries: BTreeMap::new(),
+            usn: 12000,
+            size_limit: 1000,
+            operations: Vec::new(),
+            subschema: subschema_entry(),
+            generation: 0,
+        };
+        for record in parse_ldif(ldif).unwrap() {
+            let LdifChange::Add(attrs) = record.change else {
+                panic!("line {}: the seed may only contain entries", record.line);
+            };
+            let mut entry = Entry {
+                dn: record.dn.clone(),
+                attrs,
+            };
+            let usn = state.next_usn().to_string().into_bytes();
+            let created = entry
+                .get("whenCreated")
+                .cloned()
+                .unwrap_or_else(|| vec![b"20200101090000.0Z".to_vec()]);
+            entry.set("distinguishedName", vec![record.dn.clone().into_bytes()]);
+            entry.set("name", vec![rdn_value(&record.dn).into_bytes()]);
+            entry.set("whenCreated", created.clone());
+            entry.set("whenChanged", created);
+            entry.set("uSNCreated", vec![usn.clone()]);
+            entry.set("uSNChanged", vec![usn]);
+            state.entries.insert(record.dn.to_ascii_lowercase(), entry);
+        }
+        let state = Arc::new(Mutex::new(state));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&server_state);
+                thread::spawn(move || serve(stream, &state, false));
+            }
+        });
+
+        let tls_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tls_addr = tls_listener.local_addr().unwrap();
+        let acceptor =
+            TlsAcceptor::new(Identity::from_pkcs8(SERVER_CERT, SERVER_KEY).unwrap()).unwrap();
+        let server_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in tls_listener.incoming().flatten() {
+                let (acceptor, state) = (acceptor.clone(), Arc::clone(&server_state));
+                thread::spawn(move || {
+                    if let Ok(stream) = acceptor.accept(stream) {
+                        serve(stream, &state, true);
+                    }
+                });
+            }
+        });
+
+        Self {
+            addr,
+            tls_addr,
+            state,
+        }
+    }
+
+    /// Settings for a plain connection, bound as the service account.
+    pub fn config(&self) -> LdapConfig {
+        LdapConfig {
+            servers: vec![format!("ldap://{}", self.addr)],
+            bind: BindMode::Simple {
+                username: ADMIN_DN.to_string(),
+                password: PASSWORD.to_string(),
+            },
+            tls: TlsConfig::default(),
+            connect_timeout: Some(Duration::from_secs(5)),
+            operation_timeout: Some(Duration::from_secs(5)),
+        }
+    }
+
+    /// Settings for an `ldaps://` connection that trusts the fixture certificate.
+    pub fn tls_config(&self) -> LdapConfig {
+        LdapConfig {
+            servers: vec![format!("ldaps://localhost:{}", self.tls_addr.port())],
+            tls: TlsConfig {
+                ca_bundle: Some(PathBuf::from(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/src/tests/fixtures/ldap/server.pem"
+                ))),
+                ..TlsConfig::default()
+            },
+            ..self.config()
+        }
+    }
+
+    pub fn connect(&self) -> Result<LdapConnection, LdapToolError> {
+        connect(&self.config())
+    }
+
+    pub fn connect_tls(&self) -> Result<LdapConnection, LdapToolError> {
+        connect(&self.tls_config())
+    }
+
+    pub fn exists(&self, dn: &str) -> bool {
+        self.state.lock().unwrap().entry(dn).is_some()
+    }
+
+    /// Stored values of `attr`; binary values are decoded lossily.
+    pub fn values(&self, dn: &str, attr: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .entry(dn)
+            .and_then(|entry| entry.get(attr))
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|v| String::from_utf8_lossy(v).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The password an account would bind with, decoded from `unicodePwd`.
+    pub fn password(&self, dn: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .entry(dn)
+            .and_then(|entry| entry.get("unicodePwd"))
+            .and_then(|values| values.first())
+            .and_then(|value| decode_unicode_pwd(value))
+    }
+
+    /// Caps the entries returned per page, like AD's `MaxPageSize`.
+    pub fn set_size_limit(&self, limit: usize) {
+        self.state.lock().unwrap().size_limit = limit;
+    }
+
+    pub fn operations(&self) -> Vec<Operation> {
+        self.state.lock().unwrap().operations.clone()
+    }
+
+    /// Closes the open connections, as a server that restarts or drops idle clients does. Each
+    /// one is closed without a reply when its next request arrives.
+    pub fn drop_connections(&self) {
+        self.state.lock().unwrap().generation += 1;
+    }
+}
+
+/// The result of an operation, sent as an `LDAPResult`.
+struct Outcome {
+    rc: u32,
+    matched: String,
+    message: String,
+}
+
+impl Outcome {
+    fn success() -> Self {
+        Self::failure(rc::SUCCESS, "")
+    }
+
+    fn failure(rc: u32, message: &str) -> Self {
+        Self {
+            rc,
+            matched: String::new(),
+            message: message.to_string(),
+        }
+    }
+
+    /// `noSuchObject` with the closest existing ancestor, as AD reports it.
+    fn no_such_object(state: &MockState, dn: &str) -> Self {
+        let mut matched = parent_dn(dn);
+        while let Some(parent) = matched.as_deref().filter(|p| state.entry(p).is_none()) {
+            matched = parent_dn(parent);
+        }
+        Self {
+            rc: rc::NO_SUCH_OBJECT,
+            matched: matched.unwrap_or_default(),
+            message: "0000208D: NameErr: DSID-0310028B, problem 2001 (NO_OBJECT), data 0"
+                .to_string(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        [
+            enumerated(self.rc),
+            octets(self.matched.as_bytes()),
+            octets(self.message.as_bytes()),
+        ]
+        .concat()
+    }
+}
+
+struct Session {
+    /// Lowercased DN of the bound account
+    bound: Option<String>,
+    encrypted: bool,
+    /// `MockState::generation` when the connection was opened
+    generation: u64,
+}
+
+fn serve<S: Read + Write>(mut stream: S, state: &Arc<Mutex<MockState>>, encrypted: bool) {
+    let mut session = Session {
+        bound: None,
+        encrypted,
+        generation: state.lock().unwrap().generation,
+    };
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let (message, consumed) = match parse_tag(&buffer) {
+            Ok((rest, message)) => (message, buffer.len() - rest.len()),
+            Err(e) if e.is_incomplete() => match stream.read(&mut chunk) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    buffer.extend_from_slice(&chunk[..n]);
+                    continue;
+                }
+            },
+            Err(_) => return,
+        };
+        buffer.drain(..consumed);
+        let Some(responses) = handle(state, &mut session, message) else {
+            return;
+        };
+        for response in responses {
+            if stream.write_all(&response).is_err() {
+                return;
+            }
+        }
+        if stream.flush().is_err() {
+            return;
+        }
+    }
+}
+
+/// Handles one `LDAPMessage`. Returns the messages to send back, or `None` to close the
+/// connection.
+fn handle(
+    state: &Arc<Mutex<MockState>>,
+    session: &mut Session,
+    message: StructureTag,
+) -> Option<Vec<Vec<u8>>> {
+    let mut parts = constructed(&message)?.iter();
+    let id = integer(primitive(parts.next()?)?);
+    let op = parts.next()?;
+    let controls = parts.next();
+    if op.class != TagClass::Application {
+        return None;
+    }
+
+    let mut state = state.lock().unwrap();
+    if session.generation != state.generation {
+        return None;
+    }
+    let (kind, dn, outcome, entries, response_controls) = match op.id {
+        0 => {
+            let (dn, outcome) = bind(&state, session, op);
+            ("bind", dn, outcome, Vec::new(), None)
+        }
+        2 => return None,
+        3 => {
+            let (dn, outcome, entries, paging) = search(&state, session, op, controls);
+            ("search", dn, outcome, entries, paging)
+        }
+        6 => {
+            let (dn, outcome) = modify(&mut state, session, op);
+            ("modify", dn, outcome, Vec::new(), None)
+        }
+        8 => {
+            let (dn, outcome) = add(&mut state, session, op);
+            ("add", dn, outcome, Vec::new(), None)
+        }
+        10 => {
+            let dn = text(primitive(op)?);
+            let outcome = delete(&mut state, session, &dn);
+            ("delete", dn, outcome, Vec::new(), None)
+        }
+        12 => {
+            let (dn, outcome) = modify_dn(&mut state, session, op);
+            ("modifydn", dn, outcome, Vec::new(), None)
+        }
+        // Abandon has no response
+        16 => return Some(Vec::new()),
+        23 => (
+            "extended",
+            String::new(),
+            Outcome::failure(rc::PROTOCOL_ERROR, "Unsupported extended operation"),
+            Vec::new(),
+            None,
+        ),
+        _ => return None,
+    };
+    state.operations.push(Operation {
+        kind,
+        dn,
+        rc: outcome.rc,
+    });
+
+    let responses = entries
+        .iter()
+        .map(|entry| ldap_message(id, entry, None))
+        .chain(std::iter::once(ldap_message(
+            id,
+            &tlv(0x60 | response_id(op.id), &outcome.encode()),
+            response_controls.as_deref(),
+        )))
+        .collect();
+    Some(responses)
+}
+
+/// The protocol op of the response to a request: the next number, except for search, whose
+/// entries take the next one.
+fn response_id(request: u64) -> u8 {
+    match request {
+        3 => 5,
+        id => id as u8 + 1,
+    }
+}
+
+/// The `operationsError` AD returns for anything but a rootDSE read before a successful bind.
+fn require_bind(session: &Session) -> Result<(), Outcome> {
+    match session.bound {
+        Some(_) => Ok(()),
+        None => Err(Outcome::failure(
+            rc::OPERATIONS_ERROR,
+            "000004DC: LdapErr: DSID-0C090A5C, comment: In order to perform this operation a \
+             successful bind must be completed on the connection., data 0, v4563",
+        )),
+    }
+}
+
+fn bind_error(data: &str) -> Outcome {
+    Outcome::failure(
+        rc::INVALID_CREDENTIALS,
+        &format!(
+            "80090308: LdapErr: DSID-0C09044E, comment: AcceptSecurityContext error, data {}, v4563",
+            data
+        ),
+    )
+}
+
+fn bind(state: &MockState, session: &mut Session, op: &StructureTag) -> (String, Outcome) {
+    session.bound = None;
+    let Some(parts) = constructed(op) else {
+        return (String::new(), Outcome::failure(rc::PROTOCOL_ERROR, ""));
+    };
+    let name = parts
+        .get(1)
+        .and_then(primitive)
+        .map(text)
+        .unwrap_or_default();
+    let Some(auth) = parts.get(2) else {
+        return (name, Outcome::failure(rc::PROTOCOL_ERROR, ""));
+    };
+    // Only simple binds ([0]) are supported; SASL ([3]) would need the client certificate
+    if auth.class != TagClass::Context || auth.id != 0 {
+        return (
+            name,
+            Outcome::failure(rc::AUTH_METHOD_NOT_SUPPORTED, "Unsupported SASL mechanism"),
+        );
+    }
+    let password = primitive(auth).map(text).unwrap_or_default();
+    if password.is_empty() {
+        // Anonymous (or unauthenticated) bind
+        return (name, Outcome::success());
+    }
+
+    let account = state.entries.values().find(|entry| {
+        entry.dn.eq_ignore_ascii_case(&name)
+            || entry
+                .first("userPrincipalName")
+                .is_some_and(|upn| upn.eq_ignore_ascii_case(&name))
+            || name.split_once('\\').is_some_and(|(domain, account)| {
+                domain.eq_ignore_ascii_case("EXAMPLE")
+                    && entry
+                        .first("sAMAccountName")
+                        .is_some_and(|sam| sam.eq_ignore_ascii_case(account))
+            })
+    });
+    let Some(account) = account else {
+        // AD does not reveal whether the account exists
+        return (name, bind_error("52e"));
+    };
+    let stored = account
+        .get("unicodePwd")
+        .and_then(|values| values.first())
+        .and_then(|value| decode_unicode_pwd(value));
+    if stored.as_deref() != Some(password.as_str()) {
+        return (name, bind_error("52e"));
+    }
+    let outcome = if account.number("userAccountControl").unwrap_or(0) & 0x2 != 0 {
+        bind_error("533")
+    } else if account.number("lockoutTime").unwrap_or(0) > 0 {
+        bind_error("775")
+    } else if account.number("pwdLastSet") == Some(0) {
+        bind_error("773")
+    } else {
+        session.bound = Some(account.dn.to_ascii_lowercase());
+        Outcome::success()
+    };
+    (name, outcome)
+}
+
+type SearchResponse = (String, Outcome, Vec<Vec<u8>>, Option<Vec<u8>>);
+
+fn search(
+    state: &MockState,
+    session: &Session,
+    op: &StructureTag,
+    controls: Option<&StructureTag>,
+) -> SearchResponse {
+    let Some(parts) = constructed(op).filter(|parts| parts.len() == 8) else {
+        return (
+            String::new(),
+            Outcome::failure(rc::PROTOCOL_ERROR, ""),
+            Vec::new(),
+            None,
+        );
+    };
+    let base = primitive(&parts[0]).map(text).unwrap_or_default();
+    let scope = primitive(&parts[1]).map(integer).unwrap_or(0);
+    let client_limit = primitive(&parts[3]).map(integer).unwrap_or(0).max(0) as usize;
+    let filter = &parts[6];
+    let attrs: Vec<String> = constructed(&parts[7])
+        .map(|attrs| attrs.iter().filter_map(primitive).map(text).collect())
+        .unwrap_or_default();
+
+    let root_dse = base.is_empty() && scope == 0;
+    let subschema = base.eq_ignore_ascii_case(SUBSCHEMA_DN) && scope == 0;
+    if !root_dse && let Err(outcome) = require_bind(session) {
+        return (base, outcome, Vec::new(), None);
+    }
+    if !base.is_empty() && !subschema && state.entry(&base).is_none() {
+        let outcome = Outcome::no_such_object(state, &base);
+        return (base, outcome, Vec::new(), None);
+    }
+
+    let candidates: Vec<Entry> = if root_dse {
+        vec![root_dse_entry(state)]
+    } else if subschema {
+        vec![state.subschema.clone()]
+    } else {
+        state
+            .entries
+            .values()
+            .filter(|entry| in_scope(&entry.dn, &base, scope))
+            .cloned()
+            .collect()
+    };
+    let matching: Vec<&Entry> = candidates
+        .iter()
+        .filter(|entry| matches(state, entry, filter))
+        .collect();
+
+    let paging = controls.and_then(paged_request);
+    let (page, outcome, cookie) = match paging {
+        Some((size, cookie)) => {
+            let offset = text(&cookie).parse::<usize>().unwrap_or(0);
+            let size = (size.max(0) as usize).min(state.size_limit);
+            let end = (offset + size).min(matching.len());
+            let next = if end < matching.len() && size > 0 {
+                end.to_string()
+            } else {
+                String::new()
+            };
+            (
+                matching.get(offset..end).unwrap_or_default(),
+                Outcome::success(),
+                Some(next),
+            )
+        }
+        None => {
+            let limit = match client_limit {
+                0 => state.size_limit,
+                n => n.min(state.size_limit),
+            };
+            if matching.len() > limit {
+                (
+                    &matching[..limit],
+                    Outcome::failure(rc::SIZE_LIMIT_EXCEEDED, "Size Limit Exceeded"),
+                    None,
+                )
+            } else {
+                (&matching[..], Outcome::success(), None)
+            }
+        }
+    };
+
+    let entries = page
+        .iter()
+        .map(|entry| search_entry(&entry.dn, &selected(state, entry, &attrs)))
+        .collect();
+    let response_controls = cookie.map(|cookie| {
+        sequence(
+            &[
+                octets(PAGED_RESULTS_OID.as_bytes()),
+                octets(&sequence(
+                    &[integer_value(0), octets(cookie.as_bytes())].concat(),
+                )),
+            ]
+            .concat(),
+        )
+    });
+    (base, outcome, entries, response_controls)
+}
+
+/// The page size and cookie of a Simple Paged Results control.
+fn paged_request(controls: &StructureTag) -> Option<(i64, Vec<u8>)> {
+    constructed(controls)?.iter().find_map(|control| {
+        let parts = constructed(control)?;
+        if text(primitive(parts.first()?)?) != PAGED_RESULTS_OID {
+            return None;
+        }
+        let value = parts.iter().skip(1).find(|p| p.id == 4)?;
+        let (_, value) = parse_tag(primitive(value)?).ok()?;
+        let value = constructed(&value)?;
+        Some((
+            integer(primitive(value.first()?)?),
+            primitive(value.get(1)?)?.to_vec(),
+        ))
+    })
+}
+
+fn root_dse_entry(state: &MockState) -> Entry {
+    let values = |values: &[&str]| values.iter().map(|v| v.as_bytes().to_vec()).collect();
+    Entry {
+        dn: String::new(),
+        attrs: vec![
+            ("objectClass".to_string(), values(&["top"])),
+            (
+                "namingContexts".to_string(),
+                values(&[BASE_DN, "CN=Configuration,DC=example,DC=com", SCHEMA_DN]),
+            ),
+            ("defaultNamingContext".to_string(), values(&[BASE_DN])),
+            ("rootDomainNamingContext".to_string(), values(&[BASE_DN])),
+            ("schemaNamingContext".to_string(), values(&[SCHEMA_DN])),
+            ("subschemaSubentry".to_string(), values(&[SUBSCHEMA_DN])),
+            ("dnsHostName".to_string(), values(&[DNS_HOST_NAME])),
+            (
+                "highestCommittedUSN".to_string(),
+                vec![state.usn.to_string().into_bytes()],
+            ),
+            (
+                "currentTime".to_string(),
+                vec![generalized_now().into_bytes()],
+            ),
+            (
+                "supportedControl".to_string(),
+                values(&[PAGED_RESULTS_OID, "1.2.840.113556.1.4.417"]),
+            ),
+            ("supportedLDAPVersion".to_string(), values(&["3", "2"])),
+            ("domainControllerFunctionality".to_string(), values(&["7"])),
+        ],
+    }
+}
+
+fn subschema_entry() -> Entry {
+    let record = parse_ldif(SCHEMA_FIXTURE).unwrap().remove(0);
+    let LdifChange::Add(attrs) = record.change else {
+        panic!("the schema fixture must contain the subschema entry");
+    };
+    Entry {
+        dn: record.dn,
+        attrs,
+    }
+}
+
+fn in_scope(dn: &str, base: &str, scope: i64) -> bool {
+    let (dn, base) = (dn.to_ascii_lowercase(), base.to_ascii_lowercase());
+    match scope {
+        0 => dn == base,
+        1 => parent_dn(&dn).unwrap_or_default() == base,
+        _ => base.is_empty() || dn == base || dn.ends_with(&format!(",{}", base)),
+    }
+}
+
+/// Attributes of `entry` to return for the requested list. `unicodePwd` is never returned;
+/// `msDS-User-Account-Control-Computed` and `msDS-ResultantPSO` only when asked for by name.
+/// Like AD, at most `MAX_VALUE_RANGE` values of an attribute are returned: a longer attribute
+/// comes back as `name;range=0-1499`, and `name;range=N-*` asks for the values from index N.
+fn selected(state: &MockState, entry: &Entry, attrs: &[String]) -> Vec<(String, Vec<Vec<u8>>)> {
+    let mut selected: Vec<(String, Vec<Vec<u8>>)> = all_values(state, entry, attrs)
+        .into_iter()
+        .map(|(name, values)| value_range(&name, values, 0))
+        .collect();
+    for attr in attrs {
+        let Some((name, range)) = attr.split_once(";range=") else {
+            continue;
+        };
+        let start = range
+            .split_once('-')
+            .and_then(|(start, _)| start.parse().ok());
+        let values = entry.get(name).filter(|values| !values.is_empty());
+        if let (Some(start), Some(values)) = (start, values) {
+            selected.push(value_range(name, values.clone(), start));
+        }
+    }
+    selected
+}
+
+/// The values of `name` from index `start` on, limited to `MAX_VALUE_RANGE`. The name carries
+/// the range when not all values are returned at once.
+fn value_range(name: &str, values: Vec<Vec<u8>>, start: usize) -> (String, Vec<Vec<u8>>) {
+    if start == 0 && values.len() <= MAX_VALUE_RANGE {
+        return (name.to_string(), values);
+    }
+    let end = (start + MAX_VALUE_RANGE).min(values.len());
+    let last = if end == values.len() {
+        "*".to_string()
+    } else {
+        (end - 1).to_string()
+    };
+    let range = values.get(start..end).unwrap_or_default().to_vec();
+    (format!("{};range={}-{}", name, start, last), range)
+}
+
+/// The requested attributes with all of their values.
+fn all_values(state: &MockState, entry: &Entry, attrs: &[String]) -> Vec<(String, Vec<Vec<u8>>)> {
+    let all = attrs.is_empty() || attrs.iter().any(|a| a == "*");
+    let requested = |name: &str| attrs.iter().any(|a| a.eq_ignore_ascii_case(name));
+    let mut selected: Vec<(String, Vec<Vec<u8>>)> = entry
+        .attrs
+        .iter()
+        .filter(|(name, _)| !name.eq_ignore_ascii_case("unicodePwd"))
+        .filter(|(name, _)| all || requested(name))
+        .cloned()
+        .collect();
+    for (name, with_all) in [
+        ("memberOf", true),
+        ("directReports", true),
+        ("msDS-User-Account-Control-Computed", false),
+        ("msDS-ResultantPSO", false),
+    ] {
+        if (all && with_all) || requested(name) {
+            let values = computed(state, entry, name);
+            if !values.is_empty() {
+                selected.push((name.to_string(), values));
+            }
+        }
+    }
+    selected
+}
+
+/// Values of attributes AD constructs rather than stores.
+fn computed(state: &MockState, entry: &Entry, name: &str) -> Vec<Vec<u8>> {
+    let referencing = |attr: &str| -> Vec<Vec<u8>> {
+        state
+            .entries
+            .values()
+            .filter(|other| {
+                other
+                    .get(attr)
+                    .is_some_and(|values| values.iter().any(|v| eq_text(v, entry.dn.as_bytes())))
+            })
+            .map(|other| other.dn.clone().into_bytes())
+            .collect()
+    };
+    if name.eq_ignore_ascii_case("memberOf") {
+        referencing("member")
+    } else if name.eq_ignore_ascii_case("directReports") {
+        referencing("manager")
+    } else if name.eq_ignore_ascii_case("msDS-User-Account-Control-Computed") {
+        if !entry.has_class("user") {
+            return Vec::new();
+        }
+        let mut flags = 0;
+        if entry.number("lockoutTime").unwrap_or(0) > 0 {
+            flags |= 0x10;
+        }
+        if entry.number("pwdLastSet") == Some(0) {
+            flags |= 0x80_0000;
+        }
+        vec![flags.to_string().into_bytes()]
+    } else if name.eq_ignore_ascii_case("msDS-ResultantPSO") {
+        if !entry.has_class("user") {
+            return Vec::new();
+        }
+        resultant_pso(state, &entry.dn)
+            .map(|pso| vec![pso.into_bytes()])
+            .unwrap_or_default()
+    } else {
+        entry.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// The PSO that applies to the user `dn` as AD resolves it: PSOs linked to the user win over
+/// those linked to its groups, then the lowest precedence wins, ties going to the lowest DN.
+fn resultant_pso(state: &MockState, dn: &str) -> Option<String> {
+    let psos: Vec<&Entry> = state
+        .entries
+        .values()
+        .filter(|entry| entry.has_class("msDS-PasswordSettings"))
+        .collect();
+    let applies_to = |pso: &Entry, targets: &dyn Fn(&str) -> bool| {
+        pso.get("msDS-PSOAppliesTo")
+            .is_some_and(|values| values.iter().any(|v| targets(&text(v))))
+    };
+    let groups = transitive_groups(state, dn);
+    let direct: Vec<&Entry> = psos
+        .iter()
+        .copied()
+        .filter(|pso| applies_to(pso, &|target| target.eq_ignore_ascii_case(dn)))
+        .collect();
+    let candidates = if direct.is_empty() {
+        psos.into_iter()
+            .filter(|pso| applies_to(pso, &|target| groups.contains(&target.to_ascii_lowercase())))
+            .collect()
+    } else {
+        direct
+    };
+    candidates
+        .into_iter()
+        .min_by_key(|pso| {
+            (
+                pso.number("msDS-PasswordSettingsPrecedence")
+                    .unwrap_or(i64::MAX),
+                pso.dn.to_ascii_lowercase(),
+            )
+        })
+        .map(|pso| pso.dn.clone())
+}
+
+/// Lowercased DNs of the groups `dn` belongs to, directly or through nesting.
+fn transitive_groups(state: &MockState, dn: &str) -> HashSet<String> {
+    let mut groups = HashSet::new();
+    let mut queue = VecDeque::from([dn.to_string()]);
+    while let Some(current) = queue.pop_front() {
+        for group in state.entries.values() {
+            let is_member = group
+                .get("member")
+                .is_some_and(|values| values.iter().any(|v| eq_text(v, current.as_bytes())));
+            if is_member && groups.insert(group.dn.to_ascii_lowercase()) {
+                queue.push_back(group.dn.clone());
+            }
+        }
+    }
+    groups
+}
+
+/// Evaluates a BER-encoded search filter against `entry`.
+fn matches(state: &MockState, entry: &Entry, filter: &StructureTag) -> bool {
+    if filter.class != TagClass::Context {
+        return false;
+    }
+    let children = || constructed(filter).map(Vec::as_slice).unwrap_or_default();
+    let assertion = || -> Option<(String, Vec<u8>)> {
+        let parts = constructed(filter)?;
+        Some((
+            text(primitive(parts.first()?)?),
+            primitive(parts.get(1)?)?.to_vec(),
+        ))
+    };
+    let values = |attr: &str| computed(state, entry, attr);
+    match filter.id {
+        0 => children().iter().all(|f| matches(state, entry, f)),
+        1 => children().iter().any(|f| matches(state, entry, f)),
+        2 => !children().iter().any(|f| matches(state, entry, f)),
+        // Equality and approximate match
+        3 | 8 => assertion().is_some_and(|(attr, wanted)| {
+            values(&attr)
+                .iter()
+                .any(|value| equals(&attr, value, &wanted))
+        }),
+        4 => {
+            let Some(parts) = constructed(filter) else {
+                return false;
+            };
+            let (Some(attr), Some(pieces)) = (
+                parts.first().and_then(primitive).map(text),
+                parts.get(1).and_then(constructed),
+            ) else {
+                return false;
+            };
+            values(&attr)
+                .iter()
+                .any(|value| substrings(&lossy_lower(value), pieces))
+        }
+        5 => assertion().is_some_and(|(attr, wanted)| {
+            values(&attr)
+                .iter()
+                .any(|value| compare(value, &wanted) != Ordering::Less)
+        }),
+        6 => assertion().is_some_and(|(attr, wanted)| {
+            values(&attr)
+                .iter()
+                .any(|value| compare(value, &wanted) != Ordering::Greater)
+        }),
+        7 => {
+            let attr = primitive(filter).map(text).unwrap_or_default();
+            attr.eq_ignore_ascii_case("objectClass") || !values(&attr).is_empty()
+        }
+        9 => extensible(state, entry, children()),
+        _ => false,
+    }
+}
+
+/// Extensible match with the AD matching rules: bitwise AND/OR and LDAP_MATCHING_RULE_IN_CHAIN.
+fn extensible(state: &MockState, entry: &Entry, parts: &[StructureTag]) -> bool {
+    let field = |id: u64| {
+        parts
+            .iter()
+            .find(|p| p.id == id)
+            .and_then(primitive)
+            .map(|v| v.to_vec())
+    };
+    let (Some(rule), Some(attr), Some(value)) = (field(1), field(2), field(3)) else {
+        return false;
+    };
+    let (rule, attr) = (text(&rule), text(&attr));
+    match rule.as_str() {
+        MATCHING_RULE_BIT_AND | MATCHING_RULE_BIT_OR => {
+            let Ok(mask) = text(&value).parse::<i64>() else {
+                return false;
+            };
+            computed(state, entry, &attr).iter().any(|v| {
+                text(v).parse::<i64>().is_ok_and(|v| {
+                    let bits = v & mask;
+                    if rule == MATCHING_RULE_BIT_AND {
+                        bits == mask
+                    } else {
+                        bits != 0
+                    }
+                })
+            })
+        }
+        MATCHING_RULE_IN_CHAIN if attr.eq_ignore_ascii_case("memberOf") => {
+            transitive_groups(state, &entry.dn).contains(&lossy_lower(&value))
+        }
+        MATCHING_RULE_IN_CHAIN if attr.eq_ignore_ascii_case("member") => {
+            transitive_groups(state, &text(&value)).contains(&entry.dn.to_ascii_lowercase())
+        }
+        _ => false,
+    }
+}
+
+fn substrings(value: &str, pieces: &[StructureTag]) -> bool {
+    let mut position = 0;
+    for piece in pieces {
+        let Some(part) = primitive(piece).map(lossy_lower) else {
+            return false;
+        };
+        match piece.id {
+            0 => {
+                if !value.starts_with(&part) {
+                    return false;
+                }
+                position = part.len();
+            }
+            1 => match value[position..].find(&part) {
+                Some(i) => position += i + part.len(),
+                None => return false,
+            },
+            _ => {
+                if !value[position..].ends_with(&part) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Equality as AD applies it: case-insensitive, and `objectCategory` also matches the short
+/// name of the category (`person` for `CN=Person,CN=Schema,...`).
+fn equals(attr: &str, value: &[u8], wanted: &[u8]) -> bool {
+    if eq_text(value, wanted) {
+        return true;
+    }
+    attr.eq_ignore_ascii_case("objectCategory")
+        && rdn_value(&text(value)).eq_ignore_ascii_case(&text(wanted))
+}
+
+/// Orders numbers numerically and everything else as case-insensitive text, which also orders
+/// GeneralizedTime values correctly.
+fn compare(value: &[u8], wanted: &[u8]) -> Ordering {
+    let (value, wanted) = (lossy_lower(value), lossy_lower(wanted));
+    match (value.parse::<i64>(), wanted.parse::<i64>()) {
+        (Ok(value), Ok(wanted)) => value.cmp(&wanted),
+        _ => value.cmp(&wanted),
+    }
+}
+
+/// Changes to one attribute, with the values of the request.
+struct Change {
+    op: i64,
+    attr: String,
+    values: Vec<Vec<u8>>,
+}
+
+fn attribute(tag: &StructureTag) -> Option<(String, Vec<Vec<u8>>)> {
+    let parts = constructed(tag)?;
+    let values = constructed(parts.get(1)?)?
+        .iter()
+        .filter_map(primitive)
+        .map(<[u8]>::to_vec)
+        .collect();
+    Some((text(primitive(parts.first()?)?), values))
+}
+
+fn modify(state: &mut MockState, session: &Session, op: &StructureTag) -> (String, Outcome) {
+    let parsed = constructed(op).and_then(|parts| {
+        let dn = text(primitive(parts.first()?)?);
+        let changes = constructed(parts.get(1)?)?
+            .iter()
+            .map(|change| {
+                let parts = constructed(change)?;
+                let (attr, values) = attribute(parts.get(1)?)?;
+                Some(Change {
+                    op: integer(primitive(parts.first()?)?),
+                    attr,
+                    values,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some((dn, changes))
+    });
+    let Some((dn, changes)) = parsed else {
+        return (String::new(), Outcome::failure(rc::PROTOCOL_ERROR, ""));
+    };
+    if let Err(outcome) = require_bind(session) {
+        return (dn, outcome);
+    }
+    let outcome = apply_changes(state, session, &dn, &changes);
+    (dn, outcome)
+}
+
+/// Applies all changes to a copy of the entry and stores it only if every change succeeds.
+fn apply_changes(
+    state: &mut MockState,
+    session: &Session,
+    dn: &str,
+    changes: &[Change],
+) -> Outcome {
+    let Some(mut entry) = state.entry(dn).cloned() else {
+        return Outcome::no_such_object(state, dn);
+    };
+    let rdn_attr = split_dn(&entry.dn)
+        .0
+        .split_once('=')
+        .map(|(attr, _)| attr.to_string())
+        .unwrap_or_default();
+    for change in changes {
+        let attr = change.attr.as_str();
+        if attr.eq_ignore_ascii_case(&rdn_attr) || attr.eq_ignore_ascii_case("name") {
+            return Outcome::failure(
+                rc::NOT_ALLOWED_ON_RDN,
+                "00002016: UpdErr: DSID-030F0B23, problem 6004 (CANT_ON_RDN), data 0",
+            );
+        }
+        if attr.eq_ignore_ascii_case("unicodePwd") {
+            match password_change(state, session, dn, change) {
+                Ok(()) => {
+                    entry.set("unicodePwd", change.values.clone());
+                    entry.set("pwdLastSet", vec![filetime_now().into_bytes()]);
+                    continue;
+                }
+                Err(outcome) => return outcome,
+            }
+        }
+
+        let mut current = entry.get(attr).cloned().unwrap_or_default();
+        let contains = |values: &[Vec<u8>], value: &[u8]| values.iter().any(|v| eq_text(v, value));
+        match change.op {
+            0 => {
+                if change.values.iter().any(|v| contains(&current, v)) {
+                    return Outcome::failure(
+                        rc::ATTRIBUTE_OR_VALUE_EXISTS,
+                        "00002083: AtrErr: DSID-03151904, #1: 0: 00002083: DSID-03151904, \
+                         problem 1006 (ATT_OR_VALUE_EXISTS), data 0",
+                    );
+                }
+                current.extend(change.values.iter().cloned());
+            }
+            1 if change.values.is_empty() => {
+                if current.is_empty() {
+                    return no_such_attribute();
+                }
+                current.clear();
+            }
+            1 => {
+                if change.values.iter().any(|v| !contains(&current, v)) {
+                    // Removing a non-member is refused rather than reported as a missing value
+                    return if attr.eq_ignore_ascii_case("member") {
+                        Outcome::failure(
+                            rc::UNWILLING_TO_PERFORM,
+                            "00000561: SvcErr: DSID-031A1254, problem 5003 (WILL_NOT_PERFORM), \
+                             data 0",
+                        )
+                    } else {
+                        no_such_attribute()
+                    };
+                }
+                current.retain(|v| !contains(&change.values, v));
+            }
+            _ => {
+                current = change.values.clone();
+                if attr.eq_ignore_ascii_case("pwdLastSet") {
+                    match text(current.first().map(Vec::as_slice).unwrap_or_default()).as_str() {
+                        "0" => {}
+                        "-1" => current = vec![filetime_now().into_bytes()],
+                        _ => return unwilling(),
+                    }
+                }
+                if attr.eq_ignore_ascii_case("lockoutTime") {
+                    if current.iter().any(|v| text(v) != "0") {
+                        return unwilling();
+                    }
+                    entry.set("badPwdCount", vec![b"0".to_vec()]);
+                }
+            }
+        }
+        entry.set(attr, current);
+    }
+    state.touch(&mut entry);
+    state.entries.insert(dn.to_ascii_lowercase(), entry);
+    Outcome::success()
+}
+
+fn no_such_attribute() -> Outcome {
+    Outcome::failure(
+        rc::NO_SUCH_ATTRIBUTE,
+        "00002085: AtrErr: DSID-03152392, #1: 0: 00002085: DSID-03152392, problem 1001 \
+         (NO_ATTRIBUTE_OR_VAL), data 0",
+    )
+}
+
+fn unwilling() -> Outcome {
+    Outcome::failure(
+        rc::UNWILLING_TO_PERFORM,
+        "0000209A: SvcErr: DSID-031A1254, problem 5003 (WILL_NOT_PERFORM), data 0",
+    )
+}
+
+/// Checks a `unicodePwd` replace: AD refuses it over an unencrypted connection and checks the
+/// new password against the length and complexity rules of the PSO that applies to `dn`, or
+/// else the domain's.
+fn password_change(
+    state: &MockState,
+    session: &Session,
+    dn: &str,
+    change: &Change,
+) -> Result<(), Outcome> {
+    if !session.encrypted {
+        return Err(Outcome::failure(
+            rc::UNWILLING_TO_PERFORM,
+            "0000001F: SvcErr: DSID-031A12D2, problem 5003 (WILL_NOT_PERFORM), data 0",
+        ));
+    }
+    let [value] = change.values.as_slice() else {
+        return Err(unwilling());
+    };
+    let Some(password) = decode_unicode_pwd(value) else {
+        return Err(unwilling());
+    };
+    let (min_length, complex) = match resultant_pso(state, dn).and_then(|pso| state.entry(&pso)) {
+        Some(pso) => (
+            pso.number("msDS-MinimumPasswordLength").unwrap_or(0),
+            pso.first("msDS-PasswordComplexityEnabled")
+                .is_some_and(|v| v.eq_ignore_ascii_case("TRUE")),
+        ),
+        None => {
+            let domain = state.domain();
+            (
+                domain.and_then(|d| d.number("minPwdLength")).unwrap_or(0),
+                domain.and_then(|d| d.number("pwdProperties")).unwrap_or(0) & 1 != 0,
+            )
+        }
+    };
+    let categories = [
+        password.chars().any(|c| c.is_ascii_uppercase()),
+        password.chars().any(|c| c.is_ascii_lowercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    if (password.chars().count() as i64) < min_length
+        || (complex && categories.iter().filter(|c| **c).count() < 3)
+    {
+        return Err(Outcome::failure(
+            rc::CONSTRAINT_VIOLATION,
+            "0000052D: Constraint violation - check_password_restrictions: the password does \
+             not meet the complexity requirements",
+        ));
+    }
+    Ok(())
+}
+
+fn add(state: &mut MockState, session: &Session, op: &StructureTag) -> (String, Outcome) {
+    let parsed = constructed(op).and_then(|parts| {
+        let dn = text(primitive(parts.first()?)?);
+        let attrs = constructed(parts.get(1)?)?
+            .iter()
+            .map(attribute)
+            .collect::<Option<Vec<_>>>()?;
+        Some((dn, attrs))
+    });
+    let Some((dn, attrs)) = parsed else {
+        return (String::new(), Outcome::failure(rc::PROTOCOL_ERROR, ""));
+    };
+    if let Err(outcome) = require_bind(session) {
+        return (dn, outcome);
+    }
+    let outcome = add_entry(state, session, &dn, attrs);
+    (dn, outcome)
+}
+
+fn add_entry(
+    state: &mut MockState,
+    session: &Session,
+    dn: &str,
+    attrs: Vec<(String, Vec<Vec<u8>>)>,
+) -> Outcome {
+    if state.entry(dn).is_some() {
+        return Outcome::failure(
+            rc::ENTRY_ALREADY_EXISTS,
+            "00002071: UpdErr: DSID-03050505, problem 6005 (ENTRY_EXISTS), data 0",
+        );
+    }
+    if parent_dn(dn).is_none_or(|parent| state.entry(&parent).is_none()) {
+        return Outcome::no_such_object(state, dn);
+    }
+    let mut entry = Entry {
+        dn: dn.to_string(),
+        attrs,
+    };
+    if entry.get("objectClass").is_none() {
+        return Outcome::failure(
+            rc::OBJECT_CLASS_VIOLATION,
+            "00002014: objectclass_violation: DSID-03150F5F, problem 5002 (UNAVAILABLE), data 0",
+        );
+    }
+    if let Some(values) = entry.get("unicodePwd").cloned() {
+        let change = Change {
+            op: 2,
+            attr: "unicodePwd".to_string(),
+            values,
+        };
+        if let Err(outcome) = password_change(state, session, dn, &change) {
+            return outcome;
+        }
+    }
+
+    let usn = state.next_usn();
+    let now = generalized_now().into_bytes();
+    let category = if entry.has_class("computer") {
+        "Computer"
+    } else if entry.has_class("user") {
+        "Person"
+    } else if entry.has_class("group") {
+        "Group"
+    } else if entry.has_class("organizationalUnit") {
+        "Organizational-Unit"
+    } else {
+        "Container"
+    };
+    if entry.has_class("user") || entry.has_class("group") {
+        if let Some(mut sid) = state
+            .domain()
+            .and_then(|domain| domain.get("objectSid"))
+            .and_then(|values| values.first().cloned())
+        {
+            sid[1] += 1;
+            sid.extend_from_slice(&(usn as u32).to_le_bytes());
+            entry.set("objectSid", vec![sid]);
+        }
+        if entry.get("groupType").is_none() && entry.has_class("group") {
+            entry.set("groupType", vec![b"-2147483646".to_vec()]);
+        }
+    }
+    if entry.has_class("user") && entry.get("pwdLastSet").is_none() {
+        let value = if entry.get("unicodePwd").is_some() {
+            filetime_now()
+        } else {
+            "0".to_string()
+        };
+        entry.set("pwdLastSet", vec![value.into_bytes()]);
+    }
+    let mut guid = [0u8; 16];
+    guid[..8].copy_from_slice(&usn.to_le_bytes());
+    entry.set("objectGUID", vec![guid.to_vec()]);
+    entry.set(
+        "objectCategory",
+        vec![format!("CN={},{}", category, SCHEMA_DN).into_bytes()],
+    );
+    entry.set("distinguishedName", vec![dn.as_bytes().to_vec()]);
+    entry.set("name", vec![rdn_value(dn).into_bytes()]);
+    entry.set("whenCreated", vec![now.clone()]);
+    entry.set("whenChanged", vec![now]);
+    entry.set("uSNCreated", vec![usn.to_string().into_bytes()]);
+    entry.set("uSNChanged", vec![usn.to_string().into_bytes()]);
+    state.entries.insert(dn.to_ascii_lowercase(), entry);
+    Outcome::success()
+}
+
+fn delete(state: &mut MockState, session: &Session, dn: &str) -> Outcome {
+    if let Err(outcome) = require_bind(session) {
+        return outcome;
+    }
+    if state.entry(dn).is_none() {
+        return Outcome::no_such_object(state, dn);
+    }
+    if state
+        .entries
+        .values()
+        .any(|entry| in_scope(&entry.dn, dn, 1))
+    {
+        return Outcome::failure(
+            rc::NOT_ALLOWED_ON_NON_LEAF,
+            "0000208C: UpdErr: DSID-030F1E1A, problem 6003 (CANT_ON_NON_LEAF), data 0",
+        );
+    }
+    state.entries.remove(&dn.to_ascii_lowercase());
+    // Linked attributes pointing at the object disappear with it
+    rewrite_references(state, |value| {
+        (!value.eq_ignore_ascii_case(dn)).then(|| value.to_string())
+    });
+    Outcome::success()
+}
+
+fn modify_dn(state: &mut MockState, session: &Session, op: &StructureTag) -> (String, Outcome) {
+    let parsed = constructed(op).and_then(|parts| {
+        let dn = text(primitive(parts.first()?)?);
+        let new_rdn = text(primitive(parts.get(1)?)?);
+        let delete_old = primitive(parts.get(2)?)?.first().is_some_and(|b| *b != 0);
+        let new_superior = parts.get(3).and_then(primitive).map(text);
+        Some((dn, new_rdn, delete_old, new_superior))
+    });
+    let Some((dn, new_rdn, delete_old, new_superior)) = parsed else {
+        return (String::new(), Outcome::failure(rc::PROTOCOL_ERROR, ""));
+    };
+    if let Err(outcome) = require_bind(session) {
+        return (dn, outcome);
+    }
+    let outcome = rename(state, &dn, &new_rdn, delete_old, new_superior);
+    (dn, outcome)
+}
+
+fn rename(
+    state: &mut MockState,
+    dn: &str,
+    new_rdn: &str,
+    delete_old: bool,
+    new_superior: Option<String>,
+) -> Outcome {
+    let Some(entry) = state.entry(dn).cloned() else {
+        return Outcome::no_such_object(state, dn);
+    };
+    let Some(parent) = new_superior.or_else(|| parent_dn(dn)) else {
+        return unwilling();
+    };
+    if state.entry(&parent).is_none() {
+        return Outcome::no_such_object(state, &parent);
+    }
+    let new_dn = format!("{},{}", new_rdn, parent);
+    if !new_dn.eq_ignore_ascii_case(dn) && state.entry(&new_dn).is_some() {
+        return Outcome::failure(
+            rc::ENTRY_ALREADY_EXISTS,
+            "00002071: UpdErr: DSID-030502F1, problem 6005 (ENTRY_EXISTS), data 0",
+        );
+    }
+
+    let old_suffix = format!(",{}", dn.to_ascii_lowercase());
+    let moved: Vec<String> = state
+        .entries
+        .keys()
+        .filter(|key| *key == &dn.to_ascii_lowercase() || key.ends_with(&old_suffix))
+        .cloned()
+        .collect();
+    let renamed = |value: &str| -> String {
+        if value.eq_ignore_ascii_case(dn) {
+            new_dn.clone()
+        } else if value.to_ascii_lowercase().ends_with(&old_suffix) {
+            format!("{}{}", &value[..value.len() - dn.len()], new_dn)
+        } else {
+            value.to_string()
+        }
+    };
+    for key in moved {
+        let Some(mut moving) = state.entries.remove(&key) else {
+            continue;
+        };
+        moving.dn = renamed(&moving.dn);
+        moving.set("distinguishedName", vec![moving.dn.clone().into_bytes()]);
+        state.entries.insert(moving.dn.to_ascii_lowercase(), moving);
+    }
+
+    let mut entry = state
+        .entries
+        .remove(&new_dn.to_ascii_lowercase())
+        .unwrap_or(entry);
+    entry.dn = new_dn.clone();
+    if let Some((attr, value)) = new_rdn.split_once('=') {
+        let mut values = entry.get(attr).cloned().unwrap_or_default();
+        if delete_old {
+            let old = rdn_value(dn);
+            values.retain(|v| !eq_text(v, old.as_bytes()));
+        }
+        if !values.iter().any(|v| eq_text(v, value.as_bytes())) {
+            values.push(rdn_value(new_rdn).into_bytes());
+        }
+        entry.set(attr, values);
+    }
+    entry.set("name", vec![rdn_value(&new_dn).into_bytes()]);
+    state.touch(&mut entry);
+    state.entries.insert(new_dn.to_ascii_lowercase(), entry);
+    rewrite_references(state, |value| Some(renamed(value)));
+    Outcome::success()
+}
+
+/// Rewrites the values of DN-valued attributes in every entry; `None` drops the value.
+fn rewrite_references<F>(state: &mut MockState, rewrite: F)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    for entry in state.entries.values_mut() {
+        for attr in DN_ATTRIBUTES {
+            let Some(values) = entry.get(attr) else {
+                continue;
+            };
+            let rewritten: Vec<Vec<u8>> = values
+                .iter()
+                .filter_map(|v| rewrite(&text(v)))
+                .map(String::into_bytes)
+                .collect();
+            if &rewritten != values {
+                entry.set(attr, rewritten);
+            }
+        }
+    }
+}
+
+fn parent_dn(dn: &str) -> Option<String> {
+    split_dn(dn).1.map(str::to_string)
+}
+
+/// The unescaped value of the first RDN: `John Doe` for `CN=John Doe,OU=Staff,...`.
+fn rdn_value(dn: &str) -> String {
+    let rdn = split_dn(dn).0;
+    let value = rdn.split_once('=').map_or(rdn, |(_, value)| value);
+    let mut unescaped = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            unescaped.push(byte);
+            continue;
+        }
+        // `\,` or a hex pair such as `\2c`
+        let Some(next) = bytes.next() else {
+            break;
+        };
+        let hex = bytes.clone().next().and_then(|low| {
+            u8::from_str_radix(&format!("{}{}", next as char, low as char), 16).ok()
+        });
+        match hex {
+            Some(decoded) => {
+                bytes.next();
+                unescaped.push(decoded);
+            }
+            None => unescaped.push(next),
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+fn decode_unicode_pwd(value: &[u8]) -> Option<String> {
+    let units: Vec<u16> = value
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    let quoted = String::from_utf16(&units).ok()?;
+    let password = quoted.strip_prefix('"')?.strip_suffix('"')?.to_string();
+    // Round-trip check that the value was encoded the way AD expects
+    (encode_unicode_pwd(&password) == value).then_some(password)
+}
+
+fn filetime_now() -> String {
+    ((Utc::now().timestamp() + FILETIME_EPOCH_OFFSET) * 10_000_000).to_string()
+}
+
+fn generalized_now() -> String {
+    Utc::now().format("%Y%m%d%H%M%S.0Z").to_string()
+}
+
+fn text(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).into_owned()
+}
+
+fn lossy_lower(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).to_lowercase()
+}
+
+fn eq_text(a: &[u8], b: &[u8]) -> bool {
+    a == b || lossy_lower(a) == lossy_lower(b)
+}
+
+fn constructed(tag: &StructureTag) -> Option<&Vec<StructureTag>> {
+    match &tag.payload {
+        PL::C(children) => Some(children),
+        PL::P(_) => None,
+    }
+}
+
+fn primitive(tag: &StructureTag) -> Option<&[u8]> {
+    match &tag.payload {
+        PL::P(bytes) => Some(bytes),
+        PL::C(_) => None,
+    }
+}
+
+/// Decodes a two's complement BER integer.
+fn integer(bytes: &[u8]) -> i64 {
+    let initial = if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+        -1
+    } else {
+        0
+    };
+    bytes
+        .iter()
+        .fold(initial, |value, byte| (value << 8) | i64::from(*byte))
+}
+
+/// Encodes a tag, a definite length and the contents.
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match content.len() {
+        len @ 0..=0x7f => encoded.push(len as u8),
+        len => {
+            let bytes = len.to_be_bytes();
+            let skip = bytes.iter().take_while(|b| **b == 0).count();
+            encoded.push(0x80 | (bytes.len() - skip) as u8);
+            encoded.extend_from_slice(&bytes[skip..]);
+        }
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+fn integer_bytes(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    // Drop leading bytes that only repeat the sign
+    while start < 7
+        && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+fn integer_value(value: i64) -> Vec<u8> {
+    tlv(0x02, &integer_bytes(value))
+}
+
+fn enumerated(value: u32) -> Vec<u8> {
+    tlv(0x0a, &integer_bytes(i64::from(value)))
+}
+
+fn octets(value: &[u8]) -> Vec<u8> {
+    tlv(0x04, value)
+}
+
+fn sequence(content: &[u8]) -> Vec<u8> {
+    tlv(0x30, content)
+}
+
+fn ldap_message(id: i64, op: &[u8], controls: Option<&[u8]>) -> Vec<u8> {
+    let mut content = integer_value(id);
+    content.extend_from_slice(op);
+    if let Some(controls) = controls {
+        content.extend(tlv(0xa0, controls));
+    }
+    sequence(&content)
+}
+
+fn search_entry(dn: &str, attrs: &[(String, Vec<Vec<u8>>)]) -> Vec<u8> {
+    let attributes: Vec<u8> = attrs
+        .iter()
+        .flat_map(|(name, values)| {
+            let values: Vec<u8> = values.iter().flat_map(|v| octets(v)).collect();
+            sequence(&[octets(name.as_bytes()), tlv(0x31, &values)].concat())
+        })
+        .collect();
+    tlv(
+        0x64,
+        &[octets(dn.as_bytes()), sequence(&attributes)].concat(),
+    )
+}
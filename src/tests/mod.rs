@@ -0,0 +1,4 @@
+mod email;
+mod ldap;
+pub mod ldap_mock;
+pub mod zoho_mock;
@@ -0,0 +1,460 @@
+//! A local stand-in for the Zoho accounts (OAuth) and mail APIs.
+//!
+//! The server listens on an ephemeral port and keeps an in-memory organization, so tests can
+//! point a `ZohoClient` at it and exercise the real request/response code paths.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::http::RetryPolicy;
+use crate::modules::email::{ZohoClient, ZohoEndpoints};
+
+pub const ZOID: &str = "800000001";
+pub const AUTH_CODE: &str = "mock-auth-code";
+pub const ACCESS_TOKEN: &str = "mock-access-token";
+pub const REFRESH_TOKEN: &str = "mock-refresh-token";
+
+pub const ACCOUNTS_FIXTURE: &str = include_str!("fixtures/zoho/accounts.json");
+pub const ACCOUNT_FIXTURE: &str = include_str!("fixtures/zoho/account.json");
+pub const INVALID_TOKEN_FIXTURE: &str = include_str!("fixtures/zoho/error_invalid_token.json");
+/// Account lists recorded from organizations with different policy ids.
+pub const ORG_FIXTURES: [&str; 3] = [
+    ACCOUNTS_FIXTURE,
+    include_str!("fixtures/zoho/accounts_org_b.json"),
+    include_str!("fixtures/zoho/accounts_org_c.json"),
+];
+pub const BUSINESS_POLICY_ID: &str = "1082700000192558003";
+pub const CONTRACTORS_POLICY_ID: &str = "1082700000192558101";
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    accounts: Vec<Value>,
+    policies: Vec<Value>,
+    requests: Vec<RecordedRequest>,
+    queued: VecDeque<MockResponse>,
+    access_tokens: Vec<String>,
+    next_zuid: i64,
+}
+
+pub struct ZohoMock {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl ZohoMock {
+    /// Starts a server seeded with the accounts from the recorded list fixture.
+    pub fn start() -> Self {
+        let fixture: Value = serde_json::from_str(ACCOUNTS_FIXTURE).unwrap();
+        Self::with_accounts(fixture["data"].as_array().unwrap().clone())
+    }
+
+    pub fn with_accounts(accounts: Vec<Value>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState {
+            accounts,
+            policies: vec![
+                json!({ "policyId": BUSINESS_POLICY_ID, "policyName": "Business Policy", "isDefault": true }),
+                json!({ "policyId": CONTRACTORS_POLICY_ID, "policyName": "Contractors", "isDefault": false }),
+            ],
+            access_tokens: vec![ACCESS_TOKEN.to_string()],
+            next_zuid: 700_100_000,
+            ..Default::default()
+        }));
+
+        let server_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                handle_connection(stream, &server_state);
+            }
+        });
+
+        Self { addr, state }
+    }
+
+    pub fn endpoints(&self) -> ZohoEndpoints {
+        let base = format!("http://{}", self.addr);
+        ZohoEndpoints {
+            accounts: base.clone(),
+            mail: base,
+        }
+    }
+
+    /// A client with a valid token and a retry policy that keeps tests fast.
+    pub fn client(&self) -> ZohoClient {
+        ZohoClient::new(self.endpoints(), ZOID, ACCESS_TOKEN).with_retry_policy(fast_retries())
+    }
+
+    /// Serves `response` for the next request instead of routing it.
+    pub fn queue(&self, response: MockResponse) {
+        self.state.lock().unwrap().queued.push_back(response);
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn accounts(&self) -> Vec<Value> {
+        self.state.lock().unwrap().accounts.clone()
+    }
+}
+
+pub fn fast_retries() -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 3,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(20),
+        max_total: Duration::from_secs(1),
+    }
+}
+
+/// A minimal account payload as returned by the accounts list.
+pub fn account(zuid: i64, email: &str, display_name: &str) -> Value {
+    json!({
+        "zuid": zuid,
+        "accountId": format!("60000{}", zuid),
+        "displayName": display_name,
+        "primaryEmailAddress": email,
+        "emailAddress": [{ "isAlias": false, "isPrimary": true, "mailId": email, "isConfirmed": true }],
+        "role": "member",
+        "enabled": true
+    })
+}
+
+fn success(data: Value) -> MockResponse {
+    MockResponse::json(
+        200,
+        json!({ "status": { "code": 200, "description": "success" }, "data": data }),
+    )
+}
+
+fn failure(status: u16, description: &str, error_code: &str) -> MockResponse {
+    MockResponse::json(
+        status,
+        json!({
+            "status": { "code": status, "description": description },
+            "data": { "errorCode": error_code }
+        }),
+    )
+}
+
+fn handle_connection(mut stream: TcpStream, state: &Arc<Mutex<MockState>>) {
+    let Some(request) = read_request(&stream) else {
+        return;
+    };
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        match state.queued.pop_front() {
+            Some(response) => response,
+            None => route(&mut state, &request),
+        }
+    };
+
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+}
+
+fn read_request(stream: &TcpStream) -> Option<RecordedRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Some(RecordedRequest {
+        method,
+        path: path.to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+fn route(state: &mut MockState, request: &RecordedRequest) -> MockResponse {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    if segments == ["oauth", "v2", "token"] {
+        return token(state, request);
+    }
+
+    let authorized = request
+        .headers
+        .get("authorization")
+        .and_then(|h| h.strip_prefix("Zoho-oauthtoken "))
+        .is_some_and(|token| state.access_tokens.iter().any(|t| t == token));
+    if !authorized {
+        return MockResponse {
+            status: 404,
+            headers: Vec::new(),
+            body: INVALID_TOKEN_FIXTURE.to_string(),
+        };
+    }
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["api", "organization", zoid, "accounts"]) if *zoid == ZOID => {
+            let start = query_usize(request, "start").unwrap_or(0);
+            let limit = query_usize(request, "limit").unwrap_or(10);
+            let page: Vec<Value> = state
+                .accounts
+                .iter()
+                .skip(start)
+                .take(limit)
+                .cloned()
+                .collect();
+            success(Value::Array(page))
+        }
+        ("GET", ["api", "organization", zoid, "accounts", id]) if *zoid == ZOID => {
+            let found = state.accounts.iter().find(|account| {
+                account["accountId"].as_str() == Some(id)
+                    || account["zuid"]
+                        .as_i64()
+                        .map(|zuid| zuid.to_string())
+                        .as_deref()
+                        == Some(id)
+                    || account["emailAddress"].as_array().is_some_and(|addresses| {
+                        addresses.iter().any(|a| {
+                            a["mailId"]
+                                .as_str()
+                                .is_some_and(|m| m.eq_ignore_ascii_case(id))
+                        })
+                    })
+            });
+            match found {
+                Some(account) => success(account.clone()),
+                None => failure(404, "Invalid Input", "USER_NOT_EXIST"),
+            }
+        }
+        ("PUT", ["api", "organization", zoid, "accounts", zuid]) if *zoid == ZOID => {
+            update_account(state, request, zuid)
+        }
+        ("POST", ["api", "organization", zoid, "accounts"]) if *zoid == ZOID => {
+            create_account(state, request)
+        }
+        ("DELETE", ["api", "organization", zoid, "accounts"]) if *zoid == ZOID => {
+            delete_accounts(state, request)
+        }
+        ("GET", ["api", "organization", zoid, "policy"]) if *zoid == ZOID => {
+            success(Value::Array(state.policies.clone()))
+        }
+        ("PUT", ["api", "organization", zoid, "policy", policy_id]) if *zoid == ZOID => {
+            assign_policy(state, request, policy_id)
+        }
+        _ => failure(404, "Invalid Input", "URL_RULE_NOT_CONFIGURED"),
+    }
+}
+
+fn query_usize(request: &RecordedRequest, key: &str) -> Option<usize> {
+    request.query.get(key).and_then(|v| v.parse().ok())
+}
+
+fn token(state: &mut MockState, request: &RecordedRequest) -> MockResponse {
+    let form: HashMap<String, String> = url::form_urlencoded::parse(request.body.as_bytes())
+        .into_owned()
+        .collect();
+    let grant_type = form.get("grant_type").map(String::as_str);
+    // Zoho reports OAuth errors with a 200 status and an `error` field
+    match grant_type {
+        Some("authorization_code") if form.get("code").map(String::as_str) == Some(AUTH_CODE) => {
+            MockResponse::json(
+                200,
+                json!({
+                    "access_token": ACCESS_TOKEN,
+                    "refresh_token": REFRESH_TOKEN,
+                    "api_domain": "https://www.zohoapis.com",
+                    "token_type": "Bearer",
+                    "expires_in": 3600
+                }),
+            )
+        }
+        Some("refresh_token")
+            if form.get("refresh_token").map(String::as_str) == Some(REFRESH_TOKEN) =>
+        {
+            let access_token = format!("{}-{}", ACCESS_TOKEN, state.access_tokens.len());
+            state.access_tokens.push(access_token.clone());
+            MockResponse::json(
+                200,
+                json!({
+                    "access_token": access_token,
+                    "api_domain": "https://www.zohoapis.com",
+                    "token_type": "Bearer",
+                    "expires_in": 3600
+                }),
+            )
+        }
+        Some("authorization_code") => MockResponse::json(200, json!({ "error": "invalid_code" })),
+        _ => MockResponse::json(200, json!({ "error": "invalid_client" })),
+    }
+}
+
+fn create_account(state: &mut MockState, request: &RecordedRequest) -> MockResponse {
+    let Ok(body) = serde_json::from_str::<Value>(&request.body) else {
+        return failure(400, "Invalid Input", "JSON_PARSE_ERROR");
+    };
+    let Some(email) = body["primaryEmailAddress"].as_str() else {
+        return failure(400, "Invalid Input", "MISSING_PRIMARY_EMAIL");
+    };
+    if state
+        .accounts
+        .iter()
+        .any(|a| a["primaryEmailAddress"].as_str() == Some(email))
+    {
+        return failure(400, "Email address already exists", "EMAIL_ALREADY_EXISTS");
+    }
+
+    state.next_zuid += 1;
+    let mut created = account(
+        state.next_zuid,
+        email,
+        body["displayName"].as_str().unwrap_or_default(),
+    );
+    created["firstName"] = body["firstName"].clone();
+    created["lastName"] = body["lastName"].clone();
+    state.accounts.push(created.clone());
+    success(created)
+}
+
+fn update_account(state: &mut MockState, request: &RecordedRequest, zuid: &str) -> MockResponse {
+    let Ok(body) = serde_json::from_str::<Value>(&request.body) else {
+        return failure(400, "Invalid Input", "JSON_PARSE_ERROR");
+    };
+    let Some(account) = state
+        .accounts
+        .iter_mut()
+        .find(|a| a["zuid"].as_i64().map(|z| z.to_string()).as_deref() == Some(zuid))
+    else {
+        return failure(404, "Invalid Input", "USER_NOT_EXIST");
+    };
+    match (body["mode"].as_str(), body["role"].as_str()) {
+        (Some("changeRole"), Some(role @ ("member" | "admin" | "super_admin"))) => {
+            account["role"] = json!(role);
+            account["iamUserRole"] = json!(role);
+            success(Value::Null)
+        }
+        (Some("changeRole"), _) => failure(400, "Invalid Input", "INVALID_ROLE"),
+        _ => failure(400, "Invalid Input", "INVALID_MODE"),
+    }
+}
+
+fn delete_accounts(state: &mut MockState, request: &RecordedRequest) -> MockResponse {
+    let Ok(body) = serde_json::from_str::<Value>(&request.body) else {
+        return failure(400, "Invalid Input", "JSON_PARSE_ERROR");
+    };
+    let zuids: Vec<i64> = body["accountList"]
+        .as_array()
+        .map(|list| list.iter().filter_map(Value::as_i64).collect())
+        .unwrap_or_default();
+    if zuids.is_empty() {
+        return failure(400, "Invalid Input", "MISSING_ACCOUNT_LIST");
+    }
+    state
+        .accounts
+        .retain(|a| !a["zuid"].as_i64().is_some_and(|zuid| zuids.contains(&zuid)));
+    success(Value::Null)
+}
+
+fn assign_policy(
+    state: &mut MockState,
+    request: &RecordedRequest,
+    policy_id: &str,
+) -> MockResponse {
+    let Some(policy) = state
+        .policies
+        .iter()
+        .find(|p| p["policyId"].as_str() == Some(policy_id))
+        .cloned()
+    else {
+        return failure(404, "Invalid Input", "POLICY_NOT_FOUND");
+    };
+    let Ok(body) = serde_json::from_str::<Value>(&request.body) else {
+        return failure(400, "Invalid Input", "JSON_PARSE_ERROR");
+    };
+    if body["mode"].as_str() != Some("assignUsers") {
+        return failure(400, "Invalid Input", "INVALID_MODE");
+    }
+    let zuids: Vec<i64> = body["userList"]
+        .as_array()
+        .map(|list| list.iter().filter_map(Value::as_i64).collect())
+        .unwrap_or_default();
+
+    let mut assigned = json!({ "zoid": ZOID.parse::<i64>().unwrap() });
+    assigned[policy_id] = policy["policyName"].clone();
+    for account in state.accounts.iter_mut() {
+        if account["zuid"]
+            .as_i64()
+            .is_some_and(|zuid| zuids.contains(&zuid))
+        {
+            account["policyId"] = assigned.clone();
+        }
+    }
+    success(Value::Null)
+}